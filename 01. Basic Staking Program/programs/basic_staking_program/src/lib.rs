@@ -1,10 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program::{self, transfer, Transfer};
-use solana_program::program::invoke_signed;
-use solana_program::system_instruction;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::system_instruction;
 
 declare_id!("8y8S3RW35AuEj2N9B5RVB8jimLEmjbRDHk91Wzjant2h");
 
+/// Layout version written by `create_pda_account` and `migrate_account`
+pub const STAKE_ACCOUNT_VERSION: u8 = 2;
+
 #[program]
 pub mod basic_staking_program {
     use super::*;
@@ -13,12 +16,21 @@ pub mod basic_staking_program {
         let pda_account = &mut ctx.accounts.pda_account;
         let clock = Clock::get()?;
 
+        pda_account.version = STAKE_ACCOUNT_VERSION;
         pda_account.owner = ctx.accounts.payer.key();
         pda_account.staked_amount = 0;
         pda_account.total_points = 0;
         pda_account.last_update_time = clock.unix_timestamp;
+        pda_account.vault = Pubkey::default();
         pda_account.bump = ctx.bumps.pda_account;
 
+        emit!(PositionCreated {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
         msg!("PDA account created successfully");
         Ok(())
     }
@@ -53,10 +65,67 @@ pub mod basic_staking_program {
             signer_seeds,
         )?;
 
+        let clock = Clock::get()?;
+
+        emit!(VaultCreated {
+            authority: ctx.accounts.authority.key(),
+            vault: ctx.accounts.pda_vault_account.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
         msg!("✅ PDA Vault System Account created successfully!");
         Ok(())
     }
 
+    /// Starts tracking what the vault owes its stakers. Positions already bound to the vault
+    /// are passed as remaining accounts and their stake counts as principal, anything else the
+    /// vault holds is surplus. Legacy positions are counted once `stake` or `unstake` binds them.
+    pub fn initialize_pool<'info>(
+        ctx: Context<'_, '_, 'info, 'info, InitializePool<'info>>,
+        params: PoolParams,
+    ) -> Result<()> {
+        params.validate()?;
+
+        let vault = ctx.accounts.pda_vault_account.key();
+        let mut counted: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        let mut total_staked: u64 = 0;
+        for info in ctx.remaining_accounts {
+            let position = Account::<StakeAccount>::try_from(info)?;
+            require_keys_eq!(position.vault, vault, StakingError::VaultMismatch);
+            require!(!counted.contains(info.key), StakingError::DuplicatePosition);
+            counted.push(info.key());
+            total_staked = total_staked
+                .checked_add(position.staked_amount)
+                .ok_or(StakingError::MathOverflow)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+
+        pool.authority = ctx.accounts.authority.key();
+        pool.min_stake = params.min_stake;
+        pool.max_stake_per_wallet = params.max_stake_per_wallet;
+        pool.max_total_staked = params.max_total_staked;
+        pool.total_staked = total_staked;
+        pool.total_pending_withdrawals = 0;
+        pool.bump = ctx.bumps.pool;
+
+        let clock = Clock::get()?;
+
+        emit!(PoolInitialized {
+            authority: pool.authority,
+            pool: pool.key(),
+            vault: ctx.accounts.pda_vault_account.key(),
+            params,
+            total_staked: pool.total_staked,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Pool initialized successfully");
+        Ok(())
+    }
+
     pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
         let from_pubkey = ctx.accounts.user.to_account_info();
         let to_pubkey = ctx.accounts.pda_vault_account.to_account_info();
@@ -70,11 +139,48 @@ pub mod basic_staking_program {
             },
         );
 
+        let vault = ctx.accounts.pda_vault_account.key();
+        bind_vault(
+            &mut ctx.accounts.pda_account,
+            &mut ctx.accounts.pool,
+            vault,
+            &ctx.accounts.authority,
+        )?;
+
+        require!(amount > 0, StakingError::InvalidAmount);
+        let pool = &ctx.accounts.pool;
+        require!(amount >= pool.min_stake, StakingError::BelowMinimumStake);
+        require!(
+            within_cap(
+                ctx.accounts.pda_account.staked_amount,
+                amount,
+                pool.max_stake_per_wallet
+            ),
+            StakingError::WalletCapExceeded
+        );
+        require!(
+            within_cap(pool.total_staked, amount, pool.max_total_staked),
+            StakingError::PoolCapExceeded
+        );
+
         transfer(cpi_context, amount)?;
 
         let pda_account = &mut ctx.accounts.pda_account;
 
         pda_account.staked_amount += amount;
+        ctx.accounts.pool.total_staked += amount;
+
+        let clock = Clock::get()?;
+
+        emit!(Staked {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
         msg!("Staking Successfull");
         Ok(())
@@ -83,6 +189,14 @@ pub mod basic_staking_program {
     pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
         require!(amount > 0, StakingError::InvalidAmount);
 
+        let vault = ctx.accounts.pda_vault_account.key();
+        bind_vault(
+            &mut ctx.accounts.pda_account,
+            &mut ctx.accounts.pool,
+            vault,
+            &ctx.accounts.authority,
+        )?;
+
         let pda_account = &mut ctx.accounts.pda_account;
 
         require!(
@@ -112,11 +226,268 @@ pub mod basic_staking_program {
 
         // Update Staked Amount
         pda_account.staked_amount -= amount;
+        ctx.accounts.pool.total_staked = ctx
+            .accounts
+            .pool
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(StakingError::MathOverflow)?;
+
+        let clock = Clock::get()?;
+
+        emit!(Unstaked {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+        let pda_account = &ctx.accounts.pda_account;
+        let new_pda_account = &mut ctx.accounts.new_pda_account;
+
+        new_pda_account.version = STAKE_ACCOUNT_VERSION;
+        new_pda_account.owner = ctx.accounts.new_owner.key();
+        new_pda_account.staked_amount = pda_account.staked_amount;
+        new_pda_account.total_points = pda_account.total_points;
+        new_pda_account.last_update_time = pda_account.last_update_time;
+        new_pda_account.vault = pda_account.vault;
+        new_pda_account.bump = ctx.bumps.new_pda_account;
+
+        let clock = Clock::get()?;
+
+        emit!(PositionTransferred {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.new_owner.key(),
+            position: new_pda_account.key(),
+            staked_amount: new_pda_account.staked_amount,
+            total_points: new_pda_account.total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position transferred successfully");
+        Ok(())
+    }
+
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        let pda_account = ctx.accounts.pda_account.to_account_info();
+
+        let legacy = {
+            let data = pda_account.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *StakeAccount::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            require!(
+                data.len() == StakeAccountV1::LEN,
+                StakingError::AlreadyMigrated
+            );
+            StakeAccountV1::deserialize(&mut &data[8..])?
+        };
+
+        let expected = Pubkey::create_program_address(
+            &[b"client1", legacy.owner.as_ref(), &[legacy.bump]],
+            ctx.program_id,
+        )
+        .map_err(|_| ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(pda_account.key(), expected, ErrorCode::ConstraintSeeds);
+
+        // Top the account up to the rent minimum of the new size before growing it
+        let space = 8 + StakeAccount::INIT_SPACE;
+        let shortfall = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(pda_account.lamports());
+        if shortfall > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: pda_account.clone(),
+                },
+            );
+            transfer(cpi_context, shortfall)?;
+        }
+        pda_account.resize(space)?;
+
+        let migrated = StakeAccount {
+            version: STAKE_ACCOUNT_VERSION,
+            owner: legacy.owner,
+            staked_amount: legacy.staked_amount,
+            total_points: legacy.total_points,
+            last_update_time: legacy.last_update_time,
+            bump: legacy.bump,
+            vault: Pubkey::default(),
+            reserved: [0; 32],
+        };
+        migrated.try_serialize(&mut &mut pda_account.try_borrow_mut_data()?[..])?;
+
+        let clock = Clock::get()?;
+
+        emit!(PositionMigrated {
+            owner: migrated.owner,
+            position: pda_account.key(),
+            from_version: 1,
+            to_version: STAKE_ACCOUNT_VERSION,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position migrated successfully");
+        Ok(())
+    }
+
+    /// Compares what the vault must hold for its stakers with what it actually holds
+    pub fn reconcile(ctx: Context<Reconcile>) -> Result<Reconciliation> {
+        Reconciliation::new(
+            &ctx.accounts.pool,
+            ctx.accounts.pda_vault_account.lamports(),
+        )
+    }
+
+    /// How much more the pool, and the wallet whose position is passed in, can take
+    pub fn capacity(ctx: Context<CapacityView>) -> Result<Capacity> {
+        let wallet_staked = ctx
+            .accounts
+            .pda_account
+            .as_ref()
+            .map_or(0, |position| position.staked_amount);
+        Ok(Capacity::new(&ctx.accounts.pool, wallet_staked))
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+
+        treasury.authority = ctx.accounts.authority.key();
+        treasury.total_swept = 0;
+        treasury.bump = ctx.bumps.treasury;
+
+        let clock = Clock::get()?;
+
+        emit!(TreasuryInitialized {
+            authority: treasury.authority,
+            treasury: treasury.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Treasury initialized successfully");
+        Ok(())
+    }
+
+    /// Moves lamports the vault holds beyond its rent and what it owes into the treasury
+    pub fn sweep_surplus(ctx: Context<SweepSurplus>) -> Result<()> {
+        let reconciliation = Reconciliation::new(
+            &ctx.accounts.pool,
+            ctx.accounts.pda_vault_account.lamports(),
+        )?;
+
+        require!(reconciliation.surplus > 0, StakingError::NoSurplus);
+
+        let authority_key = ctx.accounts.authority.key();
+        let seeds = &[
+            b"pdaVault",
+            authority_key.as_ref(),
+            &[ctx.bumps.pda_vault_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.pda_vault_account.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer,
+        );
+        transfer(cpi_context, reconciliation.surplus)?;
+
+        ctx.accounts.treasury.total_swept += reconciliation.surplus;
+
+        let clock = Clock::get()?;
+
+        emit!(SurplusSwept {
+            authority: authority_key,
+            vault: ctx.accounts.pda_vault_account.key(),
+            treasury: ctx.accounts.treasury.key(),
+            amount: reconciliation.surplus,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Surplus swept to the treasury");
+        Ok(())
+    }
+
+    /// Pays lamports swept into the treasury out to the authority, its rent stays behind
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidAmount);
+
+        let treasury = ctx.accounts.treasury.to_account_info();
+        let rent_reserve = Rent::get()?.minimum_balance(treasury.data_len());
+        require!(
+            treasury.lamports() >= rent_reserve + amount,
+            StakingError::InvalidBalance
+        );
+
+        // The treasury is owned by this program, so its lamports are moved directly
+        **treasury.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.authority.try_borrow_mut_lamports()? += amount;
 
+        let clock = Clock::get()?;
+
+        emit!(TreasuryWithdrawn {
+            authority: ctx.accounts.authority.key(),
+            treasury: treasury.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Treasury withdrawn successfully");
         Ok(())
     }
 }
 
+// Whether `staked + amount` stays within `cap`, where no cap allows anything
+fn within_cap(staked: u64, amount: u64, cap: Option<u64>) -> bool {
+    staked
+        .checked_add(amount)
+        .is_some_and(|total| cap.is_none_or(|cap| total <= cap))
+}
+
+/// A position is bound to the vault holding its stake, it can only move while empty.
+/// Positions staked before the binding existed have no vault on record, so the vault's
+/// authority has to co-sign the first stake or unstake that binds them, which also adds
+/// their stake to the pool's total.
+fn bind_vault(
+    pda_account: &mut StakeAccount,
+    pool: &mut Pool,
+    vault: Pubkey,
+    authority: &AccountInfo,
+) -> Result<()> {
+    let unbound = pda_account.vault == Pubkey::default();
+    if pda_account.staked_amount == 0 {
+        pda_account.vault = vault;
+    } else if unbound && authority.is_signer {
+        pda_account.vault = vault;
+        pool.total_staked = pool
+            .total_staked
+            .checked_add(pda_account.staked_amount)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+    require_keys_eq!(pda_account.vault, vault, StakingError::VaultMismatch);
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct CreatePdaAccount<'info> {
     #[account(mut)]
@@ -125,7 +496,7 @@ pub struct CreatePdaAccount<'info> {
     #[account(
         init,
         payer = payer,
-        space = 8 + 32 + 8 + 8 + 8 + 1, // discriminator + owner + staked_amount + total_points + last_update_time + bump
+        space = 8 + StakeAccount::INIT_SPACE,
         seeds = [b"client1", payer.key().as_ref()],
         bump
     )]
@@ -149,6 +520,29 @@ pub struct VaultPdaAccount<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct Stake<'info> {
     #[account(mut)]
@@ -171,6 +565,13 @@ pub struct Stake<'info> {
     )]
     pub pda_vault_account: SystemAccount<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -196,11 +597,200 @@ pub struct Unstake<'info> {
     )]
     pub pda_vault_account: SystemAccount<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"client1", owner.key().as_ref()],
+        bump = pda_account.bump,
+        constraint = pda_account.owner == owner.key()
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    /// CHECK: the wallet receiving the position, it does not need to sign
+    #[account(constraint = new_owner.key() != owner.key() @ StakingError::InvalidNewOwner)]
+    pub new_owner: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakeAccount::INIT_SPACE,
+        seeds = [b"client1", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_pda_account: Account<'info, StakeAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    /// Pays the rent for the extra space, anyone may migrate a position
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: a v1 `StakeAccount` cannot be deserialized as the current layout, so the
+    /// discriminator, length and `[b"client1", owner]` address are checked in the handler
+    #[account(mut, owner = crate::ID)]
+    pub pda_account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Reconcile<'info> {
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct CapacityView<'info> {
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The wallet's position, omitted for the pool alone
+    pub pda_account: Option<Account<'info, StakeAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::INIT_SPACE,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SweepSurplus<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+/// What a vault owes its stakers, the vault itself holds no data
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub authority: Pubkey,
+    /// Smallest amount a single `stake` may deposit
+    pub min_stake: u64,
+    /// Most a single position may hold, `None` for no cap
+    pub max_stake_per_wallet: Option<u64>,
+    /// Most the whole pool may hold, `None` for no cap
+    pub max_total_staked: Option<u64>,
+    /// Principal held in the vault, which it always has to be able to pay back
+    pub total_staked: u64,
+    /// Unstaked principal still owed to stakers. `unstake` pays out immediately so this stays
+    /// zero for now, but it is reserved like `total_staked` and can never be swept.
+    pub total_pending_withdrawals: u64,
+    pub bump: u8,
+}
+
+/// Where `sweep_surplus` moves the vault's surplus, at `[b"treasury", authority]`
 #[account]
+#[derive(InitSpace)]
+pub struct Treasury {
+    pub authority: Pubkey,
+    /// Surplus swept in over the treasury's lifetime
+    pub total_swept: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
 pub struct StakeAccount {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub last_update_time: i64,
+    pub bump: u8,
+    /// Vault holding the position's stake, set by its first stake. Carved out of
+    /// `reserved`, so it reads as unset on positions written before it existed
+    pub vault: Pubkey,
+    /// Room for new fields without another realloc
+    pub reserved: [u8; 32],
+}
+
+/// The original `StakeAccount` layout, which `migrate_account` upgrades. It shares the
+/// current discriminator.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StakeAccountV1 {
     pub owner: Pubkey,
     pub staked_amount: u64,
     pub total_points: u64,
@@ -208,6 +798,191 @@ pub struct StakeAccount {
     pub bump: u8,
 }
 
+impl StakeAccountV1 {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 1; // discriminator + owner + staked_amount + total_points + last_update_time + bump
+}
+
+/// Stake limits of a pool, set by `initialize_pool`
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Default)]
+pub struct PoolParams {
+    pub min_stake: u64,
+    pub max_stake_per_wallet: Option<u64>,
+    pub max_total_staked: Option<u64>,
+}
+
+impl PoolParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.max_stake_per_wallet
+                .is_none_or(|cap| cap >= self.min_stake),
+            StakingError::InvalidStakeLimits
+        );
+        Ok(())
+    }
+}
+
+/// Returned by `reconcile`
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reconciliation {
+    pub vault_balance: u64,
+    /// Rent-exempt minimum of the vault
+    pub rent_reserve: u64,
+    pub total_staked: u64,
+    pub total_pending_withdrawals: u64,
+    /// Lamports beyond what the vault owes, which `sweep_surplus` may take
+    pub surplus: u64,
+    /// Lamports missing from what the vault owes
+    pub shortfall: u64,
+}
+
+impl Reconciliation {
+    pub fn new(pool: &Pool, vault_balance: u64) -> Result<Self> {
+        let rent_reserve = Rent::get()?.minimum_balance(0);
+        let owed = rent_reserve + pool.total_staked + pool.total_pending_withdrawals;
+
+        Ok(Self {
+            vault_balance,
+            rent_reserve,
+            total_staked: pool.total_staked,
+            total_pending_withdrawals: pool.total_pending_withdrawals,
+            surplus: vault_balance.saturating_sub(owed),
+            shortfall: owed.saturating_sub(vault_balance),
+        })
+    }
+}
+
+/// Returned by `capacity`, `None` meaning no limit
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Capacity {
+    pub min_stake: u64,
+    pub total_staked: u64,
+    /// Lamports the pool can still take before its cap
+    pub pool_remaining: Option<u64>,
+    /// Stake of the position passed in, zero without one
+    pub wallet_staked: u64,
+    /// Most that wallet can still stake, under both its own cap and the pool's
+    pub wallet_remaining: Option<u64>,
+}
+
+impl Capacity {
+    pub fn new(pool: &Pool, wallet_staked: u64) -> Self {
+        let remaining = |staked: u64, cap: Option<u64>| cap.map(|cap| cap.saturating_sub(staked));
+        let pool_remaining = remaining(pool.total_staked, pool.max_total_staked);
+        let wallet_cap_remaining = remaining(wallet_staked, pool.max_stake_per_wallet);
+        let wallet_remaining = match (wallet_cap_remaining, pool_remaining) {
+            (Some(wallet), Some(pool)) => Some(wallet.min(pool)),
+            (wallet, pool) => wallet.or(pool),
+        };
+
+        Self {
+            min_stake: pool.min_stake,
+            total_staked: pool.total_staked,
+            pool_remaining,
+            wallet_staked,
+            wallet_remaining,
+        }
+    }
+}
+
+#[event]
+pub struct VaultCreated {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub vault: Pubkey,
+    pub params: PoolParams,
+    pub total_staked: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionCreated {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct Staked {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionTransferred {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub position: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionMigrated {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreasuryInitialized {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct SurplusSwept {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub total_staked: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
 #[error_code]
 pub enum StakingError {
     #[msg("Amount must be greater than 0")]
@@ -215,4 +990,34 @@ pub enum StakingError {
 
     #[msg("Invalid Balance")]
     InvalidBalance,
+
+    #[msg("New owner must be a different wallet")]
+    InvalidNewOwner,
+
+    #[msg("Position is already on the current layout")]
+    AlreadyMigrated,
+
+    #[msg("Position is staked in a different vault")]
+    VaultMismatch,
+
+    #[msg("Vault holds nothing beyond what it owes")]
+    NoSurplus,
+
+    #[msg("Position is listed more than once")]
+    DuplicatePosition,
+
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+
+    #[msg("Stake is below the pool's minimum")]
+    BelowMinimumStake,
+
+    #[msg("Stake would exceed the pool's per-wallet cap")]
+    WalletCapExceeded,
+
+    #[msg("Stake would exceed the pool's total cap")]
+    PoolCapExceeded,
+
+    #[msg("Per-wallet cap cannot be below the minimum stake")]
+    InvalidStakeLimits,
 }
@@ -55,6 +55,54 @@ describe("Test", () => {
     await program.provider.connection.confirmTransaction(txHash);
   });
 
+  it("initializes the pool", async () => {
+    const [vaultPdaAccount] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("pdaVault"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const [pool] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("pool"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const txHash = await program.methods
+      .initializePool({
+        minStake: new BN(0),
+        maxStakePerWallet: null,
+        maxTotalStaked: null,
+      })
+      .accounts({
+        authority: program.provider.publicKey,
+        pdaVaultAccount: vaultPdaAccount,
+        pool: pool,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const poolAccount = await program.account.pool.fetch(pool);
+    assert.equal(poolAccount.totalStaked.toNumber(), 0);
+    assert.equal(poolAccount.totalPendingWithdrawals.toNumber(), 0);
+  });
+
+  it("initializes the treasury", async () => {
+    const [treasury] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("treasury"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const txHash = await program.methods
+      .initializeTreasury()
+      .accounts({
+        authority: program.provider.publicKey,
+        treasury: treasury,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const treasuryAccount = await program.account.treasury.fetch(treasury);
+    assert.equal(treasuryAccount.totalSwept.toNumber(), 0);
+  });
+
   it("stake amount", async () => {
     const [vaultPdaAccount, bump] = await web3.PublicKey.findProgramAddress(
       [Buffer.from("pdaVault"), program.provider.publicKey.toBuffer()],
@@ -64,6 +112,10 @@ describe("Test", () => {
       [Buffer.from("client1"), program.provider.publicKey.toBuffer()],
       program.programId
     );
+    const [pool] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("pool"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
     const amount = new BN(4_000_000_000);
     // Send the Transaction
     const txHash = await program.methods
@@ -73,6 +125,7 @@ describe("Test", () => {
         pdaAccount: pdaAccount,
         authority: program.provider.publicKey,
         pdaVaultAccount: vaultPdaAccount,
+        pool: pool,
         systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
@@ -96,6 +149,10 @@ describe("Test", () => {
       [Buffer.from("client1"), program.provider.publicKey.toBuffer()],
       program.programId
     );
+    const [pool] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("pool"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
     const userAccount = await program.account.stakeAccount.fetch(pdaAccount);
     console.log(
       "User staked amount before unstaking: ",
@@ -112,6 +169,7 @@ describe("Test", () => {
         pdaAccount: pdaAccount,
         authority: program.provider.publicKey,
         pdaVaultAccount: vaultPdaAccount,
+        pool: pool,
         systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
@@ -129,4 +187,44 @@ describe("Test", () => {
     // Assertions
     assert.equal(userAccountUpdate.stakedAmount.toNumber(), 3000000000);
   });
+  it("transfers the position to a new wallet", async () => {
+    const newOwner = web3.Keypair.generate();
+    const [pdaAccount, bump] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("client1"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const [newPdaAccount, bump2] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("client1"), newOwner.publicKey.toBuffer()],
+      program.programId
+    );
+    const userAccount = await program.account.stakeAccount.fetch(pdaAccount);
+
+    // Send the transaction
+    const txHash = await program.methods
+      .transferPosition()
+      .accounts({
+        owner: program.provider.publicKey,
+        pdaAccount: pdaAccount,
+        newOwner: newOwner.publicKey,
+        newPdaAccount: newPdaAccount,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    // Confirm the transaction
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const newAccount = await program.account.stakeAccount.fetch(newPdaAccount);
+
+    // Assertions
+    assert.ok(newAccount.owner.equals(newOwner.publicKey));
+    assert.equal(
+      newAccount.stakedAmount.toNumber(),
+      userAccount.stakedAmount.toNumber()
+    );
+    assert.equal(
+      await program.provider.connection.getAccountInfo(pdaAccount),
+      null
+    );
+  });
 });
@@ -1,13 +1,31 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
 use anchor_lang::solana_program::program::invoke_signed;
 use anchor_lang::solana_program::system_instruction;
 use anchor_lang::system_program;
 use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::associated_token::{self, get_associated_token_address, AssociatedToken};
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
 declare_id!("Hf3qwJ436dF49JgNAiJxyUEwGzN3miMRmZiJrFgNkKCv");
 
 const REWARD_RATE_PER_SOL_PER_SECOND: u64 = 1;
 const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+const BPS_DENOMINATOR: u64 = 10_000;
+const MILLIS_PER_SLOT: u64 = 400;
+const SLOTS_PER_EPOCH: u64 = 432_000;
+/// Shortest decay period or expiry window a pool may set
+pub const MIN_DECAY_PERIOD: i64 = 86_400;
+/// Streak tiers a pool can set
+pub const MAX_STREAK_TIERS: usize = 4;
+/// Largest streak bonus, doubling the reward rate
+pub const MAX_STREAK_BONUS_BPS: u16 = 10_000;
+
+/// Positions a leaderboard ranks
+pub const LEADERBOARD_SIZE: usize = 64;
+
+/// Layout version written by `create_pda_account` and `migrate_account`
+pub const STAKE_ACCOUNT_VERSION: u8 = 2;
 
 #[program]
 pub mod basic_staking_program {
@@ -17,12 +35,28 @@ pub mod basic_staking_program {
         let pda_account = &mut ctx.accounts.pda_account;
         let clock = Clock::get()?;
 
+        pda_account.version = STAKE_ACCOUNT_VERSION;
         pda_account.owner = ctx.accounts.payer.key();
         pda_account.staked_amount = 0;
         pda_account.total_points = 0;
-        pda_account.last_update_time = clock.unix_timestamp;
+        pda_account.mark_updated(&clock);
+        pda_account.referrer = Pubkey::default();
+        pda_account.referral_points = 0;
+        pda_account.vault = Pubkey::default();
+        pda_account.season = 0;
+        pda_account.season_points = 0;
+        pda_account.expiring_points = 0;
+        pda_account.decayed_at = clock.unix_timestamp;
+        pda_account.streak_start = 0;
         pda_account.bump = ctx.bumps.pda_account;
 
+        emit!(PositionCreated {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
         msg!("PDA account created successfully");
         Ok(())
     }
@@ -57,11 +91,124 @@ pub mod basic_staking_program {
             signer_seeds,
         )?;
 
+        let clock = Clock::get()?;
+
+        emit!(VaultCreated {
+            authority: ctx.accounts.authority.key(),
+            vault: ctx.accounts.pda_vault_account.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
         msg!("✅ PDA Vault System Account created successfully!");
         Ok(())
     }
 
-    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+    pub fn initialize_pool(ctx: Context<InitializePool>, params: PoolParams) -> Result<()> {
+        params.validate()?;
+
+        let pool = &mut ctx.accounts.pool;
+
+        pool.authority = ctx.accounts.authority.key();
+        pool.referral_bps = params.referral_bps;
+        pool.fee_bps = params.fee_bps;
+        pool.time_base = params.time_base;
+        pool.min_stake = params.min_stake;
+        pool.max_stake_per_wallet = params.max_stake_per_wallet;
+        pool.max_total_staked = params.max_total_staked;
+        pool.decay = params.decay;
+        pool.streak_tiers = params.streak_tiers;
+        pool.total_staked = 0;
+        pool.total_pending_withdrawals = 0;
+        pool.allowlist_root = [0; 32];
+        pool.has_leaderboard = false;
+        pool.bump = ctx.bumps.pool;
+
+        let clock = Clock::get()?;
+
+        emit!(PoolInitialized {
+            authority: pool.authority,
+            pool: pool.key(),
+            params,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Pool initialized successfully");
+        Ok(())
+    }
+
+    pub fn update_pool_params(ctx: Context<UpdatePoolParams>, params: PoolParams) -> Result<()> {
+        params.validate()?;
+
+        let pool = &mut ctx.accounts.pool;
+
+        pool.referral_bps = params.referral_bps;
+        pool.fee_bps = params.fee_bps;
+        pool.time_base = params.time_base;
+        pool.min_stake = params.min_stake;
+        pool.max_stake_per_wallet = params.max_stake_per_wallet;
+        pool.max_total_staked = params.max_total_staked;
+        pool.decay = params.decay;
+        pool.streak_tiers = params.streak_tiers;
+
+        let clock = Clock::get()?;
+
+        emit!(ParamsUpdated {
+            authority: pool.authority,
+            pool: pool.key(),
+            params,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Pool params updated successfully");
+        Ok(())
+    }
+
+    pub fn stake(
+        ctx: Context<Stake>,
+        amount: u64,
+        referrer: Option<Pubkey>,
+        allowlist: Option<AllowlistProof>,
+    ) -> Result<()> {
+        // A position is bound to the vault holding its stake, it can only move while empty
+        let vault = ctx.accounts.pda_vault_account.key();
+        if ctx.accounts.pda_account.staked_amount == 0 {
+            ctx.accounts.pda_account.vault = vault;
+        }
+        require_keys_eq!(ctx.accounts.pda_account.vault, vault, StakingError::VaultMismatch);
+
+        require!(amount > 0, StakingError::InvalidAmount);
+        let pool = &ctx.accounts.pool;
+        require!(amount >= pool.min_stake, StakingError::BelowMinimumStake);
+        require!(
+            within_cap(ctx.accounts.pda_account.staked_amount, amount, pool.max_stake_per_wallet),
+            StakingError::WalletCapExceeded
+        );
+        require!(
+            within_cap(pool.total_staked, amount, pool.max_total_staked),
+            StakingError::PoolCapExceeded
+        );
+
+        // A pool with an allowlist only takes stake from listed wallets, up to their allocation
+        let root = ctx.accounts.pool.allowlist_root;
+        if root != [0; 32] {
+            let allowlist = allowlist.ok_or(StakingError::NotAllowlisted)?;
+            require!(
+                allowlist.verify(&root, &ctx.accounts.user.key()),
+                StakingError::NotAllowlisted
+            );
+            require!(
+                ctx.accounts
+                    .pda_account
+                    .staked_amount
+                    .checked_add(amount)
+                    .is_some_and(|staked| staked <= allowlist.max_allocation),
+                StakingError::AllocationExceeded
+            );
+        }
+
         let from_pubkey = ctx.accounts.user.to_account_info();
         let to_pubkey = ctx.accounts.pda_vault_account.to_account_info();
         let program_id = ctx.accounts.system_program.to_account_info();
@@ -77,10 +224,51 @@ pub mod basic_staking_program {
         transfer(cpi_context, amount)?;
 
         let pda_account = &mut ctx.accounts.pda_account;
+        let clock = Clock::get()?;
+
+        // The referrer is recorded once, on the first stake that names one
+        if let Some(referrer) = referrer {
+            if pda_account.referrer == Pubkey::default() {
+                require!(
+                    referrer != ctx.accounts.user.key(),
+                    StakingError::InvalidReferrer
+                );
+
+                pda_account.referrer = referrer;
+
+                emit!(ReferrerRecorded {
+                    referee: ctx.accounts.user.key(),
+                    referrer,
+                    timestamp: clock.unix_timestamp,
+                    slot: clock.slot,
+                });
+            }
+        }
 
-        pda_account.staked_amount += amount;
+        // Settle the elapsed time at the old balance before the new stake lands
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            ctx.accounts.leaderboard.as_ref(),
+        )?;
+
+        // The streak runs from the first stake, topping up keeps it going
+        if pda_account.staked_amount == 0 {
+            pda_account.streak_start = clock.unix_timestamp;
+        }
+        pda_account.add_stake(&ctx.accounts.pool, amount);
+        ctx.accounts.pool.total_staked += amount;
 
-        update_reward_points(pda_account)?;
+        emit!(Staked {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            total_points: pda_account.total_points,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
         msg!("Staking Successfull");
         Ok(())
@@ -98,7 +286,11 @@ pub mod basic_staking_program {
 
         let authority_key = ctx.accounts.authority.key();
 
-        update_reward_points(pda_account)?;
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            ctx.accounts.leaderboard.as_ref(),
+        )?;
 
         // Transfer SOL from PDA back to user
         let seeds = &[
@@ -118,38 +310,807 @@ pub mod basic_staking_program {
         );
         transfer(cpi_context, amount)?;
 
+        // Unstaking gives up the same share of the streak, the rest keeps its progress
+        let now = Clock::get()?.unix_timestamp;
+        let streak = (now - pda_account.streak_start).max(0) as u128;
+        pda_account.streak_start +=
+            (streak * amount as u128 / pda_account.staked_amount as u128) as i64;
+
         // Update Staked Amount
-        pda_account.staked_amount -= amount;
+        pda_account.remove_stake(amount);
+        ctx.accounts.pool.total_staked = ctx
+            .accounts
+            .pool
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(StakingError::MathOverflow)?;
+
+        let clock = Clock::get()?;
+
+        emit!(Unstaked {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            total_points: pda_account.total_points,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
         Ok(())
     }
 
     pub fn claim_points(ctx: Context<ClaimPoints>) -> Result<()> {
         let pda_account = &mut ctx.accounts.pda_account;
+        expire_points(pda_account, &ctx.accounts.pool.decay)?;
 
         require!(pda_account.total_points > 0, StakingError::InvalidPoints);
 
+        // The pool's cut is rounded down in the claimant's favour
+        let fee = u64::try_from(
+            pda_account.total_points as u128 * ctx.accounts.pool.fee_bps as u128
+                / BPS_DENOMINATOR as u128,
+        )
+        .map_err(|_| StakingError::MathOverflow)?;
+        let amount = pda_account.total_points - fee;
         pda_account.total_points = 0;
+        pda_account.expiring_points = 0;
+
+        let clock = Clock::get()?;
+
+        if fee > 0 {
+            let Some(treasury) = ctx.accounts.treasury.as_mut() else {
+                return err!(StakingError::TreasuryRequired);
+            };
+            treasury.fee_points += fee;
+
+            emit!(FeeCollected {
+                authority: treasury.authority,
+                treasury: treasury.key(),
+                position: pda_account.key(),
+                amount: fee,
+                timestamp: clock.unix_timestamp,
+                slot: clock.slot,
+            });
+        }
+
+        emit!(PointsClaimed {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
         msg!("You have Claimed Your Points");
         Ok(())
     }
+
+    pub fn claim_referral_points(ctx: Context<ClaimReferralPoints>) -> Result<()> {
+        let referee_account = &mut ctx.accounts.referee_account;
+
+        require!(referee_account.referral_points > 0, StakingError::InvalidPoints);
+
+        let amount = referee_account.referral_points;
+        referee_account.referral_points = 0;
+
+        // The referrer's older points decay first, so the new ones start out fresh
+        let referrer_account = &mut ctx.accounts.referrer_account;
+        expire_points(referrer_account, &ctx.accounts.pool.decay)?;
+        referrer_account.total_points += amount;
+
+        let clock = Clock::get()?;
+
+        emit!(ReferralRewardsClaimed {
+            referrer: ctx.accounts.referrer.key(),
+            referee: referee_account.owner,
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("You have Claimed Your Referral Points");
+        Ok(())
+    }
+
+    pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+        let pda_account = &ctx.accounts.pda_account;
+        let new_pda_account = &mut ctx.accounts.new_pda_account;
+
+        new_pda_account.version = STAKE_ACCOUNT_VERSION;
+        new_pda_account.owner = ctx.accounts.new_owner.key();
+        new_pda_account.staked_amount = pda_account.staked_amount;
+        new_pda_account.total_points = pda_account.total_points;
+        new_pda_account.last_update_time = pda_account.last_update_time;
+        new_pda_account.last_update_slot = pda_account.last_update_slot;
+        new_pda_account.last_update_epoch = pda_account.last_update_epoch;
+        new_pda_account.pending_stake = pda_account.pending_stake;
+        new_pda_account.referrer = pda_account.referrer;
+        new_pda_account.referral_points = pda_account.referral_points;
+        new_pda_account.vault = pda_account.vault;
+        new_pda_account.season = pda_account.season;
+        new_pda_account.season_points = pda_account.season_points;
+        new_pda_account.expiring_points = pda_account.expiring_points;
+        new_pda_account.decayed_at = pda_account.decayed_at;
+        new_pda_account.streak_start = pda_account.streak_start;
+        new_pda_account.bump = ctx.bumps.new_pda_account;
+
+        require!(
+            ctx.accounts.leaderboard.is_some() || !ctx.accounts.pool.has_leaderboard,
+            StakingError::LeaderboardRequired
+        );
+        match &ctx.accounts.leaderboard {
+            Some(leaderboard) => leaderboard
+                .load_mut()?
+                .rename(pda_account.key(), new_pda_account.key()),
+            None => require!(pda_account.season == 0, StakingError::LeaderboardRequired),
+        }
+
+        let clock = Clock::get()?;
+
+        emit!(PositionTransferred {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.new_owner.key(),
+            position: new_pda_account.key(),
+            staked_amount: new_pda_account.staked_amount,
+            total_points: new_pda_account.total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position transferred successfully");
+        Ok(())
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+
+        treasury.authority = ctx.accounts.authority.key();
+        treasury.total_swept = 0;
+        treasury.fee_points = 0;
+        treasury.bump = ctx.bumps.treasury;
+
+        let clock = Clock::get()?;
+
+        emit!(TreasuryInitialized {
+            authority: treasury.authority,
+            treasury: treasury.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Treasury initialized successfully");
+        Ok(())
+    }
+
+    pub fn initialize_leaderboard(ctx: Context<InitializeLeaderboard>) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard.load_init()?;
+        let clock = Clock::get()?;
+
+        leaderboard.authority = ctx.accounts.authority.key();
+        leaderboard.season = 1;
+        leaderboard.season_started_at = clock.unix_timestamp;
+        leaderboard.len = 0;
+        leaderboard.bump = ctx.bumps.leaderboard;
+        ctx.accounts.pool.has_leaderboard = true;
+
+        emit!(LeaderboardInitialized {
+            authority: leaderboard.authority,
+            leaderboard: ctx.accounts.leaderboard.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Leaderboard initialized successfully");
+        Ok(())
+    }
+
+    /// Archives the season's final standings into a snapshot and starts the next season
+    pub fn reset_season(ctx: Context<ResetSeason>, season: u64) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard.load_mut()?;
+        require_eq!(leaderboard.season, season, StakingError::SeasonMismatch);
+
+        let snapshot = &mut ctx.accounts.snapshot.load_init()?;
+        let clock = Clock::get()?;
+
+        snapshot.authority = leaderboard.authority;
+        snapshot.season = season;
+        snapshot.started_at = leaderboard.season_started_at;
+        snapshot.ended_at = clock.unix_timestamp;
+        snapshot.len = leaderboard.len;
+        snapshot.bump = ctx.bumps.snapshot;
+        snapshot.entries = leaderboard.entries;
+
+        leaderboard.season += 1;
+        leaderboard.season_started_at = clock.unix_timestamp;
+        leaderboard.len = 0;
+        leaderboard.entries = [LeaderboardEntry::default(); LEADERBOARD_SIZE];
+
+        emit!(SeasonReset {
+            authority: leaderboard.authority,
+            snapshot: ctx.accounts.snapshot.key(),
+            season,
+            entries: snapshot.len,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Season reset successfully");
+        Ok(())
+    }
+
+    /// Lists an item positions can buy with points. Tokens and NFTs for the whole stock move
+    /// from `source` into the item's escrow, its associated token account.
+    pub fn publish_item(ctx: Context<PublishItem>, item_id: u64, params: ItemParams) -> Result<()> {
+        params.validate()?;
+
+        let item = &mut ctx.accounts.item;
+
+        item.authority = ctx.accounts.authority.key();
+        item.item_id = item_id;
+        item.kind = params.kind;
+        item.mint = Pubkey::default();
+        item.amount = params.amount;
+        item.price = params.price;
+        item.stock = 0;
+        item.redeemed = 0;
+        item.bump = ctx.bumps.item;
+
+        if params.kind != ItemKind::WhitelistSpot {
+            let (Some(mint), Some(escrow), Some(token_program), Some(associated_token_program)) = (
+                &ctx.accounts.mint,
+                &ctx.accounts.escrow,
+                &ctx.accounts.token_program,
+                &ctx.accounts.associated_token_program,
+            ) else {
+                return err!(StakingError::MissingItemAccounts);
+            };
+            require!(
+                params.kind != ItemKind::Nft || (mint.decimals == 0 && params.amount == 1),
+                StakingError::InvalidItem
+            );
+            require_keys_eq!(
+                escrow.key(),
+                get_associated_token_address(&item.key(), &mint.key()),
+                StakingError::InvalidItem
+            );
+
+            associated_token::create_idempotent(CpiContext::new(
+                associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.authority.to_account_info(),
+                    associated_token: escrow.to_account_info(),
+                    authority: item.to_account_info(),
+                    mint: mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: token_program.to_account_info(),
+                },
+            ))?;
+
+            item.mint = mint.key();
+        }
+
+        add_stock(
+            item,
+            params.stock,
+            ctx.accounts.source.as_ref(),
+            ctx.accounts.escrow.as_ref(),
+            ctx.accounts.authority.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+        )?;
+
+        let clock = Clock::get()?;
+
+        emit!(ItemPublished {
+            authority: item.authority,
+            item: item.key(),
+            item_id,
+            kind: item.kind,
+            mint: item.mint,
+            amount: item.amount,
+            price: item.price,
+            stock: item.stock,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Item published successfully");
+        Ok(())
+    }
+
+    /// Reprices an item and adds `added_stock` to it, depositing the tokens it takes. NFT
+    /// items are never restocked.
+    pub fn update_item(ctx: Context<UpdateItem>, price: u64, added_stock: u64) -> Result<()> {
+        let item = &mut ctx.accounts.item;
+        require!(price > 0, StakingError::InvalidItem);
+        require!(
+            item.kind != ItemKind::Nft || added_stock == 0,
+            StakingError::InvalidItem
+        );
+
+        item.price = price;
+        add_stock(
+            item,
+            added_stock,
+            ctx.accounts.source.as_ref(),
+            ctx.accounts.escrow.as_ref(),
+            ctx.accounts.authority.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+        )?;
+
+        let clock = Clock::get()?;
+
+        emit!(ItemUpdated {
+            authority: item.authority,
+            item: item.key(),
+            price: item.price,
+            stock: item.stock,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Item updated successfully");
+        Ok(())
+    }
+
+    /// Spends the position's points on one unit of an item, delivered in the same instruction
+    pub fn redeem(ctx: Context<Redeem>) -> Result<()> {
+        let item = &mut ctx.accounts.item;
+        let pda_account = &mut ctx.accounts.pda_account;
+
+        // Points earned up to now can be spent, and rank before they are
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            ctx.accounts.leaderboard.as_ref(),
+        )?;
+
+        require!(item.stock > 0, StakingError::OutOfStock);
+        require!(
+            pda_account.total_points >= item.price,
+            StakingError::InvalidPoints
+        );
+
+        pda_account.spend_points(item.price);
+        item.stock -= 1;
+        item.redeemed += 1;
+
+        let clock = Clock::get()?;
+
+        match item.kind {
+            ItemKind::Tokens | ItemKind::Nft => {
+                let (Some(escrow), Some(destination), Some(token_program)) = (
+                    &ctx.accounts.escrow,
+                    &ctx.accounts.destination,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(StakingError::MissingItemAccounts);
+                };
+                require_keys_eq!(
+                    escrow.key(),
+                    get_associated_token_address(&item.key(), &item.mint),
+                    StakingError::InvalidItem
+                );
+                require_keys_eq!(destination.mint, item.mint, StakingError::InvalidItem);
+
+                let authority_key = item.authority;
+                let item_id = item.item_id.to_le_bytes();
+                let signer_seeds: &[&[&[u8]]] = &[&[
+                    b"catalogItem",
+                    authority_key.as_ref(),
+                    &item_id,
+                    &[item.bump],
+                ]];
+
+                let cpi_ctx = CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    token::Transfer {
+                        from: escrow.to_account_info(),
+                        to: destination.to_account_info(),
+                        authority: item.to_account_info(),
+                    },
+                    signer_seeds,
+                );
+                token::transfer(cpi_ctx, item.amount)?;
+            }
+            ItemKind::WhitelistSpot => {
+                let Some(spot) = &mut ctx.accounts.whitelist_spot else {
+                    return err!(StakingError::MissingItemAccounts);
+                };
+                spot.owner = ctx.accounts.user.key();
+                spot.item = item.key();
+                spot.redeemed_at = clock.unix_timestamp;
+                spot.bump = ctx.bumps.whitelist_spot.unwrap_or_default();
+            }
+        }
+
+        emit!(ItemRedeemed {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            item: item.key(),
+            price: item.price,
+            stock: item.stock,
+            total_points: pda_account.total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Item redeemed successfully");
+        Ok(())
+    }
+
+    /// Compares what the vault must hold for its stakers with what it actually holds
+    pub fn reconcile(ctx: Context<Reconcile>) -> Result<Reconciliation> {
+        Reconciliation::new(&ctx.accounts.pool, ctx.accounts.pda_vault_account.lamports())
+    }
+
+    pub fn capacity(ctx: Context<CapacityView>) -> Result<Capacity> {
+        let wallet_staked = ctx
+            .accounts
+            .pda_account
+            .as_ref()
+            .map_or(0, |position| position.staked_amount);
+        Ok(Capacity::new(&ctx.accounts.pool, wallet_staked))
+    }
+
+    /// The position's settled points once decay or expiry is applied, and how many of them
+    /// the pool takes at its next period or window boundary
+    pub fn points_outlook(ctx: Context<PointsOutlookView>) -> Result<PointsOutlook> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(PointsOutlook::new(
+            &ctx.accounts.pda_account,
+            &ctx.accounts.pool.decay,
+            now,
+        ))
+    }
+
+    pub fn sweep_surplus(ctx: Context<SweepSurplus>) -> Result<()> {
+        let reconciliation =
+            Reconciliation::new(&ctx.accounts.pool, ctx.accounts.pda_vault_account.lamports())?;
+
+        require!(reconciliation.surplus > 0, StakingError::NoSurplus);
+
+        let authority_key = ctx.accounts.authority.key();
+        let seeds = &[
+            b"pdaVault",
+            authority_key.as_ref(),
+            &[ctx.bumps.pda_vault_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.pda_vault_account.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer,
+        );
+        transfer(cpi_context, reconciliation.surplus)?;
+
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.total_swept += reconciliation.surplus;
+
+        let clock = Clock::get()?;
+
+        emit!(SurplusSwept {
+            authority: authority_key,
+            vault: ctx.accounts.pda_vault_account.key(),
+            treasury: treasury.key(),
+            amount: reconciliation.surplus,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Surplus swept to the treasury");
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidAmount);
+
+        let treasury = ctx.accounts.treasury.to_account_info();
+        let rent_reserve = Rent::get()?.minimum_balance(treasury.data_len());
+        require!(
+            treasury.lamports() >= rent_reserve + amount,
+            StakingError::InvalidBalance
+        );
+
+        // The treasury is owned by this program, so its lamports are moved directly
+        **treasury.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.authority.try_borrow_mut_lamports()? += amount;
+
+        let clock = Clock::get()?;
+
+        emit!(TreasuryWithdrawn {
+            authority: ctx.accounts.authority.key(),
+            treasury: treasury.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Treasury withdrawn successfully");
+        Ok(())
+    }
+
+    pub fn set_allowlist_root(ctx: Context<SetAllowlistRoot>, root: [u8; 32]) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        let previous_root = pool.allowlist_root;
+        pool.allowlist_root = root;
+
+        let clock = Clock::get()?;
+
+        emit!(AllowlistRootUpdated {
+            authority: pool.authority,
+            pool: pool.key(),
+            previous_root,
+            root,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Allowlist root updated successfully");
+        Ok(())
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+
+        require!(treasury.fee_points > 0, StakingError::InvalidPoints);
+
+        let amount = treasury.fee_points;
+        treasury.fee_points = 0;
+
+        // Fees are paid out as points on the authority's own position, like referral points
+        ctx.accounts.pda_account.total_points += amount;
+
+        let clock = Clock::get()?;
+
+        emit!(FeesWithdrawn {
+            authority: treasury.authority,
+            treasury: treasury.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Fees withdrawn successfully");
+        Ok(())
+    }
+
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        let pda_account = ctx.accounts.pda_account.to_account_info();
+
+        let legacy = {
+            let data = pda_account.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *StakeAccount::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            require!(
+                data.len() == StakeAccountV1::LEN,
+                StakingError::AlreadyMigrated
+            );
+            StakeAccountV1::deserialize(&mut &data[8..])?
+        };
+
+        let expected = Pubkey::create_program_address(
+            &[b"client1", legacy.owner.as_ref(), &[legacy.bump]],
+            ctx.program_id,
+        )
+        .map_err(|_| ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(pda_account.key(), expected, ErrorCode::ConstraintSeeds);
+
+        // Legacy stake sits in an unknown vault, its authority vouches for it by co-signing
+        // and the pool starts tracking it
+        let vault = if legacy.staked_amount > 0 {
+            require!(
+                ctx.accounts.authority.is_signer,
+                StakingError::VaultAuthorityRequired
+            );
+            ctx.accounts.pool.total_staked += legacy.staked_amount;
+            ctx.accounts.pda_vault_account.key()
+        } else {
+            Pubkey::default()
+        };
+
+        // Top the account up to the rent minimum of the new size before growing it
+        let space = 8 + StakeAccount::INIT_SPACE;
+        let shortfall = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(pda_account.lamports());
+        if shortfall > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: pda_account.clone(),
+                },
+            );
+            transfer(cpi_context, shortfall)?;
+        }
+        pda_account.resize(space)?;
+
+        // Streaks and decay start counting at the migration, as does accrual in slots and
+        // epochs, which v1 never recorded
+        let clock = Clock::get()?;
+        let migrated = StakeAccount {
+            version: STAKE_ACCOUNT_VERSION,
+            owner: legacy.owner,
+            staked_amount: legacy.staked_amount,
+            total_points: legacy.total_points,
+            last_update_time: legacy.last_update_time,
+            referrer: Pubkey::default(),
+            referral_points: 0,
+            vault,
+            season: 0,
+            season_points: 0,
+            expiring_points: 0,
+            decayed_at: clock.unix_timestamp,
+            streak_start: if legacy.staked_amount > 0 {
+                clock.unix_timestamp
+            } else {
+                0
+            },
+            bump: legacy.bump,
+            last_update_slot: clock.slot,
+            last_update_epoch: clock.epoch,
+            pending_stake: 0,
+            reserved: [0; 8],
+        };
+        migrated.try_serialize(&mut &mut pda_account.try_borrow_mut_data()?[..])?;
+
+        emit!(PositionMigrated {
+            owner: migrated.owner,
+            position: pda_account.key(),
+            from_version: 1,
+            to_version: STAKE_ACCOUNT_VERSION,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position migrated successfully");
+        Ok(())
+    }
+}
+
+fn update_reward_points(
+    pda_account: &mut Account<StakeAccount>,
+    pool: &Pool,
+    leaderboard: Option<&AccountLoader<Leaderboard>>,
+) -> Result<()> {
+    expire_points(pda_account, &pool.decay)?;
+
+    let clock = Clock::get()?;
+    let rewards = pda_account.settle(pool, &clock);
+
+    // Points count towards the season they settle in. A pool without a leaderboard has no
+    // seasons, but a position that was ranked must keep settling on its leaderboard.
+    require!(
+        leaderboard.is_some() || !pool.has_leaderboard,
+        StakingError::LeaderboardRequired
+    );
+    match leaderboard {
+        Some(leaderboard) => {
+            let leaderboard = &mut leaderboard.load_mut()?;
+            if pda_account.season != leaderboard.season {
+                pda_account.season = leaderboard.season;
+                pda_account.season_points = 0;
+            }
+            pda_account.season_points += rewards;
+            leaderboard.record(pda_account.key(), pda_account.season_points);
+        }
+        None => require!(pda_account.season == 0, StakingError::LeaderboardRequired),
+    }
+
+    // Referral points are paid on top of the referee's own points
+    if pda_account.referrer != Pubkey::default() {
+        let cut = u64::try_from(
+            rewards as u128 * pool.referral_bps as u128 / BPS_DENOMINATOR as u128,
+        )
+        .map_err(|_| StakingError::MathOverflow)?;
+        pda_account.referral_points = pda_account
+            .referral_points
+            .checked_add(cut)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+
+    Ok(())
 }
 
-fn update_reward_points(pda_account: &mut StakeAccount) -> Result<()> {
-    let current_time = Clock::get()?.unix_timestamp;
-    let time_elapsed = (current_time - pda_account.last_update_time) as u64;
+// Applies the pool's decay or expiry to the position's settled points up to now
+fn expire_points(pda_account: &mut Account<StakeAccount>, decay: &PointsDecay) -> Result<()> {
+    let clock = Clock::get()?;
+    let (total_points, expiring_points) = decay.apply(
+        pda_account.total_points,
+        pda_account.expiring_points,
+        pda_account.decayed_at,
+        clock.unix_timestamp,
+    );
+    let expired = pda_account.total_points - total_points;
+
+    pda_account.total_points = total_points;
+    pda_account.expiring_points = expiring_points;
+    pda_account.decayed_at = clock.unix_timestamp;
+
+    if expired > 0 {
+        emit!(PointsExpired {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount: expired,
+            total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+    }
+
+    Ok(())
+}
 
-    let rewards = (pda_account.staked_amount / LAMPORTS_PER_SOL)
-        * time_elapsed
-        * REWARD_RATE_PER_SOL_PER_SECOND;
+// Adds `added` units to an item, moving the tokens they take from the authority into escrow
+fn add_stock<'info>(
+    item: &mut Account<'info, CatalogItem>,
+    added: u64,
+    source: Option<&Account<'info, TokenAccount>>,
+    escrow: Option<&UncheckedAccount<'info>>,
+    authority: &AccountInfo<'info>,
+    token_program: Option<&Program<'info, Token>>,
+) -> Result<()> {
+    if item.kind != ItemKind::WhitelistSpot && added > 0 {
+        let (Some(source), Some(escrow), Some(token_program)) = (source, escrow, token_program)
+        else {
+            return err!(StakingError::MissingItemAccounts);
+        };
+        require_keys_eq!(
+            escrow.key(),
+            get_associated_token_address(&item.key(), &item.mint),
+            StakingError::InvalidItem
+        );
 
-    pda_account.total_points += rewards;
-    pda_account.last_update_time = current_time;
+        let deposit = added
+            .checked_mul(item.amount)
+            .ok_or(StakingError::InvalidAmount)?;
+        let cpi_ctx = CpiContext::new(
+            token_program.to_account_info(),
+            token::Transfer {
+                from: source.to_account_info(),
+                to: escrow.to_account_info(),
+                authority: authority.clone(),
+            },
+        );
+        token::transfer(cpi_ctx, deposit)?;
+    }
 
+    item.stock = item
+        .stock
+        .checked_add(added)
+        .ok_or(StakingError::InvalidAmount)?;
     Ok(())
 }
 
+// Whether `staked + amount` stays within `cap`, where no cap allows anything
+fn within_cap(staked: u64, amount: u64, cap: Option<u64>) -> bool {
+    staked
+        .checked_add(amount)
+        .is_some_and(|total| cap.is_none_or(|cap| total <= cap))
+}
+
+/// Leaf of a pool allowlist: `hashv([0x00, wallet, max_allocation as little endian])`
+pub fn allowlist_leaf(wallet: &Pubkey, max_allocation: u64) -> [u8; 32] {
+    hashv(&[&[0x00], wallet.as_ref(), &max_allocation.to_le_bytes()]).to_bytes()
+}
+
+/// Parent of two allowlist nodes, hashed in sorted order so proofs carry no directions
+pub fn merkle_parent(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[&[0x01], left, right]).to_bytes()
+}
+
 #[derive(Accounts)]
 pub struct CreatePdaAccount<'info> {
     #[account(mut)]
@@ -158,7 +1119,7 @@ pub struct CreatePdaAccount<'info> {
     #[account(
         init,
         payer = payer,
-        space = 8 + 32 + 8 + 8 + 8 + 1, // discriminator + owner + staked_amount + total_points + last_update_time + bump
+        space = 8 + StakeAccount::INIT_SPACE,
         seeds = [b"client1", payer.key().as_ref()],
         bump
     )]
@@ -180,28 +1141,135 @@ pub struct ClaimPoints<'info> {
     )]
     pub pda_account: Account<'info, StakeAccount>,
 
-    pub system_program: Program<'info, System>,
-}
+    pub authority: AccountInfo<'info>,
 
-#[derive(Accounts)]
-pub struct VaultPdaAccount<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    // Fees go to the pool the position is staked in, it cannot pick a cheaper one. A position
+    // that never staked only holds referral points and may claim through any pool.
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+        constraint = pda_account.vault == Pubkey::default()
+            || pda_vault_account.key() == pda_account.vault @ StakingError::VaultMismatch
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
 
+    /// Only needed when the claim pays a fee
     #[account(
         mut,
-        seeds = [b"pdaVault", authority.key().as_ref()],
-        bump
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
     )]
-    pub pda_vault_account: AccountInfo<'info>,
+    pub treasury: Option<Account<'info, Treasury>>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Stake<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct ClaimReferralPoints<'info> {
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", referrer.key().as_ref()],
+        bump = referrer_account.bump,
+        constraint = referrer_account.owner == referrer.key()
+    )]
+    pub referrer_account: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", referee_account.owner.as_ref()],
+        bump = referee_account.bump,
+        constraint = referee_account.referrer == referrer.key() @ StakingError::InvalidReferrer
+    )]
+    pub referee_account: Account<'info, StakeAccount>,
+
+    pub authority: AccountInfo<'info>,
+
+    // The referrer's points decay under the pool their position is staked in
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+        constraint = referrer_account.vault == Pubkey::default()
+            || pda_vault_account.key() == referrer_account.vault @ StakingError::VaultMismatch
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct VaultPdaAccount<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump
+    )]
+    pub pda_vault_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolParams<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct SetAllowlistRoot<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
 
     #[account(
         mut,
@@ -220,6 +1288,21 @@ pub struct Stake<'info> {
     )]
     pub pda_vault_account: SystemAccount<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Left out only for a pool without a leaderboard
+    #[account(
+        mut,
+        seeds = [b"leaderboard", authority.key().as_ref()],
+        bump = leaderboard.load()?.bump,
+    )]
+    pub leaderboard: Option<AccountLoader<'info, Leaderboard>>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -242,29 +1325,1385 @@ pub struct Unstake<'info> {
         mut, 
         seeds = [b"pdaVault", authority.key().as_ref()],
         bump,
+        constraint = pda_vault_account.key() == pda_account.vault @ StakingError::VaultMismatch
     )]
     pub pda_vault_account: SystemAccount<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Left out only for a pool without a leaderboard
+    #[account(
+        mut,
+        seeds = [b"leaderboard", authority.key().as_ref()],
+        bump = leaderboard.load()?.bump,
+    )]
+    pub leaderboard: Option<AccountLoader<'info, Leaderboard>>,
+
     pub system_program: Program<'info, System>,
 }
 
-#[account]
-pub struct StakeAccount {
-    pub owner: Pubkey,
-    pub staked_amount: u64,
-    pub total_points: u64,
-    pub last_update_time: i64,
-    pub bump: u8,
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"client1", owner.key().as_ref()],
+        bump = pda_account.bump,
+        constraint = pda_account.owner == owner.key()
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    /// CHECK: the wallet receiving the position, it does not need to sign
+    #[account(constraint = new_owner.key() != owner.key() @ StakingError::InvalidNewOwner)]
+    pub new_owner: UncheckedAccount<'info>,
+
+    pub authority: AccountInfo<'info>,
+
+    // The leaderboard of the pool the position is staked in follows it to the new address
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+        constraint = pda_account.vault == Pubkey::default()
+            || pda_vault_account.key() == pda_account.vault @ StakingError::VaultMismatch
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"leaderboard", authority.key().as_ref()],
+        bump = leaderboard.load()?.bump,
+    )]
+    pub leaderboard: Option<AccountLoader<'info, Leaderboard>>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakeAccount::INIT_SPACE,
+        seeds = [b"client1", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_pda_account: Account<'info, StakeAccount>,
+
+    pub system_program: Program<'info, System>,
 }
 
-#[error_code]
-pub enum StakingError {
-    #[msg("Amount must be greater than 0")]
-    InvalidAmount,
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    /// Pays the rent for the extra space, anyone may migrate a position
+    #[account(mut)]
+    pub payer: Signer<'info>,
 
-    #[msg("Invalid Balance")]
-    InvalidBalance,
+    /// CHECK: a v1 `StakeAccount` cannot be deserialized as the current layout, so the
+    /// discriminator, length and `[b"client1", owner]` address are checked in the handler
+    #[account(mut, owner = crate::ID)]
+    pub pda_account: UncheckedAccount<'info>,
 
-    #[msg("Insufficient Points")]
-    InvalidPoints,
+    /// CHECK: authority of the vault the legacy stake sits in, it has to sign when the
+    /// position holds stake
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(item_id: u64)]
+pub struct PublishItem<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + CatalogItem::INIT_SPACE,
+        seeds = [b"catalogItem", authority.key().as_ref(), &item_id.to_le_bytes()],
+        bump
+    )]
+    pub item: Account<'info, CatalogItem>,
+
+    // The rest is only needed for token and NFT items
+    pub mint: Option<Account<'info, Mint>>,
+
+    /// CHECK: the item's associated token account, created here
+    #[account(mut)]
+    pub escrow: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub source: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateItem<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"catalogItem", authority.key().as_ref(), &item.item_id.to_le_bytes()],
+        bump = item.bump,
+        has_one = authority
+    )]
+    pub item: Account<'info, CatalogItem>,
+
+    // The rest is only needed to restock token and NFT items
+    /// CHECK: checked against the item's associated token account
+    #[account(mut)]
+    pub escrow: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub source: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct Redeem<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", user.key().as_ref()],
+        bump = pda_account.bump,
+        constraint = pda_account.owner == user.key()
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    pub authority: AccountInfo<'info>,
+
+    // Points buy from the catalogue of the pool they were earned in, so a position that never
+    // staked in one has nowhere to redeem
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+        constraint = pda_vault_account.key() == pda_account.vault @ StakingError::VaultMismatch
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Left out only for a pool without a leaderboard
+    #[account(
+        mut,
+        seeds = [b"leaderboard", authority.key().as_ref()],
+        bump = leaderboard.load()?.bump,
+    )]
+    pub leaderboard: Option<AccountLoader<'info, Leaderboard>>,
+
+    #[account(
+        mut,
+        seeds = [b"catalogItem", authority.key().as_ref(), &item.item_id.to_le_bytes()],
+        bump = item.bump,
+    )]
+    pub item: Account<'info, CatalogItem>,
+
+    // Token and NFT items are delivered to `destination`, whitelist spots as `whitelist_spot`
+    #[account(mut)]
+    pub escrow: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, token::authority = user)]
+    pub destination: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + WhitelistSpot::INIT_SPACE,
+        seeds = [b"whitelistSpot", item.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub whitelist_spot: Option<Account<'info, WhitelistSpot>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLeaderboard<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Leaderboard>(),
+        seeds = [b"leaderboard", authority.key().as_ref()],
+        bump
+    )]
+    pub leaderboard: AccountLoader<'info, Leaderboard>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(season: u64)]
+pub struct ResetSeason<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"leaderboard", authority.key().as_ref()],
+        bump = leaderboard.load()?.bump,
+    )]
+    pub leaderboard: AccountLoader<'info, Leaderboard>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<LeaderboardSnapshot>(),
+        seeds = [b"leaderboardSnapshot", authority.key().as_ref(), &season.to_le_bytes()],
+        bump
+    )]
+    pub snapshot: AccountLoader<'info, LeaderboardSnapshot>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::INIT_SPACE,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Reconcile<'info> {
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct CapacityView<'info> {
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The wallet whose remaining allowance to report, omitted for the pool alone
+    pub pda_account: Option<Account<'info, StakeAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct PointsOutlookView<'info> {
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub pda_account: Account<'info, StakeAccount>,
+}
+
+#[derive(Accounts)]
+pub struct SweepSurplus<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", authority.key().as_ref()],
+        bump = pda_account.bump,
+        constraint = pda_account.owner == authority.key()
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct StakeAccount {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub last_update_time: i64,
+    pub referrer: Pubkey,
+    pub referral_points: u64,
+    pub vault: Pubkey,
+    /// Leaderboard season `season_points` were earned in
+    pub season: u64,
+    /// Points settled during `season`, what the leaderboard ranks
+    pub season_points: u64,
+    /// Part of `total_points` settled in the previous expiry window, lost when the current
+    /// one ends. Spending takes from these first.
+    pub expiring_points: u64,
+    /// When the pool's decay or expiry was last applied to `total_points`
+    pub decayed_at: i64,
+    /// Start of the current staking streak, pushed forward by unstakes
+    pub streak_start: i64,
+    pub bump: u8,
+    /// `last_update_time` read in slots and epochs, for pools that accrue in those
+    pub last_update_slot: u64,
+    pub last_update_epoch: u64,
+    /// Part of `staked_amount` added during the current epoch of an epoch pool, which only
+    /// starts earning with the next one. Carved out of `reserved`.
+    pub pending_stake: u64,
+    /// Room for new fields without another realloc
+    pub reserved: [u8; 8],
+}
+
+/// The original `StakeAccount` layout, which `migrate_account` upgrades. It shares the
+/// current discriminator.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StakeAccountV1 {
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub last_update_time: i64,
+    pub bump: u8,
+}
+
+impl StakeAccountV1 {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 1; // discriminator + owner + staked_amount + total_points + last_update_time + bump
+}
+
+impl StakeAccount {
+    /// Takes `points` off the balance, oldest points first
+    pub fn spend_points(&mut self, points: u64) {
+        self.total_points -= points;
+        self.expiring_points = self.expiring_points.saturating_sub(points);
+    }
+
+    /// Credits the points earned since the last settlement as of `time`, measured in the
+    /// pool's time base, and returns them. Streak tiers are wall-clock spans, so their bonus is
+    /// always read in unix time.
+    pub fn settle(&mut self, pool: &Pool, time: &impl TimeSource) -> u64 {
+        let base = pool.time_base;
+        let elapsed = base.now(time) - self.last_updated(base);
+        let earned = |staked: u64, seconds: u64| {
+            (staked / LAMPORTS_PER_SOL) * seconds * REWARD_RATE_PER_SOL_PER_SECOND
+        };
+        // Time past each streak tier counts again at that tier's bonus
+        let streak_bonus = pool.streak_bonus_seconds(
+            self.streak_start,
+            self.last_update_time,
+            time.unix_timestamp(),
+        );
+
+        // Pending stake sits out the first unit and this settlement's streak bonus, after
+        // which it earns like the rest
+        let settled = self.staked_amount - self.pending_stake;
+        let rewards = if self.pending_stake > 0 && elapsed > 0 {
+            earned(settled, base.to_seconds(1))
+                + earned(self.staked_amount, base.to_seconds(elapsed - 1))
+        } else {
+            earned(settled, base.to_seconds(elapsed))
+        } + earned(settled, streak_bonus);
+        if elapsed > 0 {
+            self.pending_stake = 0;
+        }
+
+        self.total_points += rewards;
+        self.mark_updated(time);
+        rewards
+    }
+
+    /// Adds `amount` of freshly settled stake. Epoch pools only credit the added stake for
+    /// whole epochs that started after it landed, so it waits as `pending_stake` while the
+    /// stake already there keeps earning the current epoch.
+    pub fn add_stake(&mut self, pool: &Pool, amount: u64) {
+        self.staked_amount += amount;
+
+        if pool.time_base == TimeBase::Epochs {
+            self.pending_stake += amount;
+        }
+    }
+
+    /// Takes `amount` out of the position, out of its pending stake first
+    pub fn remove_stake(&mut self, amount: u64) {
+        self.staked_amount -= amount;
+        self.pending_stake = self.pending_stake.saturating_sub(amount);
+    }
+
+    /// The last settlement, read in `base`
+    pub fn last_updated(&self, base: TimeBase) -> i64 {
+        match base {
+            TimeBase::UnixSeconds => self.last_update_time,
+            TimeBase::Slots => self.last_update_slot as i64,
+            TimeBase::Epochs => self.last_update_epoch as i64,
+        }
+    }
+
+    // All three are kept in every base, so a pool can change its time base under open positions
+    fn mark_updated(&mut self, time: &impl TimeSource) {
+        self.last_update_time = time.unix_timestamp();
+        self.last_update_slot = time.slot();
+        self.last_update_epoch = time.epoch();
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub authority: Pubkey,
+    pub referral_bps: u16,
+    /// Share of every claim paid to the pool's treasury instead of the claimant
+    pub fee_bps: u16,
+    /// What positions accrue points in
+    pub time_base: TimeBase,
+    /// Smallest amount a single `stake` may deposit
+    pub min_stake: u64,
+    /// Most a single position may hold, `None` for no cap
+    pub max_stake_per_wallet: Option<u64>,
+    /// Most the whole pool may hold, `None` for no cap
+    pub max_total_staked: Option<u64>,
+    /// How unspent points shrink over time
+    pub decay: PointsDecay,
+    /// Reward rate bonuses for long streaks, see `StreakTier`
+    pub streak_tiers: [StreakTier; MAX_STREAK_TIERS],
+    /// Principal held in the pool's vault, which it always has to be able to pay back
+    pub total_staked: u64,
+    /// Unstaked principal still owed to stakers. `unstake` pays out immediately so this stays
+    /// zero for now, but it is reserved like `total_staked` and can never be swept.
+    pub total_pending_withdrawals: u64,
+    /// Merkle root of the wallets allowed to stake and their allocations, all zero when the
+    /// pool is open to everyone
+    pub allowlist_root: [u8; 32],
+    /// Whether `initialize_leaderboard` has run, after which every settlement must rank on it
+    pub has_leaderboard: bool,
+    pub bump: u8,
+}
+
+/// What a pool measures accrual in. Slots and epochs are harder for validators to skew than
+/// `unix_timestamp`, but only approximate wall-clock time. Streaks, decay and seasons stay
+/// wall-clock spans in every base.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBase {
+    UnixSeconds,
+    Slots,
+    Epochs,
+}
+
+impl TimeBase {
+    /// Nominal length of one unit, so the reward rate given per second carries over
+    pub fn millis_per_unit(&self) -> u64 {
+        match self {
+            TimeBase::UnixSeconds => 1_000,
+            TimeBase::Slots => MILLIS_PER_SLOT,
+            TimeBase::Epochs => MILLIS_PER_SLOT * SLOTS_PER_EPOCH,
+        }
+    }
+
+    /// The current reading of `time` in this base
+    pub fn now(&self, time: &impl TimeSource) -> i64 {
+        match self {
+            TimeBase::UnixSeconds => time.unix_timestamp(),
+            TimeBase::Slots => time.slot() as i64,
+            TimeBase::Epochs => time.epoch() as i64,
+        }
+    }
+
+    /// Whole seconds `units` of this base stand for, none for a negative span
+    pub fn to_seconds(&self, units: i64) -> u64 {
+        (units.max(0) as u128 * self.millis_per_unit() as u128 / 1_000) as u64
+    }
+}
+
+/// Where settlement reads the time from. On chain that is the `Clock` sysvar, tests and
+/// off-chain tools can supply any time they like.
+pub trait TimeSource {
+    fn unix_timestamp(&self) -> i64;
+    fn slot(&self) -> u64;
+    fn epoch(&self) -> u64;
+}
+
+impl TimeSource for Clock {
+    fn unix_timestamp(&self) -> i64 {
+        self.unix_timestamp
+    }
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+/// How a pool takes back points left unspent, applied whenever a position settles
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointsDecay {
+    /// Points never expire
+    None,
+    /// `bps` of the balance is lost at the end of every `period` seconds
+    Rate { bps: u16, period: i64 },
+    /// Points are lost at the end of the `window` after the one they settled in
+    Expiry { window: i64 },
+}
+
+impl PointsDecay {
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Self::None => {}
+            Self::Rate { bps, period } => require!(
+                bps > 0 && bps as u64 <= BPS_DENOMINATOR && period >= MIN_DECAY_PERIOD,
+                StakingError::InvalidDecayParams
+            ),
+            Self::Expiry { window } => {
+                require!(window >= MIN_DECAY_PERIOD, StakingError::InvalidDecayParams)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `(total_points, expiring_points)` at `now` for a balance last decayed at `since`.
+    /// Boundaries fall on multiples of the period or window since the unix epoch.
+    pub fn apply(
+        &self,
+        total_points: u64,
+        expiring_points: u64,
+        since: i64,
+        now: i64,
+    ) -> (u64, u64) {
+        match *self {
+            Self::None => (total_points, expiring_points),
+            Self::Rate { bps, period } => {
+                let periods = (now.div_euclid(period) - since.div_euclid(period)).max(0) as u64;
+                let kept = keep_ratio(BPS_DENOMINATOR - bps as u64, periods);
+                let total_points = ((total_points as u128 * kept) >> 64) as u64;
+                (total_points, expiring_points.min(total_points))
+            }
+            Self::Expiry { window } => {
+                match now.div_euclid(window) - since.div_euclid(window) {
+                    // Everything settled last window is now the oldest window's worth
+                    1 => {
+                        let total_points = total_points - expiring_points;
+                        (total_points, total_points)
+                    }
+                    windows if windows > 1 => (0, 0),
+                    _ => (total_points, expiring_points),
+                }
+            }
+        }
+    }
+}
+
+// `(keep_bps / 10_000) ^ periods` as a 64.64 fixed point fraction, by squaring. The base is
+// rounded up so a whole number of points left is not rounded down a point further.
+fn keep_ratio(keep_bps: u64, mut periods: u64) -> u128 {
+    const ONE: u128 = 1 << 64;
+    let mut base = ((keep_bps as u128) * ONE).div_ceil(BPS_DENOMINATOR as u128);
+    let mut ratio = ONE;
+
+    while periods > 0 && ratio > 0 {
+        if periods & 1 == 1 {
+            ratio = (ratio * base) >> 64;
+        }
+        base = (base * base) >> 64;
+        periods >>= 1;
+    }
+
+    ratio
+}
+
+/// What `points_outlook` returns
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PointsOutlook {
+    /// Settled points left once decay or expiry up to now is applied
+    pub total_points: u64,
+    /// Points lost at `expires_at` unless spent first
+    pub expiring_points: u64,
+    /// Next period or window boundary, `None` when the pool's points never expire
+    pub expires_at: Option<i64>,
+}
+
+impl PointsOutlook {
+    pub fn new(position: &StakeAccount, decay: &PointsDecay, now: i64) -> Self {
+        let (total_points, expiring_points) = decay.apply(
+            position.total_points,
+            position.expiring_points,
+            position.decayed_at,
+            now,
+        );
+        let next_boundary = |length: i64| (now.div_euclid(length) + 1) * length;
+
+        match *decay {
+            PointsDecay::None => Self {
+                total_points,
+                expiring_points: 0,
+                expires_at: None,
+            },
+            PointsDecay::Rate { period, .. } => {
+                let expires_at = next_boundary(period);
+                let (left, _) = decay.apply(total_points, 0, now, expires_at);
+                Self {
+                    total_points,
+                    expiring_points: total_points - left,
+                    expires_at: Some(expires_at),
+                }
+            }
+            PointsDecay::Expiry { window } => Self {
+                total_points,
+                expiring_points,
+                expires_at: Some(next_boundary(window)),
+            },
+        }
+    }
+}
+
+impl Pool {
+    /// Bonus the streak tiers add to the time between `from` and `to`, for a streak started
+    /// at `streak_start`
+    pub fn streak_bonus_seconds(&self, streak_start: i64, from: i64, to: i64) -> u64 {
+        let tiers = self.active_streak_tiers();
+        let weighted: u128 = tiers
+            .iter()
+            .enumerate()
+            .map(|(index, tier)| {
+                let tier_start = streak_start + tier.after;
+                let tier_end = tiers
+                    .get(index + 1)
+                    .map_or(i64::MAX, |next| streak_start + next.after);
+                let overlap = (to.min(tier_end) - from.max(tier_start)).max(0);
+                overlap as u128 * tier.bonus_bps as u128
+            })
+            .sum();
+
+        (weighted / BPS_DENOMINATOR as u128) as u64
+    }
+
+    /// Bonus a streak of `streak` seconds earns at
+    pub fn streak_bonus_bps(&self, streak: i64) -> u16 {
+        self.active_streak_tiers()
+            .iter()
+            .take_while(|tier| tier.after <= streak)
+            .last()
+            .map_or(0, |tier| tier.bonus_bps)
+    }
+
+    fn active_streak_tiers(&self) -> &[StreakTier] {
+        let active = self
+            .streak_tiers
+            .iter()
+            .take_while(|tier| tier.bonus_bps > 0)
+            .count();
+        &self.streak_tiers[..active]
+    }
+}
+
+/// Once a streak has run for `after` seconds the reward rate goes up by `bonus_bps`. Tiers
+/// with no bonus are unused and come last.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+pub struct StreakTier {
+    pub after: i64,
+    pub bonus_bps: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Treasury {
+    pub authority: Pubkey,
+    /// Lamports swept in from the vault over the treasury's lifetime
+    pub total_swept: u64,
+    /// Points taken as fees and not yet withdrawn
+    pub fee_points: u64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    /// `amount` tokens of `mint`
+    Tokens,
+    /// A single token of a zero decimals `mint`
+    Nft,
+    /// A `WhitelistSpot` account for the redeemer, one per wallet
+    WhitelistSpot,
+}
+
+/// An item in a pool's points catalogue, at `[b"catalogItem", authority, item_id]`
+#[account]
+#[derive(InitSpace)]
+pub struct CatalogItem {
+    pub authority: Pubkey,
+    pub item_id: u64,
+    pub kind: ItemKind,
+    /// Default for whitelist spots
+    pub mint: Pubkey,
+    /// Tokens delivered per redemption
+    pub amount: u64,
+    /// Points one unit costs
+    pub price: u64,
+    pub stock: u64,
+    pub redeemed: u64,
+    pub bump: u8,
+}
+
+/// Proof that `owner` redeemed a whitelist spot `item`
+#[account]
+#[derive(InitSpace)]
+pub struct WhitelistSpot {
+    pub owner: Pubkey,
+    pub item: Pubkey,
+    pub redeemed_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct ItemParams {
+    pub kind: ItemKind,
+    pub price: u64,
+    pub stock: u64,
+    /// Tokens per redemption, ignored for whitelist spots
+    pub amount: u64,
+}
+
+impl ItemParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(self.price > 0, StakingError::InvalidItem);
+        require!(
+            self.kind == ItemKind::WhitelistSpot || self.amount > 0,
+            StakingError::InvalidItem
+        );
+        // An NFT is one of a kind, so its item holds exactly that one token
+        require!(
+            self.kind != ItemKind::Nft || self.stock == 1,
+            StakingError::InvalidItem
+        );
+
+        Ok(())
+    }
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct LeaderboardEntry {
+    pub position: Pubkey,
+    pub points: u64,
+}
+
+/// A pool's top positions by points earned this season, best first. Positions enter or move
+/// up when their points settle, in `stake` and `unstake`.
+#[account(zero_copy)]
+pub struct Leaderboard {
+    pub authority: Pubkey,
+    pub season: u64,
+    pub season_started_at: i64,
+    /// Entries in use, the rest are zeroed
+    pub len: u32,
+    pub bump: u8,
+    pub _padding: [u8; 3],
+    pub entries: [LeaderboardEntry; LEADERBOARD_SIZE],
+}
+
+impl Leaderboard {
+    pub fn ranked(&self) -> &[LeaderboardEntry] {
+        &self.entries[..self.len as usize]
+    }
+
+    /// Sets `position`'s points, keeping the entries sorted and dropping whoever falls off the
+    /// end. Ties keep the position that got there first ahead.
+    pub fn record(&mut self, position: Pubkey, points: u64) {
+        let mut len = self.len as usize;
+        if let Some(index) = self.ranked().iter().position(|entry| entry.position == position) {
+            self.entries.copy_within(index + 1..len, index);
+            len -= 1;
+        }
+
+        let rank = self.entries[..len]
+            .iter()
+            .position(|entry| entry.points < points)
+            .unwrap_or(len);
+        if points > 0 && rank < LEADERBOARD_SIZE {
+            let end = len.min(LEADERBOARD_SIZE - 1);
+            self.entries.copy_within(rank..end, rank + 1);
+            self.entries[rank] = LeaderboardEntry { position, points };
+            len = end + 1;
+        }
+
+        self.entries[len..].fill(LeaderboardEntry::default());
+        self.len = len as u32;
+    }
+
+    /// Moves `from`'s entry over to `to`, for a position that changed address
+    pub fn rename(&mut self, from: Pubkey, to: Pubkey) {
+        let len = self.len as usize;
+        if let Some(entry) = self.entries[..len].iter_mut().find(|entry| entry.position == from) {
+            entry.position = to;
+        }
+    }
+}
+
+/// A leaderboard as it stood when its season was reset, at
+/// `[b"leaderboardSnapshot", authority, season]`
+#[account(zero_copy)]
+pub struct LeaderboardSnapshot {
+    pub authority: Pubkey,
+    pub season: u64,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub len: u32,
+    pub bump: u8,
+    pub _padding: [u8; 3],
+    pub entries: [LeaderboardEntry; LEADERBOARD_SIZE],
+}
+
+impl LeaderboardSnapshot {
+    pub fn ranked(&self) -> &[LeaderboardEntry] {
+        &self.entries[..self.len as usize]
+    }
+}
+
+/// Returned by `reconcile`
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reconciliation {
+    pub vault_balance: u64,
+    /// Rent-exempt minimum of the vault
+    pub rent_reserve: u64,
+    pub total_staked: u64,
+    pub total_pending_withdrawals: u64,
+    /// Lamports beyond what the vault owes, which `sweep_surplus` may take
+    pub surplus: u64,
+    /// Lamports missing from what the vault owes
+    pub shortfall: u64,
+}
+
+impl Reconciliation {
+    pub fn new(pool: &Pool, vault_balance: u64) -> Result<Self> {
+        let rent_reserve = Rent::get()?.minimum_balance(0);
+        let owed = rent_reserve + pool.total_staked + pool.total_pending_withdrawals;
+
+        Ok(Self {
+            vault_balance,
+            rent_reserve,
+            total_staked: pool.total_staked,
+            total_pending_withdrawals: pool.total_pending_withdrawals,
+            surplus: vault_balance.saturating_sub(owed),
+            shortfall: owed.saturating_sub(vault_balance),
+        })
+    }
+}
+
+/// A wallet's allocation in a pool allowlist and the proof that it is listed
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct AllowlistProof {
+    /// Most the wallet may have staked in the pool, `u64::MAX` for no cap
+    pub max_allocation: u64,
+    /// Sibling hashes from the wallet's leaf up to the root
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl AllowlistProof {
+    pub fn verify(&self, root: &[u8; 32], wallet: &Pubkey) -> bool {
+        let leaf = allowlist_leaf(wallet, self.max_allocation);
+        self.proof
+            .iter()
+            .fold(leaf, |node, sibling| merkle_parent(&node, sibling))
+            == *root
+    }
+}
+
+/// Returned by `capacity`, `None` meaning no limit
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Capacity {
+    pub min_stake: u64,
+    pub total_staked: u64,
+    /// Lamports the pool can still take before its cap
+    pub pool_remaining: Option<u64>,
+    /// Stake of the position passed in, zero without one
+    pub wallet_staked: u64,
+    /// Most that position can still stake, under both its own cap and the pool's. An
+    /// allowlist allocation can limit it further.
+    pub wallet_remaining: Option<u64>,
+}
+
+impl Capacity {
+    pub fn new(pool: &Pool, wallet_staked: u64) -> Self {
+        let remaining = |staked: u64, cap: Option<u64>| cap.map(|cap| cap.saturating_sub(staked));
+        let pool_remaining = remaining(pool.total_staked, pool.max_total_staked);
+        let wallet_cap_remaining = remaining(wallet_staked, pool.max_stake_per_wallet);
+        let wallet_remaining = match (wallet_cap_remaining, pool_remaining) {
+            (Some(wallet), Some(pool)) => Some(wallet.min(pool)),
+            (wallet, pool) => wallet.or(pool),
+        };
+
+        Self {
+            min_stake: pool.min_stake,
+            total_staked: pool.total_staked,
+            pool_remaining,
+            wallet_staked,
+            wallet_remaining,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct PoolParams {
+    pub referral_bps: u16,
+    pub fee_bps: u16,
+    pub time_base: TimeBase,
+    pub min_stake: u64,
+    pub max_stake_per_wallet: Option<u64>,
+    pub max_total_staked: Option<u64>,
+    pub decay: PointsDecay,
+    pub streak_tiers: [StreakTier; MAX_STREAK_TIERS],
+}
+
+impl PoolParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.referral_bps as u64 <= BPS_DENOMINATOR,
+            StakingError::InvalidReferralRate
+        );
+        require!(
+            self.fee_bps as u64 <= BPS_DENOMINATOR,
+            StakingError::InvalidFeeRate
+        );
+        require!(
+            self.max_stake_per_wallet
+                .is_none_or(|cap| cap >= self.min_stake),
+            StakingError::InvalidStakeLimits
+        );
+        self.decay.validate()?;
+
+        // Active tiers come first, each later and larger than the one before
+        let tiers = &self.streak_tiers;
+        require!(
+            tiers
+                .iter()
+                .all(|tier| tier.bonus_bps <= MAX_STREAK_BONUS_BPS && tier.after >= 0)
+                && tiers.windows(2).all(|pair| {
+                    pair[1].bonus_bps == 0
+                        || (pair[0].bonus_bps > 0
+                            && pair[1].after > pair[0].after
+                            && pair[1].bonus_bps > pair[0].bonus_bps)
+                }),
+            StakingError::InvalidStreakTiers
+        );
+
+        Ok(())
+    }
+}
+
+#[event]
+pub struct VaultCreated {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub params: PoolParams,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ParamsUpdated {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub params: PoolParams,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionCreated {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct Staked {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PointsClaimed {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ReferrerRecorded {
+    pub referee: Pubkey,
+    pub referrer: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub referrer: Pubkey,
+    pub referee: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionTransferred {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub position: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionMigrated {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreasuryInitialized {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct SurplusSwept {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub total_staked: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct FeeCollected {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct AllowlistRootUpdated {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub previous_root: [u8; 32],
+    pub root: [u8; 32],
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct LeaderboardInitialized {
+    pub authority: Pubkey,
+    pub leaderboard: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct SeasonReset {
+    pub authority: Pubkey,
+    pub snapshot: Pubkey,
+    pub season: u64,
+    /// Positions ranked in the archived standings
+    pub entries: u32,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PointsExpired {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    /// Points the position has left
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ItemPublished {
+    pub authority: Pubkey,
+    pub item: Pubkey,
+    pub item_id: u64,
+    pub kind: ItemKind,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub price: u64,
+    pub stock: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ItemUpdated {
+    pub authority: Pubkey,
+    pub item: Pubkey,
+    pub price: u64,
+    pub stock: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ItemRedeemed {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub item: Pubkey,
+    pub price: u64,
+    /// Units left after this one
+    pub stock: u64,
+    /// Points the position has left
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[error_code]
+pub enum StakingError {
+    #[msg("Amount must be greater than 0")]
+    InvalidAmount,
+
+    #[msg("Invalid Balance")]
+    InvalidBalance,
+
+    #[msg("Insufficient Points")]
+    InvalidPoints,
+
+    #[msg("Invalid Referrer")]
+    InvalidReferrer,
+
+    #[msg("Referral rate cannot exceed 100% (10000 bps)")]
+    InvalidReferralRate,
+
+    #[msg("New owner must be a different wallet")]
+    InvalidNewOwner,
+
+    #[msg("Position is staked in a different vault")]
+    VaultMismatch,
+
+    #[msg("Vault holds nothing beyond what it owes")]
+    NoSurplus,
+
+    #[msg("Fee rate cannot exceed 100% (10000 bps)")]
+    InvalidFeeRate,
+
+    #[msg("Wallet is not on the pool allowlist")]
+    NotAllowlisted,
+
+    #[msg("Stake would exceed the wallet's allowlist allocation")]
+    AllocationExceeded,
+
+    #[msg("Stake is below the pool's minimum")]
+    BelowMinimumStake,
+
+    #[msg("Stake would exceed the pool's per-wallet cap")]
+    WalletCapExceeded,
+
+    #[msg("Stake would exceed the pool's total cap")]
+    PoolCapExceeded,
+
+    #[msg("Per-wallet cap cannot be below the minimum stake")]
+    InvalidStakeLimits,
+
+    #[msg("Season is not the leaderboard's current season")]
+    SeasonMismatch,
+
+    #[msg("Item is out of stock")]
+    OutOfStock,
+
+    #[msg("Item is misconfigured or its token accounts do not match")]
+    InvalidItem,
+
+    #[msg("Token and NFT items need their mint, escrow and token accounts")]
+    MissingItemAccounts,
+
+    #[msg("Decay rate must be within 100% and periods and windows at least a day")]
+    InvalidDecayParams,
+
+    #[msg("Streak tiers must grow in both duration and bonus, up to a 100% bonus")]
+    InvalidStreakTiers,
+
+    #[msg("Position is already on the current layout")]
+    AlreadyMigrated,
+
+    #[msg("Binding legacy stake to a vault needs its authority's signature")]
+    VaultAuthorityRequired,
+
+    #[msg("Pool has a leaderboard, or the position is ranked on one, which must be passed")]
+    LeaderboardRequired,
+
+    #[msg("Claims that pay a fee need the pool's treasury")]
+    TreasuryRequired,
+
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
 }
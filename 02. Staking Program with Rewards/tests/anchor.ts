@@ -9,7 +9,20 @@ describe("Test", () => {
   anchor.setProvider(anchor.AnchorProvider.env());
 
   const program = anchor.workspace.BasicStakingProgram as anchor.Program<BasicStakingProgram>;
-  
+
+  const [pool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("pool"), program.provider.publicKey.toBuffer()],
+    program.programId
+  );
+  const [treasury] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury"), program.provider.publicKey.toBuffer()],
+    program.programId
+  );
+  const [leaderboard] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("leaderboard"), program.provider.publicKey.toBuffer()],
+    program.programId
+  );
+
   it("create a user pda account", async () => {
     const [pdaAccount, bump] = await web3.PublicKey.findProgramAddress(
       [Buffer.from("client1"), program.provider.publicKey.toBuffer()],
@@ -53,6 +66,72 @@ describe("Test", () => {
     // Confirm transaction
     await program.provider.connection.confirmTransaction(txHash);
   });
+  it("initializes the pool", async () => {
+    // Send Transaction
+    const txHash = await program.methods
+      .initializePool({
+        referralBps: 500,
+        feeBps: 100,
+        timeBase: { unixSeconds: {} },
+        minStake: new BN(0),
+        maxStakePerWallet: null,
+        maxTotalStaked: null,
+        decay: { none: {} },
+        // Unused tiers are left zeroed
+        streakTiers: Array.from({ length: 4 }, () => ({ after: new BN(0), bonusBps: 0 })),
+      })
+      .accounts({
+        authority: program.provider.publicKey,
+        pool,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    // Confirm transaction
+    await program.provider.connection.confirmTransaction(txHash);
+    const poolAccount = await program.account.pool.fetch(pool);
+    // Assertions
+    assert.equal(poolAccount.referralBps, 500);
+    assert.equal(poolAccount.feeBps, 100);
+    assert.deepEqual(poolAccount.timeBase, { unixSeconds: {} });
+  });
+  it("initializes the treasury", async () => {
+    // Send Transaction
+    const txHash = await program.methods
+      .initializeTreasury()
+      .accounts({
+        authority: program.provider.publicKey,
+        treasury,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    // Confirm transaction
+    await program.provider.connection.confirmTransaction(txHash);
+    const treasuryAccount = await program.account.treasury.fetch(treasury);
+    // Assertions
+    assert.equal(treasuryAccount.feePoints.toNumber(), 0);
+  });
+  it("initializes the leaderboard", async () => {
+    // Send Transaction
+    const txHash = await program.methods
+      .initializeLeaderboard()
+      .accounts({
+        authority: program.provider.publicKey,
+        pool,
+        leaderboard,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    // Confirm transaction
+    await program.provider.connection.confirmTransaction(txHash);
+    const leaderboardAccount = await program.account.leaderboard.fetch(leaderboard);
+    const poolAccount = await program.account.pool.fetch(pool);
+    // Assertions
+    assert.equal(leaderboardAccount.season.toNumber(), 1);
+    assert.ok(poolAccount.hasLeaderboard);
+  });
   it("stake amount", async () => {
     const [vaultPdaAccount, bump] = await web3.PublicKey.findProgramAddress(
       [Buffer.from("pdaVault"), program.provider.publicKey.toBuffer()],
@@ -66,12 +145,14 @@ describe("Test", () => {
     const amount = new BN(1_000_000_000);
     // Send the Transaction
     const txHash = await program.methods
-      .stake(amount)
+      .stake(amount, null, null)
       .accounts({
         user: program.provider.publicKey,
         pdaAccount: pdaAccount,
         authority: program.provider.publicKey,
         pdaVaultAccount: vaultPdaAccount,
+        pool,
+        leaderboard,
         systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
@@ -109,6 +190,8 @@ describe("Test", () => {
         pdaAccount: pdaAccount,
         authority: program.provider.publicKey,
         pdaVaultAccount: vaultPdaAccount,
+        pool,
+        leaderboard,
         systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
@@ -159,12 +242,20 @@ describe("Test", () => {
       [Buffer.from("client1"), program.provider.publicKey.toBuffer()],
       program.programId
     );
+    const [vaultPdaAccount, bump2] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("pdaVault"), program.provider.publicKey.toBuffer()],
+      program.programId
+    );
     // Send Transction
     const txHash = await program.methods
       .claimPoints()
       .accounts({
         payer: program.provider.publicKey,
         pdaAccount: pdaAccount,
+        authority: program.provider.publicKey,
+        pdaVaultAccount: vaultPdaAccount,
+        pool,
+        treasury,
         systemProgram: web3.SystemProgram.programId,
       })
       .rpc();
@@ -0,0 +1,288 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+use stake_with_token_reward::instruction as ix;
+
+macro_rules! stake_events {
+    ($($event:ident),* $(,)?) => {
+        /// Every event the program emits, decoded from its `Program data:` log line
+        pub enum StakeEvent {
+            $($event(stake_with_token_reward::$event),)*
+        }
+
+        impl StakeEvent {
+            pub fn decode(data: &[u8]) -> Option<Self> {
+                $(
+                    if let Some(mut body) =
+                        data.strip_prefix(stake_with_token_reward::$event::DISCRIMINATOR)
+                    {
+                        return stake_with_token_reward::$event::deserialize(&mut body)
+                            .ok()
+                            .map(Self::$event);
+                    }
+                )*
+                None
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$event(_) => stringify!($event),)*
+                }
+            }
+
+            pub fn timestamp(&self) -> i64 {
+                match self {
+                    $(Self::$event(event) => event.timestamp,)*
+                }
+            }
+        }
+    };
+}
+
+stake_events!(
+    VaultCreated,
+    RewardMintCreated,
+    PoolInitialized,
+    ParamsUpdated,
+    PositionCreated,
+    Staked,
+    Unstaked,
+    RewardsMinted,
+    ReferrerRecorded,
+    ReferralRewardsClaimed,
+    VeLocked,
+    VeUnlocked,
+    RegistrarCreated,
+    VoterWeightUpdated,
+    VoteRelinquished,
+    MaxVoterWeightUpdated,
+    PositionTransferred,
+    PositionSplit,
+    PositionsMerged,
+    TreasuryInitialized,
+    FeeCollected,
+    FeesWithdrawn,
+    VestingStarted,
+    VestedWithdrawn,
+    VestingExited,
+    DistributorCreated,
+    AirdropClaimed,
+    PositionMigrated,
+    SurplusSwept,
+    TreasuryWithdrawn,
+);
+
+macro_rules! instruction_names {
+    ($($ix:ident => $name:literal),* $(,)?) => {
+        /// Name of the program instruction encoded in `data`, matched on its discriminator
+        pub fn instruction_name(data: &[u8]) -> Option<&'static str> {
+            $(
+                if data.starts_with(ix::$ix::DISCRIMINATOR) {
+                    return Some($name);
+                }
+            )*
+            None
+        }
+    };
+}
+
+instruction_names!(
+    CreatePdaAccount => "create_pda_account",
+    CreateVaultPdaAccount => "create_vault_pda_account",
+    CreateTokenMint => "create_token_mint",
+    InitializePool => "initialize_pool",
+    UpdatePoolParams => "update_pool_params",
+    Stake => "stake",
+    Unstake => "unstake",
+    ClaimRewards => "claim_rewards",
+    ClaimReferralRewards => "claim_referral_rewards",
+    LockRewardTokens => "lock_reward_tokens",
+    UnlockRewardTokens => "unlock_reward_tokens",
+    CreateRegistrar => "create_registrar",
+    CreateVoterWeightRecord => "create_voter_weight_record",
+    UpdateVoterWeightRecord => "update_voter_weight_record",
+    RelinquishVote => "relinquish_vote",
+    CreateMaxVoterWeightRecord => "create_max_voter_weight_record",
+    UpdateMaxVoterWeightRecord => "update_max_voter_weight_record",
+    TransferPosition => "transfer_position",
+    SplitPosition => "split_position",
+    MergePositions => "merge_positions",
+    InitializeTreasury => "initialize_treasury",
+    WithdrawFees => "withdraw_fees",
+    WithdrawVested => "withdraw_vested",
+    ExitVesting => "exit_vesting",
+    CreateDistributor => "create_distributor",
+    ClaimAirdrop => "claim_airdrop",
+    MigrateAccount => "migrate_account",
+    SweepSurplus => "sweep_surplus",
+    WithdrawTreasury => "withdraw_treasury",
+);
+
+pub struct DecodedEvent {
+    /// Top-level program instruction that emitted the event, `None` when reached through CPI
+    pub instruction: Option<&'static str>,
+    pub event: StakeEvent,
+}
+
+pub struct ParsedTransaction {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub events: Vec<DecodedEvent>,
+}
+
+/// Decodes a `getTransaction` result (json encoding), returns `None` for failed transactions
+pub fn parse_transaction(tx: &Value, program_id: &Pubkey) -> Result<Option<ParsedTransaction>> {
+    if !tx["meta"]["err"].is_null() {
+        return Ok(None);
+    }
+
+    let signature = tx["transaction"]["signatures"][0]
+        .as_str()
+        .ok_or_else(|| anyhow!("transaction without a signature"))?
+        .to_string();
+    let slot = tx["slot"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("transaction {signature} without a slot"))?;
+    let block_time = tx["blockTime"].as_i64();
+
+    let message = &tx["transaction"]["message"];
+    let account_keys: Vec<&str> = message["accountKeys"]
+        .as_array()
+        .ok_or_else(|| anyhow!("transaction {signature} without account keys"))?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+
+    let program = program_id.to_string();
+    let instructions: Vec<Option<&'static str>> = message["instructions"]
+        .as_array()
+        .ok_or_else(|| anyhow!("transaction {signature} without instructions"))?
+        .iter()
+        .map(|instruction| {
+            let program_index = instruction["programIdIndex"].as_u64()? as usize;
+            if account_keys.get(program_index) != Some(&program.as_str()) {
+                return None;
+            }
+            let data = bs58::decode(instruction["data"].as_str()?)
+                .into_vec()
+                .ok()?;
+            instruction_name(&data)
+        })
+        .collect();
+
+    let logs = tx["meta"]["logMessages"]
+        .as_array()
+        .ok_or_else(|| anyhow!("transaction {signature} without log messages"))?
+        .iter()
+        .filter_map(Value::as_str);
+
+    Ok(Some(ParsedTransaction {
+        events: decode_events(logs, &program, &instructions),
+        signature,
+        slot,
+        block_time,
+    }))
+}
+
+/// Walks the invoke stack in the logs so only `Program data:` lines written by this program
+/// are decoded, each attributed to the top-level instruction it ran under
+fn decode_events<'a>(
+    logs: impl Iterator<Item = &'a str>,
+    program: &str,
+    instructions: &[Option<&'static str>],
+) -> Vec<DecodedEvent> {
+    let mut events = Vec::new();
+    let mut invoke_stack: Vec<&str> = Vec::new();
+    let mut top_level_index: Option<usize> = None;
+
+    for line in logs {
+        if let Some(data) = line.strip_prefix("Program data: ") {
+            if invoke_stack.last() != Some(&program) {
+                continue;
+            }
+            let Ok(data) = STANDARD.decode(data) else {
+                continue;
+            };
+            if let Some(event) = StakeEvent::decode(&data) {
+                let instruction = match invoke_stack.len() {
+                    1 => {
+                        top_level_index.and_then(|index| instructions.get(index).copied().flatten())
+                    }
+                    _ => None,
+                };
+                events.push(DecodedEvent { instruction, event });
+            }
+        } else if let Some(rest) = line.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            let (Some(id), Some(action)) = (words.next(), words.next()) else {
+                continue;
+            };
+            match action {
+                "invoke" => {
+                    if invoke_stack.is_empty() {
+                        top_level_index = Some(top_level_index.map_or(0, |index| index + 1));
+                    }
+                    invoke_stack.push(id);
+                }
+                "success" | "failed:" => {
+                    invoke_stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+    use stake_with_token_reward::Staked;
+
+    fn staked_log() -> String {
+        let event = Staked {
+            owner: Pubkey::new_unique(),
+            position: Pubkey::new_unique(),
+            amount: 5,
+            staked_amount: 5,
+            total_points: 0,
+            vault_balance: 5,
+            timestamp: 1,
+            slot: 1,
+        };
+        format!("Program data: {}", STANDARD.encode(event.data()))
+    }
+
+    #[test]
+    fn decodes_only_events_written_by_the_program() {
+        let program = stake_with_token_reward::ID.to_string();
+        let token_program = Pubkey::new_unique().to_string();
+        let data = staked_log();
+        let logs = [
+            format!("Program {token_program} invoke [1]"),
+            data.clone(),
+            format!("Program {token_program} success"),
+            format!("Program {program} invoke [1]"),
+            format!("Program {token_program} invoke [2]"),
+            data.clone(),
+            format!("Program {token_program} success"),
+            data,
+            format!("Program {program} success"),
+        ];
+
+        let events = decode_events(
+            logs.iter().map(String::as_str),
+            &program,
+            &[None, Some("stake")],
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.name(), "Staked");
+        assert_eq!(events[0].instruction, Some("stake"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Rebuilds pools, positions and reward history of `stake_with_token_reward` from its
+//! transaction logs into SQLite.
+//!
+//! Transactions come either from an RPC node (`ingest`) or from a JSON dump holding an array
+//! of `getTransaction` results in `json` encoding (`import`). `replay` rebuilds the database
+//! from scratch and checks the result against the `StakeAccount`s currently on chain.
+
+mod decode;
+mod rpc;
+mod store;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use serde_json::Value;
+
+use decode::parse_transaction;
+use rpc::RpcClient;
+use store::Store;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// SQLite database the state is materialised into
+    #[arg(long, default_value = "stake-indexer.sqlite")]
+    db: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index the program's transactions from an RPC node
+    Ingest {
+        #[arg(long, default_value = "http://127.0.0.1:8899")]
+        rpc: String,
+
+        /// Maximum number of signatures to fetch, newest first
+        #[arg(long, default_value_t = 10_000)]
+        limit: usize,
+    },
+    /// Index transactions from a JSON dump of `getTransaction` results
+    Import { file: PathBuf },
+    /// Rebuild the database from scratch and verify it against on-chain stake accounts
+    Replay {
+        #[arg(long, default_value = "http://127.0.0.1:8899")]
+        rpc: String,
+
+        /// Rebuild from this dump instead of fetching transactions from the RPC node
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        #[arg(long, default_value_t = 10_000)]
+        limit: usize,
+    },
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let mut store = Store::open(&cli.db)?;
+
+    match cli.command {
+        Command::Ingest { rpc, limit } => {
+            let client = RpcClient::new(&rpc);
+            ingest(&mut store, fetch_transactions(&client, limit)?)?;
+        }
+        Command::Import { file } => {
+            ingest(&mut store, read_dump(&file)?)?;
+        }
+        Command::Replay { rpc, file, limit } => {
+            let client = RpcClient::new(&rpc);
+            let transactions = match file {
+                Some(file) => read_dump(&file)?,
+                None => fetch_transactions(&client, limit)?,
+            };
+
+            store.reset()?;
+            ingest(&mut store, transactions)?;
+
+            if !verify(&store, &client)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn fetch_transactions(client: &RpcClient, limit: usize) -> Result<Vec<Value>> {
+    client
+        .signatures_for_address(&stake_with_token_reward::ID, limit)?
+        .iter()
+        .map(|signature| client.transaction(signature))
+        .collect()
+}
+
+fn read_dump(file: &PathBuf) -> Result<Vec<Value>> {
+    let dump = fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+    let dump: Value = serde_json::from_str(&dump)?;
+    let mut transactions = dump
+        .as_array()
+        .cloned()
+        .ok_or_else(|| anyhow!("{} is not a JSON array of transactions", file.display()))?;
+
+    // State only rebuilds correctly when events are applied in the order they happened
+    transactions.sort_by_key(|tx| tx["slot"].as_u64().unwrap_or_default());
+    Ok(transactions)
+}
+
+fn ingest(store: &mut Store, transactions: Vec<Value>) -> Result<()> {
+    let mut indexed = 0;
+    let mut events = 0;
+
+    for tx in &transactions {
+        let Some(parsed) = parse_transaction(tx, &stake_with_token_reward::ID)? else {
+            continue;
+        };
+        if store.apply(&parsed)? {
+            indexed += 1;
+            events += parsed.events.len();
+        }
+    }
+
+    println!(
+        "Indexed {indexed} new transactions ({events} events) out of {}",
+        transactions.len()
+    );
+    Ok(())
+}
+
+/// Compares every rebuilt position with its on-chain account, returns whether all match
+fn verify(store: &Store, client: &RpcClient) -> Result<bool> {
+    let mut on_chain: HashMap<String, _> = client
+        .stake_accounts(&stake_with_token_reward::ID)?
+        .into_iter()
+        .map(|(pubkey, account)| (pubkey.to_string(), account))
+        .collect();
+
+    let mut mismatches = 0;
+    let mut indexed_staked: u64 = 0;
+    let mut on_chain_staked: u64 = 0;
+
+    for row in store.open_positions()? {
+        indexed_staked += row.staked_amount;
+
+        let Some(account) = on_chain.remove(&row.position) else {
+            println!("{}: indexed but missing on chain", row.position);
+            mismatches += 1;
+            continue;
+        };
+        on_chain_staked += account.staked_amount;
+
+        if account.owner.to_string() != row.owner
+            || account.staked_amount != row.staked_amount
+            || account.total_points != row.total_points
+        {
+            println!(
+                "{}: indexed owner {} staked {} points {}, on chain owner {} staked {} points {}",
+                row.position,
+                row.owner,
+                row.staked_amount,
+                row.total_points,
+                account.owner,
+                account.staked_amount,
+                account.total_points
+            );
+            mismatches += 1;
+        }
+    }
+
+    for (position, account) in on_chain {
+        println!("{position}: on chain but never indexed");
+        on_chain_staked += account.staked_amount;
+        mismatches += 1;
+    }
+
+    println!("Total staked: indexed {indexed_staked}, on chain {on_chain_staked}");
+
+    if mismatches > 0 {
+        println!("Replay found {mismatches} mismatched positions");
+        return Ok(false);
+    }
+    println!("Replay matches on-chain state");
+    Ok(true)
+}
@@ -0,0 +1,146 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, Discriminator, Space};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use stake_with_token_reward::StakeAccount;
+
+/// Minimal JSON-RPC client, just the calls the indexer needs
+pub struct RpcClient {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .with_context(|| format!("{method} request failed"))?
+            .into_json()?;
+
+        if let Some(error) = response.get("error") {
+            bail!("{method} returned an error: {error}");
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("{method} returned no result"))
+    }
+
+    /// Signatures touching the program, oldest first
+    pub fn signatures_for_address(&self, address: &Pubkey, limit: usize) -> Result<Vec<String>> {
+        let mut signatures = Vec::new();
+        let mut before: Option<String> = None;
+
+        while signatures.len() < limit {
+            let page_size = (limit - signatures.len()).min(1000);
+            let mut config = json!({ "limit": page_size, "commitment": "confirmed" });
+            if let Some(before) = &before {
+                config["before"] = json!(before);
+            }
+
+            let page = self.call(
+                "getSignaturesForAddress",
+                json!([address.to_string(), config]),
+            )?;
+            let page = page
+                .as_array()
+                .ok_or_else(|| anyhow!("getSignaturesForAddress did not return a list"))?;
+            if page.is_empty() {
+                break;
+            }
+
+            for entry in page {
+                // Failed transactions never changed any state
+                if !entry["err"].is_null() {
+                    continue;
+                }
+                if let Some(signature) = entry["signature"].as_str() {
+                    signatures.push(signature.to_string());
+                }
+            }
+            before = page
+                .last()
+                .and_then(|entry| entry["signature"].as_str())
+                .map(str::to_string);
+
+            if page.len() < page_size {
+                break;
+            }
+        }
+
+        signatures.reverse();
+        Ok(signatures)
+    }
+
+    pub fn transaction(&self, signature: &str) -> Result<Value> {
+        self.call(
+            "getTransaction",
+            json!([
+                signature,
+                {
+                    "encoding": "json",
+                    "commitment": "confirmed",
+                    "maxSupportedTransactionVersion": 0,
+                }
+            ]),
+        )
+    }
+
+    /// Every `StakeAccount` currently owned by the program, v1 positions awaiting
+    /// `migrate_account` are left out
+    pub fn stake_accounts(&self, program_id: &Pubkey) -> Result<Vec<(Pubkey, StakeAccount)>> {
+        let accounts = self.call(
+            "getProgramAccounts",
+            json!([
+                program_id.to_string(),
+                {
+                    "encoding": "base64",
+                    "commitment": "confirmed",
+                    "filters": [
+                        {
+                            "memcmp": {
+                                "offset": 0,
+                                "bytes": bs58::encode(StakeAccount::DISCRIMINATOR).into_string(),
+                            }
+                        },
+                        { "dataSize": 8 + StakeAccount::INIT_SPACE },
+                    ],
+                }
+            ]),
+        )?;
+
+        accounts
+            .as_array()
+            .ok_or_else(|| anyhow!("getProgramAccounts did not return a list"))?
+            .iter()
+            .map(|entry| {
+                let pubkey: Pubkey = entry["pubkey"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("account without a pubkey"))?
+                    .parse()?;
+                let data = entry["account"]["data"][0]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("account {pubkey} without data"))?;
+                let data = STANDARD.decode(data)?;
+                let account = StakeAccount::try_deserialize(&mut data.as_slice())
+                    .with_context(|| format!("decoding stake account {pubkey}"))?;
+                Ok((pubkey, account))
+            })
+            .collect()
+    }
+}
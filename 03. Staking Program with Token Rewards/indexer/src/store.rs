@@ -0,0 +1,375 @@
+use std::path::Path;
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use stake_with_token_reward::LockTier;
+
+use crate::decode::{ParsedTransaction, StakeEvent};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS pools (
+    pool TEXT PRIMARY KEY,
+    authority TEXT NOT NULL,
+    max_boost_bps INTEGER NOT NULL,
+    referral_bps INTEGER NOT NULL,
+    fee_bps INTEGER NOT NULL,
+    time_base TEXT NOT NULL,
+    updated_slot INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS positions (
+    position TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    position_index INTEGER,
+    lock_tier TEXT,
+    staked_amount INTEGER NOT NULL DEFAULT 0,
+    total_points INTEGER NOT NULL DEFAULT 0,
+    closed INTEGER NOT NULL DEFAULT 0,
+    updated_slot INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reward_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    instruction TEXT,
+    recipient TEXT NOT NULL,
+    position TEXT NOT NULL,
+    amount INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    instruction TEXT,
+    name TEXT NOT NULL
+);
+";
+
+/// A position as the indexer last saw it
+pub struct PositionRow {
+    pub position: String,
+    pub owner: String,
+    pub staked_amount: u64,
+    pub total_points: u64,
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Drops everything materialised so far, used before a replay
+    pub fn reset(&mut self) -> Result<()> {
+        self.conn.execute_batch(
+            "DELETE FROM transactions;
+             DELETE FROM pools;
+             DELETE FROM positions;
+             DELETE FROM reward_history;
+             DELETE FROM events;",
+        )?;
+        Ok(())
+    }
+
+    /// Applies one transaction's events atomically, returns false if it was already indexed
+    pub fn apply(&mut self, tx: &ParsedTransaction) -> Result<bool> {
+        let db = self.conn.transaction()?;
+
+        let seen = db
+            .query_row(
+                "SELECT 1 FROM transactions WHERE signature = ?1",
+                params![tx.signature],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if seen {
+            return Ok(false);
+        }
+
+        db.execute(
+            "INSERT INTO transactions (signature, slot, block_time) VALUES (?1, ?2, ?3)",
+            params![tx.signature, tx.slot, tx.block_time],
+        )?;
+
+        for decoded in &tx.events {
+            db.execute(
+                "INSERT INTO events (signature, slot, timestamp, instruction, name)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    tx.signature,
+                    tx.slot,
+                    decoded.event.timestamp(),
+                    decoded.instruction,
+                    decoded.event.name()
+                ],
+            )?;
+            apply_event(&db, tx, decoded.instruction, &decoded.event)?;
+        }
+
+        db.commit()?;
+        Ok(true)
+    }
+
+    pub fn open_positions(&self) -> Result<Vec<PositionRow>> {
+        let mut statement = self.conn.prepare(
+            "SELECT position, owner, staked_amount, total_points FROM positions
+             WHERE closed = 0 ORDER BY position",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(PositionRow {
+                position: row.get(0)?,
+                owner: row.get(1)?,
+                staked_amount: row.get(2)?,
+                total_points: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn upsert_position(
+    db: &Transaction,
+    position: String,
+    owner: String,
+    staked_amount: u64,
+    total_points: u64,
+    slot: u64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO positions (position, owner, staked_amount, total_points, updated_slot)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(position) DO UPDATE SET
+             owner = excluded.owner,
+             staked_amount = excluded.staked_amount,
+             total_points = excluded.total_points,
+             closed = 0,
+             updated_slot = excluded.updated_slot",
+        params![position, owner, staked_amount, total_points, slot],
+    )?;
+    Ok(())
+}
+
+fn close_position(db: &Transaction, position: String, slot: u64) -> Result<()> {
+    db.execute(
+        "UPDATE positions SET closed = 1, staked_amount = 0, total_points = 0, updated_slot = ?2
+         WHERE position = ?1",
+        params![position, slot],
+    )?;
+    Ok(())
+}
+
+/// Copies the lock tier of `source` onto a position carved out of it
+fn inherit_lock_tier(db: &Transaction, position: String, source: String) -> Result<()> {
+    db.execute(
+        "UPDATE positions SET lock_tier = (SELECT lock_tier FROM positions WHERE position = ?2)
+         WHERE position = ?1",
+        params![position, source],
+    )?;
+    Ok(())
+}
+
+fn apply_event(
+    db: &Transaction,
+    tx: &ParsedTransaction,
+    instruction: Option<&str>,
+    event: &StakeEvent,
+) -> Result<()> {
+    match event {
+        StakeEvent::PoolInitialized(e) => {
+            db.execute(
+                "INSERT OR REPLACE INTO pools
+                     (pool, authority, max_boost_bps, referral_bps, fee_bps, time_base,
+                      updated_slot)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    e.pool.to_string(),
+                    e.authority.to_string(),
+                    e.params.max_boost_bps,
+                    e.params.referral_bps,
+                    e.params.fee_bps,
+                    format!("{:?}", e.params.time_base),
+                    e.slot
+                ],
+            )?;
+        }
+        StakeEvent::ParamsUpdated(e) => {
+            db.execute(
+                "UPDATE pools SET max_boost_bps = ?2, referral_bps = ?3, fee_bps = ?4,
+                     time_base = ?5, updated_slot = ?6
+                 WHERE pool = ?1",
+                params![
+                    e.pool.to_string(),
+                    e.params.max_boost_bps,
+                    e.params.referral_bps,
+                    e.params.fee_bps,
+                    format!("{:?}", e.params.time_base),
+                    e.slot
+                ],
+            )?;
+        }
+        StakeEvent::PositionCreated(e) => {
+            upsert_position(
+                db,
+                e.position.to_string(),
+                e.owner.to_string(),
+                0,
+                0,
+                e.slot,
+            )?;
+            db.execute(
+                "UPDATE positions SET position_index = ?2, lock_tier = ?3 WHERE position = ?1",
+                params![
+                    e.position.to_string(),
+                    e.position_index,
+                    format!("{:?}", e.lock_tier)
+                ],
+            )?;
+        }
+        StakeEvent::Staked(e) => upsert_position(
+            db,
+            e.position.to_string(),
+            e.owner.to_string(),
+            e.staked_amount,
+            e.total_points,
+            e.slot,
+        )?,
+        StakeEvent::Unstaked(e) => upsert_position(
+            db,
+            e.position.to_string(),
+            e.owner.to_string(),
+            e.staked_amount,
+            e.total_points,
+            e.slot,
+        )?,
+        StakeEvent::RewardsMinted(e) => {
+            db.execute(
+                "INSERT INTO reward_history
+                     (signature, slot, timestamp, instruction, recipient, position, amount)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    tx.signature,
+                    e.slot,
+                    e.timestamp,
+                    instruction,
+                    e.recipient.to_string(),
+                    e.position.to_string(),
+                    e.amount
+                ],
+            )?;
+            // Claiming pays out every accrued point, the event only carries the amount
+            if instruction == Some("claim_rewards") {
+                db.execute(
+                    "UPDATE positions SET total_points = 0, updated_slot = ?2 WHERE position = ?1",
+                    params![e.position.to_string(), e.slot],
+                )?;
+            }
+        }
+        // A vesting claim pays out nothing yet, but still takes every accrued point
+        StakeEvent::VestingStarted(e) => {
+            db.execute(
+                "UPDATE positions SET total_points = 0, updated_slot = ?2 WHERE position = ?1",
+                params![e.position.to_string(), e.slot],
+            )?;
+        }
+        StakeEvent::PositionTransferred(e) => {
+            upsert_position(
+                db,
+                e.position.to_string(),
+                e.to.to_string(),
+                e.staked_amount,
+                e.total_points,
+                e.slot,
+            )?;
+            inherit_lock_tier(db, e.position.to_string(), e.source.to_string())?;
+            close_position(db, e.source.to_string(), e.slot)?;
+        }
+        StakeEvent::PositionSplit(e) => {
+            upsert_position(
+                db,
+                e.source.to_string(),
+                e.owner.to_string(),
+                e.staked_amount,
+                e.total_points,
+                e.slot,
+            )?;
+            upsert_position(
+                db,
+                e.new_position.to_string(),
+                e.owner.to_string(),
+                e.amount,
+                0,
+                e.slot,
+            )?;
+            inherit_lock_tier(db, e.new_position.to_string(), e.source.to_string())?;
+        }
+        StakeEvent::PositionsMerged(e) => {
+            db.execute(
+                "UPDATE positions SET staked_amount = ?2, total_points = ?3, updated_slot = ?4
+                 WHERE position = ?1",
+                params![
+                    e.destination.to_string(),
+                    e.staked_amount,
+                    e.total_points,
+                    e.slot
+                ],
+            )?;
+            close_position(db, e.source.to_string(), e.slot)?;
+        }
+        // v1 positions were never indexed, the migrated one starts from the legacy totals
+        StakeEvent::PositionMigrated(e) => {
+            upsert_position(
+                db,
+                e.position.to_string(),
+                e.owner.to_string(),
+                e.staked_amount,
+                e.total_points,
+                e.slot,
+            )?;
+            db.execute(
+                "UPDATE positions SET position_index = ?2, lock_tier = ?3 WHERE position = ?1",
+                params![
+                    e.position.to_string(),
+                    e.position_index,
+                    format!("{:?}", LockTier::Days30)
+                ],
+            )?;
+        }
+        // Recorded in the events table only, they do not change pools or positions
+        StakeEvent::VaultCreated(_)
+        | StakeEvent::RewardMintCreated(_)
+        | StakeEvent::ReferrerRecorded(_)
+        | StakeEvent::ReferralRewardsClaimed(_)
+        | StakeEvent::VeLocked(_)
+        | StakeEvent::VeUnlocked(_)
+        | StakeEvent::RegistrarCreated(_)
+        | StakeEvent::VoterWeightUpdated(_)
+        | StakeEvent::VoteRelinquished(_)
+        | StakeEvent::MaxVoterWeightUpdated(_)
+        | StakeEvent::TreasuryInitialized(_)
+        | StakeEvent::FeeCollected(_)
+        | StakeEvent::FeesWithdrawn(_)
+        | StakeEvent::VestedWithdrawn(_)
+        | StakeEvent::VestingExited(_)
+        | StakeEvent::DistributorCreated(_)
+        | StakeEvent::AirdropClaimed(_)
+        | StakeEvent::SurplusSwept(_)
+        | StakeEvent::TreasuryWithdrawn(_) => {}
+    }
+    Ok(())
+}
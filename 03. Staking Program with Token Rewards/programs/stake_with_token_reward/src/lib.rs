@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
 use anchor_lang::solana_program::program::invoke;
 use anchor_lang::solana_program::program::invoke_signed;
 use anchor_lang::system_program::{transfer, Transfer};
@@ -10,26 +11,60 @@ use anchor_spl::{
         create_metadata_accounts_v3, mpl_token_metadata::types::DataV2, CreateMetadataAccountsV3,
         Metadata as Metaplex,
     },
-    token::{mint_to, Mint, MintTo, Token, TokenAccount},
+    token::{self, burn, mint_to, Burn, Mint, MintTo, Token, TokenAccount},
 };
 
 declare_id!("FV7mYYyx5FiDoaer7yzKaEJPfwCdRtj3feDuLwsJXWfk");
 
+/// Layout version written to every position, including those `migrate_account` moves
+pub const STAKE_ACCOUNT_VERSION: u8 = 2;
+
+const REWARD_RATE_PER_SOL_PER_SECOND: u64 = 1; // 1 point per SOL per second
+const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+const BPS_DENOMINATOR: u64 = 10_000;
+const MAX_VE_LOCK_YEARS: i64 = 4;
+const MAX_VE_LOCK_DURATION: i64 = MAX_VE_LOCK_YEARS * 365 * 86400;
+const MAX_VESTING_PERIOD: i64 = MAX_VE_LOCK_DURATION;
+// Keeps a distributor's claimed bitmap within the 10 KiB an account can be created with
+pub const MAX_AIRDROP_RECIPIENTS: u32 = 80_000;
+const MILLIS_PER_SLOT: u64 = 400;
+const SLOTS_PER_EPOCH: u64 = 432_000;
+
 #[program]
 pub mod stake_with_token_reward {
-    const REWARD_RATE_PER_SOL_PER_SECOND: u64 = 1; // 1 point per SOL per second
-
     use super::*;
 
-    pub fn create_pda_account(ctx: Context<CreatePdaAccount>) -> Result<()> {
+    pub fn create_pda_account(
+        ctx: Context<CreatePdaAccount>,
+        position_index: u32,
+        lock_tier: LockTier,
+    ) -> Result<()> {
         let pda_account = &mut ctx.accounts.pda_account;
         let clock = Clock::get()?;
 
+        pda_account.version = STAKE_ACCOUNT_VERSION;
         pda_account.owner = ctx.accounts.payer.key();
+        pda_account.position_index = position_index;
         pda_account.staked_amount = 0;
         pda_account.total_points = 0;
-        pda_account.last_update_time = clock.unix_timestamp;
+        pda_account.mark_updated(&clock);
+        pda_account.mark_lock_start(&clock);
+        pda_account.lock_tier = lock_tier;
+        pda_account.referrer = Pubkey::default();
+        pda_account.referral_rewards = 0;
+        pda_account.position_mint = Pubkey::default();
         pda_account.bump = ctx.bumps.pda_account;
+        pda_account.vault = Pubkey::default();
+        pda_account.counted = true;
+
+        emit!(PositionCreated {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            position_index,
+            lock_tier,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
         msg!("PDA account created successfully");
         Ok(())
@@ -64,6 +99,15 @@ pub mod stake_with_token_reward {
             ],
             signer_seeds,
         )?;
+
+        let clock = Clock::get()?;
+
+        emit!(VaultCreated {
+            authority: ctx.accounts.authorityVault.key(),
+            vault: ctx.accounts.pda_vault_account.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
     
         msg!("✅ PDA Vault System Account created successfully!");
         Ok(())
@@ -100,10 +144,81 @@ pub mod stake_with_token_reward {
         );
 
         create_metadata_accounts_v3(metadata_ctx, token_data, false, true, None)?;
+
+        let clock = Clock::get()?;
+
+        emit!(RewardMintCreated {
+            mint: ctx.accounts.mint.key(),
+            decimals: metadata.decimals,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_pool(ctx: Context<InitializePool>, params: PoolParams) -> Result<()> {
+        params.validate()?;
+
+        let pool = &mut ctx.accounts.pool;
+
+        pool.authority = ctx.accounts.authority.key();
+        pool.max_boost_bps = params.max_boost_bps;
+        pool.referral_bps = params.referral_bps;
+        pool.fee_bps = params.fee_bps;
+        pool.time_base = params.time_base;
+        pool.vesting = params.vesting;
+        pool.bump = ctx.bumps.pool;
+        pool.vesting_claims = 0;
+        pool.total_staked = 0;
+        pool.total_pending_withdrawals = 0;
+        pool.min_stake = params.min_stake;
+        pool.max_stake_per_wallet = params.max_stake_per_wallet;
+        pool.max_total_staked = params.max_total_staked;
+
+        let clock = Clock::get()?;
+
+        emit!(PoolInitialized {
+            authority: pool.authority,
+            pool: pool.key(),
+            params,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Pool initialized successfully");
+        Ok(())
+    }
+
+    pub fn update_pool_params(ctx: Context<UpdatePoolParams>, params: PoolParams) -> Result<()> {
+        params.validate()?;
+
+        let pool = &mut ctx.accounts.pool;
+
+        pool.max_boost_bps = params.max_boost_bps;
+        pool.referral_bps = params.referral_bps;
+        pool.fee_bps = params.fee_bps;
+        pool.time_base = params.time_base;
+        pool.vesting = params.vesting;
+        pool.min_stake = params.min_stake;
+        pool.max_stake_per_wallet = params.max_stake_per_wallet;
+        pool.max_total_staked = params.max_total_staked;
+
+        let clock = Clock::get()?;
+
+        emit!(ParamsUpdated {
+            authority: pool.authority,
+            pool: pool.key(),
+            params,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Pool params updated successfully");
         Ok(())
     }
 
-    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+    pub fn stake(ctx: Context<Stake>, amount: u64, referrer: Option<Pubkey>) -> Result<()> {
         let from_pubkey = ctx.accounts.user.to_account_info();
         let to_pubkey = ctx.accounts.pda_vault_account.to_account_info();
         let program_id = ctx.accounts.system_program.to_account_info();
@@ -116,11 +231,77 @@ pub mod stake_with_token_reward {
             },
         );
 
+        let pda_account = &mut ctx.accounts.pda_account;
+        let tokenized = pda_account.position_mint != Pubkey::default();
+        let clock = Clock::get()?;
+
+        require_position_authority(
+            pda_account,
+            ctx.accounts.user.key(),
+            tokenized.then_some(&**ctx.accounts.position_token_account),
+        )?;
+
+        // A position is bound to the vault holding its stake, it can only move while empty
+        let vault = ctx.accounts.pda_vault_account.key();
+        if pda_account.staked_amount == 0 {
+            pda_account.vault = vault;
+        }
+        require_keys_eq!(pda_account.vault, vault, StakingError::VaultMismatch);
+
+        require!(amount > 0, StakingError::InvalidAmount);
+        let pool = &ctx.accounts.pool;
+        require!(amount >= pool.min_stake, StakingError::BelowMinimumStake);
+        require!(
+            within_cap(pool.total_staked, amount, pool.max_total_staked),
+            StakingError::PoolCapExceeded
+        );
+
+        // The per-wallet cap applies to the owner's stake across all their positions
+        let staker = &mut ctx.accounts.staker;
+        staker.open(
+            ctx.accounts.authorityVault.key(),
+            pda_account.owner,
+            ctx.bumps.staker,
+        );
+        staker.count(pda_account)?;
+
         transfer(cpi_context, amount)?;
 
-        let pda_account = &mut ctx.accounts.pda_account;
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            staker,
+            ctx.accounts.ve_lock.as_deref(),
+        )?;
 
-        pda_account.staked_amount += amount;
+        // The referrer is recorded once, on the first stake that names one
+        if let Some(referrer) = referrer {
+            if pda_account.referrer == Pubkey::default() {
+                require!(
+                    referrer != ctx.accounts.user.key(),
+                    StakingError::InvalidReferrer
+                );
+
+                pda_account.referrer = referrer;
+
+                emit!(ReferrerRecorded {
+                    referee: ctx.accounts.user.key(),
+                    referrer,
+                    timestamp: clock.unix_timestamp,
+                    slot: clock.slot,
+                });
+            }
+        }
+
+        ctx.accounts.staker.add(&ctx.accounts.pool, amount)?;
+        pda_account.add_stake(&ctx.accounts.pool, amount, &clock);
+        ctx.accounts.pool.total_staked += amount;
+        update_boost(
+            pda_account,
+            &ctx.accounts.pool,
+            &ctx.accounts.staker,
+            ctx.accounts.ve_lock.as_deref(),
+        )?;
 
         // Minting Tokens
         let signer_seeds: &[&[&[u8]]] = &[&[b"authority", &[ctx.bumps.authority]]];
@@ -137,6 +318,38 @@ pub mod stake_with_token_reward {
         );
         mint_to(cpi_ctx, amount)?;
 
+        // The first stake into a position mints the NFT that represents it
+        if !tokenized {
+            pda_account.position_mint = ctx.accounts.position_mint.key();
+
+            mint_position_nft(
+                &ctx.accounts.token_program,
+                &ctx.accounts.position_mint,
+                &ctx.accounts.position_token_account,
+                &ctx.accounts.authority,
+                ctx.bumps.authority,
+            )?;
+        }
+
+        emit!(Staked {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            total_points: pda_account.total_points,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        emit!(RewardsMinted {
+            recipient: ctx.accounts.destination_owner.key(),
+            position: pda_account.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
         msg!("Staking Successfull and Minted Tokens Successfully");
         Ok(())
     }
@@ -144,17 +357,37 @@ pub mod stake_with_token_reward {
     pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
         require!(amount > 0, StakingError::InvalidAmount);
 
-        let pda_account_time = ctx.accounts.pda_account.last_update_time;
-
         let current_time = Clock::get()?;
 
-        require!(current_time.unix_timestamp - pda_account_time >= 30 * 86400, StakingError::InvalidUnstake);
+        require!(
+            ctx.accounts
+                .pda_account
+                .lock_expired(ctx.accounts.pool.time_base, &current_time),
+            StakingError::InvalidUnstake
+        );
 
         let pda_account = &mut ctx.accounts.pda_account;
 
+        require_position_authority(
+            pda_account,
+            ctx.accounts.user.key(),
+            ctx.accounts.position_token_account.as_deref(),
+        )?;
+
         require!(pda_account.staked_amount > amount, StakingError::InvalidBalance);
+        require_not_voting(pda_account)?;
 
         let authority_key = ctx.accounts.authority.key();
+        let staker = &mut ctx.accounts.staker;
+        staker.open(authority_key, pda_account.owner, ctx.bumps.staker);
+        staker.count(pda_account)?;
+
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            staker,
+            ctx.accounts.ve_lock.as_deref(),
+        )?;
 
         // Transfer SOL from PDA back to user
         let seeds = &[
@@ -175,197 +408,3939 @@ pub mod stake_with_token_reward {
         transfer(cpi_context, amount)?;
 
         // Update Staked Amount
-        pda_account.staked_amount -= amount;
+        ctx.accounts.staker.remove(amount)?;
+        pda_account.remove_stake(amount);
+        ctx.accounts.pool.total_staked = ctx
+            .accounts
+            .pool
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(StakingError::MathOverflow)?;
+
+        emit!(Unstaked {
+            owner: pda_account.owner,
+            position: pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            total_points: pda_account.total_points,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            timestamp: current_time.unix_timestamp,
+            slot: current_time.slot,
+        });
 
     Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct CreatePdaAccount<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+        let pda_account = &mut ctx.accounts.pda_account;
 
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + 32 + 8 + 8 + 8 + 1, // discriminator + owner + staked_amount + total_points + last_update_time + bump
-        seeds = [b"client1", payer.key().as_ref()],
-        bump
-    )]
-    pub pda_account: Account<'info, StakeAccount>,
+        require_position_authority(
+            pda_account,
+            ctx.accounts.user.key(),
+            ctx.accounts.position_token_account.as_deref(),
+        )?;
 
-    pub system_program: Program<'info, System>,
-}
+        ctx.accounts
+            .ve_lock
+            .open(ctx.accounts.user.key(), ctx.bumps.ve_lock);
 
-#[derive(Accounts)]
-pub struct VaultPdaAccount<'info> {
-    /// CHECK
-    #[account(mut)]
-    pub authorityVault: Signer<'info>,
+        let staker = &mut ctx.accounts.staker;
+        staker.open(
+            ctx.accounts.authority_vault.key(),
+            pda_account.owner,
+            ctx.bumps.staker,
+        );
+        staker.count(pda_account)?;
 
-    #[account(
-        mut,
-        seeds = [b"pdaVault", authorityVault.key().as_ref()],
-        bump
-    )]
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            staker,
+            Some(&ctx.accounts.ve_lock),
+        )?;
 
-    /// CHECK
-    pub pda_vault_account: AccountInfo<'info>,
+        require!(pda_account.total_points > 0, StakingError::InvalidPoints);
 
-    pub system_program: Program<'info, System>,
-}
+        let fee = protocol_fee(pda_account.total_points, &ctx.accounts.pool)?;
+        let rewards = pda_account.total_points - fee;
+        pda_account.total_points = 0;
 
-#[derive(Accounts)]
-#[instruction(params: TokenMintMetadata)]
-pub struct CreateTokenMint<'info> {
-    /// CHECK: PDA derived from [b"metadata", metadata_program_id, mint]
-    #[account(mut)]
-    pub metadata: UncheckedAccount<'info>,
+        let vesting = ctx.accounts.pool.vesting;
+        let clock = Clock::get()?;
 
-    #[account(
-        init,
-        payer = payer,
-        seeds = [b"mint"],
-        bump,
-        mint::decimals = params.decimals,
-        mint::authority = authority.key(),
-    )]
-    pub mint: Account<'info, Mint>,
+        require!(
+            vesting.is_enabled() == ctx.accounts.vesting_escrow.is_some(),
+            StakingError::VestingEscrowMismatch
+        );
 
-    /// CHECK: PDA that controls the mint
-    #[account(
-        seeds = [b"authority"],
-        bump,
-    )]
-    pub authority: UncheckedAccount<'info>,
+        // Vesting pools park the claim in an escrow, its tokens are only minted as they vest
+        if let Some(escrow) = &mut ctx.accounts.vesting_escrow {
+            escrow.beneficiary = ctx.accounts.user.key();
+            escrow.position = pda_account.key();
+            escrow.pool = ctx.accounts.pool.key();
+            escrow.claim_index = ctx.accounts.pool.vesting_claims;
+            escrow.total = rewards;
+            escrow.withdrawn = 0;
+            escrow.start = clock.unix_timestamp;
+            escrow.cliff = vesting.cliff;
+            escrow.period = vesting.period;
+            escrow.early_exit = vesting.early_exit;
+            escrow.bump = ctx.bumps.vesting_escrow.unwrap_or_default();
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+            ctx.accounts.pool.vesting_claims += 1;
 
-    pub rent: Sysvar<'info, Rent>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub token_metadata_program: Program<'info, Metaplex>,
-}
+            emit!(VestingStarted {
+                beneficiary: escrow.beneficiary,
+                position: escrow.position,
+                escrow: escrow.key(),
+                amount: rewards,
+                cliff_end: escrow.start + escrow.cliff,
+                end: escrow.start + escrow.period,
+                timestamp: clock.unix_timestamp,
+                slot: clock.slot,
+            });
+        } else {
+            mint_reward_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.destination,
+                &ctx.accounts.authority,
+                ctx.bumps.authority,
+                rewards,
+            )?;
+            ctx.accounts.ve_lock.credit(rewards)?;
 
-#[derive(Accounts)]
-pub struct Stake<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+            emit!(RewardsMinted {
+                recipient: ctx.accounts.user.key(),
+                position: pda_account.key(),
+                amount: rewards,
+                timestamp: clock.unix_timestamp,
+                slot: clock.slot,
+            });
+        }
 
-    #[account(
-        mut,
-        seeds = [b"client1", user.key().as_ref()],
-        bump = pda_account.bump,
-        constraint = pda_account.owner == user.key()
-    )]
-    pub pda_account: Account<'info, StakeAccount>,
+        collect_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.authority,
+            ctx.bumps.authority,
+            ctx.accounts.treasury.as_mut(),
+            ctx.accounts.treasury_token_account.as_ref(),
+            pda_account.key(),
+            fee,
+        )?;
 
-    #[account(mut)]
-    pub authorityVault: Signer<'info>,
+        msg!("You have Claimed Your Rewards");
+        Ok(())
+    }
 
-    #[account(
-        mut, 
-        seeds = [b"pdaVault", authorityVault.key().as_ref()],
-        bump,
-    )]
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let referee_account = &mut ctx.accounts.referee_account;
 
-    /// CHECK
-    pub pda_vault_account: SystemAccount<'info>,
+        require!(referee_account.referral_rewards > 0, StakingError::InvalidPoints);
 
-    // For Minting Tokens
-    #[account(
-        mut,
-        seeds = [b"mint"],
-        bump,
-        mint::authority = authority.key(),
-    )]
-    pub mint: Account<'info, Mint>,
+        let fee = protocol_fee(referee_account.referral_rewards, &ctx.accounts.pool)?;
+        let amount = referee_account.referral_rewards - fee;
+        referee_account.referral_rewards = 0;
 
-    /// CHECK
-    #[account(
-    seeds = [b"authority"],
-    bump
-    )]
-    pub authority: UncheckedAccount<'info>,
+        let signer_seeds: &[&[&[u8]]] = &[&[b"authority", &[ctx.bumps.authority]]];
 
-    #[account(
-        init_if_needed,
-        payer = payer,
-        associated_token::mint = mint,
-        associated_token::authority = destination_owner,
-    )]
-    pub destination: Account<'info, TokenAccount>,
+        let cpi_accounts = MintTo {
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        mint_to(cpi_ctx, amount)?;
 
-    /// CHECK: we use this to validate token owner
-    pub destination_owner: UncheckedAccount<'info>,
+        let ve_lock = &mut ctx.accounts.ve_lock;
+        ve_lock.open(ctx.accounts.referrer.key(), ctx.bumps.ve_lock);
+        ve_lock.credit(amount)?;
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        collect_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.authority,
+            ctx.bumps.authority,
+            ctx.accounts.treasury.as_mut(),
+            ctx.accounts.treasury_token_account.as_ref(),
+            referee_account.key(),
+            fee,
+        )?;
 
-    pub rent: Sysvar<'info, Rent>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-}
+        let clock = Clock::get()?;
 
+        emit!(ReferralRewardsClaimed {
+            referrer: ctx.accounts.referrer.key(),
+            referee: referee_account.owner,
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
+        emit!(RewardsMinted {
+            recipient: ctx.accounts.referrer.key(),
+            position: referee_account.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
 
-#[derive(Accounts)]
-pub struct Unstake<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+        msg!("You have Claimed Your Referral Rewards");
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        seeds = [b"client1", user.key().as_ref()],
-        bump = pda_account.bump,
-        constraint = pda_account.owner == user.key()
-    )]
+    pub fn lock_reward_tokens(
+        ctx: Context<LockRewardTokens>,
+        amount: u64,
+        lock_duration: i64,
+    ) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidAmount);
+        require!(
+            lock_duration > 0 && lock_duration <= MAX_VE_LOCK_DURATION,
+            StakingError::InvalidLockDuration
+        );
 
-    pub pda_account: Account<'info, StakeAccount>,
+        let current_time = Clock::get()?.unix_timestamp;
 
-    /// CHECK
-    pub authority: AccountInfo<'info>,
+        // Only claimed rewards can be locked, not the tokens `stake` mints per staked lamport
+        let ve_lock = &ctx.accounts.ve_lock;
+        require!(
+            ve_lock
+                .locked_amount
+                .checked_add(amount)
+                .is_some_and(|locked| locked <= ve_lock.claimed),
+            StakingError::NotClaimed
+        );
 
-    #[account(
-        mut, 
-        seeds = [b"pdaVault", authority.key().as_ref()],
-        bump,
-    )]
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.ve_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, amount)?;
 
-    /// CHECK
-    pub pda_vault_account: SystemAccount<'info>,
+        let ve_lock = &mut ctx.accounts.ve_lock;
 
-    pub system_program: Program<'info, System>,
-}
+        // A lock can only be topped up or extended, never shortened. Positions pick up the
+        // bigger boost from their next settlement on.
+        ve_lock.open(ctx.accounts.user.key(), ctx.bumps.ve_lock);
+        ve_lock.locked_amount += amount;
+        ve_lock.lock_end = ve_lock.lock_end.max(current_time + lock_duration);
 
-#[account]
-pub struct StakeAccount {
-    pub owner: Pubkey,
-    pub staked_amount: u64,
-    pub total_points: u64,
-    pub last_update_time: i64,
-    pub bump: u8,
-}
+        emit!(VeLocked {
+            owner: ve_lock.owner,
+            amount,
+            locked_amount: ve_lock.locked_amount,
+            lock_end: ve_lock.lock_end,
+            timestamp: current_time,
+            slot: Clock::get()?.slot,
+        });
 
-#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct TokenMintMetadata {
-    pub name: String,
-    pub symbol: String,
-    pub uri: String,
-    pub decimals: u8,
-}
+        msg!("Reward tokens locked until {}", ve_lock.lock_end);
+        Ok(())
+    }
 
-#[error_code]
-pub enum StakingError {
-    #[msg("Amount must be greater than 0")]
-    InvalidAmount,
+    pub fn unlock_reward_tokens(ctx: Context<UnlockRewardTokens>) -> Result<()> {
+        let ve_lock = &mut ctx.accounts.ve_lock;
 
-    #[msg("Invalid Balance")]
-    InvalidBalance,
+        require!(ve_lock.locked_amount > 0, StakingError::InvalidBalance);
+        require!(
+            Clock::get()?.unix_timestamp >= ve_lock.lock_end,
+            StakingError::LockNotExpired
+        );
+
+        let amount = ve_lock.locked_amount;
+        ve_lock.locked_amount = 0;
+
+        let signer_seeds: &[&[&[u8]]] = &[&[b"authority", &[ctx.bumps.authority]]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.ve_vault.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        let clock = Clock::get()?;
+
+        emit!(VeUnlocked {
+            owner: ctx.accounts.user.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Reward tokens unlocked successfully");
+        Ok(())
+    }
+
+    pub fn create_registrar(ctx: Context<CreateRegistrar>, lock_weighted: bool) -> Result<()> {
+        // Only the realm's authority may plug the pool into it, and only for one of its mints
+        let realm = RealmV2Header::read(&ctx.accounts.realm)?;
+        require!(
+            realm.authority == Some(ctx.accounts.realm_authority.key()),
+            StakingError::NotRealmAuthority
+        );
+        require!(
+            realm.governs(&ctx.accounts.governing_token_mint.key()),
+            StakingError::InvalidRealm
+        );
+
+        let registrar = &mut ctx.accounts.registrar;
+
+        registrar.governance_program_id = ctx.accounts.governance_program_id.key();
+        registrar.realm = ctx.accounts.realm.key();
+        registrar.governing_token_mint = ctx.accounts.governing_token_mint.key();
+        registrar.pool = ctx.accounts.pool.key();
+        registrar.lock_weighted = lock_weighted;
+        registrar.bump = ctx.bumps.registrar;
+
+        let clock = Clock::get()?;
+
+        emit!(RegistrarCreated {
+            registrar: registrar.key(),
+            realm: registrar.realm,
+            governing_token_mint: registrar.governing_token_mint,
+            pool: registrar.pool,
+            lock_weighted,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Registrar created successfully");
+        Ok(())
+    }
+
+    pub fn create_voter_weight_record(ctx: Context<CreateVoterWeightRecord>) -> Result<()> {
+        let registrar = &ctx.accounts.registrar;
+        let voter_weight_record = &mut ctx.accounts.voter_weight_record;
+
+        voter_weight_record.realm = registrar.realm;
+        voter_weight_record.governing_token_mint = registrar.governing_token_mint;
+        voter_weight_record.governing_token_owner = ctx.accounts.voter.key();
+        voter_weight_record.voter_weight = 0;
+        voter_weight_record.voter_weight_expiry = Some(0);
+        voter_weight_record.weight_action = None;
+        voter_weight_record.weight_action_target = None;
+
+        let clock = Clock::get()?;
+
+        emit!(VoterWeightUpdated {
+            realm: voter_weight_record.realm,
+            voter: voter_weight_record.governing_token_owner,
+            voter_weight: 0,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Voter weight record created successfully");
+        Ok(())
+    }
+
+    /// Voter weight covers every position of the voter: `pda_account` plus any
+    /// further positions passed as remaining accounts. The weight only serves `action` on
+    /// `target`. A vote on a proposal records every position counted, so the stake behind
+    /// it cannot be counted for the proposal again, not even after changing hands.
+    pub fn update_voter_weight_record<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateVoterWeightRecord<'info>>,
+        action: VoterWeightAction,
+        target: Pubkey,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let voter = ctx.accounts.voter.key();
+        let registrar = &ctx.accounts.registrar;
+
+        // Votes have to be on a proposal of the realm's governance that is being voted on
+        let casting_vote = action == VoterWeightAction::CastVote;
+        if casting_vote {
+            let proposal = ctx
+                .accounts
+                .proposal
+                .as_ref()
+                .ok_or(StakingError::InvalidProposal)?;
+            require_keys_eq!(proposal.key(), target, StakingError::InvalidProposal);
+            require_keys_eq!(
+                *proposal.owner,
+                registrar.governance_program_id,
+                StakingError::InvalidProposal
+            );
+            let proposal = ProposalV2Header::read(proposal)?;
+            require_keys_eq!(
+                proposal.governing_token_mint,
+                registrar.governing_token_mint,
+                StakingError::InvalidProposal
+            );
+            require!(proposal.is_voting(), StakingError::InvalidProposal);
+        }
+
+        // A voter without positions still updates, to a weight of zero
+        let mut counted = Vec::new();
+        let mut staked_amount = 0;
+        if let Some(pda_account) = &mut ctx.accounts.pda_account {
+            require_position_authority(
+                pda_account,
+                voter,
+                ctx.accounts.position_token_account.as_deref(),
+            )?;
+            if casting_vote {
+                record_vote(
+                    &ctx.accounts.voter,
+                    &ctx.accounts.system_program,
+                    ctx.accounts.vote_record.as_deref(),
+                    registrar,
+                    target,
+                    pda_account,
+                )?;
+            }
+            counted.push(pda_account.key());
+            staked_amount = pda_account.staked_amount;
+        }
+
+        // Remaining accounts are positions, each tokenized one followed by its position NFT
+        // token account, and each followed by its vote record when casting a vote
+        let mut remaining = ctx.remaining_accounts.iter();
+        while let Some(account_info) = remaining.next() {
+            let mut position = Account::<StakeAccount>::try_from(account_info)?;
+            let position_token_account = if position.position_mint != Pubkey::default() {
+                let account_info = remaining.next().ok_or(StakingError::NotPositionHolder)?;
+                Some(Account::<TokenAccount>::try_from(account_info)?)
+            } else {
+                None
+            };
+
+            require_position_authority(&position, voter, position_token_account.as_deref())?;
+            require!(
+                position.is_in_pool(&ctx.accounts.pool),
+                StakingError::VaultMismatch
+            );
+            require!(
+                !counted.contains(&position.key()),
+                StakingError::PositionMismatch
+            );
+
+            if casting_vote {
+                record_vote(
+                    &ctx.accounts.voter,
+                    &ctx.accounts.system_program,
+                    remaining.next(),
+                    registrar,
+                    target,
+                    &mut position,
+                )?;
+                position.exit(&crate::ID)?;
+            }
+            counted.push(position.key());
+            staked_amount += position.staked_amount;
+        }
+
+        let voter_weight = if ctx.accounts.registrar.lock_weighted {
+            let ve_balance = ctx
+                .accounts
+                .ve_lock
+                .as_ref()
+                .map_or(0, |lock| lock.ve_balance(clock.unix_timestamp));
+            let boost_bps = boost_multiplier_bps(&ctx.accounts.pool, ve_balance, staked_amount);
+
+            (staked_amount as u128 * boost_bps as u128 / BPS_DENOMINATOR as u128) as u64
+        } else {
+            staked_amount
+        };
+
+        // The weight is only valid for `action` on `target`, in this same slot
+        let voter_weight_record = &mut ctx.accounts.voter_weight_record;
+        voter_weight_record.voter_weight = voter_weight;
+        voter_weight_record.voter_weight_expiry = Some(clock.slot);
+        voter_weight_record.weight_action = Some(action);
+        voter_weight_record.weight_action_target = Some(target);
+
+        emit!(VoterWeightUpdated {
+            realm: voter_weight_record.realm,
+            voter,
+            voter_weight,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Voter weight updated to {}", voter_weight);
+        Ok(())
+    }
+
+    /// Closes a vote record once its proposal is no longer being voted on, which lets the
+    /// position move its stake again
+    pub fn relinquish_vote(ctx: Context<RelinquishVote>) -> Result<()> {
+        // A proposal the governance program no longer owns was closed, its vote is over
+        let proposal = &ctx.accounts.proposal;
+        if *proposal.owner == ctx.accounts.registrar.governance_program_id {
+            require!(
+                !ProposalV2Header::read(proposal)?.is_voting(),
+                StakingError::PositionVoting
+            );
+        }
+
+        let pda_account = &mut ctx.accounts.pda_account;
+        pda_account.active_votes = pda_account
+            .active_votes
+            .checked_sub(1)
+            .ok_or(StakingError::MathOverflow)?;
+
+        let clock = Clock::get()?;
+
+        emit!(VoteRelinquished {
+            voter: ctx.accounts.voter.key(),
+            position: pda_account.key(),
+            proposal: proposal.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Vote relinquished successfully");
+        Ok(())
+    }
+
+    pub fn create_max_voter_weight_record(ctx: Context<CreateMaxVoterWeightRecord>) -> Result<()> {
+        let registrar = &ctx.accounts.registrar;
+        let max_voter_weight_record = &mut ctx.accounts.max_voter_weight_record;
+
+        max_voter_weight_record.realm = registrar.realm;
+        max_voter_weight_record.governing_token_mint = registrar.governing_token_mint;
+        max_voter_weight_record.max_voter_weight = 0;
+        max_voter_weight_record.max_voter_weight_expiry = Some(0);
+
+        let clock = Clock::get()?;
+
+        emit!(MaxVoterWeightUpdated {
+            realm: max_voter_weight_record.realm,
+            max_voter_weight: 0,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Max voter weight record created successfully");
+        Ok(())
+    }
+
+    pub fn update_max_voter_weight_record(ctx: Context<UpdateMaxVoterWeightRecord>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        // Only tracked principal counts, lamports sent straight to the vault carry no weight
+        let total_staked = ctx.accounts.pool.total_staked;
+
+        let max_voter_weight = if ctx.accounts.registrar.lock_weighted {
+            (total_staked as u128 * ctx.accounts.pool.max_boost_bps as u128
+                / BPS_DENOMINATOR as u128) as u64
+        } else {
+            total_staked
+        };
+
+        let max_voter_weight_record = &mut ctx.accounts.max_voter_weight_record;
+        max_voter_weight_record.max_voter_weight = max_voter_weight;
+        max_voter_weight_record.max_voter_weight_expiry = Some(clock.slot);
+
+        emit!(MaxVoterWeightUpdated {
+            realm: max_voter_weight_record.realm,
+            max_voter_weight,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Max voter weight updated to {}", max_voter_weight);
+        Ok(())
+    }
+
+    pub fn transfer_position(ctx: Context<TransferPosition>, new_position_index: u32) -> Result<()> {
+        let pda_account = &ctx.accounts.pda_account;
+
+        // Tokenized positions change hands by transferring their NFT instead
+        require!(
+            pda_account.position_mint == Pubkey::default(),
+            StakingError::PositionTokenized
+        );
+        require_not_voting(pda_account)?;
+
+        // The stake leaves the owner's total and joins the new owner's, under their cap
+        if pda_account.staked_amount > 0 {
+            let (Some(pool), Some(staker), Some(new_staker)) = (
+                ctx.accounts.pool.as_ref(),
+                ctx.accounts.staker.as_mut(),
+                ctx.accounts.new_staker.as_mut(),
+            ) else {
+                return err!(StakingError::StakerRequired);
+            };
+            let bumps = &ctx.bumps;
+            staker.open(
+                pool.authority,
+                pda_account.owner,
+                bumps.staker.unwrap_or_default(),
+            );
+            if pda_account.counted {
+                staker.remove(pda_account.staked_amount)?;
+            }
+            new_staker.open(
+                pool.authority,
+                ctx.accounts.new_owner.key(),
+                bumps.new_staker.unwrap_or_default(),
+            );
+            new_staker.add(pool, pda_account.staked_amount)?;
+        }
+
+        let new_pda_account = &mut ctx.accounts.new_pda_account;
+
+        new_pda_account.version = STAKE_ACCOUNT_VERSION;
+        new_pda_account.owner = ctx.accounts.new_owner.key();
+        new_pda_account.position_index = new_position_index;
+        new_pda_account.staked_amount = pda_account.staked_amount;
+        new_pda_account.pending_stake = pda_account.pending_stake;
+        new_pda_account.counted = true;
+        // The new owner's lock is weighed from the next settlement on
+        new_pda_account.boost_bps = 0;
+        new_pda_account.total_points = pda_account.total_points;
+        new_pda_account.last_update_time = pda_account.last_update_time;
+        new_pda_account.last_update_slot = pda_account.last_update_slot;
+        new_pda_account.last_update_epoch = pda_account.last_update_epoch;
+        new_pda_account.lock_start = pda_account.lock_start;
+        new_pda_account.lock_start_slot = pda_account.lock_start_slot;
+        new_pda_account.lock_start_epoch = pda_account.lock_start_epoch;
+        new_pda_account.lock_tier = pda_account.lock_tier;
+        new_pda_account.referrer = pda_account.referrer;
+        new_pda_account.referral_rewards = pda_account.referral_rewards;
+        new_pda_account.position_mint = Pubkey::default();
+        new_pda_account.bump = ctx.bumps.new_pda_account;
+        new_pda_account.vault = pda_account.vault;
+
+        let clock = Clock::get()?;
+
+        emit!(PositionTransferred {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.new_owner.key(),
+            source: pda_account.key(),
+            position: new_pda_account.key(),
+            staked_amount: new_pda_account.staked_amount,
+            total_points: new_pda_account.total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position transferred successfully");
+        Ok(())
+    }
+
+    pub fn split_position(
+        ctx: Context<SplitPosition>,
+        amount: u64,
+        new_position_index: u32,
+    ) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidAmount);
+
+        let pda_account = &mut ctx.accounts.pda_account;
+
+        require_position_authority(
+            pda_account,
+            ctx.accounts.user.key(),
+            ctx.accounts.position_token_account.as_deref(),
+        )?;
+
+        require!(pda_account.staked_amount > amount, StakingError::InvalidBalance);
+        require_not_voting(pda_account)?;
+
+        // Both halves stay with the same owner, so their total does not change
+        let staker = &mut ctx.accounts.staker;
+        staker.open(
+            ctx.accounts.authority_vault.key(),
+            pda_account.owner,
+            ctx.bumps.staker,
+        );
+        staker.count(pda_account)?;
+
+        update_reward_points(
+            pda_account,
+            &ctx.accounts.pool,
+            staker,
+            ctx.accounts.ve_lock.as_deref(),
+        )?;
+
+        // The split-off stake takes pending stake first, so none of it starts earning early
+        let pending_stake = pda_account.pending_stake.min(amount);
+        pda_account.remove_stake(amount);
+
+        // The split-off position keeps the original lock so it cannot be used to skip it
+        let new_pda_account = &mut ctx.accounts.new_pda_account;
+
+        new_pda_account.version = STAKE_ACCOUNT_VERSION;
+        new_pda_account.owner = pda_account.owner;
+        new_pda_account.position_index = new_position_index;
+        new_pda_account.staked_amount = amount;
+        new_pda_account.pending_stake = pending_stake;
+        new_pda_account.counted = true;
+        new_pda_account.boost_bps = pda_account.boost_bps;
+        new_pda_account.total_points = 0;
+        new_pda_account.last_update_time = pda_account.last_update_time;
+        new_pda_account.last_update_slot = pda_account.last_update_slot;
+        new_pda_account.last_update_epoch = pda_account.last_update_epoch;
+        new_pda_account.lock_start = pda_account.lock_start;
+        new_pda_account.lock_start_slot = pda_account.lock_start_slot;
+        new_pda_account.lock_start_epoch = pda_account.lock_start_epoch;
+        new_pda_account.lock_tier = pda_account.lock_tier;
+        new_pda_account.referrer = pda_account.referrer;
+        new_pda_account.referral_rewards = 0;
+        new_pda_account.position_mint = ctx.accounts.new_position_mint.key();
+        new_pda_account.bump = ctx.bumps.new_pda_account;
+        new_pda_account.vault = pda_account.vault;
+
+        mint_position_nft(
+            &ctx.accounts.token_program,
+            &ctx.accounts.new_position_mint,
+            &ctx.accounts.new_position_token_account,
+            &ctx.accounts.authority,
+            ctx.bumps.authority,
+        )?;
+
+        let clock = Clock::get()?;
+
+        emit!(PositionSplit {
+            owner: pda_account.owner,
+            source: pda_account.key(),
+            new_position: new_pda_account.key(),
+            amount,
+            staked_amount: pda_account.staked_amount,
+            total_points: pda_account.total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position split successfully");
+        Ok(())
+    }
+
+    pub fn merge_positions(ctx: Context<MergePositions>) -> Result<()> {
+        let source = &mut ctx.accounts.source;
+        let destination = &mut ctx.accounts.destination;
+
+        require_position_authority(
+            source,
+            ctx.accounts.user.key(),
+            ctx.accounts.source_token_account.as_deref(),
+        )?;
+        require_position_authority(
+            destination,
+            ctx.accounts.user.key(),
+            ctx.accounts.destination_token_account.as_deref(),
+        )?;
+
+        require!(
+            source.lock_tier == destination.lock_tier && source.referrer == destination.referrer,
+            StakingError::PositionMismatch
+        );
+        require_not_voting(source)?;
+        // An empty destination takes on the source's vault, as a first stake would
+        if destination.staked_amount == 0 {
+            destination.vault = source.vault;
+        }
+        require_keys_eq!(source.vault, destination.vault, StakingError::VaultMismatch);
+
+        // Stake merged into another owner's position counts against their per-wallet cap
+        let authority_key = ctx.accounts.authority_vault.key();
+        let source_staker = &mut ctx.accounts.source_staker;
+        source_staker.open(authority_key, source.owner, ctx.bumps.source_staker);
+        source_staker.count(source)?;
+        let ve_lock = ctx.accounts.ve_lock.as_deref();
+        update_reward_points(source, &ctx.accounts.pool, source_staker, ve_lock)?;
+        if source.owner != destination.owner {
+            let (Some(destination_staker), Some(bump)) = (
+                ctx.accounts.destination_staker.as_mut(),
+                ctx.bumps.destination_staker,
+            ) else {
+                return err!(StakingError::StakerRequired);
+            };
+            destination_staker.open(authority_key, destination.owner, bump);
+            destination_staker.count(destination)?;
+            update_reward_points(destination, &ctx.accounts.pool, destination_staker, ve_lock)?;
+            destination_staker.add(&ctx.accounts.pool, source.staked_amount)?;
+            source_staker.remove(source.staked_amount)?;
+        } else {
+            update_reward_points(destination, &ctx.accounts.pool, source_staker, ve_lock)?;
+        }
+
+        destination.staked_amount += source.staked_amount;
+        destination.pending_stake += source.pending_stake;
+        destination.total_points += source.total_points;
+        destination.referral_rewards += source.referral_rewards;
+        destination.boost_bps = destination.boost_bps.min(source.boost_bps);
+        // The merged position unlocks with whichever of the two unlocks last
+        destination.lock_start = destination.lock_start.max(source.lock_start);
+        destination.lock_start_slot = destination.lock_start_slot.max(source.lock_start_slot);
+        destination.lock_start_epoch = destination.lock_start_epoch.max(source.lock_start_epoch);
+
+        // The source position is closed, so its NFT is burned with it
+        if source.position_mint != Pubkey::default() {
+            let (Some(source_position_mint), Some(source_token_account)) = (
+                ctx.accounts.source_position_mint.as_ref(),
+                ctx.accounts.source_token_account.as_ref(),
+            ) else {
+                return err!(StakingError::NotPositionHolder);
+            };
+
+            require_keys_eq!(
+                source_position_mint.key(),
+                source.position_mint,
+                StakingError::NotPositionHolder
+            );
+
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: source_position_mint.to_account_info(),
+                    from: source_token_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            );
+            burn(cpi_ctx, 1)?;
+        }
+
+        let clock = Clock::get()?;
+
+        emit!(PositionsMerged {
+            source: source.key(),
+            destination: destination.key(),
+            staked_amount: destination.staked_amount,
+            total_points: destination.total_points,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Positions merged successfully");
+        Ok(())
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+
+        treasury.authority = ctx.accounts.authority.key();
+        treasury.total_fees = 0;
+        treasury.bump = ctx.bumps.treasury;
+
+        let clock = Clock::get()?;
+
+        emit!(TreasuryInitialized {
+            authority: treasury.authority,
+            treasury: treasury.key(),
+            token_account: ctx.accounts.treasury_token_account.key(),
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Treasury initialized successfully");
+        Ok(())
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidAmount);
+        require!(
+            ctx.accounts.treasury_token_account.amount >= amount,
+            StakingError::InvalidBalance
+        );
+
+        let authority_key = ctx.accounts.authority.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"treasury",
+            authority_key.as_ref(),
+            &[ctx.accounts.treasury.bump],
+        ]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.treasury_token_account.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.treasury.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        let clock = Clock::get()?;
+
+        emit!(FeesWithdrawn {
+            authority: authority_key,
+            treasury: ctx.accounts.treasury.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Fees withdrawn successfully");
+        Ok(())
+    }
+
+    /// Compares what the vault must hold for its stakers with what it actually holds
+    pub fn reconcile(ctx: Context<Reconcile>) -> Result<Reconciliation> {
+        Reconciliation::new(
+            &ctx.accounts.pool,
+            ctx.accounts.pda_vault_account.lamports(),
+        )
+    }
+
+    pub fn capacity(ctx: Context<CapacityView>) -> Result<Capacity> {
+        let wallet_staked = ctx
+            .accounts
+            .staker
+            .as_ref()
+            .map_or(0, |staker| staker.total_staked);
+        Ok(Capacity::new(&ctx.accounts.pool, wallet_staked))
+    }
+
+    pub fn sweep_surplus(ctx: Context<SweepSurplus>) -> Result<()> {
+        let reconciliation = Reconciliation::new(
+            &ctx.accounts.pool,
+            ctx.accounts.pda_vault_account.lamports(),
+        )?;
+
+        require!(reconciliation.surplus > 0, StakingError::NoSurplus);
+
+        let authority_key = ctx.accounts.authority.key();
+        let seeds = &[
+            b"pdaVault",
+            authority_key.as_ref(),
+            &[ctx.bumps.pda_vault_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.pda_vault_account.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer,
+        );
+        transfer(cpi_context, reconciliation.surplus)?;
+
+        let clock = Clock::get()?;
+
+        emit!(SurplusSwept {
+            authority: authority_key,
+            vault: ctx.accounts.pda_vault_account.key(),
+            treasury: ctx.accounts.treasury.key(),
+            amount: reconciliation.surplus,
+            vault_balance: ctx.accounts.pda_vault_account.lamports(),
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Surplus swept to the treasury");
+        Ok(())
+    }
+
+    /// Pays lamports swept into the treasury out to the authority, its rent stays behind
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidAmount);
+
+        let treasury = ctx.accounts.treasury.to_account_info();
+        let rent_reserve = Rent::get()?.minimum_balance(treasury.data_len());
+        require!(
+            treasury.lamports() >= rent_reserve + amount,
+            StakingError::InvalidBalance
+        );
+
+        // The treasury is owned by this program, so its lamports are moved directly
+        **treasury.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.authority.try_borrow_mut_lamports()? += amount;
+
+        let clock = Clock::get()?;
+
+        emit!(TreasuryWithdrawn {
+            authority: ctx.accounts.authority.key(),
+            treasury: treasury.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Treasury withdrawn successfully");
+        Ok(())
+    }
+
+    pub fn withdraw_vested(ctx: Context<WithdrawVested>) -> Result<()> {
+        let escrow = &mut ctx.accounts.vesting_escrow;
+        let clock = Clock::get()?;
+
+        let amount = escrow.vested(clock.unix_timestamp) - escrow.withdrawn;
+        require!(amount > 0, StakingError::NothingVested);
+
+        escrow.withdrawn += amount;
+
+        mint_reward_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.destination,
+            &ctx.accounts.authority,
+            ctx.bumps.authority,
+            amount,
+        )?;
+        let ve_lock = &mut ctx.accounts.ve_lock;
+        ve_lock.open(escrow.beneficiary, ctx.bumps.ve_lock);
+        ve_lock.credit(amount)?;
+
+        emit!(VestedWithdrawn {
+            beneficiary: escrow.beneficiary,
+            position: escrow.position,
+            escrow: escrow.key(),
+            amount,
+            withdrawn: escrow.withdrawn,
+            total: escrow.total,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        emit!(RewardsMinted {
+            recipient: escrow.beneficiary,
+            position: escrow.position,
+            amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        // A fully released escrow is closed and its rent returned
+        if escrow.withdrawn == escrow.total {
+            escrow.close(ctx.accounts.beneficiary.to_account_info())?;
+        }
+
+        msg!("Withdrew {} vested reward tokens", amount);
+        Ok(())
+    }
+
+    pub fn exit_vesting(ctx: Context<WithdrawVested>) -> Result<()> {
+        let escrow = &mut ctx.accounts.vesting_escrow;
+        let clock = Clock::get()?;
+
+        require!(escrow.early_exit, StakingError::EarlyExitDisabled);
+
+        let vested = escrow.vested(clock.unix_timestamp);
+        let amount = vested - escrow.withdrawn;
+        let forfeited = escrow.total - vested;
+
+        escrow.withdrawn = vested;
+
+        if amount > 0 {
+            mint_reward_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.destination,
+                &ctx.accounts.authority,
+                ctx.bumps.authority,
+                amount,
+            )?;
+            let ve_lock = &mut ctx.accounts.ve_lock;
+            ve_lock.open(escrow.beneficiary, ctx.bumps.ve_lock);
+            ve_lock.credit(amount)?;
+
+            emit!(RewardsMinted {
+                recipient: escrow.beneficiary,
+                position: escrow.position,
+                amount,
+                timestamp: clock.unix_timestamp,
+                slot: clock.slot,
+            });
+        }
+
+        // The unvested remainder was never minted, so forfeiting it only means dropping the escrow
+        emit!(VestingExited {
+            beneficiary: escrow.beneficiary,
+            position: escrow.position,
+            escrow: escrow.key(),
+            amount,
+            forfeited,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        escrow.close(ctx.accounts.beneficiary.to_account_info())?;
+
+        msg!("Exited vesting, {} reward tokens forfeited", forfeited);
+        Ok(())
+    }
+
+    pub fn create_distributor(
+        ctx: Context<CreateDistributor>,
+        distributor_id: u64,
+        root: [u8; 32],
+        total_amount: u64,
+        num_recipients: u32,
+    ) -> Result<()> {
+        require!(
+            total_amount > 0 && (1..=MAX_AIRDROP_RECIPIENTS).contains(&num_recipients),
+            StakingError::InvalidAirdrop
+        );
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.source.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, total_amount)?;
+
+        let distributor = &mut ctx.accounts.distributor;
+
+        distributor.authority = ctx.accounts.authority.key();
+        distributor.distributor_id = distributor_id;
+        distributor.mint = ctx.accounts.mint.key();
+        distributor.root = root;
+        distributor.total_amount = total_amount;
+        distributor.claimed_amount = 0;
+        distributor.num_recipients = num_recipients;
+        distributor.claimed = vec![0; Distributor::bitmap_len(num_recipients)];
+        distributor.bump = ctx.bumps.distributor;
+
+        let clock = Clock::get()?;
+
+        emit!(DistributorCreated {
+            authority: distributor.authority,
+            distributor: distributor.key(),
+            mint: distributor.mint,
+            root,
+            total_amount,
+            num_recipients,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Distributor created for {} recipients", num_recipients);
+        Ok(())
+    }
+
+    pub fn claim_airdrop(ctx: Context<ClaimAirdrop>, claim: AirdropClaim) -> Result<()> {
+        let distributor = &mut ctx.accounts.distributor;
+        let claimant = ctx.accounts.claimant.key();
+
+        require!(
+            claim.index < distributor.num_recipients && claim.verify(&distributor.root, &claimant),
+            StakingError::InvalidProof
+        );
+        require!(
+            !distributor.is_claimed(claim.index),
+            StakingError::AlreadyClaimed
+        );
+
+        distributor.mark_claimed(claim.index);
+        distributor.claimed_amount += claim.amount;
+
+        let authority_key = distributor.authority;
+        let distributor_id = distributor.distributor_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"distributor",
+            authority_key.as_ref(),
+            &distributor_id,
+            &[distributor.bump],
+        ]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.vault.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: distributor.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_ctx, claim.amount)?;
+
+        let clock = Clock::get()?;
+
+        emit!(AirdropClaimed {
+            distributor: distributor.key(),
+            claimant,
+            index: claim.index,
+            amount: claim.amount,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Claimed {} airdropped tokens", claim.amount);
+        Ok(())
+    }
+
+    /// Moves `owner`'s position from the v1 layout at `[b"client1", owner]` to a 30 day
+    /// position at `position_index`, closing the legacy account
+    pub fn migrate_account(ctx: Context<MigrateAccount>, position_index: u32) -> Result<()> {
+        let legacy_account = ctx.accounts.legacy_account.to_account_info();
+
+        let legacy = {
+            let data = legacy_account.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *StakeAccount::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            require!(
+                data.len() == StakeAccountV1::LEN,
+                StakingError::AlreadyMigrated
+            );
+            StakeAccountV1::deserialize(&mut &data[8..])?
+        };
+        require_keys_eq!(
+            legacy.owner,
+            ctx.accounts.owner.key(),
+            StakingError::NotPositionHolder
+        );
+
+        // Legacy stake sits in an unknown vault, its authority vouches for it by co-signing
+        // and the pool starts tracking it. An empty position needs neither.
+        let vault = if legacy.staked_amount > 0 {
+            require!(
+                ctx.accounts.authority.is_signer,
+                StakingError::VaultAuthorityRequired
+            );
+            let pool = ctx
+                .accounts
+                .pool
+                .as_mut()
+                .ok_or(StakingError::VaultAuthorityRequired)?;
+            pool.total_staked = pool
+                .total_staked
+                .checked_add(legacy.staked_amount)
+                .ok_or(StakingError::MathOverflow)?;
+            require!(
+                ctx.accounts.staker.is_some(),
+                StakingError::StakerRequired
+            );
+            ctx.accounts.pda_vault_account.key()
+        } else {
+            Pubkey::default()
+        };
+
+        // Points earned under v1 carry over, but accrual and the 30 day lock restart at the
+        // migration in every time base, so no v1 interval is credited again
+        let clock = Clock::get()?;
+        let pda_account = &mut ctx.accounts.pda_account;
+
+        pda_account.version = STAKE_ACCOUNT_VERSION;
+        pda_account.owner = legacy.owner;
+        pda_account.staked_amount = legacy.staked_amount;
+        pda_account.total_points = legacy.total_points;
+        pda_account.last_update_time = clock.unix_timestamp;
+        pda_account.bump = ctx.bumps.pda_account;
+        pda_account.lock_start = clock.unix_timestamp;
+        pda_account.referrer = Pubkey::default();
+        pda_account.referral_rewards = 0;
+        pda_account.position_index = position_index;
+        pda_account.lock_tier = LockTier::Days30;
+        pda_account.position_mint = Pubkey::default();
+        pda_account.last_update_slot = clock.slot;
+        pda_account.last_update_epoch = clock.epoch;
+        pda_account.lock_start_slot = clock.slot;
+        pda_account.lock_start_epoch = clock.epoch;
+        pda_account.vault = vault;
+        pda_account.pending_stake = 0;
+        pda_account.counted = false;
+        pda_account.boost_bps = 0;
+        pda_account.active_votes = 0;
+        pda_account.reserved = [0; 19];
+
+        // The stake is already in the vault, so it joins the owner's total without the cap
+        match (ctx.accounts.staker.as_mut(), ctx.bumps.staker) {
+            (Some(staker), Some(bump)) => {
+                staker.open(ctx.accounts.authority.key(), legacy.owner, bump);
+                staker.count(pda_account)?;
+            }
+            _ => pda_account.counted = true,
+        }
+
+        // Closing the legacy account refunds its rent to the owner
+        let owner = ctx.accounts.owner.to_account_info();
+        **owner.try_borrow_mut_lamports()? += legacy_account.lamports();
+        **legacy_account.try_borrow_mut_lamports()? = 0;
+        legacy_account.assign(&system_program::ID);
+        legacy_account.resize(0)?;
+
+        emit!(PositionMigrated {
+            owner: pda_account.owner,
+            legacy: ctx.accounts.legacy_account.key(),
+            position: pda_account.key(),
+            position_index,
+            staked_amount: pda_account.staked_amount,
+            total_points: pda_account.total_points,
+            from_version: 1,
+            to_version: STAKE_ACCOUNT_VERSION,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+        });
+
+        msg!("Position migrated successfully");
+        Ok(())
+    }
+}
+
+// Only the owner's own ve lock boosts a position, as their `Staker` total is what it is
+// weighed against. `staker` has to have counted the position already.
+fn update_reward_points(
+    pda_account: &mut StakeAccount,
+    pool: &Pool,
+    staker: &Staker,
+    ve_lock: Option<&VeLock>,
+) -> Result<()> {
+    let ve_lock = ve_lock.filter(|lock| lock.owner == pda_account.owner);
+    pda_account.settle(pool, ve_lock, staker.total_staked, &Clock::get()?)
+}
+
+// Checkpoints the boost again once the position's stake or the owner's total has changed
+fn update_boost(
+    pda_account: &mut StakeAccount,
+    pool: &Pool,
+    staker: &Staker,
+    ve_lock: Option<&VeLock>,
+) -> Result<()> {
+    let ve_lock = ve_lock.filter(|lock| lock.owner == pda_account.owner);
+    pda_account.checkpoint_boost(pool, ve_lock, staker.total_staked, &Clock::get()?);
+    Ok(())
+}
+
+// Opens the vote record of `position` on `proposal`, which freezes the position's stake until
+// the record is relinquished. A record already there means the stake voted on it before.
+fn record_vote<'info>(
+    voter: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    vote_record: Option<&AccountInfo<'info>>,
+    registrar: &Account<'info, Registrar>,
+    proposal: Pubkey,
+    position: &mut Account<'info, StakeAccount>,
+) -> Result<()> {
+    let vote_record = vote_record.ok_or(StakingError::InvalidVoteRecord)?;
+    let position_key = position.key();
+    let (address, bump) = Pubkey::find_program_address(
+        &[b"voteRecord", proposal.as_ref(), position_key.as_ref()],
+        &crate::ID,
+    );
+    require_keys_eq!(vote_record.key(), address, StakingError::InvalidVoteRecord);
+    require!(*vote_record.owner != crate::ID, StakingError::AlreadyVoted);
+
+    // Lamports sent to the address beforehand must not keep the record from being opened
+    let space = 8 + VoteRecord::INIT_SPACE;
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer_seeds: &[&[&[u8]]] =
+        &[&[b"voteRecord", proposal.as_ref(), position_key.as_ref(), &[bump]]];
+    let top_up = rent.saturating_sub(vote_record.lamports());
+    if top_up > 0 {
+        transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                Transfer {
+                    from: voter.to_account_info(),
+                    to: vote_record.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Allocate {
+                account_to_allocate: vote_record.clone(),
+            },
+            signer_seeds,
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Assign {
+                account_to_assign: vote_record.clone(),
+            },
+            signer_seeds,
+        ),
+        &crate::ID,
+    )?;
+
+    let record = VoteRecord {
+        registrar: registrar.key(),
+        proposal,
+        position: position_key,
+        voter: voter.key(),
+        bump,
+    };
+    record.try_serialize(&mut &mut vote_record.try_borrow_mut_data()?[..])?;
+
+    position.active_votes = position
+        .active_votes
+        .checked_add(1)
+        .ok_or(StakingError::MathOverflow)?;
+    Ok(())
+}
+
+// Positions counted towards a vote still in progress cannot move their stake, which could
+// otherwise be counted for the same proposal a second time
+fn require_not_voting(position: &StakeAccount) -> Result<()> {
+    require!(position.active_votes == 0, StakingError::PositionVoting);
+    Ok(())
+}
+
+// `staked + amount` fits under `cap`, when there is one
+fn within_cap(staked: u64, amount: u64, cap: Option<u64>) -> bool {
+    staked
+        .checked_add(amount)
+        .is_some_and(|total| cap.is_none_or(|cap| total <= cap))
+}
+
+// Untokenized positions answer to their owner, tokenized ones to whoever holds the position NFT
+fn require_position_authority(
+    position: &StakeAccount,
+    signer: Pubkey,
+    position_token_account: Option<&TokenAccount>,
+) -> Result<()> {
+    if position.position_mint == Pubkey::default() {
+        require_keys_eq!(position.owner, signer, StakingError::NotPositionHolder);
+        return Ok(());
+    }
+
+    let token_account = position_token_account.ok_or(StakingError::NotPositionHolder)?;
+
+    require!(
+        token_account.mint == position.position_mint
+            && token_account.owner == signer
+            && token_account.amount == 1,
+        StakingError::NotPositionHolder
+    );
+
+    Ok(())
+}
+
+// The position mint stays under the program's authority PDA, which only ever mints one token per position
+fn mint_position_nft<'info>(
+    token_program: &Program<'info, Token>,
+    position_mint: &Account<'info, Mint>,
+    position_token_account: &Account<'info, TokenAccount>,
+    authority: &UncheckedAccount<'info>,
+    authority_bump: u8,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[b"authority", &[authority_bump]]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        MintTo {
+            mint: position_mint.to_account_info(),
+            to: position_token_account.to_account_info(),
+            authority: authority.to_account_info(),
+        },
+        signer_seeds,
+    );
+    mint_to(cpi_ctx, 1)
+}
+
+// Mints reward tokens to `destination` under the program's authority PDA
+fn mint_reward_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    destination: &Account<'info, TokenAccount>,
+    authority: &UncheckedAccount<'info>,
+    authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[b"authority", &[authority_bump]]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        MintTo {
+            mint: mint.to_account_info(),
+            to: destination.to_account_info(),
+            authority: authority.to_account_info(),
+        },
+        signer_seeds,
+    );
+    mint_to(cpi_ctx, amount)
+}
+
+/// Leaf of an airdrop: `hashv([0x00, index, claimant, amount])`, integers little endian
+pub fn airdrop_leaf(index: u32, claimant: &Pubkey, amount: u64) -> [u8; 32] {
+    hashv(&[
+        &[0x00],
+        &index.to_le_bytes(),
+        claimant.as_ref(),
+        &amount.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Parent of two airdrop nodes, hashed in sorted order so proofs carry no directions
+pub fn merkle_parent(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[&[0x01], left, right]).to_bytes()
+}
+
+// The pool's cut of a claim, rounded down in the claimant's favour
+fn protocol_fee(amount: u64, pool: &Pool) -> Result<u64> {
+    let fee = amount as u128 * pool.fee_bps as u128 / BPS_DENOMINATOR as u128;
+    Ok(u64::try_from(fee).map_err(|_| StakingError::MathOverflow)?)
+}
+
+// Mints a claim's fee to the pool's treasury and records it
+#[allow(clippy::too_many_arguments)]
+fn collect_fee<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    authority: &UncheckedAccount<'info>,
+    authority_bump: u8,
+    treasury: Option<&mut Account<'info, Treasury>>,
+    treasury_token_account: Option<&Account<'info, TokenAccount>>,
+    position: Pubkey,
+    fee: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+    let (Some(treasury), Some(treasury_token_account)) = (treasury, treasury_token_account) else {
+        return err!(StakingError::TreasuryRequired);
+    };
+
+    let signer_seeds: &[&[&[u8]]] = &[&[b"authority", &[authority_bump]]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        MintTo {
+            mint: mint.to_account_info(),
+            to: treasury_token_account.to_account_info(),
+            authority: authority.to_account_info(),
+        },
+        signer_seeds,
+    );
+    mint_to(cpi_ctx, fee)?;
+
+    treasury.total_fees += fee;
+
+    let clock = Clock::get()?;
+
+    emit!(FeeCollected {
+        authority: treasury.authority,
+        treasury: treasury.key(),
+        position,
+        amount: fee,
+        timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+    });
+
+    Ok(())
+}
+
+// 1x plus the ve-balance to stake ratio, capped at the pool's max multiplier
+fn boost_multiplier_bps(pool: &Pool, ve_balance: u64, staked_amount: u64) -> u64 {
+    if staked_amount == 0 {
+        return BPS_DENOMINATOR;
+    }
+
+    let boost = BPS_DENOMINATOR as u128
+        + ve_balance as u128 * BPS_DENOMINATOR as u128 / staked_amount as u128;
+
+    boost.min(pool.max_boost_bps as u128) as u64
+}
+
+#[derive(Accounts)]
+#[instruction(position_index: u32)]
+pub struct CreatePdaAccount<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + StakeAccount::INIT_SPACE,
+        seeds = [b"client1", payer.key().as_ref(), &position_index.to_le_bytes()],
+        bump
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VaultPdaAccount<'info> {
+    /// CHECK
+    #[account(mut)]
+    pub authorityVault: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pdaVault", authorityVault.key().as_ref()],
+        bump
+    )]
+
+    /// CHECK
+    pub pda_vault_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: TokenMintMetadata)]
+pub struct CreateTokenMint<'info> {
+    /// CHECK: PDA derived from [b"metadata", metadata_program_id, mint]
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"mint"],
+        bump,
+        mint::decimals = params.decimals,
+        mint::authority = authority.key(),
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: PDA that controls the mint
+    #[account(
+        seeds = [b"authority"],
+        bump,
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, Metaplex>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolParams<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", pda_account.owner.as_ref(), &pda_account.position_index.to_le_bytes()],
+        bump = pda_account.bump,
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    #[account(mut)]
+    pub authorityVault: Signer<'info>,
+
+    #[account(
+        mut, 
+        seeds = [b"pdaVault", authorityVault.key().as_ref()],
+        bump,
+    )]
+
+    /// CHECK
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authorityVault.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authorityVault.key().as_ref(), pda_account.owner.as_ref()],
+        bump
+    )]
+    pub staker: Box<Account<'info, Staker>>,
+
+    #[account(
+        seeds = [b"veLock", user.key().as_ref()],
+        bump = ve_lock.bump,
+    )]
+    pub ve_lock: Option<Account<'info, VeLock>>,
+
+    // For Minting Tokens
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump,
+        mint::authority = authority.key(),
+    )]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK
+    #[account(
+    seeds = [b"authority"],
+    bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = destination_owner,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    /// CHECK: we use this to validate token owner
+    pub destination_owner: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [b"positionMint", pda_account.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = authority,
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = position_mint,
+        associated_token::authority = user,
+    )]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", pda_account.owner.as_ref(), &pda_account.position_index.to_le_bytes()],
+        bump = pda_account.bump,
+    )]
+
+    pub pda_account: Account<'info, StakeAccount>,
+
+    #[account(
+        constraint = position_token_account.owner == user.key() @ StakingError::NotPositionHolder
+    )]
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        mut, 
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+        constraint = pda_vault_account.key() == pda_account.vault @ StakingError::VaultMismatch
+    )]
+
+    /// CHECK
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // Created for positions staked before the totals existed
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority.key().as_ref(), pda_account.owner.as_ref()],
+        bump
+    )]
+    pub staker: Account<'info, Staker>,
+
+    #[account(
+        seeds = [b"veLock", user.key().as_ref()],
+        bump = ve_lock.bump,
+    )]
+    pub ve_lock: Option<Account<'info, VeLock>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", pda_account.owner.as_ref(), &pda_account.position_index.to_le_bytes()],
+        bump = pda_account.bump,
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    #[account(
+        constraint = position_token_account.owner == user.key() @ StakingError::NotPositionHolder
+    )]
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: only used to derive the pool address
+    pub authority_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", authority_vault.key().as_ref()],
+        bump = pool.bump,
+        constraint = pda_account.is_in_pool(&pool) @ StakingError::VaultMismatch
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority_vault.key().as_ref(), pda_account.owner.as_ref()],
+        bump
+    )]
+    pub staker: Box<Account<'info, Staker>>,
+
+    /// The treasury accounts are only needed when the claim pays a fee
+    #[account(
+        mut,
+        seeds = [b"treasury", authority_vault.key().as_ref()],
+        bump = treasury.bump,
+    )]
+    pub treasury: Option<Account<'info, Treasury>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasuryTokens", authority_vault.key().as_ref()],
+        bump,
+    )]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Boosts the claim, and records the claimed tokens as lockable
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VeLock::INIT_SPACE,
+        seeds = [b"veLock", user.key().as_ref()],
+        bump
+    )]
+    pub ve_lock: Box<Account<'info, VeLock>>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump,
+        mint::authority = authority.key(),
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: PDA that controls the mint
+    #[account(
+        seeds = [b"authority"],
+        bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    // Required by pools that vest claims, in place of minting to `destination`
+    #[account(
+        init,
+        payer = user,
+        space = 8 + VestingEscrow::INIT_SPACE,
+        seeds = [b"vesting", pool.key().as_ref(), &pool.vesting_claims.to_le_bytes()],
+        bump
+    )]
+    pub vesting_escrow: Option<Account<'info, VestingEscrow>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawVested<'info> {
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vesting", vesting_escrow.pool.as_ref(), &vesting_escrow.claim_index.to_le_bytes()],
+        bump = vesting_escrow.bump,
+        has_one = beneficiary @ StakingError::NotBeneficiary
+    )]
+    pub vesting_escrow: Account<'info, VestingEscrow>,
+
+    // Records the vested tokens as lockable
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        space = 8 + VeLock::INIT_SPACE,
+        seeds = [b"veLock", beneficiary.key().as_ref()],
+        bump
+    )]
+    pub ve_lock: Account<'info, VeLock>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump,
+        mint::authority = authority.key(),
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: PDA that controls the mint
+    #[account(
+        seeds = [b"authority"],
+        bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        associated_token::mint = mint,
+        associated_token::authority = beneficiary,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(distributor_id: u64, root: [u8; 32], total_amount: u64, num_recipients: u32)]
+pub struct CreateDistributor<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Distributor::INIT_SPACE + Distributor::bitmap_len(num_recipients),
+        seeds = [b"distributor", authority.key().as_ref(), &distributor_id.to_le_bytes()],
+        bump
+    )]
+    pub distributor: Account<'info, Distributor>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"distributorVault", distributor.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = distributor,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = authority,
+    )]
+    pub source: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAirdrop<'info> {
+    #[account(mut)]
+    pub claimant: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"distributor", distributor.authority.as_ref(), &distributor.distributor_id.to_le_bytes()],
+        bump = distributor.bump,
+    )]
+    pub distributor: Account<'info, Distributor>,
+
+    #[account(
+        mut,
+        seeds = [b"distributorVault", distributor.key().as_ref()],
+        bump,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(address = distributor.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = claimant,
+        associated_token::mint = mint,
+        associated_token::authority = claimant,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", referee_account.owner.as_ref(), &referee_account.position_index.to_le_bytes()],
+        bump = referee_account.bump,
+        constraint = referee_account.referrer == referrer.key() @ StakingError::InvalidReferrer
+    )]
+    pub referee_account: Account<'info, StakeAccount>,
+
+    /// CHECK: only used to derive the pool address
+    pub authority_vault: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority_vault.key().as_ref()],
+        bump = pool.bump,
+        constraint = referee_account.is_in_pool(&pool) @ StakingError::VaultMismatch
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The treasury accounts are only needed when the claim pays a fee
+    #[account(
+        mut,
+        seeds = [b"treasury", authority_vault.key().as_ref()],
+        bump = treasury.bump,
+    )]
+    pub treasury: Option<Account<'info, Treasury>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasuryTokens", authority_vault.key().as_ref()],
+        bump,
+    )]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Records the referral rewards as lockable
+    #[account(
+        init_if_needed,
+        payer = referrer,
+        space = 8 + VeLock::INIT_SPACE,
+        seeds = [b"veLock", referrer.key().as_ref()],
+        bump
+    )]
+    pub ve_lock: Account<'info, VeLock>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump,
+        mint::authority = authority.key(),
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: PDA that controls the mint
+    #[account(
+        seeds = [b"authority"],
+        bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = referrer,
+        associated_token::mint = mint,
+        associated_token::authority = referrer,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::INIT_SPACE,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"treasuryTokens", authority.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = treasury,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"mint"],
+        bump,
+    )]
+    pub mint: Account<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        seeds = [b"treasuryTokens", authority.key().as_ref()],
+        bump,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = treasury_token_account.mint,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Reconcile<'info> {
+    /// CHECK: only used to derive the vault and pool addresses
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct CapacityView<'info> {
+    /// CHECK: only used to derive the pool address
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The owner whose remaining allowance to report, omitted for the pool alone
+    #[account(
+        seeds = [b"staker", authority.key().as_ref(), staker.owner.as_ref()],
+        bump = staker.bump,
+    )]
+    pub staker: Option<Account<'info, Staker>>,
+}
+
+#[derive(Accounts)]
+pub struct SweepSurplus<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", authority.key().as_ref()],
+        bump = treasury.bump,
+        has_one = authority
+    )]
+    pub treasury: Account<'info, Treasury>,
+}
+
+#[derive(Accounts)]
+pub struct LockRewardTokens<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VeLock::INIT_SPACE,
+        seeds = [b"veLock", user.key().as_ref()],
+        bump
+    )]
+    pub ve_lock: Account<'info, VeLock>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"veVault"],
+        bump,
+        token::mint = mint,
+        token::authority = authority,
+    )]
+    pub ve_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"mint"],
+        bump,
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: PDA that owns the ve vault
+    #[account(
+        seeds = [b"authority"],
+        bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockRewardTokens<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"veLock", user.key().as_ref()],
+        bump = ve_lock.bump,
+        constraint = ve_lock.owner == user.key()
+    )]
+    pub ve_lock: Account<'info, VeLock>,
+
+    #[account(
+        mut,
+        seeds = [b"veVault"],
+        bump,
+    )]
+    pub ve_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA that owns the ve vault
+    #[account(
+        seeds = [b"authority"],
+        bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = ve_vault.mint,
+        token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateRegistrar<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+        has_one = authority
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 32 + 32 + 1 + 1, // discriminator + governance_program_id + realm + governing_token_mint + pool + lock_weighted + bump
+        seeds = [b"registrar", realm.key().as_ref(), governing_token_mint.key().as_ref()],
+        bump
+    )]
+    pub registrar: Account<'info, Registrar>,
+
+    /// CHECK: the SPL Governance program instance the realm belongs to
+    #[account(executable)]
+    pub governance_program_id: UncheckedAccount<'info>,
+
+    /// CHECK: realm account owned by the governance program, read as a `RealmV2Header`
+    #[account(owner = governance_program_id.key())]
+    pub realm: UncheckedAccount<'info>,
+
+    pub realm_authority: Signer<'info>,
+
+    pub governing_token_mint: Account<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateVoterWeightRecord<'info> {
+    #[account(mut)]
+    pub voter: Signer<'info>,
+
+    pub registrar: Account<'info, Registrar>,
+
+    #[account(
+        init,
+        payer = voter,
+        space = 8 + 32 + 32 + 32 + 8 + (1 + 8) + (1 + 1) + (1 + 32) + 8, // discriminator + realm + governing_token_mint + governing_token_owner + voter_weight + voter_weight_expiry + weight_action + weight_action_target + reserved
+        seeds = [registrar.key().as_ref(), b"voter-weight-record", voter.key().as_ref()],
+        bump
+    )]
+    pub voter_weight_record: Account<'info, VoterWeightRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVoterWeightRecord<'info> {
+    #[account(mut)]
+    pub voter: Signer<'info>,
+
+    #[account(has_one = pool)]
+    pub registrar: Account<'info, Registrar>,
+
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", pda_account.owner.as_ref(), &pda_account.position_index.to_le_bytes()],
+        bump = pda_account.bump,
+        constraint = pda_account.is_in_pool(&pool) @ StakingError::VaultMismatch
+    )]
+    pub pda_account: Option<Account<'info, StakeAccount>>,
+
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: proposal voted on, required to cast a vote, read as a `ProposalV2Header`
+    pub proposal: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: vote record of `pda_account` on `proposal`, opened when casting a vote
+    #[account(mut)]
+    pub vote_record: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"veLock", voter.key().as_ref()],
+        bump = ve_lock.bump,
+    )]
+    pub ve_lock: Option<Account<'info, VeLock>>,
+
+    #[account(
+        mut,
+        seeds = [registrar.key().as_ref(), b"voter-weight-record", voter.key().as_ref()],
+        bump,
+        constraint = voter_weight_record.governing_token_owner == voter.key()
+    )]
+    pub voter_weight_record: Account<'info, VoterWeightRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RelinquishVote<'info> {
+    /// CHECK: the voter who opened the record, refunded its rent
+    #[account(mut, address = vote_record.voter)]
+    pub voter: UncheckedAccount<'info>,
+
+    pub registrar: Account<'info, Registrar>,
+
+    #[account(
+        mut,
+        address = vote_record.position,
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    /// CHECK: proposal of the vote record, read as a `ProposalV2Header` while the governance
+    /// program owns it
+    #[account(address = vote_record.proposal)]
+    pub proposal: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = voter,
+        has_one = registrar,
+        seeds = [b"voteRecord", proposal.key().as_ref(), pda_account.key().as_ref()],
+        bump = vote_record.bump,
+    )]
+    pub vote_record: Account<'info, VoteRecord>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMaxVoterWeightRecord<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub registrar: Account<'info, Registrar>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 32 + 8 + (1 + 8) + 8, // discriminator + realm + governing_token_mint + max_voter_weight + max_voter_weight_expiry + reserved
+        seeds = [registrar.key().as_ref(), b"max-voter-weight-record"],
+        bump
+    )]
+    pub max_voter_weight_record: Account<'info, MaxVoterWeightRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMaxVoterWeightRecord<'info> {
+    #[account(has_one = pool)]
+    pub registrar: Account<'info, Registrar>,
+
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [registrar.key().as_ref(), b"max-voter-weight-record"],
+        bump,
+    )]
+    pub max_voter_weight_record: Account<'info, MaxVoterWeightRecord>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_index: u32)]
+pub struct MigrateAccount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: a v1 `StakeAccount` cannot be deserialized as the current layout, so the
+    /// discriminator and length are checked in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"client1", owner.key().as_ref()],
+        bump,
+    )]
+    pub legacy_account: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakeAccount::INIT_SPACE,
+        seeds = [b"client1", owner.key().as_ref(), &position_index.to_le_bytes()],
+        bump
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    /// CHECK: authority of the vault the legacy stake sits in, it has to sign when the
+    /// position holds stake
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"pdaVault", authority.key().as_ref()],
+        bump,
+    )]
+    pub pda_vault_account: SystemAccount<'info>,
+
+    /// Only needed when the position holds stake
+    #[account(
+        mut,
+        seeds = [b"pool", authority.key().as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Option<Account<'info, Pool>>,
+
+    /// Only needed when the position holds stake
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub staker: Option<Box<Account<'info, Staker>>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_position_index: u32)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"client1", owner.key().as_ref(), &pda_account.position_index.to_le_bytes()],
+        bump = pda_account.bump,
+        constraint = pda_account.owner == owner.key()
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    /// CHECK: the wallet receiving the position, it does not need to sign
+    #[account(constraint = new_owner.key() != owner.key() @ StakingError::InvalidNewOwner)]
+    pub new_owner: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakeAccount::INIT_SPACE,
+        seeds = [b"client1", new_owner.key().as_ref(), &new_position_index.to_le_bytes()],
+        bump
+    )]
+    pub new_pda_account: Account<'info, StakeAccount>,
+
+    /// CHECK: only used to derive the pool and staker addresses
+    pub authority_vault: AccountInfo<'info>,
+
+    /// Pool the position is staked in, the stakers below are only needed with it when the
+    /// position holds stake
+    #[account(
+        seeds = [b"pool", authority_vault.key().as_ref()],
+        bump = pool.bump,
+        constraint = pda_account.is_in_pool(pool) @ StakingError::VaultMismatch
+    )]
+    pub pool: Option<Box<Account<'info, Pool>>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority_vault.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub staker: Option<Box<Account<'info, Staker>>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority_vault.key().as_ref(), new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_staker: Option<Box<Account<'info, Staker>>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, new_position_index: u32)]
+pub struct SplitPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", pda_account.owner.as_ref(), &pda_account.position_index.to_le_bytes()],
+        bump = pda_account.bump,
+    )]
+    pub pda_account: Account<'info, StakeAccount>,
+
+    #[account(
+        constraint = position_token_account.owner == user.key() @ StakingError::NotPositionHolder
+    )]
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + StakeAccount::INIT_SPACE,
+        seeds = [b"client1", pda_account.owner.as_ref(), &new_position_index.to_le_bytes()],
+        bump
+    )]
+    pub new_pda_account: Account<'info, StakeAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"positionMint", new_pda_account.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = authority,
+    )]
+    pub new_position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = new_position_mint,
+        associated_token::authority = user,
+    )]
+    pub new_position_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA that controls the position mints
+    #[account(
+        seeds = [b"authority"],
+        bump
+    )]
+    pub authority: UncheckedAccount<'info>,
+
+    /// CHECK: only used to derive the pool address
+    pub authority_vault: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority_vault.key().as_ref()],
+        bump = pool.bump,
+        constraint = pda_account.is_in_pool(&pool) @ StakingError::VaultMismatch
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // Created for positions staked before the totals existed
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority_vault.key().as_ref(), pda_account.owner.as_ref()],
+        bump
+    )]
+    pub staker: Box<Account<'info, Staker>>,
+
+    #[account(
+        seeds = [b"veLock", user.key().as_ref()],
+        bump = ve_lock.bump,
+    )]
+    pub ve_lock: Option<Account<'info, VeLock>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct MergePositions<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"client1", source.owner.as_ref(), &source.position_index.to_le_bytes()],
+        bump = source.bump,
+    )]
+    pub source: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"client1", destination.owner.as_ref(), &destination.position_index.to_le_bytes()],
+        bump = destination.bump,
+        constraint = destination.key() != source.key() @ StakingError::PositionMismatch
+    )]
+    pub destination: Account<'info, StakeAccount>,
+
+    #[account(
+        mut,
+        constraint = source_token_account.owner == user.key() @ StakingError::NotPositionHolder
+    )]
+    pub source_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = destination_token_account.owner == user.key() @ StakingError::NotPositionHolder
+    )]
+    pub destination_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub source_position_mint: Option<Account<'info, Mint>>,
+
+    /// CHECK: only used to derive the pool address
+    pub authority_vault: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"pool", authority_vault.key().as_ref()],
+        bump = pool.bump,
+        constraint = source.is_in_pool(&pool) @ StakingError::VaultMismatch
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority_vault.key().as_ref(), source.owner.as_ref()],
+        bump
+    )]
+    pub source_staker: Box<Account<'info, Staker>>,
+
+    /// Only needed, and only allowed, when the positions have different owners
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + Staker::INIT_SPACE,
+        seeds = [b"staker", authority_vault.key().as_ref(), destination.owner.as_ref()],
+        bump,
+        constraint = destination.owner != source.owner @ StakingError::PositionMismatch
+    )]
+    pub destination_staker: Option<Box<Account<'info, Staker>>>,
+
+    #[account(
+        seeds = [b"veLock", user.key().as_ref()],
+        bump = ve_lock.bump,
+    )]
+    pub ve_lock: Option<Account<'info, VeLock>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct StakeAccount {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub last_update_time: i64,
+    pub bump: u8,
+    /// Start of the lockup, restarted whenever stake is added
+    pub lock_start: i64,
+    pub referrer: Pubkey,
+    pub referral_rewards: u64,
+    pub position_index: u32,
+    pub lock_tier: LockTier,
+    pub position_mint: Pubkey,
+    /// `last_update_time` and `lock_start` as slots and epochs, for pools that count those
+    pub last_update_slot: u64,
+    pub last_update_epoch: u64,
+    pub lock_start_slot: u64,
+    pub lock_start_epoch: u64,
+    /// Vault holding the position's stake, set by its first stake
+    pub vault: Pubkey,
+    /// Stake added on an epoch pool during the epoch last settled, which only earns from the
+    /// next one
+    pub pending_stake: u64,
+    /// Whether `staked_amount` is part of the owner's `Staker` total. Carved out of
+    /// `reserved`, so positions staked before the totals existed read as uncounted.
+    pub counted: bool,
+    /// The ve boost as of the last settlement, the most the next one credits. Carved out of
+    /// `reserved`, where zero reads as no boost.
+    pub boost_bps: u16,
+    /// Vote records open on the position, which keep its stake where it is. Carved out of
+    /// `reserved`.
+    pub active_votes: u16,
+    /// Room for new fields without another realloc
+    pub reserved: [u8; 19],
+}
+
+/// The original `StakeAccount` layout at `[b"client1", owner]`, which `migrate_account`
+/// moves to an indexed position. It shares the current discriminator.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StakeAccountV1 {
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub last_update_time: i64,
+    pub bump: u8,
+}
+
+impl StakeAccountV1 {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 1; // discriminator + owner + staked_amount + total_points + last_update_time + bump
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockTier {
+    Days30,
+    Days90,
+    Days180,
+    Days365,
+}
+
+impl StakeAccount {
+    /// Whether the position is staked in `pool`'s vault. The pool, vault and treasury all
+    /// derive from the pool authority, so this ties the position to all three.
+    pub fn is_in_pool(&self, pool: &Pool) -> bool {
+        let (vault, _) =
+            Pubkey::find_program_address(&[b"pdaVault", pool.authority.as_ref()], &crate::ID);
+        self.vault == vault
+    }
+
+    /// Credits the rewards earned since the last settlement as of `time`, measured in the
+    /// pool's time base. The ve balance of `ve_lock` is weighed against `owner_staked`, the
+    /// owner's stake across all their positions, so one lock boosts their stake once.
+    pub fn settle(
+        &mut self,
+        pool: &Pool,
+        ve_lock: Option<&VeLock>,
+        owner_staked: u64,
+        time: &impl TimeSource,
+    ) -> Result<()> {
+        let base = pool.time_base;
+        let elapsed = base.now(time) - self.last_updated(base);
+        let earned = |staked: u64, units: i64| {
+            (staked / LAMPORTS_PER_SOL) * base.to_seconds(units) * REWARD_RATE_PER_SOL_PER_SECOND
+        };
+
+        // Pending stake sits out the first unit, after which it earns like the rest
+        let rewards = if self.pending_stake > 0 && elapsed > 0 {
+            earned(self.staked_amount - self.pending_stake, 1)
+                + earned(self.staked_amount, elapsed - 1)
+        } else {
+            earned(self.staked_amount, elapsed)
+        };
+        if elapsed > 0 {
+            self.pending_stake = 0;
+        }
+
+        // The elapsed time earns the lower of the boost checkpointed last time and the current
+        // one, so a lock taken out since only boosts from this settlement on, while a lock that
+        // decayed or ended counts at once
+        let checkpoint_bps = (self.boost_bps as u64).max(BPS_DENOMINATOR);
+        self.checkpoint_boost(pool, ve_lock, owner_staked, time);
+        let boost_bps = checkpoint_bps.min(self.boost_bps as u64);
+
+        let tier_bps = self.lock_tier.reward_multiplier_bps();
+
+        let boosted_rewards = (rewards as u128 * boost_bps as u128 * tier_bps as u128
+            / (BPS_DENOMINATOR as u128 * BPS_DENOMINATOR as u128)) as u64;
+
+        self.total_points += boosted_rewards;
+        self.mark_updated(time);
+
+        // Referral rewards are paid on top of the referee's own rewards
+        if self.referrer != Pubkey::default() {
+            let cut = u64::try_from(
+                boosted_rewards as u128 * pool.referral_bps as u128 / BPS_DENOMINATOR as u128,
+            )
+            .map_err(|_| StakingError::MathOverflow)?;
+            self.referral_rewards = self
+                .referral_rewards
+                .checked_add(cut)
+                .ok_or(StakingError::MathOverflow)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the boost `ve_lock` gives as of `time`, weighed against `owner_staked`, as the
+    /// most the next settlement credits
+    pub fn checkpoint_boost(
+        &mut self,
+        pool: &Pool,
+        ve_lock: Option<&VeLock>,
+        owner_staked: u64,
+        time: &impl TimeSource,
+    ) {
+        // ve locks always run on unix time
+        let ve_balance = ve_lock.map_or(0, |lock| lock.ve_balance(time.unix_timestamp()));
+        self.boost_bps = boost_multiplier_bps(pool, ve_balance, owner_staked) as u16;
+    }
+
+    /// Adds `amount` of freshly settled stake as of `time`. Added stake restarts the lock of the
+    /// whole position, so it cannot ride out a lock that is about to end. Epoch pools only
+    /// credit the added stake for whole epochs that started after it landed, so it waits as
+    /// `pending_stake` while the stake already there keeps earning the current epoch.
+    pub fn add_stake(&mut self, pool: &Pool, amount: u64, time: &impl TimeSource) {
+        self.staked_amount += amount;
+        self.mark_lock_start(time);
+
+        if pool.time_base == TimeBase::Epochs {
+            self.pending_stake += amount;
+        }
+    }
+
+    /// Takes `amount` out of the position, out of its pending stake first
+    pub fn remove_stake(&mut self, amount: u64) {
+        self.staked_amount -= amount;
+        self.pending_stake = self.pending_stake.saturating_sub(amount);
+    }
+
+    /// Whether the lock tier's duration has run since the lock started, measured in `base`
+    pub fn lock_expired(&self, base: TimeBase, time: &impl TimeSource) -> bool {
+        base.to_seconds(base.now(time) - self.lock_started(base))
+            >= self.lock_tier.duration() as u64
+    }
+
+    /// The last settlement, read in `base`
+    pub fn last_updated(&self, base: TimeBase) -> i64 {
+        match base {
+            TimeBase::UnixSeconds => self.last_update_time,
+            TimeBase::Slots => self.last_update_slot as i64,
+            TimeBase::Epochs => self.last_update_epoch as i64,
+        }
+    }
+
+    /// The start of the lock, read in `base`
+    pub fn lock_started(&self, base: TimeBase) -> i64 {
+        match base {
+            TimeBase::UnixSeconds => self.lock_start,
+            TimeBase::Slots => self.lock_start_slot as i64,
+            TimeBase::Epochs => self.lock_start_epoch as i64,
+        }
+    }
+
+    // Both are kept in every base, so a pool can change its time base under open positions
+    fn mark_updated(&mut self, time: &impl TimeSource) {
+        self.last_update_time = time.unix_timestamp();
+        self.last_update_slot = time.slot();
+        self.last_update_epoch = time.epoch();
+    }
+
+    fn mark_lock_start(&mut self, time: &impl TimeSource) {
+        self.lock_start = time.unix_timestamp();
+        self.lock_start_slot = time.slot();
+        self.lock_start_epoch = time.epoch();
+    }
+}
+
+/// What a pool measures accrual and lockups in. Slots and epochs are harder for validators to
+/// skew than `unix_timestamp`, but only approximate wall-clock time.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBase {
+    UnixSeconds,
+    Slots,
+    Epochs,
+}
+
+impl TimeBase {
+    /// Nominal length of one unit, so rates and lock durations given in seconds carry over
+    pub fn millis_per_unit(&self) -> u64 {
+        match self {
+            TimeBase::UnixSeconds => 1_000,
+            TimeBase::Slots => MILLIS_PER_SLOT,
+            TimeBase::Epochs => MILLIS_PER_SLOT * SLOTS_PER_EPOCH,
+        }
+    }
+
+    /// The current reading of `time` in this base
+    pub fn now(&self, time: &impl TimeSource) -> i64 {
+        match self {
+            TimeBase::UnixSeconds => time.unix_timestamp(),
+            TimeBase::Slots => time.slot() as i64,
+            TimeBase::Epochs => time.epoch() as i64,
+        }
+    }
+
+    /// Whole seconds `units` of this base stand for, none for a negative span
+    pub fn to_seconds(&self, units: i64) -> u64 {
+        (units.max(0) as u128 * self.millis_per_unit() as u128 / 1_000) as u64
+    }
+}
+
+/// Where settlement and lockup checks read the time from. On chain that is the `Clock`
+/// sysvar, tests and off-chain tools can supply any time they like.
+pub trait TimeSource {
+    fn unix_timestamp(&self) -> i64;
+    fn slot(&self) -> u64;
+    fn epoch(&self) -> u64;
+}
+
+impl TimeSource for Clock {
+    fn unix_timestamp(&self) -> i64 {
+        self.unix_timestamp
+    }
+
+    fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl LockTier {
+    pub fn duration(&self) -> i64 {
+        match self {
+            LockTier::Days30 => 30 * 86400,
+            LockTier::Days90 => 90 * 86400,
+            LockTier::Days180 => 180 * 86400,
+            LockTier::Days365 => 365 * 86400,
+        }
+    }
+
+    /// Longer lockups earn a larger share of rewards.
+    pub fn reward_multiplier_bps(&self) -> u64 {
+        match self {
+            LockTier::Days30 => 10_000,
+            LockTier::Days90 => 12_500,
+            LockTier::Days180 => 15_000,
+            LockTier::Days365 => 20_000,
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub authority: Pubkey,
+    pub max_boost_bps: u16,
+    pub referral_bps: u16,
+    /// Share of every claim minted to the pool's treasury instead of the claimant
+    pub fee_bps: u16,
+    /// What accrual and lockups are measured in
+    pub time_base: TimeBase,
+    /// How claimed rewards are released
+    pub vesting: Vesting,
+    /// Vesting escrows opened by claims against the pool so far, the next escrow's
+    /// `claim_index`. It never goes down, so escrow addresses are never reused.
+    pub vesting_claims: u64,
+    /// Principal staked into the pool's vault across all positions
+    pub total_staked: u64,
+    /// Unstaked principal still owed to stakers. `unstake` pays out immediately so this stays
+    /// zero for now, but it is reserved like `total_staked` and can never be swept.
+    pub total_pending_withdrawals: u64,
+    /// Smallest amount a single stake may deposit
+    pub min_stake: u64,
+    /// Most one owner may hold across all their positions, `None` for no cap
+    pub max_stake_per_wallet: Option<u64>,
+    /// Most the whole pool may hold, `None` for no cap
+    pub max_total_staked: Option<u64>,
+    pub bump: u8,
+}
+
+/// Returned by `reconcile`
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reconciliation {
+    pub vault_balance: u64,
+    /// Rent-exempt minimum of the vault
+    pub rent_reserve: u64,
+    pub total_staked: u64,
+    pub total_pending_withdrawals: u64,
+    /// Lamports beyond what the vault owes, which `sweep_surplus` may take
+    pub surplus: u64,
+    /// Lamports missing from what the vault owes
+    pub shortfall: u64,
+}
+
+impl Reconciliation {
+    pub fn new(pool: &Pool, vault_balance: u64) -> Result<Self> {
+        let rent_reserve = Rent::get()?.minimum_balance(0);
+        let owed = rent_reserve + pool.total_staked + pool.total_pending_withdrawals;
+
+        Ok(Self {
+            vault_balance,
+            rent_reserve,
+            total_staked: pool.total_staked,
+            total_pending_withdrawals: pool.total_pending_withdrawals,
+            surplus: vault_balance.saturating_sub(owed),
+            shortfall: owed.saturating_sub(vault_balance),
+        })
+    }
+}
+
+/// Returned by `capacity`, `None` meaning no limit
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Capacity {
+    pub min_stake: u64,
+    pub total_staked: u64,
+    /// Lamports the pool can still take before its cap
+    pub pool_remaining: Option<u64>,
+    /// Stake of the owner passed in across the pool, zero without one
+    pub wallet_staked: u64,
+    /// Most that owner can still stake, under both their own cap and the pool's
+    pub wallet_remaining: Option<u64>,
+}
+
+impl Capacity {
+    pub fn new(pool: &Pool, wallet_staked: u64) -> Self {
+        let remaining = |staked: u64, cap: Option<u64>| cap.map(|cap| cap.saturating_sub(staked));
+        let pool_remaining = remaining(pool.total_staked, pool.max_total_staked);
+        let wallet_cap_remaining = remaining(wallet_staked, pool.max_stake_per_wallet);
+        let wallet_remaining = match (wallet_cap_remaining, pool_remaining) {
+            (Some(wallet), Some(pool)) => Some(wallet.min(pool)),
+            (wallet, pool) => wallet.or(pool),
+        };
+
+        Self {
+            min_stake: pool.min_stake,
+            total_staked: pool.total_staked,
+            pool_remaining,
+            wallet_staked,
+            wallet_remaining,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct PoolParams {
+    pub max_boost_bps: u16,
+    pub referral_bps: u16,
+    pub fee_bps: u16,
+    pub time_base: TimeBase,
+    pub vesting: Vesting,
+    pub min_stake: u64,
+    pub max_stake_per_wallet: Option<u64>,
+    pub max_total_staked: Option<u64>,
+}
+
+/// How a pool releases claimed rewards. Without a period claims are paid out at once,
+/// otherwise they vest linearly in unix seconds and nothing is released before the cliff.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, InitSpace, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+pub struct Vesting {
+    pub period: i64,
+    pub cliff: i64,
+    /// Whether holders may close an escrow early, forfeiting whatever has not vested
+    pub early_exit: bool,
+}
+
+impl Vesting {
+    pub fn is_enabled(&self) -> bool {
+        self.period > 0
+    }
+}
+
+impl PoolParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.max_boost_bps as u64 >= BPS_DENOMINATOR,
+            StakingError::InvalidBoost
+        );
+        require!(
+            self.referral_bps as u64 <= BPS_DENOMINATOR,
+            StakingError::InvalidReferralRate
+        );
+        require!(
+            self.fee_bps as u64 <= BPS_DENOMINATOR,
+            StakingError::InvalidFeeRate
+        );
+        require!(
+            (0..=MAX_VESTING_PERIOD).contains(&self.vesting.period)
+                && (0..=self.vesting.period).contains(&self.vesting.cliff),
+            StakingError::InvalidVesting
+        );
+        require!(
+            self.max_stake_per_wallet
+                .is_none_or(|cap| cap >= self.min_stake),
+            StakingError::InvalidStakeLimits
+        );
+
+        Ok(())
+    }
+}
+
+/// What one owner has staked into a pool across all their positions, at
+/// `[b"staker", authority, owner]`, which the pool's per-wallet cap applies to
+#[account]
+#[derive(InitSpace)]
+pub struct Staker {
+    /// Authority of the pool
+    pub authority: Pubkey,
+    pub owner: Pubkey,
+    pub total_staked: u64,
+    pub bump: u8,
+}
+
+impl Staker {
+    /// Fills in an account `init_if_needed` may just have created
+    pub fn open(&mut self, authority: Pubkey, owner: Pubkey, bump: u8) {
+        self.authority = authority;
+        self.owner = owner;
+        self.bump = bump;
+    }
+
+    /// Adds the stake of `position` if it was staked before the totals existed. It is already
+    /// in the vault, so the cap does not apply.
+    pub fn count(&mut self, position: &mut StakeAccount) -> Result<()> {
+        if !position.counted {
+            self.total_staked = self
+                .total_staked
+                .checked_add(position.staked_amount)
+                .ok_or(StakingError::MathOverflow)?;
+            position.counted = true;
+        }
+        Ok(())
+    }
+
+    /// Adds `amount` of new stake, which has to fit under `pool`'s per-wallet cap
+    pub fn add(&mut self, pool: &Pool, amount: u64) -> Result<()> {
+        require!(
+            within_cap(self.total_staked, amount, pool.max_stake_per_wallet),
+            StakingError::WalletCapExceeded
+        );
+        self.total_staked += amount;
+        Ok(())
+    }
+
+    /// Takes `amount` of a counted position's stake out again
+    pub fn remove(&mut self, amount: u64) -> Result<()> {
+        self.total_staked = self
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// Holds the fees taken from claims, as reward tokens in the `treasuryTokens` account, and
+/// lamports swept out of the vault
+#[account]
+#[derive(InitSpace)]
+pub struct Treasury {
+    pub authority: Pubkey,
+    /// Fees collected over the treasury's lifetime
+    pub total_fees: u64,
+    pub bump: u8,
+}
+
+/// One vesting claim, its tokens are minted to the beneficiary as they vest
+#[account]
+#[derive(InitSpace)]
+pub struct VestingEscrow {
+    pub beneficiary: Pubkey,
+    pub position: Pubkey,
+    pub pool: Pubkey,
+    /// The pool's `vesting_claims` when the escrow was opened
+    pub claim_index: u64,
+    pub total: u64,
+    pub withdrawn: u64,
+    pub start: i64,
+    pub cliff: i64,
+    pub period: i64,
+    pub early_exit: bool,
+    pub bump: u8,
+}
+
+impl VestingEscrow {
+    /// How much of the claim has vested by `now`, nothing before the cliff and all of it
+    /// once the period has run
+    pub fn vested(&self, now: i64) -> u64 {
+        let elapsed = now - self.start;
+
+        if elapsed < self.cliff {
+            return 0;
+        }
+        if elapsed >= self.period {
+            return self.total;
+        }
+
+        (self.total as u128 * elapsed as u128 / self.period as u128) as u64
+    }
+}
+
+/// A Merkle airdrop of `mint` tokens, paid out of the `distributorVault` token account
+#[account]
+#[derive(InitSpace)]
+pub struct Distributor {
+    pub authority: Pubkey,
+    pub distributor_id: u64,
+    pub mint: Pubkey,
+    pub root: [u8; 32],
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+    pub num_recipients: u32,
+    /// One bit per leaf index, set once that leaf is claimed. `INIT_SPACE` only counts its
+    /// length prefix, the bitmap is sized by `bitmap_len` on creation.
+    #[max_len(0)]
+    pub claimed: Vec<u8>,
+    pub bump: u8,
+}
+
+impl Distributor {
+    pub fn bitmap_len(num_recipients: u32) -> usize {
+        num_recipients.div_ceil(8) as usize
+    }
+
+    pub fn is_claimed(&self, index: u32) -> bool {
+        self.claimed[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn mark_claimed(&mut self, index: u32) {
+        self.claimed[index as usize / 8] |= 1 << (index % 8);
+    }
+}
+
+/// One recipient's share of an airdrop and the proof of it
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AirdropClaim {
+    /// Position of the recipient's leaf, the bit it is tracked under
+    pub index: u32,
+    pub amount: u64,
+    /// Sibling hashes from the recipient's leaf up to the root
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl AirdropClaim {
+    pub fn verify(&self, root: &[u8; 32], claimant: &Pubkey) -> bool {
+        let leaf = airdrop_leaf(self.index, claimant, self.amount);
+        self.proof
+            .iter()
+            .fold(leaf, |node, sibling| merkle_parent(&node, sibling))
+            == *root
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct VeLock {
+    pub owner: Pubkey,
+    pub locked_amount: u64,
+    pub lock_end: i64,
+    /// Reward tokens the owner has claimed, the most that may be locked at once. The tokens
+    /// `stake` mints per staked lamport never count, so they cannot boost the stake itself.
+    pub claimed: u64,
+    pub bump: u8,
+}
+
+impl VeLock {
+    /// Fills in an account `init_if_needed` may just have created
+    pub fn open(&mut self, owner: Pubkey, bump: u8) {
+        self.owner = owner;
+        self.bump = bump;
+    }
+
+    /// Records `amount` of freshly claimed reward tokens as lockable
+    pub fn credit(&mut self, amount: u64) -> Result<()> {
+        self.claimed = self
+            .claimed
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Voting/boost weight: the locked amount scaled by the time left on the
+    /// lock, decaying linearly to zero at `lock_end`.
+    pub fn ve_balance(&self, current_time: i64) -> u64 {
+        if current_time >= self.lock_end {
+            return 0;
+        }
+
+        let remaining = (self.lock_end - current_time).min(MAX_VE_LOCK_DURATION) as u128;
+
+        (self.locked_amount as u128 * remaining / MAX_VE_LOCK_DURATION as u128) as u64
+    }
+}
+
+#[account]
+pub struct Registrar {
+    pub governance_program_id: Pubkey,
+    pub realm: Pubkey,
+    pub governing_token_mint: Pubkey,
+    pub pool: Pubkey,
+    pub lock_weighted: bool,
+    pub bump: u8,
+}
+
+/// The leading fields of an SPL Governance `RealmV2`, up to its authority. The name and
+/// reserved space that follow are never read.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RealmV2Header {
+    pub account_type: u8,
+    pub community_mint: Pubkey,
+    /// `RealmConfig` up to the council mint: two legacy bytes, 6 reserved, the minimum
+    /// community weight to create a governance and the max voter weight source
+    pub config_legacy: [u8; 8],
+    pub min_community_weight_to_create_governance: u64,
+    pub community_mint_max_voter_weight_source: (u8, u64),
+    pub council_mint: Option<Pubkey>,
+    pub reserved: [u8; 6],
+    pub legacy1: u16,
+    pub authority: Option<Pubkey>,
+}
+
+impl RealmV2Header {
+    /// `GovernanceAccountType::RealmV2`
+    pub const ACCOUNT_TYPE: u8 = 16;
+
+    pub fn read(realm: &AccountInfo) -> Result<Self> {
+        let header = Self::deserialize(&mut &realm.try_borrow_data()?[..])
+            .map_err(|_| StakingError::InvalidRealm)?;
+        require!(
+            header.account_type == Self::ACCOUNT_TYPE,
+            StakingError::InvalidRealm
+        );
+        Ok(header)
+    }
+
+    pub fn governs(&self, mint: &Pubkey) -> bool {
+        self.community_mint == *mint || self.council_mint == Some(*mint)
+    }
+}
+
+/// The leading fields of an SPL Governance `ProposalV2`, up to its state. The rest is never
+/// read.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProposalV2Header {
+    pub account_type: u8,
+    pub governance: Pubkey,
+    pub governing_token_mint: Pubkey,
+    pub state: u8,
+}
+
+impl ProposalV2Header {
+    /// `GovernanceAccountType::ProposalV2`
+    pub const ACCOUNT_TYPE: u8 = 14;
+    /// `ProposalState::Voting`
+    pub const VOTING: u8 = 2;
+
+    pub fn read(proposal: &AccountInfo) -> Result<Self> {
+        let header = Self::deserialize(&mut &proposal.try_borrow_data()?[..])
+            .map_err(|_| StakingError::InvalidProposal)?;
+        require!(
+            header.account_type == Self::ACCOUNT_TYPE,
+            StakingError::InvalidProposal
+        );
+        Ok(header)
+    }
+
+    pub fn is_voting(&self) -> bool {
+        self.state == Self::VOTING
+    }
+}
+
+/// A position counted towards a vote on `proposal`, at `[b"voteRecord", proposal, position]`.
+/// While it is open the position cannot move its stake.
+#[account]
+#[derive(InitSpace)]
+pub struct VoteRecord {
+    pub registrar: Pubkey,
+    pub proposal: Pubkey,
+    pub position: Pubkey,
+    /// Who cast the vote and gets the rent back
+    pub voter: Pubkey,
+    pub bump: u8,
+}
+
+/// Voter weight in the SPL Governance add-in layout, read by the governance
+/// program when it is configured to use this program as its voter weight addin.
+#[account]
+pub struct VoterWeightRecord {
+    pub realm: Pubkey,
+    pub governing_token_mint: Pubkey,
+    pub governing_token_owner: Pubkey,
+    pub voter_weight: u64,
+    pub voter_weight_expiry: Option<u64>,
+    pub weight_action: Option<VoterWeightAction>,
+    pub weight_action_target: Option<Pubkey>,
+    pub reserved: [u8; 8],
+}
+
+/// Max voter weight in the SPL Governance add-in layout.
+#[account]
+pub struct MaxVoterWeightRecord {
+    pub realm: Pubkey,
+    pub governing_token_mint: Pubkey,
+    pub max_voter_weight: u64,
+    pub max_voter_weight_expiry: Option<u64>,
+    pub reserved: [u8; 8],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoterWeightAction {
+    CastVote,
+    CommentProposal,
+    CreateGovernance,
+    CreateProposal,
+    SignOffProposal,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct TokenMintMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub decimals: u8,
+}
+
+#[event]
+pub struct VaultCreated {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct RewardMintCreated {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub params: PoolParams,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ParamsUpdated {
+    pub authority: Pubkey,
+    pub pool: Pubkey,
+    pub params: PoolParams,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionCreated {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub position_index: u32,
+    pub lock_tier: LockTier,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct Staked {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct RewardsMinted {
+    pub recipient: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ReferrerRecorded {
+    pub referee: Pubkey,
+    pub referrer: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub referrer: Pubkey,
+    pub referee: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VeLocked {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub locked_amount: u64,
+    pub lock_end: i64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VeUnlocked {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct RegistrarCreated {
+    pub registrar: Pubkey,
+    pub realm: Pubkey,
+    pub governing_token_mint: Pubkey,
+    pub pool: Pubkey,
+    pub lock_weighted: bool,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VoterWeightUpdated {
+    pub realm: Pubkey,
+    pub voter: Pubkey,
+    pub voter_weight: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VoteRelinquished {
+    pub voter: Pubkey,
+    pub position: Pubkey,
+    pub proposal: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct MaxVoterWeightUpdated {
+    pub realm: Pubkey,
+    pub max_voter_weight: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionTransferred {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub source: Pubkey,
+    pub position: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionMigrated {
+    pub owner: Pubkey,
+    pub legacy: Pubkey,
+    pub position: Pubkey,
+    pub position_index: u32,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionSplit {
+    pub owner: Pubkey,
+    pub source: Pubkey,
+    pub new_position: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PositionsMerged {
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    pub staked_amount: u64,
+    pub total_points: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreasuryInitialized {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub token_account: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct FeeCollected {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct SurplusSwept {
+    pub authority: Pubkey,
+    pub vault: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub total_staked: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VestingStarted {
+    pub beneficiary: Pubkey,
+    pub position: Pubkey,
+    pub escrow: Pubkey,
+    pub amount: u64,
+    pub cliff_end: i64,
+    pub end: i64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VestedWithdrawn {
+    pub beneficiary: Pubkey,
+    pub position: Pubkey,
+    pub escrow: Pubkey,
+    pub amount: u64,
+    pub withdrawn: u64,
+    pub total: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct VestingExited {
+    pub beneficiary: Pubkey,
+    pub position: Pubkey,
+    pub escrow: Pubkey,
+    pub amount: u64,
+    pub forfeited: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct DistributorCreated {
+    pub authority: Pubkey,
+    pub distributor: Pubkey,
+    pub mint: Pubkey,
+    pub root: [u8; 32],
+    pub total_amount: u64,
+    pub num_recipients: u32,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct AirdropClaimed {
+    pub distributor: Pubkey,
+    pub claimant: Pubkey,
+    pub index: u32,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[error_code]
+pub enum StakingError {
+    #[msg("Amount must be greater than 0")]
+    InvalidAmount,
+
+    #[msg("Invalid Balance")]
+    InvalidBalance,
+
+    #[msg("Cannot Unstake before the position's lock period has ended")]
+    InvalidUnstake,
+
+    #[msg("Insufficient Points")]
+    InvalidPoints,
+
+    #[msg("Max boost must be at least 1x (10000 bps)")]
+    InvalidBoost,
+
+    #[msg("Lock duration must be between 1 second and 4 years")]
+    InvalidLockDuration,
+
+    #[msg("Cannot Unlock before the lock has expired")]
+    LockNotExpired,
+
+    #[msg("Invalid Referrer")]
+    InvalidReferrer,
+
+    #[msg("Referral rate cannot exceed 100% (10000 bps)")]
+    InvalidReferralRate,
+
+    #[msg("New owner must be a different wallet")]
+    InvalidNewOwner,
+
+    #[msg("Positions must be distinct and share lock tier and referrer")]
+    PositionMismatch,
+
+    #[msg("Signer does not hold this position")]
+    NotPositionHolder,
+
+    #[msg("Tokenized positions are transferred by sending the position NFT")]
+    PositionTokenized,
+
+    #[msg("Fee rate cannot exceed 100% (10000 bps)")]
+    InvalidFeeRate,
+
+    #[msg("Vesting period must be at most 4 years, with a cliff no longer than the period")]
+    InvalidVesting,
+
+    #[msg("A vesting escrow is required when, and only when, the pool vests claims")]
+    VestingEscrowMismatch,
+
+    #[msg("Nothing has vested since the last withdrawal")]
+    NothingVested,
+
+    #[msg("This escrow cannot be exited before it has vested")]
+    EarlyExitDisabled,
+
+    #[msg("Signer is not the escrow's beneficiary")]
+    NotBeneficiary,
+
+    #[msg("An airdrop needs tokens and between 1 and 80000 recipients")]
+    InvalidAirdrop,
+
+    #[msg("Claim does not match the distributor's Merkle root")]
+    InvalidProof,
+
+    #[msg("This airdrop claim has already been paid out")]
+    AlreadyClaimed,
+
+    #[msg("Position is staked in a different vault")]
+    VaultMismatch,
+
+    #[msg("Realm is not an SPL Governance realm with this governing token mint")]
+    InvalidRealm,
+
+    #[msg("Signer is not the realm's authority")]
+    NotRealmAuthority,
+
+    #[msg("Position is already on the current layout")]
+    AlreadyMigrated,
+
+    #[msg("Binding legacy stake to a vault needs its authority's signature")]
+    VaultAuthorityRequired,
+
+    #[msg("Vault holds nothing beyond what it owes")]
+    NoSurplus,
+
+    #[msg("Stake is below the pool's minimum")]
+    BelowMinimumStake,
+
+    #[msg("Stake would exceed the pool's per-wallet cap")]
+    WalletCapExceeded,
+
+    #[msg("Stake would exceed the pool's total cap")]
+    PoolCapExceeded,
+
+    #[msg("Per-wallet cap cannot be below the minimum stake")]
+    InvalidStakeLimits,
+
+    #[msg("Moving stake between owners needs both of their staker accounts")]
+    StakerRequired,
+
+    #[msg("Claims that pay a fee need the pool's treasury")]
+    TreasuryRequired,
+
+    #[msg("Only claimed reward tokens can be locked")]
+    NotClaimed,
+
+    #[msg("Not a proposal of the realm being voted on")]
+    InvalidProposal,
+
+    #[msg("Vote record does not match the position and proposal")]
+    InvalidVoteRecord,
+
+    #[msg("The position already voted on this proposal")]
+    AlreadyVoted,
 
-    #[msg("Cannot Unstake before 30 days of staking")]
-    InvalidUnstake
+    #[msg("The position counts towards a vote still in progress")]
+    PositionVoting,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
 }
\ No newline at end of file
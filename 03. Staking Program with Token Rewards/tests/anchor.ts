@@ -49,15 +49,48 @@ describe("Test", () => {
 
   const userPublicKey = new web3.PublicKey("HVw1Z2KFYfKjdL2UThi5RGBvSUpsF4zdsPrucV8TggQm");
 
+  // Positions are seeded by owner plus a little-endian u32 position index
+  const positionIndexSeed = (index: number) =>
+    new BN(index).toArrayLike(Buffer, "le", 4);
+
+  // Each position is represented by an NFT whose mint is derived from the position
+  const positionMintFor = (position: web3.PublicKey) =>
+    web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("positionMint"), position.toBuffer()],
+      program.programId
+    )[0];
+
+  // Pool PDA
+  const [pool] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("pool"), userPublicKey.toBuffer()],
+    program.programId
+  );
+
+  // Treasury PDA and the token account it collects claim fees in
+  const [treasury] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury"), userPublicKey.toBuffer()],
+    program.programId
+  );
+  const [treasuryTokenAccount] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("treasuryTokens"), userPublicKey.toBuffer()],
+    program.programId
+  );
+
+  // What the user has staked into the pool across all their positions
+  const [staker] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("staker"), userPublicKey.toBuffer(), userPublicKey.toBuffer()],
+    program.programId
+  );
+
   it("create a user pda account", async () => {
     const [pdaAccount, bump] = await web3.PublicKey.findProgramAddress(
-      [Buffer.from("client1"), userPublicKey.toBuffer()],
+      [Buffer.from("client1"), userPublicKey.toBuffer(), positionIndexSeed(0)],
       program.programId
     );
 
     // Send Transaction
     const txHash = await program.methods
-      .createPdaAccount()
+      .createPdaAccount(0, { days30: {} })
       .accounts({
         payer: userPublicKey,
         pdaAccount: pdaAccount,
@@ -128,6 +161,64 @@ describe("Test", () => {
       console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
   })
 
+  it("initializes the pool", async () => {
+    const txHash = await program.methods
+      .initializePool({
+        maxBoostBps: 20_000,
+        referralBps: 500,
+        feeBps: 100,
+        timeBase: { unixSeconds: {} },
+        // Claims are paid out liquid, a non-zero period would park them in vesting escrows
+        vesting: { period: new BN(0), cliff: new BN(0), earlyExit: false },
+        minStake: new BN(0),
+        maxStakePerWallet: null,
+        maxTotalStaked: null,
+      })
+      .accounts({
+        authority: userPublicKey,
+        pool,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([userKeypair])
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+
+    // Confirm transaction
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const poolAccount = await program.account.pool.fetch(pool);
+
+    // Assertions
+    assert.equal(poolAccount.maxBoostBps, 20_000);
+    assert.equal(poolAccount.referralBps, 500);
+    assert.equal(poolAccount.feeBps, 100);
+    assert.deepEqual(poolAccount.timeBase, { unixSeconds: {} });
+  });
+
+  it("initializes the treasury", async () => {
+    const txHash = await program.methods
+      .initializeTreasury()
+      .accounts({
+        authority: userPublicKey,
+        treasury,
+        treasuryTokenAccount,
+        mint,
+        systemProgram: web3.SystemProgram.programId,
+        tokenProgram: new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+      })
+      .signers([userKeypair])
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+
+    // Confirm transaction
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const treasuryAccount = await program.account.treasury.fetch(treasury);
+
+    // Assertions
+    assert.equal(treasuryAccount.totalFees.toNumber(), 0);
+  });
+
 
     it("stake amount", async () => {
     const [vaultPdaAccount, bump] = await web3.PublicKey.findProgramAddress(
@@ -136,7 +227,7 @@ describe("Test", () => {
     );
 
     const [pdaAccount, bump2] = await web3.PublicKey.findProgramAddress(
-      [Buffer.from("client1"), userPublicKey.toBuffer()],
+      [Buffer.from("client1"), userPublicKey.toBuffer(), positionIndexSeed(0)],
       program.programId
     );
 
@@ -149,12 +240,20 @@ describe("Test", () => {
 
     // Send the Transaction
     const txHash = await program.methods
-      .stake(amount)
+      .stake(amount, null)
       .accounts({
         user: userPublicKey,
         pdaAccount: pdaAccount,
         authorityVault: userPublicKey,
         pdaVaultAccount: vaultPdaAccount,
+        pool,
+        staker,
+        veLock: null,
+        positionMint: positionMintFor(pdaAccount),
+        positionTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(pdaAccount),
+          owner: userPublicKey,
+        }),
         mint,
         authority,
         destination,
@@ -180,6 +279,7 @@ describe("Test", () => {
     
     // Assertions
     assert.equal(userAccount.stakedAmount.toNumber(), 2000000000);
+    assert.ok(userAccount.positionMint.equals(positionMintFor(pdaAccount)));
   });
 
   it("Unstake amount", async () => {
@@ -189,7 +289,7 @@ describe("Test", () => {
     );
     
     const [pdaAccount, bump2] = await web3.PublicKey.findProgramAddress(
-      [Buffer.from("client1"), userPublicKey.toBuffer()],
+      [Buffer.from("client1"), userPublicKey.toBuffer(), positionIndexSeed(0)],
       program.programId
     );
 
@@ -211,6 +311,13 @@ describe("Test", () => {
         pdaAccount: pdaAccount,
         authority: userPublicKey,
         pdaVaultAccount: vaultPdaAccount,
+        pool,
+        staker,
+        veLock: null,
+        positionTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(pdaAccount),
+          owner: userPublicKey,
+        }),
         systemProgram: web3.SystemProgram.programId,
       })
       .signers([userKeypair])
@@ -229,4 +336,187 @@ describe("Test", () => {
     // Assertions
     assert.equal(userAccountUpdate.stakedAmount.toNumber(), 1000000000);
   });
-});
\ No newline at end of file
+
+  it("claims the accrued rewards", async () => {
+    const [pdaAccount, bump] = await web3.PublicKey.findProgramAddress(
+      [Buffer.from("client1"), userPublicKey.toBuffer(), positionIndexSeed(0)],
+      program.programId
+    );
+
+    const destination = await anchor.utils.token.associatedAddress({
+      mint: mint,
+      owner: userPublicKey,
+    });
+
+    // Claimed rewards are credited to the ve lock, which caps what may be locked
+    const [veLock] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("veLock"), userPublicKey.toBuffer()],
+      program.programId
+    );
+
+    // Send the transaction
+    const txHash = await program.methods
+      .claimRewards()
+      .accounts({
+        user: userPublicKey,
+        pdaAccount: pdaAccount,
+        authorityVault: userPublicKey,
+        pool,
+        staker,
+        treasury,
+        treasuryTokenAccount,
+        veLock,
+        positionTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(pdaAccount),
+          owner: userPublicKey,
+        }),
+        mint,
+        authority,
+        destination,
+        vestingEscrow: null,
+        systemProgram: web3.SystemProgram.programId,
+        tokenProgram: new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .signers([userKeypair])
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+
+    // Confirm the transaction
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const account = await program.account.stakeAccount.fetch(pdaAccount);
+
+    // Assertions
+    assert.equal(account.totalPoints.toNumber(), 0);
+  });
+
+  it("locks reward tokens for a ve boost", async () => {
+    const [veLock] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("veLock"), userPublicKey.toBuffer()],
+      program.programId
+    );
+
+    const [veVault] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("veVault")],
+      program.programId
+    );
+
+    const userTokenAccount = await anchor.utils.token.associatedAddress({
+      mint: mint,
+      owner: userPublicKey,
+    });
+
+    // Only the reward tokens claimed so far may be locked
+    const amount = (await program.account.veLock.fetch(veLock)).claimed;
+    const lockDuration = new BN(365 * 86400);
+
+    // Send the transaction
+    const txHash = await program.methods
+      .lockRewardTokens(amount, lockDuration)
+      .accounts({
+        user: userPublicKey,
+        veLock,
+        veVault,
+        mint,
+        authority,
+        userTokenAccount,
+        systemProgram: web3.SystemProgram.programId,
+        tokenProgram: new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+      })
+      .signers([userKeypair])
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+
+    // Confirm the transaction
+    await program.provider.connection.confirmTransaction(txHash);
+
+    const lockAccount = await program.account.veLock.fetch(veLock);
+
+    // Assertions
+    assert.equal(lockAccount.lockedAmount.toNumber(), amount.toNumber());
+  });
+
+  it("splits off and merges back a position", async () => {
+    const [pdaAccount] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("client1"), userPublicKey.toBuffer(), positionIndexSeed(0)],
+      program.programId
+    );
+    const [newPdaAccount] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("client1"), userPublicKey.toBuffer(), positionIndexSeed(1)],
+      program.programId
+    );
+
+    const before = await program.account.stakeAccount.fetch(pdaAccount);
+
+    // Send the split transaction
+    const splitTxHash = await program.methods
+      .splitPosition(new BN(500_000_000), 1)
+      .accounts({
+        user: userPublicKey,
+        pdaAccount,
+        newPdaAccount,
+        authorityVault: userPublicKey,
+        pool,
+        staker,
+        veLock: null,
+        positionTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(pdaAccount),
+          owner: userPublicKey,
+        }),
+        newPositionMint: positionMintFor(newPdaAccount),
+        newPositionTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(newPdaAccount),
+          owner: userPublicKey,
+        }),
+        authority,
+        tokenProgram: new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .signers([userKeypair])
+      .rpc();
+    console.log(`Use 'solana confirm -v ${splitTxHash}' to see the logs`);
+    await program.provider.connection.confirmTransaction(splitTxHash);
+
+    const split = await program.account.stakeAccount.fetch(newPdaAccount);
+    assert.equal(split.stakedAmount.toNumber(), 500000000);
+
+    // Send the merge transaction
+    const mergeTxHash = await program.methods
+      .mergePositions()
+      .accounts({
+        user: userPublicKey,
+        source: newPdaAccount,
+        destination: pdaAccount,
+        authorityVault: userPublicKey,
+        pool,
+        sourceStaker: staker,
+        destinationStaker: null,
+        veLock: null,
+        sourceTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(newPdaAccount),
+          owner: userPublicKey,
+        }),
+        destinationTokenAccount: await anchor.utils.token.associatedAddress({
+          mint: positionMintFor(pdaAccount),
+          owner: userPublicKey,
+        }),
+        sourcePositionMint: positionMintFor(newPdaAccount),
+        systemProgram: web3.SystemProgram.programId,
+        tokenProgram: new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+      })
+      .signers([userKeypair])
+      .rpc();
+    console.log(`Use 'solana confirm -v ${mergeTxHash}' to see the logs`);
+    await program.provider.connection.confirmTransaction(mergeTxHash);
+
+    const merged = await program.account.stakeAccount.fetch(pdaAccount);
+
+    // Assertions
+    assert.equal(
+      merged.stakedAmount.toNumber(),
+      before.stakedAmount.toNumber()
+    );
+  });
+});
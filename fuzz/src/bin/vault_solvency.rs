@@ -0,0 +1,12 @@
+//! Random `create_pda_account` / `stake` / `unstake` / `claim_points` sequences across several
+//! users and vaults, see `program_tests::fuzz` for the invariants checked.
+
+use honggfuzz::fuzz;
+
+fn main() {
+    loop {
+        fuzz!(|data: &[u8]| {
+            program_tests::fuzz::run(data);
+        });
+    }
+}
@@ -0,0 +1,376 @@
+//! Random action sequences against the rewards program, checking the vault invariants after
+//! every transaction. Shared by the `vault_solvency` honggfuzz target and the seeded run in
+//! `tests/vault_solvency.rs`.
+//!
+//! The invariants are:
+//!
+//! - every vault holds at least its rent plus the `staked_amount` of the positions bound to it
+//! - every pool's `total_staked` is the sum of the `staked_amount` of those positions, so
+//!   `sweep_surplus` can never take principal
+//! - no pool holds more than its cap, and no position more than the per-wallet cap
+//! - every leaderboard is sorted and agrees with the season points of the positions on it
+//! - lamports are conserved across the wallets, vaults, pools and positions, so no balance
+//!   went negative and nothing was minted
+//! - every position's `staked_amount` matches the stakes and unstakes that succeeded
+//! - a position's points never decrease, except to zero when its owner claims them
+
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use arbitrary::{Arbitrary, Unstructured};
+use solana_system_interface::instruction as system_instruction;
+use staking_client::rewards::{
+    self, pda, Leaderboard, PointsDecay, Pool, PoolParams, StakeAccount, TimeBase,
+};
+use staking_client::Pubkey;
+
+use crate::Svm;
+
+/// Longest action sequence run from a single input
+pub const MAX_ACTIONS: usize = 64;
+
+const MAX_USERS: u8 = 4;
+const MAX_VAULTS: u8 = 3;
+
+/// Stake limits of every pool, low enough for a few stakes to run into them
+const MIN_STAKE: u64 = 1_000;
+const MAX_STAKE_PER_WALLET: u64 = 6 * LAMPORTS_PER_SOL;
+const MAX_TOTAL_STAKED: u64 = 10 * LAMPORTS_PER_SOL;
+
+#[derive(Arbitrary, Clone, Debug)]
+pub enum Action {
+    CreatePosition {
+        user: u8,
+    },
+    Stake {
+        user: u8,
+        vault: u8,
+        amount: Amount,
+        referrer: Option<u8>,
+    },
+    Unstake {
+        user: u8,
+        vault: u8,
+        amount: Amount,
+    },
+    ClaimPoints {
+        user: u8,
+        vault: u8,
+    },
+    Warp {
+        seconds: u16,
+    },
+    /// A user sends lamports straight to a vault
+    Donate {
+        user: u8,
+        vault: u8,
+        lamports: u32,
+    },
+    SweepSurplus {
+        vault: u8,
+    },
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Amount {
+    /// Up to 4 SOL
+    Lamports(u64),
+    /// The position's whole stake less a few lamports, to probe the balance checks
+    AllBut(u8),
+}
+
+impl Amount {
+    fn resolve(self, staked: u64) -> u64 {
+        match self {
+            Amount::Lamports(lamports) => lamports % (4 * LAMPORTS_PER_SOL + 1),
+            Amount::AllBut(less) => staked.saturating_sub(less.into()),
+        }
+    }
+}
+
+pub struct Scenario {
+    svm: Svm,
+    users: Vec<Pubkey>,
+    authorities: Vec<Pubkey>,
+    /// `staked_amount` per user, from the transactions that succeeded
+    staked: Vec<u64>,
+    /// `total_points` per user after the last transaction
+    points: Vec<u64>,
+    total_lamports: u64,
+}
+
+impl Scenario {
+    /// `users` wallets without positions, and `vaults` authorities with a vault and a pool
+    pub fn new(users: usize, vaults: usize) -> Self {
+        let mut svm = Svm::new();
+        let users: Vec<Pubkey> = (0..users).map(|_| svm.new_wallet()).collect();
+        let authorities: Vec<Pubkey> = (0..vaults).map(|_| svm.new_wallet()).collect();
+
+        for authority in &authorities {
+            svm.process(
+                &[
+                    rewards::create_vault_pda_account(authority),
+                    rewards::initialize_pool(
+                        authority,
+                        PoolParams {
+                            referral_bps: 1_000,
+                            fee_bps: 500,
+                            time_base: TimeBase::UnixSeconds,
+                            min_stake: MIN_STAKE,
+                            max_stake_per_wallet: Some(MAX_STAKE_PER_WALLET),
+                            max_total_staked: Some(MAX_TOTAL_STAKED),
+                            decay: PointsDecay::None,
+                            streak_tiers: Default::default(),
+                        },
+                    ),
+                    rewards::initialize_treasury(authority),
+                    rewards::initialize_leaderboard(authority),
+                ],
+                &[*authority],
+            )
+            .unwrap();
+        }
+
+        let mut scenario = Self {
+            svm,
+            staked: vec![0; users.len()],
+            points: vec![0; users.len()],
+            users,
+            authorities,
+            total_lamports: 0,
+        };
+        scenario.total_lamports = scenario.tracked_lamports();
+        scenario
+    }
+
+    /// Runs `action` as one transaction, then checks every invariant
+    pub fn apply(&mut self, action: &Action) {
+        let mut claimed = None;
+
+        match *action {
+            Action::CreatePosition { user } => {
+                let user = self.user(user);
+                let _ = self
+                    .svm
+                    .process(&[rewards::create_pda_account(&user)], &[user]);
+            }
+            Action::Stake {
+                user,
+                vault,
+                amount,
+                referrer,
+            } => {
+                let index = self.user_index(user);
+                let (user, authority) = (self.users[index], self.authority(vault));
+                let amount = amount.resolve(self.staked[index]);
+                let referrer = referrer.map(|referrer| self.user(referrer));
+                if self
+                    .svm
+                    .process(
+                        &[rewards::stake(&user, &authority, amount, referrer)],
+                        &[user],
+                    )
+                    .is_ok()
+                {
+                    self.staked[index] += amount;
+                }
+            }
+            Action::Unstake {
+                user,
+                vault,
+                amount,
+            } => {
+                let index = self.user_index(user);
+                let (user, authority) = (self.users[index], self.authority(vault));
+                let amount = amount.resolve(self.staked[index]);
+                if self
+                    .svm
+                    .process(&[rewards::unstake(&user, &authority, amount)], &[user])
+                    .is_ok()
+                {
+                    self.staked[index] -= amount;
+                }
+            }
+            Action::ClaimPoints { user, vault } => {
+                let index = self.user_index(user);
+                let (user, authority) = (self.users[index], self.authority(vault));
+                if self
+                    .svm
+                    .process(&[rewards::claim_points(&user, &authority)], &[user])
+                    .is_ok()
+                {
+                    claimed = Some(index);
+                }
+            }
+            Action::Warp { seconds } => self.svm.warp_by(seconds.into()),
+            Action::Donate {
+                user,
+                vault,
+                lamports,
+            } => {
+                let (user, authority) = (self.user(user), self.authority(vault));
+                let _ = self.svm.process(
+                    &[system_instruction::transfer(
+                        &user,
+                        &pda::vault(&authority).0,
+                        lamports.into(),
+                    )],
+                    &[user],
+                );
+            }
+            Action::SweepSurplus { vault } => {
+                let authority = self.authority(vault);
+                let _ = self
+                    .svm
+                    .process(&[rewards::sweep_surplus(&authority)], &[authority]);
+            }
+        }
+
+        self.check_invariants(action, claimed);
+    }
+
+    fn check_invariants(&mut self, action: &Action, claimed: Option<usize>) {
+        assert_eq!(
+            self.tracked_lamports(),
+            self.total_lamports,
+            "lamports not conserved after {action:?}"
+        );
+
+        let positions: Vec<Option<StakeAccount>> =
+            self.users.iter().map(|user| self.position(user)).collect();
+
+        for (index, position) in positions.iter().enumerate() {
+            let (staked, points) = position.as_ref().map_or((0, 0), |position| {
+                (position.staked_amount, position.total_points)
+            });
+            assert_eq!(
+                staked, self.staked[index],
+                "user {index} staked_amount diverged after {action:?}"
+            );
+            assert!(
+                staked <= MAX_STAKE_PER_WALLET,
+                "user {index} holds {staked} over the per-wallet cap after {action:?}"
+            );
+            if claimed == Some(index) {
+                assert_eq!(points, 0, "user {index} kept points after claiming");
+            } else {
+                assert!(
+                    points >= self.points[index],
+                    "user {index} points fell from {} to {points} after {action:?}",
+                    self.points[index]
+                );
+            }
+            self.points[index] = points;
+        }
+
+        let rent = self.svm.rent().minimum_balance(0);
+        for authority in &self.authorities {
+            let vault = pda::vault(authority).0;
+            let owed: u64 = positions
+                .iter()
+                .flatten()
+                .filter(|position| position.vault == vault)
+                .map(|position| position.staked_amount)
+                .sum();
+            let pool: Pool = self.svm.get(&pda::pool(authority).0);
+            assert_eq!(
+                pool.total_staked, owed,
+                "pool of vault {vault} tracks {} staked but its positions hold {owed} after {action:?}",
+                pool.total_staked
+            );
+
+            assert!(
+                pool.total_staked <= MAX_TOTAL_STAKED,
+                "pool of vault {vault} holds {} over its cap after {action:?}",
+                pool.total_staked
+            );
+
+            let leaderboard: Leaderboard = self.svm.get(&pda::leaderboard(authority).0);
+            let ranked = leaderboard.ranked();
+            assert!(
+                ranked
+                    .windows(2)
+                    .all(|pair| pair[0].points >= pair[1].points),
+                "leaderboard of vault {vault} out of order after {action:?}"
+            );
+            for entry in ranked {
+                let season_points = self
+                    .users
+                    .iter()
+                    .zip(&positions)
+                    .find(|(user, _)| pda::stake_account(user).0 == entry.position)
+                    .and_then(|(_, position)| position.as_ref())
+                    .map(|position| position.season_points);
+                assert_eq!(
+                    season_points,
+                    Some(entry.points),
+                    "leaderboard of vault {vault} disagrees with {} after {action:?}",
+                    entry.position
+                );
+            }
+
+            let held = self.svm.lamports(&vault);
+            assert!(
+                held >= rent + owed,
+                "vault {vault} holds {held} but owes {owed} plus {rent} rent after {action:?}"
+            );
+        }
+    }
+
+    fn tracked_lamports(&self) -> u64 {
+        let wallets = self.users.iter().chain(&self.authorities).copied();
+        let program_accounts = self
+            .users
+            .iter()
+            .map(|user| pda::stake_account(user).0)
+            .chain(self.authorities.iter().flat_map(|authority| {
+                [
+                    pda::vault(authority).0,
+                    pda::pool(authority).0,
+                    pda::treasury(authority).0,
+                ]
+            }));
+        wallets
+            .chain(program_accounts)
+            .map(|address| self.svm.lamports(&address))
+            .sum()
+    }
+
+    fn position(&self, user: &Pubkey) -> Option<StakeAccount> {
+        let address = pda::stake_account(user).0;
+        self.svm.account(&address).map(|_| self.svm.get(&address))
+    }
+
+    fn user_index(&self, user: u8) -> usize {
+        user as usize % self.users.len()
+    }
+
+    fn user(&self, user: u8) -> Pubkey {
+        self.users[self.user_index(user)]
+    }
+
+    fn authority(&self, vault: u8) -> Pubkey {
+        self.authorities[vault as usize % self.authorities.len()]
+    }
+}
+
+/// Decodes `data` into a scenario size and up to `MAX_ACTIONS` actions and runs them,
+/// panicking on the first broken invariant
+pub fn run(data: &[u8]) {
+    let mut input = Unstructured::new(data);
+    let (Ok(users), Ok(vaults)) = (
+        input.int_in_range(1..=MAX_USERS),
+        input.int_in_range(1..=MAX_VAULTS),
+    ) else {
+        return;
+    };
+
+    let mut scenario = Scenario::new(users.into(), vaults.into());
+    for _ in 0..MAX_ACTIONS {
+        let Ok(action) = Action::arbitrary(&mut input) else {
+            return;
+        };
+        scenario.apply(&action);
+        if input.is_empty() {
+            return;
+        }
+    }
+}
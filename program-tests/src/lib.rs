@@ -0,0 +1,536 @@
+//! LiteSVM test harness for the staking programs.
+//!
+//! `Svm` wraps a `LiteSVM` bank with the real SPL Token and associated token account programs
+//! and the three staking programs. Each staking program loads from its SBF build when one
+//! exists (`SBF_OUT_DIR`, `BPF_OUT_DIR` or the Anchor workspace's `target/deploy`). Otherwise
+//! it runs natively through `native`. Metaplex token metadata is always the stand-in in
+//! `metadata`.
+//!
+//! Transactions are signed by keypairs the harness keeps for its wallets, and a separate
+//! harness payer covers the fees, so wallet balances only move by what the programs do. An
+//! account an instruction marks as a signer but `process` is not given as one goes in
+//! unsigned, so the program's own signer checks decide. The `Clock` sysvar is set by the
+//! harness, so tests can warp time.
+
+pub mod fuzz;
+mod metadata;
+mod native;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use anchor_lang::solana_program::account_info::AccountInfo;
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::{Instruction, InstructionError};
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::rent::Rent;
+use anchor_lang::{system_program, AccountDeserialize, AccountSerialize, Event};
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::spl_token;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use litesvm::LiteSVM;
+use solana_account::{AccountSharedData, ReadableAccount};
+use solana_keypair::Keypair;
+use solana_message::Message;
+use solana_program_runtime::invoke_context::BuiltinFunctionWithContext;
+use solana_sdk_ids::native_loader;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+
+use native::builtin;
+
+/// Entrypoint of a natively compiled program
+pub type Processor = for<'info> fn(&Pubkey, &'info [AccountInfo<'info>], &[u8]) -> ProgramResult;
+
+/// Unix timestamp the harness clock starts at
+pub const GENESIS_UNIX_TIMESTAMP: i64 = 1_700_000_000;
+
+/// Lamports every new test wallet is funded with
+pub const WALLET_LAMPORTS: u64 = 100_000_000_000;
+
+/// Lamports of the harness payer, which only pays transaction fees
+const PAYER_LAMPORTS: u64 = 1_000_000 * LAMPORTS_PER_SOL;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+impl From<solana_account::Account> for Account {
+    fn from(account: solana_account::Account) -> Self {
+        Self {
+            lamports: account.lamports,
+            data: account.data,
+            owner: account.owner,
+            executable: account.executable,
+        }
+    }
+}
+
+impl From<Account> for solana_account::Account {
+    fn from(account: Account) -> Self {
+        Self {
+            lamports: account.lamports,
+            data: account.data,
+            owner: account.owner,
+            executable: account.executable,
+            rent_epoch: u64::MAX,
+        }
+    }
+}
+
+/// Why a transaction failed, the subset of the runtime's `TransactionError` tests match on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    /// The instruction at this index failed
+    InstructionError(usize, InstructionError),
+    /// The transaction would leave this account funded but below the rent-exempt minimum
+    InsufficientFundsForRent { account: Pubkey },
+    /// Rejected by the runtime before or around execution
+    Rejected(solana_transaction_error::TransactionError),
+}
+
+impl TransactionError {
+    fn new(error: solana_transaction_error::TransactionError, message: &Message) -> Self {
+        use solana_transaction_error::TransactionError as Error;
+        match error {
+            Error::InstructionError(index, error) => Self::InstructionError(index.into(), error),
+            Error::InsufficientFundsForRent { account_index } => Self::InsufficientFundsForRent {
+                account: message.account_keys[usize::from(account_index)],
+            },
+            error => Self::Rejected(error),
+        }
+    }
+
+    /// Custom error code, which is how Anchor and `StakingError` failures surface
+    pub fn custom_code(&self) -> Option<u32> {
+        self.instruction_error()
+            .and_then(staking_client::custom_error_code)
+    }
+
+    /// The failed instruction's error
+    pub fn instruction_error(&self) -> Option<&InstructionError> {
+        match self {
+            Self::InstructionError(_, error) => Some(error),
+            Self::InsufficientFundsForRent { .. } | Self::Rejected(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InstructionError(index, error) => {
+                write!(f, "instruction {index} failed: {error}")
+            }
+            Self::InsufficientFundsForRent { account } => {
+                write!(
+                    f,
+                    "account {account} would be left below the rent-exempt minimum"
+                )
+            }
+            Self::Rejected(error) => write!(f, "transaction rejected: {error}"),
+        }
+    }
+}
+
+/// A staking program and where its SBF build lives
+struct Program {
+    id: Pubkey,
+    name: &'static str,
+    workspace: &'static str,
+    entrypoint: BuiltinFunctionWithContext,
+}
+
+/// The SBF build of `name`, when there is one
+fn sbf_build(name: &str, workspace: &str) -> Option<PathBuf> {
+    let file = format!("{name}.so");
+    ["SBF_OUT_DIR", "BPF_OUT_DIR"]
+        .iter()
+        .filter_map(|var| std::env::var_os(var).map(PathBuf::from))
+        .chain([PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(workspace)
+            .join("target/deploy")])
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
+
+pub struct Svm {
+    bank: LiteSVM,
+    payer: Keypair,
+    keypairs: HashMap<Pubkey, Keypair>,
+    clock: Clock,
+    rent: Rent,
+    logs: Vec<String>,
+    return_data: Option<(Pubkey, Vec<u8>)>,
+}
+
+impl Default for Svm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Svm {
+    /// A fresh bank with the three staking programs, SPL Token, the associated token account
+    /// program and Metaplex token metadata loaded
+    pub fn new() -> Self {
+        native::install_stubs();
+
+        let mut svm = Self {
+            bank: LiteSVM::new()
+                .with_sigverify(true)
+                .with_transaction_history(0)
+                .with_log_bytes_limit(None),
+            payer: Keypair::new(),
+            keypairs: HashMap::new(),
+            clock: Clock {
+                slot: 1,
+                epoch_start_timestamp: GENESIS_UNIX_TIMESTAMP,
+                epoch: 0,
+                leader_schedule_epoch: 0,
+                unix_timestamp: GENESIS_UNIX_TIMESTAMP,
+            },
+            rent: Rent::default(),
+            logs: Vec::new(),
+            return_data: None,
+        };
+        svm.rent = svm.bank.get_sysvar();
+        svm.bank.set_sysvar(&svm.clock);
+        svm.airdrop(&svm.payer.pubkey(), PAYER_LAMPORTS);
+
+        let programs = [
+            Program {
+                id: basic_staking_program::ID,
+                name: "basic_staking_program",
+                workspace: "01. Basic Staking Program",
+                entrypoint: builtin!(basic_staking_program::entry),
+            },
+            Program {
+                id: staking_program_with_rewards::ID,
+                name: "staking_program_with_rewards",
+                workspace: "02. Staking Program with Rewards",
+                entrypoint: builtin!(staking_program_with_rewards::entry),
+            },
+            Program {
+                id: stake_with_token_reward::ID,
+                name: "stake_with_token_reward",
+                workspace: "03. Staking Program with Token Rewards",
+                entrypoint: builtin!(stake_with_token_reward::entry),
+            },
+        ];
+        for program in programs {
+            match sbf_build(program.name, program.workspace) {
+                Some(path) => svm
+                    .bank
+                    .add_program_from_file(program.id, &path)
+                    .unwrap_or_else(|err| panic!("{} failed to load: {err}", path.display())),
+                None => svm.add_builtin(program.id, program.entrypoint),
+            }
+        }
+        svm.add_builtin(
+            anchor_spl::metadata::ID,
+            builtin!(metadata::process_instruction),
+        );
+        svm
+    }
+
+    /// Registers a native program. The runtime only dispatches to builtins owned by the native
+    /// loader, where `LiteSVM::add_builtin` leaves them owned by the BPF loader.
+    fn add_builtin(&mut self, program_id: Pubkey, entrypoint: BuiltinFunctionWithContext) {
+        self.bank.add_builtin(program_id, entrypoint);
+        self.set_account(
+            program_id,
+            Account {
+                lamports: 1,
+                data: Vec::new(),
+                owner: native_loader::ID,
+                executable: true,
+            },
+        );
+    }
+
+    /// A new address `process` can sign for, with no account behind it yet
+    pub fn new_keypair(&mut self) -> Pubkey {
+        let keypair = Keypair::new();
+        let address = keypair.pubkey();
+        self.keypairs.insert(address, keypair);
+        address
+    }
+
+    /// A new system-owned wallet funded with `WALLET_LAMPORTS`, which `process` can sign for
+    pub fn new_wallet(&mut self) -> Pubkey {
+        let wallet = self.new_keypair();
+        self.airdrop(&wallet, WALLET_LAMPORTS);
+        wallet
+    }
+
+    pub fn airdrop(&mut self, address: &Pubkey, lamports: u64) {
+        let mut account = self.account(address).unwrap_or(Account {
+            owner: system_program::ID,
+            ..Account::default()
+        });
+        account.lamports += lamports;
+        self.set_account(*address, account);
+    }
+
+    pub fn set_account(&mut self, address: Pubkey, account: Account) {
+        self.bank
+            .set_account(address, account.into())
+            .unwrap_or_else(|err| panic!("account {address} could not be set: {err:?}"));
+    }
+
+    pub fn account(&self, address: &Pubkey) -> Option<Account> {
+        self.bank.get_account(address).map(Account::from)
+    }
+
+    pub fn lamports(&self, address: &Pubkey) -> u64 {
+        self.bank.get_balance(address).unwrap_or(0)
+    }
+
+    /// Deserializes an Anchor account, panicking when it is missing or of another type
+    pub fn get<T: AccountDeserialize>(&self, address: &Pubkey) -> T {
+        let account = self
+            .account(address)
+            .unwrap_or_else(|| panic!("account {address} does not exist"));
+        T::try_deserialize(&mut account.data.as_slice())
+            .unwrap_or_else(|err| panic!("account {address} failed to deserialize: {err}"))
+    }
+
+    /// Unpacks an SPL token account, panicking when it is missing or not a token account
+    pub fn token_account(&self, address: &Pubkey) -> spl_token::state::Account {
+        let account = self
+            .account(address)
+            .unwrap_or_else(|| panic!("token account {address} does not exist"));
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap_or_else(|err| panic!("token account {address} failed to unpack: {err}"))
+    }
+
+    /// Token balance of `address`, zero when the account does not exist
+    pub fn token_balance(&self, address: &Pubkey) -> u64 {
+        self.account(address)
+            .map_or(0, |_| self.token_account(address).amount)
+    }
+
+    /// Unpacks an SPL mint, panicking when it is missing or not a mint
+    pub fn mint(&self, address: &Pubkey) -> spl_token::state::Mint {
+        let account = self
+            .account(address)
+            .unwrap_or_else(|| panic!("mint {address} does not exist"));
+        spl_token::state::Mint::unpack(&account.data)
+            .unwrap_or_else(|err| panic!("mint {address} failed to unpack: {err}"))
+    }
+
+    /// Credits `amount` of `mint` to `owner`'s associated token account by writing the
+    /// accounts directly, for mints only a program can mint from. Returns the token account.
+    pub fn mint_tokens(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let address = get_associated_token_address(owner, mint);
+        let mut token_account = match self.account(&address) {
+            Some(_) => self.token_account(&address),
+            None => spl_token::state::Account {
+                mint: *mint,
+                owner: *owner,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            },
+        };
+        token_account.amount += amount;
+        self.write_packed(address, token_account);
+
+        let mut mint_state = self.mint(mint);
+        mint_state.supply += amount;
+        self.write_packed(*mint, mint_state);
+        address
+    }
+
+    /// Gives `owner` `amount` reward tokens of the token rewards program as if they had
+    /// claimed them, so their ve lock lets them be locked. Returns the token account.
+    pub fn claimed_reward_tokens(&mut self, owner: &Pubkey, amount: u64) -> Pubkey {
+        use staking_client::token_rewards::{self, pda, VeLock};
+
+        let tokens = self.mint_tokens(&pda::mint().0, owner, amount);
+        let (address, bump) = pda::ve_lock(owner);
+        let mut lock = match self.account(&address) {
+            Some(_) => self.get::<VeLock>(&address),
+            None => VeLock {
+                owner: *owner,
+                locked_amount: 0,
+                lock_end: 0,
+                claimed: 0,
+                bump,
+            },
+        };
+        lock.claimed += amount;
+
+        let mut data = Vec::new();
+        lock.try_serialize(&mut data).unwrap();
+        self.set_account(
+            address,
+            Account {
+                lamports: self.rent.minimum_balance(data.len()),
+                data,
+                owner: token_rewards::ID,
+                executable: false,
+            },
+        );
+        tokens
+    }
+
+    fn write_packed<T: Pack>(&mut self, address: Pubkey, state: T) {
+        let mut data = vec![0; T::LEN];
+        T::pack(state, &mut data).unwrap();
+        self.set_account(
+            address,
+            Account {
+                lamports: self.rent.minimum_balance(T::LEN),
+                data,
+                owner: spl_token::ID,
+                executable: false,
+            },
+        );
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn rent(&self) -> &Rent {
+        &self.rent
+    }
+
+    /// Moves the clock forward by `seconds`, advancing the slot along with it
+    pub fn warp_by(&mut self, seconds: i64) {
+        self.clock.unix_timestamp += seconds;
+        self.clock.slot += (seconds.max(0) as u64).max(1);
+        self.bank.set_sysvar(&self.clock);
+    }
+
+    /// Sets the clock to `unix_timestamp`
+    pub fn warp_to(&mut self, unix_timestamp: i64) {
+        self.warp_by(unix_timestamp - self.clock.unix_timestamp);
+    }
+
+    /// Logs of the last transaction, including `Program data:` lines for events
+    pub fn logs(&self) -> &[String] {
+        &self.logs
+    }
+
+    /// Anchor events of type `E` emitted by the last transaction, in order
+    pub fn events<E: Event>(&self) -> Vec<E> {
+        self.logs
+            .iter()
+            .filter_map(|log| log.strip_prefix("Program data: "))
+            .filter_map(|data| STANDARD.decode(data).ok())
+            .filter_map(|data| {
+                let mut event = data.strip_prefix(E::DISCRIMINATOR)?;
+                E::deserialize(&mut event).ok()
+            })
+            .collect()
+    }
+
+    /// Program and data of the last `sol_set_return_data` in the last transaction
+    pub fn return_data(&self) -> Option<&(Pubkey, Vec<u8>)> {
+        self.return_data.as_ref()
+    }
+
+    /// Runs the instructions as one atomic transaction signed by `signers`
+    pub fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[Pubkey],
+    ) -> Result<(), TransactionError> {
+        // Without a signature the account goes in unsigned, as a wallet would have to send it
+        let instructions: Vec<Instruction> = instructions
+            .iter()
+            .cloned()
+            .map(|mut instruction| {
+                for meta in &mut instruction.accounts {
+                    meta.is_signer &= signers.contains(&meta.pubkey);
+                }
+                instruction
+            })
+            .collect();
+
+        let blockhash = self.bank.latest_blockhash();
+        let message =
+            Message::new_with_blockhash(&instructions, Some(&self.payer.pubkey()), &blockhash);
+        let required = &message.account_keys[..message.header.num_required_signatures.into()];
+        let keypairs: Vec<&Keypair> = required
+            .iter()
+            .map(|signer| {
+                if *signer == self.payer.pubkey() {
+                    return &self.payer;
+                }
+                self.keypairs
+                    .get(signer)
+                    .unwrap_or_else(|| panic!("{signer} is not a harness wallet and cannot sign"))
+            })
+            .collect();
+        let transaction = Transaction::new(&keypairs, message.clone(), blockhash);
+
+        let (result, meta) = match self.bank.simulate_transaction(transaction.clone()) {
+            Ok(simulated) => match self.check_rent_states(&message, &simulated.post_accounts) {
+                Ok(()) => match self.bank.send_transaction(transaction) {
+                    Ok(meta) => (Ok(()), meta),
+                    Err(failed) => (
+                        Err(TransactionError::new(failed.err, &message)),
+                        failed.meta,
+                    ),
+                },
+                Err(err) => (Err(err), simulated.meta),
+            },
+            Err(failed) => (
+                Err(TransactionError::new(failed.err, &message)),
+                failed.meta,
+            ),
+        };
+        self.logs = meta.logs;
+        self.return_data = (meta.return_data.program_id != Pubkey::default())
+            .then_some((meta.return_data.program_id, meta.return_data.data));
+
+        if result.is_ok() {
+            self.clock.slot += 1;
+            self.bank.set_sysvar(&self.clock);
+        }
+        result
+    }
+
+    /// Funded accounts may not end up below the rent-exempt minimum, unless they already were
+    /// and only lost lamports. LiteSVM skips this for accounts without data, the runtime does not.
+    fn check_rent_states(
+        &self,
+        message: &Message,
+        after: &[(Pubkey, AccountSharedData)],
+    ) -> Result<(), TransactionError> {
+        let rent_paying =
+            |lamports: u64, len: usize| lamports > 0 && !self.rent.is_exempt(lamports, len);
+
+        for (address, after) in after {
+            let writable = message
+                .account_keys
+                .iter()
+                .position(|key| key == address)
+                .is_some_and(|index| message.is_maybe_writable(index, None));
+            if !writable || !rent_paying(after.lamports(), after.data().len()) {
+                continue;
+            }
+            let allowed = self.account(address).is_some_and(|before| {
+                rent_paying(before.lamports, before.data.len())
+                    && before.data.len() == after.data().len()
+                    && after.lamports() <= before.lamports
+            });
+            if !allowed {
+                return Err(TransactionError::InsufficientFundsForRent { account: *address });
+            }
+        }
+        Ok(())
+    }
+}
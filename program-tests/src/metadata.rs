@@ -0,0 +1,121 @@
+//! Stand-in for the Metaplex token metadata program.
+//!
+//! Metaplex only ships the real program as an SBF binary, so `Svm` always loads this instead.
+//! It implements `CreateMetadataAccountV3`, the one instruction the staking programs invoke,
+//! with the checks the real program makes on its accounts. The metadata account it writes is the real `Metadata` layout, so tests can decode
+//! it with `mpl_token_metadata`.
+
+use anchor_lang::solana_program::account_info::{next_account_info, AccountInfo};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::rent::Rent;
+use anchor_lang::solana_program::sysvar::Sysvar;
+use anchor_spl::metadata::mpl_token_metadata::accounts::Metadata;
+use anchor_spl::metadata::mpl_token_metadata::instructions::CreateMetadataAccountV3InstructionArgs;
+use anchor_spl::metadata::mpl_token_metadata::types::Key;
+use anchor_spl::metadata::mpl_token_metadata::{
+    MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH,
+};
+use anchor_spl::token::spl_token;
+use borsh010::{BorshDeserialize, BorshSerialize};
+use solana_system_interface::instruction as system_instruction;
+
+const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+
+/// Size the real program allocates for every metadata account
+const MAX_METADATA_LEN: usize = 679;
+
+pub fn process_instruction<'info>(
+    program_id: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+    data: &[u8],
+) -> ProgramResult {
+    let (discriminator, mut args) = data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    if *discriminator != CREATE_METADATA_ACCOUNT_V3 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let args = CreateMetadataAccountV3InstructionArgs::deserialize(&mut args)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    let accounts = &mut accounts.iter();
+    let metadata = next_account_info(accounts)?;
+    let mint = next_account_info(accounts)?;
+    let mint_authority = next_account_info(accounts)?;
+    let payer = next_account_info(accounts)?;
+    let update_authority = next_account_info(accounts)?;
+    let system_program = next_account_info(accounts)?;
+
+    let (expected, bump) = Metadata::find_pda(mint.key);
+    if *metadata.key != expected {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !metadata.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    if *mint.owner != spl_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mint_state = spl_token::state::Mint::unpack(&mint.try_borrow_data()?)?;
+    if mint_state.mint_authority != COption::Some(*mint_authority.key) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !mint_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let data = args.data;
+    if data.name.len() > MAX_NAME_LENGTH
+        || data.symbol.len() > MAX_SYMBOL_LENGTH
+        || data.uri.len() > MAX_URI_LENGTH
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            metadata.key,
+            Rent::get()?.minimum_balance(MAX_METADATA_LEN),
+            MAX_METADATA_LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), metadata.clone(), system_program.clone()],
+        &[&[
+            Metadata::PREFIX,
+            program_id.as_ref(),
+            mint.key.as_ref(),
+            &[bump],
+        ]],
+    )?;
+
+    let state = Metadata {
+        key: Key::MetadataV1,
+        update_authority: *update_authority.key,
+        mint: *mint.key,
+        name: data.name,
+        symbol: data.symbol,
+        uri: data.uri,
+        seller_fee_basis_points: data.seller_fee_basis_points,
+        creators: data.creators,
+        primary_sale_happened: false,
+        is_mutable: args.is_mutable,
+        edition_nonce: None,
+        token_standard: None,
+        collection: data.collection,
+        uses: data.uses,
+        collection_details: args.collection_details,
+        programmable_config: None,
+    };
+    let serialized = state
+        .try_to_vec()
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+    metadata.try_borrow_mut_data()?[..serialized.len()].copy_from_slice(&serialized);
+    Ok(())
+}
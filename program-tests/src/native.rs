@@ -0,0 +1,346 @@
+//! Runs natively compiled programs inside LiteSVM.
+//!
+//! The staking programs run from their SBF builds when those exist. Without them, each program
+//! is registered as a builtin whose entrypoint serializes the instruction's accounts in the SBF
+//! loader's input layout, calls the program's Rust entrypoint and writes the results back
+//! through the runtime's `BorrowedAccount`s. The runtime therefore applies its usual checks
+//! to every change. Syscalls go through `SyscallStubs` to the current `InvokeContext`, and
+//! CPIs go through `InvokeContext::process_instruction`, so a native program can call the
+//! SBF token programs and the other way round. This follows `solana-program-test`.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Once};
+
+use anchor_lang::solana_program::account_info::AccountInfo;
+use anchor_lang::solana_program::entrypoint::{deserialize, ProgramResult, SUCCESS};
+use anchor_lang::solana_program::instruction::{Instruction, InstructionError};
+use anchor_lang::solana_program::program_error::{ProgramError, UNSUPPORTED_SYSVAR};
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::stable_layout::stable_instruction::StableInstruction;
+use anchor_lang::solana_program::sysvar::Sysvar;
+use solana_log_collector::ic_msg;
+use solana_program_runtime::invoke_context::InvokeContext;
+use solana_program_runtime::serialization::serialize_parameters;
+use solana_program_runtime::stable_log;
+use solana_sbpf::error::EbpfError;
+use solana_sbpf::vm::{get_runtime_environment_key, EbpfVm};
+use solana_timings::ExecuteTimings;
+
+use crate::Processor;
+
+thread_local! {
+    /// The `InvokeContext` of the instruction running on this thread, for the syscall stubs
+    static INVOKE_CONTEXT: RefCell<Option<usize>> = const { RefCell::new(None) };
+    /// Error of the last failed CPI, which aborts the transaction with the callee's error
+    static CPI_ERROR: RefCell<Option<InstructionError>> = const { RefCell::new(None) };
+}
+
+fn set_invoke_context(context: &mut InvokeContext) {
+    INVOKE_CONTEXT.with(|current| {
+        current.replace(Some(context as *mut InvokeContext as usize));
+    });
+}
+
+fn invoke_context<'a, 'b>() -> &'a mut InvokeContext<'b> {
+    let pointer = INVOKE_CONTEXT
+        .with(|current| *current.borrow())
+        .expect("syscall outside of a program");
+    // SAFETY: set by `invoke_builtin_function` for the instruction running on this thread,
+    // which outlives every syscall it makes
+    unsafe { &mut *(pointer as *mut InvokeContext) }
+}
+
+/// Installs the syscall stubs, once per process
+pub fn install_stubs() {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(Stubs));
+    });
+}
+
+/// The LiteSVM builtin entrypoint for a native program
+macro_rules! builtin {
+    ($processor:path) => {{
+        let entrypoint: solana_program_runtime::invoke_context::BuiltinFunctionWithContext =
+            |vm, _, _, _, _, _| $crate::native::run_builtin(vm, $processor);
+        entrypoint
+    }};
+}
+
+pub(crate) use builtin;
+
+/// Body of every `builtin!` entrypoint
+pub fn run_builtin(vm: *mut EbpfVm<InvokeContext<'static>>, processor: Processor) {
+    // SAFETY: the runtime passes builtins a pointer offset by the environment key, exactly
+    // as `solana-program-test`'s `processor!` undoes it
+    let vm = unsafe {
+        &mut *((vm as *mut u64).offset(-(get_runtime_environment_key() as isize))
+            as *mut EbpfVm<InvokeContext>)
+    };
+    vm.program_result = invoke_builtin_function(processor, vm.context_object_pointer)
+        .map_err(EbpfError::SyscallError)
+        .into();
+}
+
+fn invoke_builtin_function(
+    processor: Processor,
+    invoke_context: &mut InvokeContext,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    set_invoke_context(invoke_context);
+    CPI_ERROR.with(|error| error.borrow_mut().take());
+
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let indices: HashSet<_> =
+        (0..instruction_context.get_number_of_instruction_accounts()).collect();
+
+    // Native programs must still consume units
+    invoke_context.consume_checked(1)?;
+
+    let log_collector = invoke_context.get_log_collector();
+    let program_id = *instruction_context.get_last_program_key(transaction_context)?;
+    stable_log::program_invoke(
+        &log_collector,
+        &program_id,
+        invoke_context.get_stack_height(),
+    );
+
+    let (mut parameters, _, _) = serialize_parameters(
+        transaction_context,
+        instruction_context,
+        true,
+        invoke_context
+            .get_feature_set()
+            .mask_out_rent_epoch_in_vm_serialization,
+    )?;
+    // SAFETY: `serialize_parameters` writes the layout `deserialize` reads, and `parameters`
+    // outlives every `AccountInfo` borrowed from it
+    let (id, account_infos, data) =
+        unsafe { deserialize(&mut parameters.as_slice_mut()[0] as *mut u8) };
+
+    let result =
+        match std::panic::catch_unwind(AssertUnwindSafe(|| processor(id, &account_infos, data))) {
+            Ok(result) => result.map_err(|err| {
+                CPI_ERROR
+                    .with(|error| error.borrow_mut().take())
+                    .unwrap_or_else(|| InstructionError::from(u64::from(err)))
+            }),
+            Err(_) => Err(InstructionError::ProgramFailedToComplete),
+        };
+    if let Err(err) = result {
+        stable_log::program_failure(&log_collector, &program_id, &err);
+        return Err(Box::new(err));
+    }
+    stable_log::program_success(&log_collector, &program_id);
+
+    // A CPI may have replaced the context's instruction frames, so look them up again
+    let infos: HashMap<_, _> = account_infos.iter().map(|info| (*info.key, info)).collect();
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    for index in indices {
+        let mut account =
+            instruction_context.try_borrow_instruction_account(transaction_context, index)?;
+        if !account.is_writable() {
+            continue;
+        }
+        let Some(info) = infos.get(account.get_key()) else {
+            continue;
+        };
+        if account.get_lamports() != info.lamports() {
+            account.set_lamports(info.lamports())?;
+        }
+        if account.can_data_be_resized(info.data_len()).is_ok() {
+            account.set_data_from_slice(&info.try_borrow_data()?)?;
+        }
+        if account.get_owner() != info.owner {
+            account.set_owner(info.owner.as_ref())?;
+        }
+    }
+    Ok(0)
+}
+
+/// Records a failed CPI and hands the caller the closest `ProgramError`
+fn abort(err: InstructionError) -> ProgramError {
+    let program_error =
+        ProgramError::try_from(err.clone()).unwrap_or(ProgramError::InvalidAccountData);
+    CPI_ERROR.with(|error| {
+        error.borrow_mut().get_or_insert(err);
+    });
+    program_error
+}
+
+fn write_sysvar<T: Sysvar + Clone>(
+    sysvar: Result<Arc<T>, InstructionError>,
+    var_addr: *mut u8,
+) -> u64 {
+    let invoke_context = invoke_context();
+    let cost = invoke_context.get_execution_cost().sysvar_base_cost + T::size_of() as u64;
+    if invoke_context.consume_checked(cost).is_err() {
+        panic!("exceeded the compute budget");
+    }
+    match sysvar {
+        // SAFETY: the sysvar getters pass a pointer to an uninitialized `T`
+        Ok(sysvar) => unsafe {
+            std::ptr::write(var_addr as *mut T, T::clone(&sysvar));
+            SUCCESS
+        },
+        Err(_) => UNSUPPORTED_SYSVAR,
+    }
+}
+
+struct Stubs;
+
+impl Stubs {
+    fn invoke(
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> Result<(), InstructionError> {
+        let instruction = StableInstruction::from(instruction.clone());
+        let invoke_context = invoke_context();
+        let log_collector = invoke_context.get_log_collector();
+        let transaction_context = &invoke_context.transaction_context;
+        let caller = *transaction_context
+            .get_current_instruction_context()?
+            .get_last_program_key(transaction_context)?;
+        stable_log::program_invoke(
+            &log_collector,
+            &instruction.program_id,
+            invoke_context.get_stack_height(),
+        );
+
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| InstructionError::InvalidSeeds)?;
+        let (instruction_accounts, program_indices) =
+            invoke_context.prepare_instruction(&instruction, &signers)?;
+
+        // The callee sees the caller's changes to its accounts so far
+        let transaction_context = &invoke_context.transaction_context;
+        let instruction_context = transaction_context.get_current_instruction_context()?;
+        let mut writable = Vec::with_capacity(instruction_accounts.len());
+        for instruction_account in &instruction_accounts {
+            let key = transaction_context
+                .get_key_of_account_at_index(instruction_account.index_in_transaction)?;
+            let info_index = account_infos
+                .iter()
+                .position(|info| info.key == key)
+                .ok_or(InstructionError::MissingAccount)?;
+            let info = &account_infos[info_index];
+            let mut account = instruction_context.try_borrow_instruction_account(
+                transaction_context,
+                instruction_account.index_in_caller,
+            )?;
+            if account.get_lamports() != info.lamports() {
+                account.set_lamports(info.lamports())?;
+            }
+            let data = info
+                .try_borrow_data()
+                .map_err(|_| InstructionError::AccountBorrowFailed)?;
+            match account.can_data_be_resized(data.len()) {
+                Ok(()) => account.set_data_from_slice(&data)?,
+                Err(err) if account.get_data() != *data => return Err(err),
+                Err(_) => {}
+            }
+            if account.get_owner() != info.owner {
+                account.set_owner(info.owner.as_ref())?;
+            }
+            if instruction_account.is_writable {
+                writable.push((instruction_account.index_in_caller, info_index));
+            }
+        }
+
+        let mut compute_units_consumed = 0;
+        invoke_context.process_instruction(
+            &instruction.data,
+            &instruction_accounts,
+            &program_indices,
+            &mut compute_units_consumed,
+            &mut ExecuteTimings::default(),
+        )?;
+
+        // And the caller sees the callee's
+        let transaction_context = &invoke_context.transaction_context;
+        let instruction_context = transaction_context.get_current_instruction_context()?;
+        for (index_in_caller, info_index) in writable {
+            let account = instruction_context
+                .try_borrow_instruction_account(transaction_context, index_in_caller)?;
+            let info = &account_infos[info_index];
+            **info
+                .try_borrow_mut_lamports()
+                .map_err(|_| InstructionError::AccountBorrowFailed)? = account.get_lamports();
+            if info.owner != account.get_owner() {
+                info.assign(account.get_owner());
+            }
+            if info.data_len() != account.get_data().len() {
+                info.resize(account.get_data().len())
+                    .map_err(|_| InstructionError::InvalidRealloc)?;
+            }
+            info.try_borrow_mut_data()
+                .map_err(|_| InstructionError::AccountBorrowFailed)?
+                .copy_from_slice(account.get_data());
+        }
+
+        stable_log::program_success(&log_collector, &instruction.program_id);
+        Ok(())
+    }
+}
+
+impl SyscallStubs for Stubs {
+    fn sol_log(&self, message: &str) {
+        ic_msg!(invoke_context(), "Program log: {}", message);
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        stable_log::program_data(&invoke_context().get_log_collector(), fields);
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        Self::invoke(instruction, account_infos, signers_seeds).map_err(abort)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        write_sysvar(invoke_context().get_sysvar_cache().get_clock(), var_addr)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        write_sysvar(
+            invoke_context().get_sysvar_cache().get_epoch_schedule(),
+            var_addr,
+        )
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        write_sysvar(invoke_context().get_sysvar_cache().get_rent(), var_addr)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        let (program_id, data) = invoke_context().transaction_context.get_return_data();
+        Some((*program_id, data.to_vec()))
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        let transaction_context = &mut invoke_context().transaction_context;
+        let caller = *transaction_context
+            .get_current_instruction_context()
+            .and_then(|context| context.get_last_program_key(transaction_context))
+            .expect("return data set outside of a program");
+        transaction_context
+            .set_return_data(caller, data.to_vec())
+            .expect("return data fits");
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        invoke_context().get_stack_height() as u64
+    }
+}
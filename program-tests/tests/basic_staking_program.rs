@@ -0,0 +1,902 @@
+use anchor_lang::error::ErrorCode;
+use anchor_lang::{AccountSerialize, AnchorDeserialize, AnchorSerialize, Discriminator, Space};
+use basic_staking_program::{PoolInitialized, VaultCreated};
+use program_tests::{Account, Svm};
+use solana_system_interface::error::SystemError;
+use staking_client::basic::{
+    self, pda, Capacity, Pool, PoolParams, Reconciliation, StakeAccount, StakeAccountV1,
+    StakingError, Treasury, STAKE_ACCOUNT_VERSION,
+};
+use staking_client::Pubkey;
+
+struct Fixture {
+    svm: Svm,
+    authority: Pubkey,
+    user: Pubkey,
+}
+
+/// A vault for `authority` and an empty position for `user`
+fn setup() -> Fixture {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+
+    svm.process(
+        &[
+            basic::create_vault_pda_account(&authority),
+            basic::initialize_pool(&authority, Default::default(), &[]),
+            basic::initialize_treasury(&authority),
+        ],
+        &[authority],
+    )
+    .unwrap();
+    svm.process(&[basic::create_pda_account(&user)], &[user])
+        .unwrap();
+
+    Fixture {
+        svm,
+        authority,
+        user,
+    }
+}
+
+/// Overwrites `owner`'s position with one in the v1 layout, as deployed before versioning
+fn write_v1_position(svm: &mut Svm, owner: &Pubkey, legacy: StakeAccountV1) {
+    let mut data = StakeAccount::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    assert_eq!(data.len(), StakeAccountV1::LEN);
+
+    svm.set_account(
+        pda::stake_account(owner).0,
+        Account {
+            lamports: svm.rent().minimum_balance(data.len()),
+            data,
+            owner: basic::ID,
+            executable: false,
+        },
+    );
+}
+
+fn v1_position(owner: &Pubkey, staked_amount: u64) -> StakeAccountV1 {
+    StakeAccountV1 {
+        owner: *owner,
+        staked_amount,
+        total_points: 42,
+        last_update_time: 1_700_000_000,
+        bump: pda::stake_account(owner).1,
+    }
+}
+
+#[test]
+fn create_vault_funds_a_rent_exempt_system_account() {
+    let Fixture { svm, authority, .. } = setup();
+    let vault = svm.account(&pda::vault(&authority).0).unwrap();
+
+    assert_eq!(vault.owner, anchor_lang::system_program::ID);
+    assert_eq!(vault.lamports, svm.rent().minimum_balance(0));
+    assert!(vault.data.is_empty());
+}
+
+#[test]
+fn create_vault_and_initialize_pool_emit_their_events() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let vault = pda::vault(&authority).0;
+
+    svm.process(&[basic::create_vault_pda_account(&authority)], &[authority])
+        .unwrap();
+    let [created] = &svm.events::<VaultCreated>()[..] else {
+        panic!("expected one VaultCreated event");
+    };
+    assert_eq!((created.authority, created.vault), (authority, vault));
+
+    svm.process(
+        &[basic::initialize_pool(&authority, Default::default(), &[])],
+        &[authority],
+    )
+    .unwrap();
+    let [initialized] = &svm.events::<PoolInitialized>()[..] else {
+        panic!("expected one PoolInitialized event");
+    };
+    assert_eq!(initialized.pool, pda::pool(&authority).0);
+    assert_eq!(initialized.vault, vault);
+}
+
+#[test]
+fn create_vault_twice_fails() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+    svm.warp_by(1);
+
+    let err = svm
+        .process(&[basic::create_vault_pda_account(&authority)], &[authority])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn create_position_records_owner_and_time() {
+    let Fixture { svm, user, .. } = setup();
+    let position: StakeAccount = svm.get(&pda::stake_account(&user).0);
+
+    assert_eq!(position.version, STAKE_ACCOUNT_VERSION);
+    assert_eq!(position.owner, user);
+    assert_eq!(position.staked_amount, 0);
+    assert_eq!(position.total_points, 0);
+    assert_eq!(position.last_update_time, svm.clock().unix_timestamp);
+    assert_eq!(position.bump, pda::stake_account(&user).1);
+}
+
+#[test]
+fn create_position_requires_the_payer_signature() {
+    let mut svm = Svm::new();
+    let user = svm.new_wallet();
+
+    let err = svm
+        .process(&[basic::create_pda_account(&user)], &[])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotSigner))
+    );
+}
+
+#[test]
+fn stake_moves_lamports_into_the_vault() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let vault = pda::vault(&authority).0;
+    let vault_before = svm.lamports(&vault);
+    let user_before = svm.lamports(&user);
+
+    svm.process(&[basic::stake(&user, &authority, 2_000_000_000)], &[user])
+        .unwrap();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user).0);
+    assert_eq!(position.staked_amount, 2_000_000_000);
+    assert_eq!(svm.lamports(&vault), vault_before + 2_000_000_000);
+    assert_eq!(svm.lamports(&user), user_before - 2_000_000_000);
+    assert!(svm
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("Program data: ")));
+}
+
+#[test]
+fn stake_more_than_the_wallet_holds_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let balance = svm.lamports(&user);
+
+    let err = svm
+        .process(&[basic::stake(&user, &authority, balance + 1)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::ResultWithNegativeLamports as u32)
+    );
+}
+
+#[test]
+fn stake_zero_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+
+    let err = svm
+        .process(&[basic::stake(&user, &authority, 0)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidAmount))
+    );
+}
+
+#[test]
+fn initialize_pool_rejects_a_wallet_cap_below_the_minimum() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+
+    let err = svm
+        .process(
+            &[
+                basic::create_vault_pda_account(&authority),
+                basic::initialize_pool(
+                    &authority,
+                    PoolParams {
+                        min_stake: 2_000,
+                        max_stake_per_wallet: Some(1_000),
+                        max_total_staked: None,
+                    },
+                    &[],
+                ),
+            ],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidStakeLimits))
+    );
+}
+
+#[test]
+fn stake_enforces_the_minimum_and_both_caps() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+    let other = svm.new_wallet();
+    svm.process(
+        &[
+            basic::create_vault_pda_account(&authority),
+            basic::initialize_pool(
+                &authority,
+                PoolParams {
+                    min_stake: 1_000,
+                    max_stake_per_wallet: Some(3_000),
+                    max_total_staked: Some(4_000),
+                },
+                &[],
+            ),
+        ],
+        &[authority],
+    )
+    .unwrap();
+    for wallet in [user, other] {
+        svm.process(&[basic::create_pda_account(&wallet)], &[wallet])
+            .unwrap();
+    }
+
+    for (wallet, amount, expected) in [
+        (user, 999, Some(StakingError::BelowMinimumStake)),
+        (user, 3_000, None),
+        (user, 1_000, Some(StakingError::WalletCapExceeded)),
+        (other, 2_000, Some(StakingError::PoolCapExceeded)),
+        (other, 1_000, None),
+    ] {
+        let result = svm.process(&[basic::stake(&wallet, &authority, amount)], &[wallet]);
+        match expected {
+            Some(error) => assert_eq!(result.unwrap_err().custom_code(), Some(u32::from(error))),
+            None => result.unwrap(),
+        }
+    }
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 4_000);
+}
+
+fn capacity(svm: &mut Svm, authority: &Pubkey, position: Option<&Pubkey>) -> Capacity {
+    svm.process(&[basic::capacity(authority, position)], &[])
+        .unwrap();
+    let (program_id, data) = svm.return_data().unwrap();
+    assert_eq!(*program_id, basic::ID);
+    Capacity::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn capacity_reports_the_room_left_under_each_cap() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let position = pda::stake_account(&user).0;
+    assert_eq!(
+        capacity(&mut svm, &authority, Some(&position)),
+        Capacity {
+            min_stake: 0,
+            total_staked: 0,
+            pool_remaining: None,
+            wallet_staked: 0,
+            wallet_remaining: None,
+        }
+    );
+
+    let limited = svm.new_wallet();
+    svm.process(
+        &[
+            basic::create_vault_pda_account(&limited),
+            basic::initialize_pool(
+                &limited,
+                PoolParams {
+                    min_stake: 1_000,
+                    max_stake_per_wallet: Some(3_000),
+                    max_total_staked: Some(4_000),
+                },
+                &[],
+            ),
+        ],
+        &[limited],
+    )
+    .unwrap();
+    svm.process(&[basic::stake(&user, &limited, 2_000)], &[user])
+        .unwrap();
+
+    assert_eq!(
+        capacity(&mut svm, &limited, Some(&position)),
+        Capacity {
+            min_stake: 1_000,
+            total_staked: 2_000,
+            pool_remaining: Some(2_000),
+            wallet_staked: 2_000,
+            wallet_remaining: Some(1_000),
+        }
+    );
+    assert_eq!(
+        capacity(&mut svm, &limited, None).wallet_remaining,
+        Some(2_000)
+    );
+}
+
+#[test]
+fn stake_without_a_position_fails() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+    let stranger = svm.new_wallet();
+
+    let err = svm
+        .process(&[basic::stake(&stranger, &authority, 1_000)], &[stranger])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotInitialized))
+    );
+}
+
+#[test]
+fn unstake_returns_lamports_from_the_vault() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[basic::stake(&user, &authority, 2_000_000_000)], &[user])
+        .unwrap();
+    let user_before = svm.lamports(&user);
+
+    svm.process(&[basic::unstake(&user, &authority, 500_000_000)], &[user])
+        .unwrap();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user).0);
+    assert_eq!(position.staked_amount, 1_500_000_000);
+    assert_eq!(svm.lamports(&user), user_before + 500_000_000);
+}
+
+#[test]
+fn unstake_zero_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[basic::stake(&user, &authority, 1_000)], &[user])
+        .unwrap();
+
+    let err = svm
+        .process(&[basic::unstake(&user, &authority, 0)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidAmount))
+    );
+}
+
+#[test]
+fn unstake_requires_more_staked_than_requested() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[basic::stake(&user, &authority, 1_000)], &[user])
+        .unwrap();
+
+    for amount in [1_000, 1_001] {
+        let err = svm
+            .process(&[basic::unstake(&user, &authority, amount)], &[user])
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::InvalidBalance))
+        );
+    }
+}
+
+#[test]
+fn unstake_from_another_authority_vault_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let other = svm.new_wallet();
+    svm.process(
+        &[
+            basic::create_vault_pda_account(&other),
+            basic::initialize_pool(&other, Default::default(), &[]),
+        ],
+        &[other],
+    )
+    .unwrap();
+    let staker = svm.new_wallet();
+    svm.process(
+        &[
+            basic::create_pda_account(&staker),
+            basic::stake(&staker, &other, 10_000),
+        ],
+        &[staker],
+    )
+    .unwrap();
+    svm.process(&[basic::stake(&user, &authority, 1_000)], &[user])
+        .unwrap();
+    let vault_before = svm.lamports(&pda::vault(&other).0);
+
+    let err = svm
+        .process(&[basic::unstake(&user, &other, 500)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+    assert_eq!(svm.lamports(&pda::vault(&other).0), vault_before);
+}
+
+#[test]
+fn stake_records_the_vault_and_keeps_it_while_staked() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let other = svm.new_wallet();
+    svm.process(
+        &[
+            basic::create_vault_pda_account(&other),
+            basic::initialize_pool(&other, Default::default(), &[]),
+        ],
+        &[other],
+    )
+    .unwrap();
+    svm.process(&[basic::stake(&user, &authority, 1_000)], &[user])
+        .unwrap();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user).0);
+    assert_eq!(position.vault, pda::vault(&authority).0);
+
+    let err = svm
+        .process(&[basic::stake(&user, &other, 1_000)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+}
+
+#[test]
+fn a_migrated_staked_position_needs_the_authority_to_bind_it() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    // Stake held by the vault before positions were bound or counted by its pool
+    svm.airdrop(&pda::vault(&authority).0, 3_000);
+    write_v1_position(&mut svm, &user, v1_position(&user, 3_000));
+    svm.process(&[basic::migrate_account(&user, &user)], &[user])
+        .unwrap();
+    let address = pda::stake_account(&user).0;
+
+    let err = svm
+        .process(&[basic::unstake(&user, &authority, 1_000)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+
+    svm.process(
+        &[basic::signed_by_authority(basic::unstake(
+            &user, &authority, 1_000,
+        ))],
+        &[user, authority],
+    )
+    .unwrap();
+
+    let position: StakeAccount = svm.get(&address);
+    assert_eq!(position.vault, pda::vault(&authority).0);
+    assert_eq!(position.staked_amount, 2_000);
+    // Binding added the legacy stake to the pool before the unstake took it out
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2_000);
+    assert_eq!(reconcile(&mut svm, &authority).surplus, 0);
+}
+
+#[test]
+fn transfer_position_moves_the_stake_to_the_new_owner() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let new_owner = Pubkey::new_unique();
+    svm.process(&[basic::stake(&user, &authority, 1_000)], &[user])
+        .unwrap();
+
+    svm.process(&[basic::transfer_position(&user, &new_owner)], &[user])
+        .unwrap();
+
+    assert!(svm.account(&pda::stake_account(&user).0).is_none());
+    let position: StakeAccount = svm.get(&pda::stake_account(&new_owner).0);
+    assert_eq!(position.owner, new_owner);
+    assert_eq!(position.staked_amount, 1_000);
+    assert_eq!(position.vault, pda::vault(&authority).0);
+}
+
+#[test]
+fn transfer_position_to_self_fails() {
+    let Fixture { mut svm, user, .. } = setup();
+
+    let err = svm
+        .process(&[basic::transfer_position(&user, &user)], &[user])
+        .unwrap_err();
+    // Both positions are the same PDA, so `init` rejects it before `InvalidNewOwner` is checked
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn transfer_position_to_a_wallet_with_a_position_fails() {
+    let Fixture { mut svm, user, .. } = setup();
+    let other = svm.new_wallet();
+    svm.process(&[basic::create_pda_account(&other)], &[other])
+        .unwrap();
+
+    let err = svm
+        .process(&[basic::transfer_position(&user, &other)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn failed_transactions_leave_state_untouched() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let user_before = svm.lamports(&user);
+
+    svm.process(
+        &[
+            basic::stake(&user, &authority, 1_000),
+            basic::unstake(&user, &authority, 0),
+        ],
+        &[user],
+    )
+    .unwrap_err();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user).0);
+    assert_eq!(position.staked_amount, 0);
+    assert_eq!(svm.lamports(&user), user_before);
+}
+
+#[test]
+fn migrate_account_upgrades_a_v1_position() {
+    let Fixture { mut svm, user, .. } = setup();
+    let payer = svm.new_wallet();
+    write_v1_position(&mut svm, &user, v1_position(&user, 1_000));
+    let address = pda::stake_account(&user).0;
+    let space = 8 + StakeAccount::INIT_SPACE;
+    let shortfall = svm.rent().minimum_balance(space) - svm.lamports(&address);
+    let payer_before = svm.lamports(&payer);
+
+    svm.process(&[basic::migrate_account(&payer, &user)], &[payer])
+        .unwrap();
+
+    let account = svm.account(&address).unwrap();
+    assert_eq!(account.data.len(), space);
+    assert_eq!(account.lamports, svm.rent().minimum_balance(space));
+    assert_eq!(svm.lamports(&payer), payer_before - shortfall);
+
+    let position: StakeAccount = svm.get(&address);
+    assert_eq!(position.version, STAKE_ACCOUNT_VERSION);
+    assert_eq!(position.owner, user);
+    assert_eq!(position.staked_amount, 1_000);
+    assert_eq!(position.total_points, 42);
+    assert_eq!(position.last_update_time, 1_700_000_000);
+    assert_eq!(position.bump, pda::stake_account(&user).1);
+    assert_eq!(position.vault, Pubkey::default());
+    assert_eq!(position.reserved, [0; 32]);
+}
+
+#[test]
+fn migrate_account_twice_fails() {
+    let Fixture { mut svm, user, .. } = setup();
+    write_v1_position(&mut svm, &user, v1_position(&user, 0));
+    svm.process(&[basic::migrate_account(&user, &user)], &[user])
+        .unwrap();
+
+    let err = svm
+        .process(&[basic::migrate_account(&user, &user)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::AlreadyMigrated))
+    );
+}
+
+#[test]
+fn migrate_account_rejects_a_position_at_another_owners_address() {
+    let Fixture { mut svm, user, .. } = setup();
+    let other = Pubkey::new_unique();
+    let mut legacy = v1_position(&other, 1_000);
+    legacy.bump = pda::stake_account(&user).1;
+    write_v1_position(&mut svm, &user, legacy);
+
+    let err = svm
+        .process(&[basic::migrate_account(&user, &user)], &[user])
+        .unwrap_err();
+    assert_eq!(err.custom_code(), Some(ErrorCode::ConstraintSeeds as u32));
+}
+
+#[test]
+fn a_v1_position_stakes_again_once_migrated() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    // Stake held by the vault before positions were bound or counted by its pool
+    svm.airdrop(&pda::vault(&authority).0, 3_000);
+    write_v1_position(&mut svm, &user, v1_position(&user, 3_000));
+
+    let err = svm
+        .process(&[basic::stake(&user, &authority, 1_000)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(ErrorCode::AccountDidNotDeserialize as u32)
+    );
+
+    svm.process(
+        &[
+            basic::migrate_account(&user, &user),
+            basic::signed_by_authority(basic::stake(&user, &authority, 1_000)),
+            basic::unstake(&user, &authority, 2_000),
+        ],
+        &[user, authority],
+    )
+    .unwrap();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user).0);
+    assert_eq!(position.staked_amount, 2_000);
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2_000);
+}
+
+fn reconcile(svm: &mut Svm, authority: &Pubkey) -> Reconciliation {
+    svm.process(&[basic::reconcile(authority)], &[]).unwrap();
+    let (program_id, data) = svm.return_data().unwrap();
+    assert_eq!(*program_id, basic::ID);
+    Reconciliation::try_from_slice(data).unwrap()
+}
+
+/// A vault with no pool yet, holding `staked` for `owner`'s position plus `donated`
+fn pool_less_vault(svm: &mut Svm, authority: &Pubkey, owner: &Pubkey, staked: u64, donated: u64) {
+    svm.process(&[basic::create_vault_pda_account(authority)], &[*authority])
+        .unwrap();
+    svm.process(&[basic::create_pda_account(owner)], &[*owner])
+        .unwrap();
+
+    let address = pda::stake_account(owner).0;
+    let mut account = svm.account(&address).unwrap();
+    let mut position: StakeAccount = svm.get(&address);
+    position.staked_amount = staked;
+    position.vault = pda::vault(authority).0;
+    account.data.clear();
+    position.try_serialize(&mut account.data).unwrap();
+    svm.set_account(address, account);
+    svm.airdrop(&pda::vault(authority).0, staked + donated);
+}
+
+#[test]
+fn initialize_pool_counts_the_positions_bound_to_the_vault() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+    pool_less_vault(&mut svm, &authority, &user, 3_000, 500);
+
+    svm.process(
+        &[basic::initialize_pool(
+            &authority,
+            Default::default(),
+            &[pda::stake_account(&user).0],
+        )],
+        &[authority],
+    )
+    .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.authority, authority);
+    assert_eq!(pool.total_staked, 3_000);
+    assert_eq!(pool.total_pending_withdrawals, 0);
+    assert_eq!(pool.bump, pda::pool(&authority).1);
+    // A donation is not stake, so it stays sweepable
+    assert_eq!(reconcile(&mut svm, &authority).surplus, 500);
+}
+
+#[test]
+fn initialize_pool_of_a_fresh_vault_starts_empty() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    svm.process(&[basic::create_vault_pda_account(&authority)], &[authority])
+        .unwrap();
+    svm.airdrop(&pda::vault(&authority).0, 3_000);
+
+    svm.process(
+        &[basic::initialize_pool(&authority, Default::default(), &[])],
+        &[authority],
+    )
+    .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 0);
+    assert_eq!(reconcile(&mut svm, &authority).surplus, 3_000);
+}
+
+#[test]
+fn initialize_pool_rejects_a_position_listed_twice() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+    pool_less_vault(&mut svm, &authority, &user, 3_000, 0);
+    let position = pda::stake_account(&user).0;
+
+    let err = svm
+        .process(
+            &[basic::initialize_pool(
+                &authority,
+                Default::default(),
+                &[position, position],
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::DuplicatePosition))
+    );
+}
+
+#[test]
+fn initialize_pool_rejects_a_position_bound_elsewhere() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+    pool_less_vault(&mut svm, &authority, &user, 3_000, 0);
+    let other = svm.new_wallet();
+    svm.process(&[basic::create_vault_pda_account(&other)], &[other])
+        .unwrap();
+
+    let err = svm
+        .process(
+            &[basic::initialize_pool(
+                &other,
+                Default::default(),
+                &[pda::stake_account(&user).0],
+            )],
+            &[other],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+}
+
+#[test]
+fn stake_and_unstake_track_the_pool_total() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+
+    svm.process(
+        &[
+            basic::stake(&user, &authority, 3_000),
+            basic::unstake(&user, &authority, 1_000),
+        ],
+        &[user],
+    )
+    .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2_000);
+}
+
+#[test]
+fn sweep_surplus_moves_only_the_surplus_to_the_treasury() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[basic::stake(&user, &authority, 3_000)], &[user])
+        .unwrap();
+    svm.airdrop(&pda::vault(&authority).0, 500);
+    let rent_reserve = svm.rent().minimum_balance(0);
+
+    assert_eq!(
+        reconcile(&mut svm, &authority),
+        Reconciliation {
+            vault_balance: rent_reserve + 3_500,
+            rent_reserve,
+            total_staked: 3_000,
+            total_pending_withdrawals: 0,
+            surplus: 500,
+            shortfall: 0,
+        }
+    );
+
+    let treasury = pda::treasury(&authority).0;
+    let treasury_before = svm.lamports(&treasury);
+    svm.process(&[basic::sweep_surplus(&authority)], &[authority])
+        .unwrap();
+
+    assert_eq!(svm.lamports(&treasury), treasury_before + 500);
+    assert_eq!(svm.get::<Treasury>(&treasury).total_swept, 500);
+    assert_eq!(
+        svm.lamports(&pda::vault(&authority).0),
+        rent_reserve + 3_000
+    );
+
+    let err = svm
+        .process(&[basic::sweep_surplus(&authority)], &[authority])
+        .unwrap_err();
+    assert_eq!(err.custom_code(), Some(u32::from(StakingError::NoSurplus)));
+
+    // Every staked lamport is still there to be paid back
+    svm.process(&[basic::unstake(&user, &authority, 2_999)], &[user])
+        .unwrap();
+
+    let authority_before = svm.lamports(&authority);
+    svm.process(&[basic::withdraw_treasury(&authority, 500)], &[authority])
+        .unwrap();
+    assert_eq!(svm.lamports(&authority), authority_before + 500);
+}
+
+#[test]
+fn withdraw_treasury_keeps_it_rent_exempt() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+    let treasury = pda::treasury(&authority).0;
+    svm.airdrop(&treasury, 500);
+
+    let err = svm
+        .process(&[basic::withdraw_treasury(&authority, 501)], &[authority])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidBalance))
+    );
+}
@@ -0,0 +1,728 @@
+//! Time-dependent scenarios: exact point totals across several settlements, and the lockup
+//! boundaries of every tier. The clock only moves through `warp_by`, between transactions.
+
+use program_tests::{Svm, TransactionError};
+use staking_client::token_rewards::{self, LockTier, PoolParams, TimeBase, TokenMintMetadata};
+use staking_client::{rewards, Pubkey};
+
+const SOL: u64 = 1_000_000_000;
+const MINUTE: i64 = 60;
+const DAY: i64 = 86_400;
+const MAX_VE_LOCK_DURATION: i64 = 4 * 365 * DAY;
+
+mod rewards_program {
+    use super::*;
+    use staking_client::rewards::{pda, PointsDecay, PoolParams, StakeAccount, TimeBase};
+
+    struct Fixture {
+        svm: Svm,
+        authority: Pubkey,
+        user: Pubkey,
+    }
+
+    /// A vault and a 10% referral pool for `authority`, and an empty position for `user`
+    fn setup() -> Fixture {
+        setup_with(TimeBase::UnixSeconds)
+    }
+
+    /// `setup` with the pool counting time in `time_base`
+    fn setup_with(time_base: TimeBase) -> Fixture {
+        let mut svm = Svm::new();
+        let authority = svm.new_wallet();
+        let user = svm.new_wallet();
+
+        svm.process(
+            &[
+                rewards::create_vault_pda_account(&authority),
+                rewards::initialize_pool(
+                    &authority,
+                    PoolParams {
+                        referral_bps: 1_000,
+                        fee_bps: 0,
+                        time_base,
+                        min_stake: 0,
+                        max_stake_per_wallet: None,
+                        max_total_staked: None,
+                        decay: PointsDecay::None,
+                        streak_tiers: Default::default(),
+                    },
+                ),
+                rewards::initialize_treasury(&authority),
+                rewards::initialize_leaderboard(&authority),
+            ],
+            &[authority],
+        )
+        .unwrap();
+        svm.process(&[rewards::create_pda_account(&user)], &[user])
+            .unwrap();
+
+        Fixture {
+            svm,
+            authority,
+            user,
+        }
+    }
+
+    impl Fixture {
+        fn stake(&mut self, amount: u64, referrer: Option<Pubkey>) {
+            self.svm
+                .process(
+                    &[rewards::stake(
+                        &self.user,
+                        &self.authority,
+                        amount,
+                        referrer,
+                    )],
+                    &[self.user],
+                )
+                .unwrap();
+        }
+
+        fn unstake(&mut self, amount: u64) {
+            self.svm
+                .process(
+                    &[rewards::unstake(&self.user, &self.authority, amount)],
+                    &[self.user],
+                )
+                .unwrap();
+        }
+
+        /// Settles accrued points without changing the whole SOL staked
+        fn settle(&mut self) {
+            self.stake(1, None);
+        }
+
+        fn position(&self) -> StakeAccount {
+            self.svm.get(&pda::stake_account(&self.user).0)
+        }
+    }
+
+    #[test]
+    fn points_only_count_whole_sol() {
+        let mut fixture = setup();
+        fixture.stake(SOL + SOL / 2, None);
+
+        fixture.svm.warp_by(100);
+        fixture.settle();
+
+        assert_eq!(fixture.position().total_points, 100);
+    }
+
+    #[test]
+    fn a_stake_only_earns_from_when_it_lands() {
+        let mut fixture = setup();
+
+        // The position sat empty for a day, which earns nothing
+        fixture.svm.warp_by(DAY);
+        fixture.stake(2 * SOL, None);
+        assert_eq!(fixture.position().total_points, 0);
+
+        fixture.svm.warp_by(100);
+        fixture.stake(SOL, None);
+        assert_eq!(fixture.position().total_points, 200);
+
+        fixture.svm.warp_by(100);
+        fixture.unstake(SOL);
+        assert_eq!(fixture.position().total_points, 200 + 300);
+
+        fixture.svm.warp_by(50);
+        fixture.settle();
+        let position = fixture.position();
+        assert_eq!(position.total_points, 200 + 300 + 100);
+        assert_eq!(
+            position.last_update_time,
+            fixture.svm.clock().unix_timestamp
+        );
+    }
+
+    #[test]
+    fn referral_points_are_rounded_down_per_settlement() {
+        let mut fixture = setup();
+        let referrer = Pubkey::new_unique();
+        fixture.stake(3 * SOL, Some(referrer));
+
+        // 15 points per settlement, of which 10% is 1.5
+        fixture.svm.warp_by(5);
+        fixture.settle();
+        fixture.svm.warp_by(5);
+        fixture.settle();
+
+        let position = fixture.position();
+        assert_eq!(position.total_points, 30);
+        assert_eq!(position.referral_points, 2);
+    }
+
+    #[test]
+    fn claimed_points_accrue_again_from_zero() {
+        let mut fixture = setup();
+        let user = fixture.user;
+        fixture.stake(2 * SOL, None);
+        fixture.svm.warp_by(10);
+        fixture.settle();
+
+        fixture
+            .svm
+            .process(&[rewards::claim_points(&user, &fixture.authority)], &[user])
+            .unwrap();
+        fixture.svm.warp_by(10);
+        fixture.settle();
+
+        assert_eq!(fixture.position().total_points, 20);
+    }
+
+    #[test]
+    fn slot_pools_count_each_slot_as_its_nominal_length() {
+        let mut fixture = setup_with(TimeBase::Slots);
+        fixture.stake(2 * SOL, None);
+
+        // The harness moves one slot per second, and a slot stands for 400 ms
+        fixture.svm.warp_by(1_000);
+        fixture.settle();
+        assert_eq!(fixture.position().total_points, 2 * 400);
+    }
+}
+
+mod token_rewards_program {
+    use super::*;
+    use staking_client::token_rewards::{pda, StakeAccount, StakingError};
+
+    struct Fixture {
+        svm: Svm,
+        authority: Pubkey,
+        user: Pubkey,
+    }
+
+    /// The reward mint, and a vault and pool with a 2x max boost for `authority`
+    fn setup() -> Fixture {
+        setup_with(TimeBase::UnixSeconds)
+    }
+
+    /// `setup` with the pool counting time in `time_base`
+    fn setup_with(time_base: TimeBase) -> Fixture {
+        let mut svm = Svm::new();
+        let authority = svm.new_wallet();
+        let user = svm.new_wallet();
+
+        svm.process(
+            &[
+                token_rewards::create_token_mint(
+                    &authority,
+                    TokenMintMetadata {
+                        name: "Stake Reward".to_string(),
+                        symbol: "STK".to_string(),
+                        uri: String::new(),
+                        decimals: 9,
+                    },
+                ),
+                token_rewards::create_vault_pda_account(&authority),
+                token_rewards::initialize_pool(
+                    &authority,
+                    PoolParams {
+                        max_boost_bps: 20_000,
+                        referral_bps: 0,
+                        fee_bps: 0,
+                        time_base,
+                        vesting: Default::default(),
+                        min_stake: 0,
+                        max_stake_per_wallet: None,
+                        max_total_staked: None,
+                    },
+                ),
+                token_rewards::initialize_treasury(&authority),
+            ],
+            &[authority],
+        )
+        .unwrap();
+
+        Fixture {
+            svm,
+            authority,
+            user,
+        }
+    }
+
+    impl Fixture {
+        fn create_position(&mut self, index: u32, lock_tier: LockTier) {
+            self.svm
+                .process(
+                    &[token_rewards::create_pda_account(
+                        &self.user, index, lock_tier,
+                    )],
+                    &[self.user],
+                )
+                .unwrap();
+        }
+
+        fn stake(&mut self, index: u32, amount: u64) {
+            let position = pda::stake_account(&self.user, index).0;
+            // The user's ve lock, once they have one, sets the boost the stake earns
+            let ve_lock = pda::ve_lock(&self.user).0;
+            let ve_lock = self.svm.account(&ve_lock).map(|_| ve_lock);
+            self.svm
+                .process(
+                    &[token_rewards::stake(
+                        &self.user,
+                        &self.user,
+                        &position,
+                        &self.authority,
+                        &self.user,
+                        &self.user,
+                        ve_lock,
+                        amount,
+                        None,
+                    )],
+                    &[self.user, self.authority],
+                )
+                .unwrap();
+        }
+
+        fn unstake(&mut self, index: u32, amount: u64) -> Result<(), TransactionError> {
+            let position = pda::stake_account(&self.user, index).0;
+            self.svm.process(
+                &[token_rewards::unstake(
+                    &self.user,
+                    &self.user,
+                    &position,
+                    Some(pda::position_token_account(&self.user, &position)),
+                    &self.authority,
+                    None,
+                    amount,
+                )],
+                &[self.user],
+            )
+        }
+
+        /// Claims position `index` and returns how many reward tokens it paid out
+        fn claim(&mut self, index: u32) -> u64 {
+            let position = pda::stake_account(&self.user, index).0;
+            let tokens = pda::reward_token_account(&self.user);
+            let before = self.svm.token_balance(&tokens);
+            self.svm
+                .process(
+                    &[token_rewards::claim_rewards(
+                        &self.user,
+                        &self.user,
+                        &position,
+                        Some(pda::position_token_account(&self.user, &position)),
+                        &self.authority,
+                        None,
+                    )],
+                    &[self.user],
+                )
+                .unwrap();
+            self.svm.token_balance(&tokens) - before
+        }
+
+        fn position(&self, index: u32) -> StakeAccount {
+            self.svm.get(&pda::stake_account(&self.user, index).0)
+        }
+    }
+
+    #[test]
+    fn unstake_opens_exactly_at_the_end_of_each_tier() {
+        for tier in [
+            LockTier::Days30,
+            LockTier::Days90,
+            LockTier::Days180,
+            LockTier::Days365,
+        ] {
+            let mut fixture = setup();
+            fixture.create_position(0, tier);
+            fixture.stake(0, 2 * SOL);
+
+            // One minute short, e.g. 29d 23h 59m for the 30 day tier
+            fixture.svm.warp_by(tier.duration() - MINUTE);
+            let err = fixture.unstake(0, SOL).unwrap_err();
+            assert_eq!(
+                err.custom_code(),
+                Some(u32::from(StakingError::InvalidUnstake)),
+                "{tier:?}"
+            );
+
+            fixture.svm.warp_by(MINUTE);
+            fixture.unstake(0, SOL).unwrap();
+            assert_eq!(fixture.position(0).staked_amount, SOL, "{tier:?}");
+        }
+    }
+
+    #[test]
+    fn unstake_stays_open_after_the_lock() {
+        let mut fixture = setup();
+        fixture.create_position(0, LockTier::Days30);
+        fixture.stake(0, 3 * SOL);
+
+        fixture.svm.warp_by(30 * DAY);
+        fixture.unstake(0, SOL).unwrap();
+        fixture.svm.warp_by(DAY);
+        fixture.unstake(0, SOL).unwrap();
+
+        assert_eq!(fixture.position(0).staked_amount, SOL);
+    }
+
+    #[test]
+    fn points_are_scaled_by_the_tier_at_every_settlement() {
+        let mut fixture = setup();
+        fixture.create_position(0, LockTier::Days180);
+        fixture.stake(0, 2 * SOL);
+
+        // 2 SOL for 100 seconds at 1.5x, settled by the second stake
+        fixture.svm.warp_by(100);
+        fixture.stake(0, SOL);
+        assert_eq!(fixture.position(0).total_points, 300);
+
+        // Then 3 SOL for 100 seconds at 1.5x
+        fixture.svm.warp_by(100);
+        assert_eq!(fixture.claim(0), 300 + 450);
+        assert_eq!(fixture.position(0).total_points, 0);
+    }
+
+    #[test]
+    fn the_boost_follows_the_decaying_ve_balance() {
+        let mut fixture = setup();
+        let user = fixture.user;
+        fixture.create_position(0, LockTier::Days30);
+
+        // 1 SOL locked for the maximum starts at a ve balance of 1 SOL, a 1.5x boost on 2 SOL
+        let tokens = fixture.svm.claimed_reward_tokens(&user, SOL);
+        fixture
+            .svm
+            .process(
+                &[token_rewards::lock_reward_tokens(
+                    &user,
+                    &tokens,
+                    SOL,
+                    MAX_VE_LOCK_DURATION,
+                )],
+                &[user],
+            )
+            .unwrap();
+        fixture.stake(0, 2 * SOL);
+
+        // The boost is read when points settle, by which time half the lock has run out
+        fixture.svm.warp_by(MAX_VE_LOCK_DURATION / 2);
+        let elapsed = (MAX_VE_LOCK_DURATION / 2) as u64;
+        assert_eq!(fixture.claim(0), 2 * elapsed * 12_500 / 10_000);
+
+        // Once the lock has ended the ve balance is gone and so is the boost
+        fixture.svm.warp_by(MAX_VE_LOCK_DURATION / 2);
+        assert_eq!(fixture.claim(0), 2 * elapsed);
+    }
+
+    #[test]
+    fn slot_pools_count_each_slot_as_its_nominal_length() {
+        let mut fixture = setup_with(TimeBase::Slots);
+        fixture.create_position(0, LockTier::Days30);
+        fixture.stake(0, 2 * SOL);
+
+        // The harness moves one slot per second, and a slot stands for 400 ms
+        fixture.svm.warp_by(1_000);
+        assert_eq!(fixture.claim(0), 2 * 400);
+
+        // The lock runs out after 30 days worth of slots, not 30 days of wall-clock time
+        fixture.svm.warp_by(30 * DAY - 1_000);
+        let err = fixture.unstake(0, SOL).unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::InvalidUnstake))
+        );
+        fixture.svm.warp_by(30 * DAY * 3 / 2);
+        fixture.unstake(0, SOL).unwrap();
+    }
+}
+
+/// Settlement and lockup checks run off chain against injected times
+mod time_source {
+    use super::*;
+    use staking_client::token_rewards::{Pool, StakeAccount, StakingError, TimeSource};
+
+    const SECONDS_PER_EPOCH: u64 = 172_800;
+
+    struct At {
+        unix_timestamp: i64,
+        slot: u64,
+        epoch: u64,
+    }
+
+    impl TimeSource for At {
+        fn unix_timestamp(&self) -> i64 {
+            self.unix_timestamp
+        }
+
+        fn slot(&self) -> u64 {
+            self.slot
+        }
+
+        fn epoch(&self) -> u64 {
+            self.epoch
+        }
+    }
+
+    impl rewards::TimeSource for At {
+        fn unix_timestamp(&self) -> i64 {
+            self.unix_timestamp
+        }
+
+        fn slot(&self) -> u64 {
+            self.slot
+        }
+
+        fn epoch(&self) -> u64 {
+            self.epoch
+        }
+    }
+
+    fn pool(time_base: TimeBase) -> Pool {
+        Pool {
+            authority: Pubkey::new_unique(),
+            max_boost_bps: 10_000,
+            referral_bps: 0,
+            fee_bps: 0,
+            time_base,
+            vesting: Default::default(),
+            bump: 255,
+            vesting_claims: 0,
+            total_staked: 0,
+            total_pending_withdrawals: 0,
+            min_stake: 0,
+            max_stake_per_wallet: None,
+            max_total_staked: None,
+        }
+    }
+
+    /// 1 SOL on the 30 day tier, last settled and locked at epoch 10
+    fn position() -> StakeAccount {
+        StakeAccount {
+            version: 2,
+            owner: Pubkey::new_unique(),
+            position_index: 0,
+            staked_amount: SOL,
+            total_points: 0,
+            last_update_time: 0,
+            lock_start: 0,
+            lock_tier: LockTier::Days30,
+            last_update_slot: 0,
+            last_update_epoch: 10,
+            lock_start_slot: 0,
+            lock_start_epoch: 10,
+            referrer: Pubkey::default(),
+            referral_rewards: 0,
+            position_mint: Pubkey::default(),
+            bump: 255,
+            vault: Pubkey::default(),
+            pending_stake: 0,
+            counted: true,
+            boost_bps: 0,
+            active_votes: 0,
+            reserved: [0; 19],
+        }
+    }
+
+    #[test]
+    fn epoch_pools_settle_whole_epochs_and_ignore_the_unix_clock() {
+        let pool = pool(TimeBase::Epochs);
+        let mut position = position();
+
+        // A skewed timestamp years ahead changes nothing within the same epoch
+        position
+            .settle(
+                &pool,
+                None,
+                0,
+                &At {
+                    unix_timestamp: 100 * DAY * 365,
+                    slot: 0,
+                    epoch: 10,
+                },
+            )
+            .unwrap();
+        assert_eq!(position.total_points, 0);
+
+        position
+            .settle(
+                &pool,
+                None,
+                0,
+                &At {
+                    unix_timestamp: 0,
+                    slot: 0,
+                    epoch: 13,
+                },
+            )
+            .unwrap();
+        assert_eq!(position.total_points, 3 * SECONDS_PER_EPOCH);
+        assert_eq!(position.last_update_epoch, 13);
+    }
+
+    #[test]
+    fn epoch_pools_credit_added_stake_from_the_next_whole_epoch() {
+        let pool = pool(TimeBase::Epochs);
+        let mut position = position();
+        let at_epoch = |epoch| At {
+            unix_timestamp: 0,
+            slot: 0,
+            epoch,
+        };
+
+        // Another SOL lands during epoch 12, after settling the first one as the program does
+        position.settle(&pool, None, 0, &at_epoch(12)).unwrap();
+        position.add_stake(&pool, SOL, &at_epoch(12));
+        assert_eq!(position.total_points, 2 * SECONDS_PER_EPOCH);
+        assert_eq!(position.lock_start_epoch, 12);
+
+        assert_eq!(position.pending_stake, SOL);
+
+        // Only the SOL that was already staked earns epoch 12, even across settlements
+        position.settle(&pool, None, 0, &at_epoch(12)).unwrap();
+        position.settle(&pool, None, 0, &at_epoch(13)).unwrap();
+        assert_eq!(position.total_points, 3 * SECONDS_PER_EPOCH);
+        assert_eq!(position.pending_stake, 0);
+
+        // Epoch 13 is the first whole one for both SOL
+        position.settle(&pool, None, 0, &at_epoch(14)).unwrap();
+        assert_eq!(position.total_points, 5 * SECONDS_PER_EPOCH);
+    }
+
+    /// An epoch pool of the rewards program without decay or streak tiers
+    fn rewards_pool() -> rewards::Pool {
+        rewards::Pool {
+            authority: Pubkey::new_unique(),
+            referral_bps: 0,
+            fee_bps: 0,
+            time_base: rewards::TimeBase::Epochs,
+            min_stake: 0,
+            max_stake_per_wallet: None,
+            max_total_staked: None,
+            decay: rewards::PointsDecay::None,
+            streak_tiers: Default::default(),
+            total_staked: 0,
+            total_pending_withdrawals: 0,
+            allowlist_root: [0; 32],
+            has_leaderboard: false,
+            bump: 255,
+        }
+    }
+
+    /// 1 SOL in the rewards program, last settled at epoch 10
+    fn rewards_position() -> rewards::StakeAccount {
+        rewards::StakeAccount {
+            version: rewards::STAKE_ACCOUNT_VERSION,
+            owner: Pubkey::new_unique(),
+            staked_amount: SOL,
+            total_points: 0,
+            last_update_time: 0,
+            referrer: Pubkey::default(),
+            referral_points: 0,
+            vault: Pubkey::default(),
+            season: 0,
+            season_points: 0,
+            expiring_points: 0,
+            decayed_at: 0,
+            streak_start: 0,
+            bump: 255,
+            last_update_slot: 0,
+            last_update_epoch: 10,
+            pending_stake: 0,
+            reserved: [0; 8],
+        }
+    }
+
+    #[test]
+    fn rewards_epoch_pools_credit_added_stake_from_the_next_whole_epoch() {
+        let pool = rewards_pool();
+        let mut position = rewards_position();
+        let at_epoch = |epoch| At {
+            unix_timestamp: 0,
+            slot: 0,
+            epoch,
+        };
+
+        // Another SOL lands during epoch 12, after settling the first one as the program does
+        assert_eq!(position.settle(&pool, &at_epoch(12)), 2 * SECONDS_PER_EPOCH);
+        position.add_stake(&pool, SOL);
+        assert_eq!(position.pending_stake, SOL);
+
+        // Only the SOL that was already staked earns epoch 12, even across settlements
+        position.settle(&pool, &at_epoch(12));
+        position.settle(&pool, &at_epoch(13));
+        assert_eq!(position.total_points, 3 * SECONDS_PER_EPOCH);
+        assert_eq!(position.pending_stake, 0);
+
+        // Epoch 13 is the first whole one for both SOL
+        position.settle(&pool, &at_epoch(14));
+        assert_eq!(position.total_points, 5 * SECONDS_PER_EPOCH);
+    }
+
+    #[test]
+    fn rewards_epoch_pools_unstake_pending_stake_first() {
+        let pool = rewards_pool();
+        let mut position = rewards_position();
+        let at_epoch = |epoch| At {
+            unix_timestamp: 0,
+            slot: 0,
+            epoch,
+        };
+
+        position.settle(&pool, &at_epoch(12));
+        position.add_stake(&pool, 2 * SOL);
+        position.remove_stake(SOL);
+        assert_eq!(position.pending_stake, SOL);
+
+        position.settle(&pool, &at_epoch(13));
+        assert_eq!(position.total_points, 3 * SECONDS_PER_EPOCH);
+    }
+
+    #[test]
+    fn epoch_pools_unstake_pending_stake_first() {
+        let pool = pool(TimeBase::Epochs);
+        let mut position = position();
+        let at_epoch = |epoch| At {
+            unix_timestamp: 0,
+            slot: 0,
+            epoch,
+        };
+
+        position.settle(&pool, None, 0, &at_epoch(12)).unwrap();
+        position.add_stake(&pool, 2 * SOL, &at_epoch(12));
+        position.remove_stake(SOL);
+        assert_eq!(position.pending_stake, SOL);
+
+        position.settle(&pool, None, 0, &at_epoch(13)).unwrap();
+        assert_eq!(position.total_points, 3 * SECONDS_PER_EPOCH);
+    }
+
+    #[test]
+    fn a_referral_cut_past_the_balance_limit_fails_the_settlement() {
+        let mut pool = pool(TimeBase::Epochs);
+        pool.referral_bps = 1_000;
+        let mut position = position();
+        position.referrer = Pubkey::new_unique();
+        position.referral_rewards = u64::MAX;
+        let at_epoch = |epoch| At {
+            unix_timestamp: 0,
+            slot: 0,
+            epoch,
+        };
+
+        assert_eq!(
+            position.settle(&pool, None, 0, &at_epoch(11)),
+            Err(StakingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn epoch_locks_end_after_the_tier_in_whole_epochs() {
+        let pool = pool(TimeBase::Epochs);
+        let position = position();
+        let at_epoch = |epoch| At {
+            unix_timestamp: 0,
+            slot: 0,
+            epoch,
+        };
+
+        // 30 days is exactly 15 epochs of 432,000 slots at 400 ms
+        assert!(!position.lock_expired(pool.time_base, &at_epoch(24)));
+        assert!(position.lock_expired(pool.time_base, &at_epoch(25)));
+        // Read in unix seconds the same lock has barely started
+        assert!(!position.lock_expired(TimeBase::UnixSeconds, &at_epoch(25)));
+    }
+}
@@ -0,0 +1,3100 @@
+use anchor_lang::error::ErrorCode;
+use anchor_lang::{AccountSerialize, AnchorDeserialize, AnchorSerialize, Discriminator, Space};
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account;
+use anchor_spl::metadata::mpl_token_metadata::accounts::Metadata;
+use anchor_spl::token::spl_token;
+use program_tests::{Account, Svm};
+use solana_system_interface::error::SystemError;
+use stake_with_token_reward::{
+    AirdropClaimed, FeeCollected, FeesWithdrawn, VestingExited, VestingStarted,
+};
+use staking_client::token_rewards::{
+    self, pda, Airdrop, Capacity, Distributor, LockTier, MaxVoterWeightRecord, Pool, PoolParams,
+    ProposalV2Header, RealmV2Header, Reconciliation, Registrar, StakeAccount, StakeAccountV1,
+    Staker, StakingError, TimeBase, TokenMintMetadata, Treasury, VeLock, Vesting, VestingEscrow,
+    VoteRecord, VoterWeightAction, VoterWeightRecord, STAKE_ACCOUNT_VERSION,
+};
+use staking_client::Pubkey;
+
+const SOL: u64 = 1_000_000_000;
+const DAY: i64 = 86_400;
+const MAX_VE_LOCK_DURATION: i64 = 4 * 365 * DAY;
+
+struct Fixture {
+    svm: Svm,
+    authority: Pubkey,
+    user: Pubkey,
+}
+
+impl Fixture {
+    fn position(&self, owner: &Pubkey, index: u32) -> StakeAccount {
+        self.svm.get(&pda::stake_account(owner, index).0)
+    }
+
+    fn create_position(&mut self, owner: &Pubkey, index: u32, lock_tier: LockTier) -> Pubkey {
+        self.svm
+            .process(
+                &[token_rewards::create_pda_account(owner, index, lock_tier)],
+                &[*owner],
+            )
+            .unwrap();
+        pda::stake_account(owner, index).0
+    }
+
+    /// Stakes into `owner`'s position `index`, minting the reward receipt to the owner
+    fn stake(
+        &mut self,
+        owner: &Pubkey,
+        index: u32,
+        amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> Result<(), program_tests::TransactionError> {
+        let position = pda::stake_account(owner, index).0;
+        // The owner's ve lock, once they have one, sets the boost the stake earns
+        let ve_lock = pda::ve_lock(owner).0;
+        let ve_lock = self.svm.account(&ve_lock).map(|_| ve_lock);
+        self.svm.process(
+            &[token_rewards::stake(
+                owner,
+                owner,
+                &position,
+                &self.authority,
+                owner,
+                owner,
+                ve_lock,
+                amount,
+                referrer,
+            )],
+            &[*owner, self.authority],
+        )
+    }
+}
+
+/// The reward mint, a vault and a pool with a 1.5x max boost and 10% referrals for
+/// `authority`, and an empty 30 day position at index 0 for `user`
+fn setup() -> Fixture {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+
+    svm.process(
+        &[
+            token_rewards::create_token_mint(
+                &authority,
+                TokenMintMetadata {
+                    name: "Stake Reward".to_string(),
+                    symbol: "STK".to_string(),
+                    uri: "https://example.com/stk.json".to_string(),
+                    decimals: 9,
+                },
+            ),
+            token_rewards::create_vault_pda_account(&authority),
+            token_rewards::initialize_pool(
+                &authority,
+                PoolParams {
+                    max_boost_bps: 15_000,
+                    referral_bps: 1_000,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    vesting: Default::default(),
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                },
+            ),
+            token_rewards::initialize_treasury(&authority),
+        ],
+        &[authority],
+    )
+    .unwrap();
+
+    let mut fixture = Fixture {
+        svm,
+        authority,
+        user,
+    };
+    fixture.create_position(&user, 0, LockTier::Days30);
+    fixture
+}
+
+fn assert_staking_error(err: program_tests::TransactionError, expected: StakingError) {
+    assert_eq!(err.custom_code(), Some(u32::from(expected)));
+}
+
+#[test]
+fn create_token_mint_creates_the_mint_and_its_metadata() {
+    let Fixture { svm, .. } = setup();
+    let mint = pda::mint().0;
+
+    let state = svm.mint(&mint);
+    assert_eq!(state.decimals, 9);
+    assert_eq!(
+        state.mint_authority,
+        Some(pda::authority().0).into(),
+        "the authority PDA mints rewards"
+    );
+
+    let metadata = svm.account(&pda::metadata(&mint).0).unwrap();
+    assert_eq!(metadata.owner, anchor_spl::metadata::ID);
+    let metadata = Metadata::from_bytes(&metadata.data).unwrap();
+    assert_eq!(metadata.mint, mint);
+    assert_eq!(metadata.update_authority, pda::authority().0);
+    assert_eq!(metadata.name, "Stake Reward");
+    assert_eq!(metadata.symbol, "STK");
+    assert!(!metadata.is_mutable);
+}
+
+#[test]
+fn create_token_mint_twice_fails() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+    svm.warp_by(1);
+
+    let err = svm
+        .process(
+            &[token_rewards::create_token_mint(
+                &authority,
+                TokenMintMetadata {
+                    name: "Again".to_string(),
+                    symbol: "AGN".to_string(),
+                    uri: String::new(),
+                    decimals: 6,
+                },
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn initialize_pool_validates_the_params() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+
+    for (params, expected) in [
+        (
+            PoolParams {
+                max_boost_bps: 9_999,
+                referral_bps: 0,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Default::default(),
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+            },
+            StakingError::InvalidBoost,
+        ),
+        (
+            PoolParams {
+                max_boost_bps: 10_000,
+                referral_bps: 10_001,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Default::default(),
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+            },
+            StakingError::InvalidReferralRate,
+        ),
+        (
+            PoolParams {
+                max_boost_bps: 10_000,
+                referral_bps: 0,
+                fee_bps: 10_001,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Default::default(),
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+            },
+            StakingError::InvalidFeeRate,
+        ),
+        (
+            PoolParams {
+                max_boost_bps: 10_000,
+                referral_bps: 0,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Vesting {
+                    period: 100,
+                    cliff: 101,
+                    early_exit: false,
+                },
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+            },
+            StakingError::InvalidVesting,
+        ),
+        (
+            PoolParams {
+                max_boost_bps: 10_000,
+                referral_bps: 0,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Vesting {
+                    period: MAX_VE_LOCK_DURATION + 1,
+                    cliff: 0,
+                    early_exit: false,
+                },
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+            },
+            StakingError::InvalidVesting,
+        ),
+        (
+            PoolParams {
+                max_boost_bps: 10_000,
+                referral_bps: 0,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Default::default(),
+                min_stake: SOL,
+                max_stake_per_wallet: Some(SOL - 1),
+                max_total_staked: None,
+            },
+            StakingError::InvalidStakeLimits,
+        ),
+    ] {
+        let err = svm
+            .process(
+                &[token_rewards::initialize_pool(&authority, params)],
+                &[authority],
+            )
+            .unwrap_err();
+        assert_staking_error(err, expected);
+    }
+}
+
+#[test]
+fn update_pool_params_changes_the_pool() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    svm.process(
+        &[token_rewards::update_pool_params(
+            &authority,
+            PoolParams {
+                max_boost_bps: 30_000,
+                referral_bps: 0,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                vesting: Default::default(),
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+            },
+        )],
+        &[authority],
+    )
+    .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.authority, authority);
+    assert_eq!(pool.max_boost_bps, 30_000);
+    assert_eq!(pool.referral_bps, 0);
+}
+
+#[test]
+fn update_pool_params_validates_the_params() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    let err = svm
+        .process(
+            &[token_rewards::update_pool_params(
+                &authority,
+                PoolParams {
+                    max_boost_bps: 5_000,
+                    referral_bps: 0,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    vesting: Default::default(),
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                },
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidBoost);
+}
+
+#[test]
+fn create_position_records_index_and_tier() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.create_position(&user, 7, LockTier::Days365);
+
+    let position = fixture.position(&user, 7);
+    assert_eq!(position.owner, user);
+    assert_eq!(position.position_index, 7);
+    assert_eq!(position.lock_tier, LockTier::Days365);
+    assert_eq!(position.lock_start, fixture.svm.clock().unix_timestamp);
+    assert_eq!(position.position_mint, Pubkey::default());
+}
+
+#[test]
+fn stake_moves_lamports_and_mints_the_receipt_and_position_nft() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let vault = pda::vault(&authority).0;
+    let vault_before = fixture.svm.lamports(&vault);
+
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+
+    let position_key = pda::stake_account(&user, 0).0;
+    let position = fixture.position(&user, 0);
+    assert_eq!(position.staked_amount, 2 * SOL);
+    assert_eq!(position.position_mint, pda::position_mint(&position_key).0);
+    assert_eq!(fixture.svm.lamports(&vault), vault_before + 2 * SOL);
+    let pool: Pool = fixture.svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2 * SOL);
+    assert_eq!(
+        fixture.svm.token_balance(&pda::reward_token_account(&user)),
+        2 * SOL
+    );
+
+    let nft = fixture
+        .svm
+        .token_account(&pda::position_token_account(&user, &position_key));
+    assert_eq!(nft.owner, user);
+    assert_eq!(nft.amount, 1);
+    assert_eq!(fixture.svm.mint(&position.position_mint).supply, 1);
+
+    // Staking again tops up the position without minting another NFT
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    assert_eq!(fixture.position(&user, 0).staked_amount, 3 * SOL);
+    assert_eq!(fixture.svm.mint(&position.position_mint).supply, 1);
+}
+
+#[test]
+fn stake_requires_the_vault_authority_signature() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let position = pda::stake_account(&user, 0).0;
+
+    let err = svm
+        .process(
+            &[token_rewards::stake(
+                &user, &user, &position, &authority, &user, &user, None, SOL, None,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotSigner))
+    );
+}
+
+#[test]
+fn stake_into_someone_elses_position_fails() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let stranger = fixture.svm.new_wallet();
+    let position = pda::stake_account(&user, 0).0;
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::stake(
+                &stranger,
+                &user,
+                &position,
+                &fixture.authority,
+                &stranger,
+                &stranger,
+                None,
+                SOL,
+                None,
+            )],
+            &[stranger, fixture.authority],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NotPositionHolder);
+}
+
+#[test]
+fn stake_referring_yourself_fails() {
+    let mut fixture = setup();
+    let user = fixture.user;
+
+    let err = fixture.stake(&user, 0, SOL, Some(user)).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidReferrer);
+}
+
+#[test]
+fn claim_rewards_mints_tier_boosted_points() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let position = fixture.create_position(&user, 1, LockTier::Days90);
+    fixture.stake(&user, 1, 2 * SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::claim_rewards(
+                &user,
+                &user,
+                &position,
+                Some(pda::position_token_account(&user, &position)),
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap();
+
+    // 2 SOL for 100 seconds at the 90 day tier's 1.25x
+    assert_eq!(
+        fixture.svm.token_balance(&pda::reward_token_account(&user)),
+        2 * SOL + 250
+    );
+    assert_eq!(fixture.position(&user, 1).total_points, 0);
+}
+
+/// `setup` with a 10% fee, and 2 SOL staked for 100 seconds in a 90 day position at index 1
+fn fee_fixture() -> (Fixture, Pubkey) {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::update_pool_params(
+                &authority,
+                PoolParams {
+                    max_boost_bps: 15_000,
+                    referral_bps: 1_000,
+                    fee_bps: 1_000,
+                    time_base: TimeBase::UnixSeconds,
+                    vesting: Default::default(),
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                },
+            )],
+            &[authority],
+        )
+        .unwrap();
+    let position = fixture.create_position(&user, 1, LockTier::Days90);
+    fixture.stake(&user, 1, 2 * SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+    (fixture, position)
+}
+
+fn claim(
+    fixture: &mut Fixture,
+    position: &Pubkey,
+    authority: &Pubkey,
+) -> Result<(), program_tests::TransactionError> {
+    let user = fixture.user;
+    fixture.svm.process(
+        &[token_rewards::claim_rewards(
+            &user,
+            &user,
+            position,
+            Some(pda::position_token_account(&user, position)),
+            authority,
+            None,
+        )],
+        &[user],
+    )
+}
+
+#[test]
+fn claim_rewards_mints_the_fee_to_the_treasury() {
+    let (mut fixture, position) = fee_fixture();
+    let authority = fixture.authority;
+
+    claim(&mut fixture, &position, &authority).unwrap();
+
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::reward_token_account(&fixture.user)),
+        2 * SOL + 225
+    );
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::treasury_token_account(&authority).0),
+        25
+    );
+    let treasury: Treasury = fixture.svm.get(&pda::treasury(&authority).0);
+    assert_eq!(treasury.total_fees, 25);
+    let [fee] = &fixture.svm.events::<FeeCollected>()[..] else {
+        panic!("expected one FeeCollected event");
+    };
+    assert_eq!(fee.amount, 25);
+    assert_eq!(fee.position, position);
+}
+
+#[test]
+fn claims_need_the_treasury_only_to_pay_a_fee() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    // A pool whose treasury was never initialized
+    let bare = fixture.svm.new_wallet();
+    let params = PoolParams {
+        max_boost_bps: 15_000,
+        referral_bps: 1_000,
+        fee_bps: 0,
+        time_base: TimeBase::UnixSeconds,
+        vesting: Default::default(),
+        min_stake: 0,
+        max_stake_per_wallet: None,
+        max_total_staked: None,
+    };
+    fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_vault_pda_account(&bare),
+                token_rewards::initialize_pool(&bare, params),
+            ],
+            &[bare],
+        )
+        .unwrap();
+    let position = pda::stake_account(&user, 0).0;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::stake(
+                &user,
+                &user,
+                &position,
+                &bare,
+                &user,
+                &user,
+                None,
+                2 * SOL,
+                None,
+            )],
+            &[user, bare],
+        )
+        .unwrap();
+    fixture.svm.warp_by(100);
+
+    let claim = |authority| {
+        token_rewards::without_treasury(
+            token_rewards::claim_rewards(
+                &user,
+                &user,
+                &position,
+                Some(pda::position_token_account(&user, &position)),
+                authority,
+                None,
+            ),
+            authority,
+        )
+    };
+    fixture.svm.process(&[claim(&bare)], &[user]).unwrap();
+    assert_eq!(
+        fixture.svm.token_balance(&pda::reward_token_account(&user)),
+        2 * SOL + 200
+    );
+
+    let (mut fixture, position) = fee_fixture();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::without_treasury(
+                token_rewards::claim_rewards(
+                    &user,
+                    &user,
+                    &position,
+                    Some(pda::position_token_account(&user, &position)),
+                    &authority,
+                    None,
+                ),
+                &authority,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::TreasuryRequired);
+}
+
+#[test]
+fn claim_rewards_takes_the_fee_of_a_large_balance() {
+    let (mut fixture, position) = fee_fixture();
+    let authority = fixture.authority;
+    let mut staked: StakeAccount = fixture.svm.get(&position);
+    staked.total_points = u64::MAX / 2;
+    let mut account = fixture.svm.account(&position).unwrap();
+    account.data.clear();
+    staked.try_serialize(&mut account.data).unwrap();
+    fixture.svm.set_account(position, account);
+
+    claim(&mut fixture, &position, &authority).unwrap();
+
+    let treasury: Treasury = fixture.svm.get(&pda::treasury(&authority).0);
+    assert_eq!(treasury.total_fees, (u64::MAX / 2 + 250) / 10);
+}
+
+#[test]
+fn claims_through_another_pool_fail() {
+    let (mut fixture, position) = fee_fixture();
+    let user = fixture.user;
+    let referrer = fixture.svm.new_wallet();
+    fixture.stake(&user, 0, SOL, Some(referrer)).unwrap();
+    fixture.svm.warp_by(1_000);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    // A pool of one's own, without the fee
+    let other = other_pool(&mut fixture);
+
+    let err = claim(&mut fixture, &position, &other).unwrap_err();
+    assert_staking_error(err, StakingError::VaultMismatch);
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::claim_referral_rewards(
+                &referrer,
+                &pda::stake_account(&user, 0).0,
+                &other,
+            )],
+            &[referrer],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::VaultMismatch);
+}
+
+#[test]
+fn withdraw_fees_pays_out_of_the_treasury() {
+    let (mut fixture, position) = fee_fixture();
+    let (user, authority) = (fixture.user, fixture.authority);
+    claim(&mut fixture, &position, &authority).unwrap();
+    let destination = pda::reward_token_account(&user);
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::withdraw_fees(&authority, &destination, 26)],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidBalance);
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::withdraw_fees(&authority, &destination, 25)],
+            &[authority],
+        )
+        .unwrap();
+    assert_eq!(fixture.svm.token_balance(&destination), 2 * SOL + 250);
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::treasury_token_account(&authority).0),
+        0
+    );
+    let [withdrawn] = &fixture.svm.events::<FeesWithdrawn>()[..] else {
+        panic!("expected one FeesWithdrawn event");
+    };
+    assert_eq!(withdrawn.destination, destination);
+}
+
+#[test]
+fn withdraw_fees_requires_the_treasury_authority() {
+    let (mut fixture, position) = fee_fixture();
+    let (user, authority) = (fixture.user, fixture.authority);
+    claim(&mut fixture, &position, &authority).unwrap();
+    let destination = pda::reward_token_account(&user);
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::withdraw_fees(&user, &destination, 25)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotInitialized))
+    );
+}
+
+#[test]
+fn claim_rewards_without_points_fails() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let position = pda::stake_account(&user, 0).0;
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::claim_rewards(
+                &user,
+                &user,
+                &position,
+                Some(pda::position_token_account(&user, &position)),
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidPoints);
+}
+
+#[test]
+fn claim_rewards_without_the_position_nft_fails() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+    let position = pda::stake_account(&user, 0).0;
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::claim_rewards(
+                &user,
+                &user,
+                &position,
+                None,
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NotPositionHolder);
+}
+
+fn unstake(
+    fixture: &mut Fixture,
+    holder: &Pubkey,
+    index: u32,
+    amount: u64,
+) -> Result<(), program_tests::TransactionError> {
+    let position = pda::stake_account(&fixture.user, index).0;
+    fixture.svm.process(
+        &[token_rewards::unstake(
+            holder,
+            &fixture.user,
+            &position,
+            Some(pda::position_token_account(holder, &position)),
+            &fixture.authority,
+            None,
+            amount,
+        )],
+        &[*holder],
+    )
+}
+
+#[test]
+fn unstake_is_locked_for_the_tier_duration() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+
+    fixture.svm.warp_by(30 * DAY - 1);
+    let err = unstake(&mut fixture, &user, 0, SOL).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidUnstake);
+
+    fixture.svm.warp_by(1);
+    let before = fixture.svm.lamports(&user);
+    unstake(&mut fixture, &user, 0, SOL).unwrap();
+
+    assert_eq!(fixture.svm.lamports(&user), before + SOL);
+    let position = fixture.position(&user, 0);
+    assert_eq!(position.staked_amount, SOL);
+    assert_eq!(position.total_points, 2 * 30 * DAY as u64);
+    let pool: Pool = fixture.svm.get(&pda::pool(&fixture.authority).0);
+    assert_eq!(pool.total_staked, SOL);
+}
+
+#[test]
+fn adding_stake_restarts_the_lock() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+
+    // Topping up a day before the lock ends locks the whole position for another 30 days
+    fixture.svm.warp_by(29 * DAY);
+    fixture.stake(&user, 0, 10 * SOL, None).unwrap();
+    assert_eq!(
+        fixture.position(&user, 0).lock_start,
+        fixture.svm.clock().unix_timestamp
+    );
+
+    fixture.svm.warp_by(30 * DAY - 1);
+    let err = unstake(&mut fixture, &user, 0, SOL).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidUnstake);
+
+    fixture.svm.warp_by(1);
+    unstake(&mut fixture, &user, 0, 10 * SOL).unwrap();
+}
+
+#[test]
+fn unstake_validates_the_amount() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    fixture.svm.warp_by(30 * DAY);
+
+    let err = unstake(&mut fixture, &user, 0, 0).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidAmount);
+
+    let err = unstake(&mut fixture, &user, 0, SOL).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidBalance);
+}
+
+/// A second vault, pool and treasury, under a new authority
+fn other_pool(fixture: &mut Fixture) -> Pubkey {
+    let other = fixture.svm.new_wallet();
+    let params = PoolParams {
+        max_boost_bps: 15_000,
+        referral_bps: 1_000,
+        fee_bps: 0,
+        time_base: TimeBase::UnixSeconds,
+        vesting: Default::default(),
+        min_stake: 0,
+        max_stake_per_wallet: None,
+        max_total_staked: None,
+    };
+    fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_vault_pda_account(&other),
+                token_rewards::initialize_pool(&other, params),
+                token_rewards::initialize_treasury(&other),
+            ],
+            &[other],
+        )
+        .unwrap();
+    other
+}
+
+#[test]
+fn unstake_from_another_vault_fails() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let other = other_pool(&mut fixture);
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    fixture.svm.airdrop(&pda::vault(&other).0, 10 * SOL);
+    fixture.svm.warp_by(30 * DAY);
+
+    let position = pda::stake_account(&user, 0).0;
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::unstake(
+                &user,
+                &user,
+                &position,
+                Some(pda::position_token_account(&user, &position)),
+                &other,
+                None,
+                SOL,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::VaultMismatch);
+    assert_eq!(
+        fixture.position(&user, 0).vault,
+        pda::vault(&fixture.authority).0
+    );
+}
+
+#[test]
+fn the_position_nft_holder_controls_the_position() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let buyer = fixture.svm.new_wallet();
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    fixture.svm.warp_by(30 * DAY);
+
+    let position = pda::stake_account(&user, 0).0;
+    let position_mint = pda::position_mint(&position).0;
+    fixture
+        .svm
+        .process(
+            &[
+                create_associated_token_account(&buyer, &buyer, &position_mint, &spl_token::ID),
+                spl_token::instruction::transfer(
+                    &spl_token::ID,
+                    &pda::position_token_account(&user, &position),
+                    &pda::position_token_account(&buyer, &position),
+                    &user,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+            ],
+            &[buyer, user],
+        )
+        .unwrap();
+
+    let err = unstake(&mut fixture, &user, 0, SOL).unwrap_err();
+    assert_staking_error(err, StakingError::NotPositionHolder);
+
+    let before = fixture.svm.lamports(&buyer);
+    unstake(&mut fixture, &buyer, 0, SOL).unwrap();
+    assert_eq!(fixture.svm.lamports(&buyer), before + SOL);
+}
+
+#[test]
+fn claim_referral_rewards_mints_to_the_referrer() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let referrer = fixture.svm.new_wallet();
+    let position = pda::stake_account(&user, 0).0;
+    fixture.stake(&user, 0, SOL, Some(referrer)).unwrap();
+    fixture.svm.warp_by(1_000);
+    // Accrue 1 SOL for 1000 seconds, of which the referrer gets 10% on top
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    assert_eq!(fixture.position(&user, 0).referral_rewards, 100);
+
+    let impostor = fixture.svm.new_wallet();
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::claim_referral_rewards(
+                &impostor,
+                &position,
+                &fixture.authority,
+            )],
+            &[impostor],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidReferrer);
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::claim_referral_rewards(
+                &referrer,
+                &position,
+                &fixture.authority,
+            )],
+            &[referrer],
+        )
+        .unwrap();
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::reward_token_account(&referrer)),
+        100
+    );
+    assert_eq!(fixture.position(&user, 0).referral_rewards, 0);
+
+    fixture.svm.warp_by(1);
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::claim_referral_rewards(
+                &referrer,
+                &position,
+                &fixture.authority,
+            )],
+            &[referrer],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidPoints);
+}
+
+#[test]
+fn lock_reward_tokens_validates_amount_and_duration() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let tokens = pda::reward_token_account(&user);
+
+    for (amount, duration, expected) in [
+        (0, DAY, StakingError::InvalidAmount),
+        (SOL, 0, StakingError::InvalidLockDuration),
+        (
+            SOL,
+            MAX_VE_LOCK_DURATION + 1,
+            StakingError::InvalidLockDuration,
+        ),
+    ] {
+        let err = fixture
+            .svm
+            .process(
+                &[token_rewards::lock_reward_tokens(
+                    &user, &tokens, amount, duration,
+                )],
+                &[user],
+            )
+            .unwrap_err();
+        assert_staking_error(err, expected);
+    }
+}
+
+#[test]
+fn locked_reward_tokens_unlock_when_the_lock_ends() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let tokens = fixture.svm.claimed_reward_tokens(&user, SOL);
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::lock_reward_tokens(
+                &user,
+                &tokens,
+                SOL / 2,
+                10 * DAY,
+            )],
+            &[user],
+        )
+        .unwrap();
+
+    let lock: VeLock = fixture.svm.get(&pda::ve_lock(&user).0);
+    assert_eq!(lock.owner, user);
+    assert_eq!(lock.locked_amount, SOL / 2);
+    assert_eq!(lock.lock_end, fixture.svm.clock().unix_timestamp + 10 * DAY);
+    assert_eq!(fixture.svm.token_balance(&tokens), SOL / 2);
+    assert_eq!(fixture.svm.token_balance(&pda::ve_vault().0), SOL / 2);
+
+    fixture.svm.warp_by(10 * DAY - 1);
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::unlock_reward_tokens(&user, &tokens)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::LockNotExpired);
+
+    fixture.svm.warp_by(1);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::unlock_reward_tokens(&user, &tokens)],
+            &[user],
+        )
+        .unwrap();
+    assert_eq!(fixture.svm.token_balance(&tokens), SOL);
+    assert_eq!(fixture.svm.token_balance(&pda::ve_vault().0), 0);
+
+    fixture.svm.warp_by(1);
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::unlock_reward_tokens(&user, &tokens)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidBalance);
+}
+
+/// Locks `amount` of `user`'s reward tokens, which they are credited with claiming, for the
+/// maximum duration
+fn lock_claimed(fixture: &mut Fixture, user: &Pubkey, amount: u64) {
+    let tokens = fixture.svm.claimed_reward_tokens(user, amount);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::lock_reward_tokens(
+                user,
+                &tokens,
+                amount,
+                MAX_VE_LOCK_DURATION,
+            )],
+            &[*user],
+        )
+        .unwrap();
+}
+
+/// Claims `user`'s own position `position` and returns how many reward tokens it paid out
+fn claim_boosted(fixture: &mut Fixture, user: &Pubkey, position: &Pubkey) -> u64 {
+    let tokens = pda::reward_token_account(user);
+    let before = fixture.svm.token_balance(&tokens);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::claim_rewards(
+                user,
+                user,
+                position,
+                Some(pda::position_token_account(user, position)),
+                &fixture.authority,
+                None,
+            )],
+            &[*user],
+        )
+        .unwrap();
+    fixture.svm.token_balance(&tokens) - before
+}
+
+#[test]
+fn a_ve_lock_boosts_rewards_up_to_the_pool_cap() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let position = pda::stake_account(&user, 0).0;
+
+    // Locking as many claimed reward tokens as there are staked lamports for the maximum
+    // nearly doubles the weight, capped at 1.5x
+    lock_claimed(&mut fixture, &user, SOL);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+
+    assert_eq!(claim_boosted(&mut fixture, &user, &position), 150);
+}
+
+#[test]
+fn a_new_ve_lock_only_boosts_from_the_next_settlement() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let position = pda::stake_account(&user, 0).0;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+
+    // Locking just before claiming does not boost the time already staked
+    lock_claimed(&mut fixture, &user, SOL);
+    assert_eq!(claim_boosted(&mut fixture, &user, &position), 100);
+
+    fixture.svm.warp_by(100);
+    assert_eq!(claim_boosted(&mut fixture, &user, &position), 150);
+}
+
+#[test]
+fn a_ve_lock_boosts_the_owners_stake_across_positions_once() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let first = pda::stake_account(&user, 0).0;
+    let second = fixture.create_position(&user, 1, LockTier::Days30);
+
+    // Half a SOL of ve balance against 2 SOL staked is a 1.25x boost on each position,
+    // where each alone would have reached the cap
+    lock_claimed(&mut fixture, &user, SOL / 2);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    fixture.stake(&user, 1, SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+
+    assert_eq!(claim_boosted(&mut fixture, &user, &first), 124);
+    assert_eq!(claim_boosted(&mut fixture, &user, &second), 124);
+}
+
+#[test]
+fn only_claimed_reward_tokens_can_be_ve_locked() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let position = pda::stake_account(&user, 0).0;
+    let tokens = pda::reward_token_account(&user);
+    let lock = |fixture: &mut Fixture, amount| {
+        fixture.svm.process(
+            &[token_rewards::lock_reward_tokens(
+                &user,
+                &tokens,
+                amount,
+                MAX_VE_LOCK_DURATION,
+            )],
+            &[user],
+        )
+    };
+
+    // The tokens minted for the stake are not rewards, so they cannot boost it
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let err = lock(&mut fixture, 1).unwrap_err();
+    assert_staking_error(err, StakingError::NotClaimed);
+
+    fixture.svm.warp_by(100);
+    let authority = fixture.authority;
+    claim(&mut fixture, &position, &authority).unwrap();
+    let ve_lock: VeLock = fixture.svm.get(&pda::ve_lock(&user).0);
+    assert_eq!(ve_lock.owner, user);
+    assert_eq!(ve_lock.claimed, 100);
+
+    lock(&mut fixture, 100).unwrap();
+    let err = lock(&mut fixture, 1).unwrap_err();
+    assert_staking_error(err, StakingError::NotClaimed);
+}
+
+/// A stand-in governance program and a realm of it over the reward mint, whose authority
+/// is a fresh wallet. Returns the governance program, the realm and its authority.
+fn governance_realm(fixture: &mut Fixture) -> (Pubkey, Pubkey, Pubkey) {
+    let governance = Pubkey::new_unique();
+    let realm = Pubkey::new_unique();
+    let realm_authority = fixture.svm.new_wallet();
+    fixture.svm.set_account(
+        governance,
+        Account {
+            lamports: 1,
+            owner: solana_sdk_ids::native_loader::ID,
+            executable: true,
+            ..Account::default()
+        },
+    );
+    let header = RealmV2Header {
+        account_type: RealmV2Header::ACCOUNT_TYPE,
+        community_mint: pda::mint().0,
+        config_legacy: [0; 8],
+        min_community_weight_to_create_governance: 1,
+        community_mint_max_voter_weight_source: (0, 10_000_000_000),
+        council_mint: None,
+        reserved: [0; 6],
+        legacy1: 0,
+        authority: Some(realm_authority),
+    };
+    // The realm name and reserved space follow the header
+    let mut data = header.try_to_vec().unwrap();
+    data.extend([0; 4 + 128]);
+    fixture.svm.set_account(
+        realm,
+        Account {
+            lamports: SOL,
+            owner: governance,
+            data,
+            ..Account::default()
+        },
+    );
+    (governance, realm, realm_authority)
+}
+
+/// A registrar over `authority`'s pool for a realm of a stand-in governance program
+fn registrar(fixture: &mut Fixture, lock_weighted: bool) -> Pubkey {
+    let (governance, realm, realm_authority) = governance_realm(fixture);
+    let mint = pda::mint().0;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::create_registrar(
+                &fixture.authority,
+                &realm_authority,
+                &governance,
+                &realm,
+                &mint,
+                lock_weighted,
+            )],
+            &[fixture.authority, realm_authority],
+        )
+        .unwrap();
+    pda::registrar(&realm, &mint).0
+}
+
+#[test]
+fn create_registrar_requires_a_realm_of_the_governance_program() {
+    let mut fixture = setup();
+    let authority = fixture.authority;
+    let governance = Pubkey::new_unique();
+    let realm = fixture.svm.new_wallet();
+    fixture.svm.set_account(
+        governance,
+        Account {
+            lamports: 1,
+            owner: solana_sdk_ids::native_loader::ID,
+            executable: true,
+            ..Account::default()
+        },
+    );
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::create_registrar(
+                &authority,
+                &authority,
+                &governance,
+                &realm,
+                &pda::mint().0,
+                false,
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::ConstraintOwner))
+    );
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::create_registrar(
+                &authority,
+                &authority,
+                &realm,
+                &realm,
+                &pda::mint().0,
+                false,
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::ConstraintExecutable))
+    );
+}
+
+#[test]
+fn create_registrar_requires_the_realm_authority_and_a_governing_mint() {
+    let mut fixture = setup();
+    let authority = fixture.authority;
+    let (governance, realm, realm_authority) = governance_realm(&mut fixture);
+    let create = |realm_authority: &Pubkey, mint: &Pubkey| {
+        token_rewards::create_registrar(
+            &authority,
+            realm_authority,
+            &governance,
+            &realm,
+            mint,
+            false,
+        )
+    };
+
+    // The pool authority cannot plug its pool into someone else's realm
+    let err = fixture
+        .svm
+        .process(&[create(&authority, &pda::mint().0)], &[authority])
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NotRealmAuthority);
+
+    // Nor can the realm authority register a mint the realm does not govern, such as the
+    // mint of a position NFT
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let position = pda::stake_account(&user, 0).0;
+    let err = fixture
+        .svm
+        .process(
+            &[create(&realm_authority, &pda::position_mint(&position).0)],
+            &[authority, realm_authority],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidRealm);
+
+    // Accounts of the governance program other than realms are refused
+    let mut account = fixture.svm.account(&realm).unwrap();
+    account.data[0] = RealmV2Header::ACCOUNT_TYPE + 1;
+    fixture.svm.set_account(realm, account);
+    let err = fixture
+        .svm
+        .process(
+            &[create(&realm_authority, &pda::mint().0)],
+            &[authority, realm_authority],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidRealm);
+}
+
+#[test]
+fn voter_weight_counts_every_position_of_the_voter() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let registrar_key = registrar(&mut fixture, false);
+    let registrar: Registrar = fixture.svm.get(&registrar_key);
+    let realm = registrar.realm;
+    assert_eq!(registrar.pool, pda::pool(&authority).0);
+    assert!(!registrar.lock_weighted);
+
+    let second = fixture.create_position(&user, 1, LockTier::Days90);
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    fixture.stake(&user, 1, 3 * SOL, None).unwrap();
+    let first = pda::stake_account(&user, 0).0;
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::create_voter_weight_record(
+                &user,
+                &registrar_key,
+            )],
+            &[user],
+        )
+        .unwrap();
+    let slot = fixture.svm.clock().slot;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::update_voter_weight_record(
+                &user,
+                &registrar_key,
+                &pda::pool(&authority).0,
+                None,
+                VoterWeightAction::CreateGovernance,
+                &realm,
+                &[
+                    (first, Some(pda::position_token_account(&user, &first))),
+                    (second, Some(pda::position_token_account(&user, &second))),
+                ],
+            )],
+            &[user],
+        )
+        .unwrap();
+
+    let record: VoterWeightRecord = fixture
+        .svm
+        .get(&pda::voter_weight_record(&registrar_key, &user).0);
+    assert_eq!(record.governing_token_owner, user);
+    assert_eq!(record.voter_weight, 5 * SOL);
+    assert_eq!(record.voter_weight_expiry, Some(slot));
+    // The weight only serves the action it was updated for
+    assert_eq!(
+        record.weight_action,
+        Some(VoterWeightAction::CreateGovernance)
+    );
+    assert_eq!(record.weight_action_target, Some(realm));
+}
+
+#[test]
+fn voter_weight_of_a_voter_without_positions_is_zero() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let registrar_key = registrar(&mut fixture, true);
+    let realm = fixture.svm.get::<Registrar>(&registrar_key).realm;
+    // Other voters' stake does not count
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let voter = fixture.svm.new_wallet();
+    let slot = fixture.svm.clock().slot;
+
+    fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_voter_weight_record(&voter, &registrar_key),
+                token_rewards::update_voter_weight_record(
+                    &voter,
+                    &registrar_key,
+                    &pda::pool(&authority).0,
+                    None,
+                    VoterWeightAction::CreateGovernance,
+                    &realm,
+                    &[],
+                ),
+            ],
+            &[voter],
+        )
+        .unwrap();
+
+    let record: VoterWeightRecord = fixture
+        .svm
+        .get(&pda::voter_weight_record(&registrar_key, &voter).0);
+    assert_eq!(record.voter_weight, 0);
+    assert_eq!(record.voter_weight_expiry, Some(slot));
+}
+
+#[test]
+fn voter_weight_counts_a_position_once() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let registrar_key = registrar(&mut fixture, false);
+    let realm = fixture.svm.get::<Registrar>(&registrar_key).realm;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let position = pda::stake_account(&user, 0).0;
+    let nft = pda::position_token_account(&user, &position);
+
+    let err = fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_voter_weight_record(&user, &registrar_key),
+                token_rewards::update_voter_weight_record(
+                    &user,
+                    &registrar_key,
+                    &pda::pool(&authority).0,
+                    None,
+                    VoterWeightAction::CreateGovernance,
+                    &realm,
+                    &[(position, Some(nft)), (position, Some(nft))],
+                ),
+            ],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::PositionMismatch);
+}
+
+#[test]
+fn voter_weight_reads_untokenized_positions_without_a_token_account() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let registrar_key = registrar(&mut fixture, false);
+    let realm = fixture.svm.get::<Registrar>(&registrar_key).realm;
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    let first = pda::stake_account(&user, 0).0;
+    let third = fixture.create_position(&user, 2, LockTier::Days30);
+    fixture.stake(&user, 2, SOL, None).unwrap();
+
+    // A position staked before positions were tokenized
+    let untokenized = fixture.create_position(&user, 1, LockTier::Days30);
+    fixture.stake(&user, 1, SOL, None).unwrap();
+    let mut position = fixture.position(&user, 1);
+    position.position_mint = Pubkey::default();
+    let mut account = fixture.svm.account(&untokenized).unwrap();
+    account.data.clear();
+    position.try_serialize(&mut account.data).unwrap();
+    fixture.svm.set_account(untokenized, account);
+
+    fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_voter_weight_record(&user, &registrar_key),
+                token_rewards::update_voter_weight_record(
+                    &user,
+                    &registrar_key,
+                    &pda::pool(&authority).0,
+                    None,
+                    VoterWeightAction::CreateGovernance,
+                    &realm,
+                    &[
+                        (first, Some(pda::position_token_account(&user, &first))),
+                        (untokenized, None),
+                        (third, Some(pda::position_token_account(&user, &third))),
+                    ],
+                ),
+            ],
+            &[user],
+        )
+        .unwrap();
+
+    let record: VoterWeightRecord = fixture
+        .svm
+        .get(&pda::voter_weight_record(&registrar_key, &user).0);
+    assert_eq!(record.voter_weight, 4 * SOL);
+}
+
+#[test]
+fn voter_weight_only_counts_positions_of_the_registrar_pool() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let registrar_key = registrar(&mut fixture, false);
+    let realm = fixture.svm.get::<Registrar>(&registrar_key).realm;
+    let other = other_pool(&mut fixture);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let first = pda::stake_account(&user, 0).0;
+    let elsewhere = fixture.create_position(&user, 1, LockTier::Days30);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::stake(
+                &user,
+                &user,
+                &elsewhere,
+                &other,
+                &user,
+                &user,
+                None,
+                10 * SOL,
+                None,
+            )],
+            &[user, other],
+        )
+        .unwrap();
+
+    let err = fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_voter_weight_record(&user, &registrar_key),
+                token_rewards::update_voter_weight_record(
+                    &user,
+                    &registrar_key,
+                    &pda::pool(&authority).0,
+                    None,
+                    VoterWeightAction::CreateGovernance,
+                    &realm,
+                    &[
+                        (first, Some(pda::position_token_account(&user, &first))),
+                        (
+                            elsewhere,
+                            Some(pda::position_token_account(&user, &elsewhere)),
+                        ),
+                    ],
+                ),
+            ],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::VaultMismatch);
+}
+
+#[test]
+fn max_voter_weight_is_the_boosted_tracked_principal() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    let registrar_key = registrar(&mut fixture, true);
+    fixture.stake(&user, 0, 4 * SOL, None).unwrap();
+    // Lamports sent straight to the vault are not staked by anyone
+    fixture.svm.airdrop(&pda::vault(&authority).0, 100 * SOL);
+    let slot = fixture.svm.clock().slot;
+
+    fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_max_voter_weight_record(&authority, &registrar_key),
+                token_rewards::update_max_voter_weight_record(&registrar_key, &authority),
+            ],
+            &[authority],
+        )
+        .unwrap();
+
+    let record: MaxVoterWeightRecord = fixture
+        .svm
+        .get(&pda::max_voter_weight_record(&registrar_key).0);
+    assert_eq!(record.max_voter_weight, 6 * SOL);
+    assert_eq!(record.max_voter_weight_expiry, Some(slot));
+}
+
+/// Writes a proposal of the registrar's governance program on the reward mint, in `state`
+fn set_proposal(fixture: &mut Fixture, registrar_key: &Pubkey, proposal: Pubkey, state: u8) {
+    let registrar: Registrar = fixture.svm.get(registrar_key);
+    let header = ProposalV2Header {
+        account_type: ProposalV2Header::ACCOUNT_TYPE,
+        governance: Pubkey::new_unique(),
+        governing_token_mint: registrar.governing_token_mint,
+        state,
+    };
+    // The rest of the proposal follows the header
+    let mut data = header.try_to_vec().unwrap();
+    data.extend([0; 64]);
+    fixture.svm.set_account(
+        proposal,
+        Account {
+            lamports: SOL,
+            owner: registrar.governance_program_id,
+            data,
+            ..Account::default()
+        },
+    );
+}
+
+/// Updates `voter`'s weight to cast a vote on `proposal` with their `positions`
+fn cast_vote(
+    fixture: &mut Fixture,
+    voter: &Pubkey,
+    registrar_key: &Pubkey,
+    proposal: &Pubkey,
+    positions: &[(Pubkey, Option<Pubkey>)],
+) -> Result<(), program_tests::TransactionError> {
+    fixture.svm.process(
+        &[token_rewards::update_voter_weight_record(
+            voter,
+            registrar_key,
+            &pda::pool(&fixture.authority).0,
+            None,
+            VoterWeightAction::CastVote,
+            proposal,
+            positions,
+        )],
+        &[*voter],
+    )
+}
+
+#[test]
+fn casting_a_vote_counts_each_position_once_per_proposal() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let buyer = fixture.svm.new_wallet();
+    let registrar_key = registrar(&mut fixture, false);
+    let proposal = Pubkey::new_unique();
+    set_proposal(
+        &mut fixture,
+        &registrar_key,
+        proposal,
+        ProposalV2Header::VOTING,
+    );
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let position = pda::stake_account(&user, 0).0;
+    let position_mint = pda::position_mint(&position).0;
+
+    fixture
+        .svm
+        .process(
+            &[
+                token_rewards::create_voter_weight_record(&user, &registrar_key),
+                token_rewards::create_voter_weight_record(&buyer, &registrar_key),
+            ],
+            &[user, buyer],
+        )
+        .unwrap();
+    cast_vote(
+        &mut fixture,
+        &user,
+        &registrar_key,
+        &proposal,
+        &[(
+            position,
+            Some(pda::position_token_account(&user, &position)),
+        )],
+    )
+    .unwrap();
+
+    let record: VoterWeightRecord = fixture
+        .svm
+        .get(&pda::voter_weight_record(&registrar_key, &user).0);
+    assert_eq!(record.voter_weight, SOL);
+    assert_eq!(record.weight_action, Some(VoterWeightAction::CastVote));
+    assert_eq!(record.weight_action_target, Some(proposal));
+    let vote_record: VoteRecord = fixture.svm.get(&pda::vote_record(&proposal, &position).0);
+    assert_eq!(vote_record.registrar, registrar_key);
+    assert_eq!(vote_record.position, position);
+    assert_eq!(vote_record.voter, user);
+    assert_eq!(fixture.position(&user, 0).active_votes, 1);
+
+    // Selling the position NFT does not let the stake vote on the proposal again
+    fixture
+        .svm
+        .process(
+            &[
+                create_associated_token_account(&buyer, &buyer, &position_mint, &spl_token::ID),
+                spl_token::instruction::transfer(
+                    &spl_token::ID,
+                    &pda::position_token_account(&user, &position),
+                    &pda::position_token_account(&buyer, &position),
+                    &user,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+            ],
+            &[buyer, user],
+        )
+        .unwrap();
+    let nft = Some(pda::position_token_account(&buyer, &position));
+    let err = cast_vote(
+        &mut fixture,
+        &buyer,
+        &registrar_key,
+        &proposal,
+        &[(position, nft)],
+    )
+    .unwrap_err();
+    assert_staking_error(err, StakingError::AlreadyVoted);
+
+    // It still votes on other proposals
+    let other = Pubkey::new_unique();
+    set_proposal(
+        &mut fixture,
+        &registrar_key,
+        other,
+        ProposalV2Header::VOTING,
+    );
+    cast_vote(
+        &mut fixture,
+        &buyer,
+        &registrar_key,
+        &other,
+        &[(position, nft)],
+    )
+    .unwrap();
+    assert_eq!(fixture.position(&user, 0).active_votes, 2);
+}
+
+#[test]
+fn casting_a_vote_requires_a_proposal_being_voted_on() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let registrar_key = registrar(&mut fixture, false);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let position = pda::stake_account(&user, 0).0;
+    let positions = [(
+        position,
+        Some(pda::position_token_account(&user, &position)),
+    )];
+    fixture
+        .svm
+        .process(
+            &[token_rewards::create_voter_weight_record(
+                &user,
+                &registrar_key,
+            )],
+            &[user],
+        )
+        .unwrap();
+
+    // A draft is not open for votes yet
+    let proposal = Pubkey::new_unique();
+    set_proposal(&mut fixture, &registrar_key, proposal, 0);
+    let err = cast_vote(&mut fixture, &user, &registrar_key, &proposal, &positions).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidProposal);
+
+    // Nor does a proposal on another mint count
+    set_proposal(
+        &mut fixture,
+        &registrar_key,
+        proposal,
+        ProposalV2Header::VOTING,
+    );
+    let mut account = fixture.svm.account(&proposal).unwrap();
+    account.data[33..65].copy_from_slice(Pubkey::new_unique().as_ref());
+    fixture.svm.set_account(proposal, account);
+    let err = cast_vote(&mut fixture, &user, &registrar_key, &proposal, &positions).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidProposal);
+
+    // Nor an account the governance program does not own
+    let stranger = fixture.svm.new_wallet();
+    let err = cast_vote(&mut fixture, &user, &registrar_key, &stranger, &positions).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidProposal);
+}
+
+#[test]
+fn positions_with_an_open_vote_cannot_move_their_stake() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let registrar_key = registrar(&mut fixture, false);
+    let proposal = Pubkey::new_unique();
+    set_proposal(
+        &mut fixture,
+        &registrar_key,
+        proposal,
+        ProposalV2Header::VOTING,
+    );
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    fixture.svm.warp_by(30 * DAY);
+    let position = pda::stake_account(&user, 0).0;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::create_voter_weight_record(
+                &user,
+                &registrar_key,
+            )],
+            &[user],
+        )
+        .unwrap();
+    cast_vote(
+        &mut fixture,
+        &user,
+        &registrar_key,
+        &proposal,
+        &[(
+            position,
+            Some(pda::position_token_account(&user, &position)),
+        )],
+    )
+    .unwrap();
+
+    let err = unstake(&mut fixture, &user, 0, SOL).unwrap_err();
+    assert_staking_error(err, StakingError::PositionVoting);
+    let err = split(&mut fixture, SOL, 1).unwrap_err();
+    assert_staking_error(err, StakingError::PositionVoting);
+
+    // The vote record stays until the proposal is decided
+    let relinquish = token_rewards::relinquish_vote(&user, &registrar_key, &position, &proposal);
+    let err = fixture
+        .svm
+        .process(std::slice::from_ref(&relinquish), &[user])
+        .unwrap_err();
+    assert_staking_error(err, StakingError::PositionVoting);
+
+    // Anyone may close it then, refunding the voter
+    set_proposal(
+        &mut fixture,
+        &registrar_key,
+        proposal,
+        ProposalV2Header::VOTING + 1,
+    );
+    let vote_record = pda::vote_record(&proposal, &position).0;
+    let rent = fixture.svm.lamports(&vote_record);
+    let before = fixture.svm.lamports(&user);
+    let stranger = fixture.svm.new_wallet();
+    fixture.svm.process(&[relinquish], &[stranger]).unwrap();
+    assert!(fixture.svm.account(&vote_record).is_none());
+    assert_eq!(fixture.svm.lamports(&user), before + rent);
+    assert_eq!(fixture.position(&user, 0).active_votes, 0);
+
+    unstake(&mut fixture, &user, 0, SOL).unwrap();
+}
+
+#[test]
+fn transfer_position_moves_an_untokenized_position() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let new_owner = Pubkey::new_unique();
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::transfer_position(
+                &user, 0, &new_owner, 3, None,
+            )],
+            &[user],
+        )
+        .unwrap();
+
+    assert!(fixture
+        .svm
+        .account(&pda::stake_account(&user, 0).0)
+        .is_none());
+    let position = fixture.position(&new_owner, 3);
+    assert_eq!(position.owner, new_owner);
+    assert_eq!(position.position_index, 3);
+    assert_eq!(position.lock_tier, LockTier::Days30);
+}
+
+#[test]
+fn transfer_position_rejects_tokenized_positions_and_self_transfers() {
+    let mut fixture = setup();
+    let user = fixture.user;
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::transfer_position(&user, 0, &user, 1, None)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidNewOwner);
+
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::transfer_position(
+                &user,
+                0,
+                &Pubkey::new_unique(),
+                0,
+                Some(&fixture.authority),
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::PositionTokenized);
+}
+
+fn split(
+    fixture: &mut Fixture,
+    amount: u64,
+    new_index: u32,
+) -> Result<(), program_tests::TransactionError> {
+    let user = fixture.user;
+    let position = pda::stake_account(&user, 0).0;
+    fixture.svm.process(
+        &[token_rewards::split_position(
+            &user,
+            &user,
+            &position,
+            Some(pda::position_token_account(&user, &position)),
+            &fixture.authority,
+            None,
+            amount,
+            new_index,
+        )],
+        &[user],
+    )
+}
+
+#[test]
+fn split_position_carves_out_a_new_tokenized_position() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, 3 * SOL, None).unwrap();
+
+    split(&mut fixture, SOL, 1).unwrap();
+
+    let source = fixture.position(&user, 0);
+    let split_off = fixture.position(&user, 1);
+    assert_eq!(source.staked_amount, 2 * SOL);
+    assert_eq!(split_off.staked_amount, SOL);
+    assert_eq!(split_off.lock_start, source.lock_start);
+    assert_eq!(split_off.lock_tier, source.lock_tier);
+    assert_eq!(split_off.vault, source.vault);
+
+    let new_position = pda::stake_account(&user, 1).0;
+    assert_eq!(split_off.position_mint, pda::position_mint(&new_position).0);
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::position_token_account(&user, &new_position)),
+        1
+    );
+}
+
+#[test]
+fn split_position_validates_the_amount() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, SOL, None).unwrap();
+
+    let err = split(&mut fixture, 0, 1).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidAmount);
+
+    let err = split(&mut fixture, SOL, 1).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidBalance);
+}
+
+#[test]
+fn merge_positions_folds_the_source_into_the_destination() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, 3 * SOL, None).unwrap();
+    split(&mut fixture, SOL, 1).unwrap();
+
+    let destination = pda::stake_account(&user, 0).0;
+    let source = pda::stake_account(&user, 1).0;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::merge_positions(
+                &user,
+                &user,
+                &source,
+                None,
+                &destination,
+                Some(pda::position_token_account(&user, &source)),
+                Some(pda::position_token_account(&user, &destination)),
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap();
+
+    assert!(fixture.svm.account(&source).is_none());
+    assert_eq!(fixture.position(&user, 0).staked_amount, 3 * SOL);
+    assert_eq!(fixture.svm.mint(&pda::position_mint(&source).0).supply, 0);
+}
+
+#[test]
+fn merge_positions_requires_matching_distinct_positions() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let destination = pda::stake_account(&user, 0).0;
+    let other_tier = fixture.create_position(&user, 1, LockTier::Days180);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    fixture.stake(&user, 1, SOL, None).unwrap();
+
+    for source in [destination, other_tier] {
+        let err = fixture
+            .svm
+            .process(
+                &[token_rewards::merge_positions(
+                    &user,
+                    &user,
+                    &source,
+                    None,
+                    &destination,
+                    Some(pda::position_token_account(&user, &source)),
+                    Some(pda::position_token_account(&user, &destination)),
+                    &fixture.authority,
+                    None,
+                )],
+                &[user],
+            )
+            .unwrap_err();
+        assert_staking_error(err, StakingError::PositionMismatch);
+    }
+}
+
+#[test]
+fn merge_positions_requires_a_shared_vault() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    let other = other_pool(&mut fixture);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    let source = fixture.create_position(&user, 1, LockTier::Days30);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::stake(
+                &user, &user, &source, &other, &user, &user, None, SOL, None,
+            )],
+            &[user, other],
+        )
+        .unwrap();
+
+    let destination = pda::stake_account(&user, 0).0;
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::merge_positions(
+                &user,
+                &user,
+                &source,
+                None,
+                &destination,
+                Some(pda::position_token_account(&user, &source)),
+                Some(pda::position_token_account(&user, &destination)),
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::VaultMismatch);
+}
+
+#[test]
+fn merging_a_tokenized_source_without_its_nft_fails() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, 3 * SOL, None).unwrap();
+    split(&mut fixture, SOL, 1).unwrap();
+
+    let destination = pda::stake_account(&user, 0).0;
+    let source = pda::stake_account(&user, 1).0;
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::merge_positions(
+                &user,
+                &user,
+                &source,
+                None,
+                &destination,
+                None,
+                Some(pda::position_token_account(&user, &destination)),
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NotPositionHolder);
+}
+
+/// `setup` with claims vesting per `vesting`, and 2 SOL staked in `user`'s position for 1000
+/// seconds
+fn vesting_fixture(vesting: Vesting) -> (Fixture, Pubkey) {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::update_pool_params(
+                &authority,
+                PoolParams {
+                    max_boost_bps: 15_000,
+                    referral_bps: 1_000,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    vesting,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                },
+            )],
+            &[authority],
+        )
+        .unwrap();
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    fixture.svm.warp_by(1_000);
+    (fixture, pda::stake_account(&user, 0).0)
+}
+
+/// Claims `user`'s position into the pool's next vesting escrow and returns the escrow
+fn claim_vesting(fixture: &mut Fixture, position: &Pubkey) -> Pubkey {
+    let user = fixture.user;
+    let pool = pda::pool(&fixture.authority).0;
+    let escrow = pda::vesting_escrow(&pool, fixture.svm.get::<Pool>(&pool).vesting_claims).0;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::claim_rewards(
+                &user,
+                &user,
+                position,
+                Some(pda::position_token_account(&user, position)),
+                &fixture.authority,
+                Some(escrow),
+            )],
+            &[user],
+        )
+        .unwrap();
+    escrow
+}
+
+fn withdraw_vested(
+    fixture: &mut Fixture,
+    beneficiary: &Pubkey,
+    escrow: &Pubkey,
+) -> Result<(), program_tests::TransactionError> {
+    fixture.svm.process(
+        &[token_rewards::withdraw_vested(beneficiary, escrow)],
+        &[*beneficiary],
+    )
+}
+
+#[test]
+fn claims_on_a_vesting_pool_open_an_escrow_instead_of_minting() {
+    let (mut fixture, position) = vesting_fixture(Vesting {
+        period: 1_000,
+        cliff: 0,
+        early_exit: false,
+    });
+    let (user, authority) = (fixture.user, fixture.authority);
+
+    // Without an escrow the claim is refused rather than paid out liquid
+    let err = claim(&mut fixture, &position, &authority).unwrap_err();
+    assert_staking_error(err, StakingError::VestingEscrowMismatch);
+
+    let escrow = claim_vesting(&mut fixture, &position);
+
+    // Only the 2 SOL stake receipt, the 2000 reward tokens are not minted yet
+    assert_eq!(
+        fixture.svm.token_balance(&pda::reward_token_account(&user)),
+        2 * SOL
+    );
+    let state: VestingEscrow = fixture.svm.get(&escrow);
+    assert_eq!(state.beneficiary, user);
+    assert_eq!(state.position, position);
+    assert_eq!(
+        (state.pool, state.claim_index),
+        (pda::pool(&authority).0, 0)
+    );
+    assert_eq!((state.total, state.withdrawn), (2_000, 0));
+    assert_eq!(fixture.position(&user, 0).total_points, 0);
+    let pool: Pool = fixture.svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.vesting_claims, 1);
+    let [started] = &fixture.svm.events::<VestingStarted>()[..] else {
+        panic!("expected one VestingStarted event");
+    };
+    assert_eq!(started.amount, 2_000);
+    assert_eq!(started.end - started.cliff_end, 1_000);
+
+    // The next claim gets an escrow of its own
+    fixture.svm.warp_by(500);
+    let second = claim_vesting(&mut fixture, &position);
+    assert_ne!(second, escrow);
+    assert_eq!(fixture.svm.get::<VestingEscrow>(&second).total, 1_000);
+}
+
+#[test]
+fn a_recreated_position_gets_fresh_vesting_escrows() {
+    let (mut fixture, destination) = vesting_fixture(Vesting {
+        period: 1_000,
+        cliff: 0,
+        early_exit: false,
+    });
+    let user = fixture.user;
+    let source = pda::stake_account(&user, 1).0;
+    split(&mut fixture, SOL, 1).unwrap();
+    fixture.svm.warp_by(100);
+    let first = claim_vesting(&mut fixture, &source);
+
+    // Merging closes the source, it is then re-created at the same address
+    fixture
+        .svm
+        .process(
+            &[token_rewards::merge_positions(
+                &user,
+                &user,
+                &source,
+                None,
+                &destination,
+                Some(pda::position_token_account(&user, &source)),
+                Some(pda::position_token_account(&user, &destination)),
+                &fixture.authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap();
+    fixture.create_position(&user, 1, LockTier::Days30);
+    fixture.stake(&user, 1, SOL, None).unwrap();
+    fixture.svm.warp_by(100);
+    let second = claim_vesting(&mut fixture, &source);
+
+    assert_ne!(second, first);
+    assert_eq!(fixture.svm.get::<VestingEscrow>(&first).total, 100);
+    assert_eq!(fixture.svm.get::<VestingEscrow>(&second).total, 100);
+}
+
+#[test]
+fn vested_rewards_release_linearly_after_the_cliff() {
+    let (mut fixture, position) = vesting_fixture(Vesting {
+        period: 1_000,
+        cliff: 200,
+        early_exit: false,
+    });
+    let user = fixture.user;
+    let escrow = claim_vesting(&mut fixture, &position);
+    let balance =
+        |fixture: &Fixture| fixture.svm.token_balance(&pda::reward_token_account(&user)) - 2 * SOL;
+
+    fixture.svm.warp_by(199);
+    let err = withdraw_vested(&mut fixture, &user, &escrow).unwrap_err();
+    assert_staking_error(err, StakingError::NothingVested);
+
+    // Past the cliff, everything vested since the claim is released at once
+    fixture.svm.warp_by(51);
+    withdraw_vested(&mut fixture, &user, &escrow).unwrap();
+    assert_eq!(balance(&fixture), 500);
+
+    let err = withdraw_vested(&mut fixture, &user, &escrow).unwrap_err();
+    assert_staking_error(err, StakingError::NothingVested);
+
+    fixture.svm.warp_by(250);
+    withdraw_vested(&mut fixture, &user, &escrow).unwrap();
+    assert_eq!(balance(&fixture), 1_000);
+    assert_eq!(fixture.svm.get::<VestingEscrow>(&escrow).withdrawn, 1_000);
+
+    // The last withdrawal empties and closes the escrow
+    fixture.svm.warp_by(10_000);
+    withdraw_vested(&mut fixture, &user, &escrow).unwrap();
+    assert_eq!(balance(&fixture), 2_000);
+    assert!(fixture.svm.account(&escrow).is_none());
+}
+
+#[test]
+fn only_the_beneficiary_withdraws_vested_rewards() {
+    let (mut fixture, position) = vesting_fixture(Vesting {
+        period: 1_000,
+        cliff: 0,
+        early_exit: true,
+    });
+    let escrow = claim_vesting(&mut fixture, &position);
+    fixture.svm.warp_by(500);
+
+    let stranger = fixture.svm.new_wallet();
+    let err = withdraw_vested(&mut fixture, &stranger, &escrow).unwrap_err();
+    assert_staking_error(err, StakingError::NotBeneficiary);
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::exit_vesting(&stranger, &escrow)],
+            &[stranger],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NotBeneficiary);
+}
+
+#[test]
+fn exit_vesting_forfeits_what_has_not_vested() {
+    let (mut fixture, position) = vesting_fixture(Vesting {
+        period: 1_000,
+        cliff: 0,
+        early_exit: true,
+    });
+    let user = fixture.user;
+    let escrow = claim_vesting(&mut fixture, &position);
+
+    fixture.svm.warp_by(100);
+    withdraw_vested(&mut fixture, &user, &escrow).unwrap();
+    fixture.svm.warp_by(300);
+    fixture
+        .svm
+        .process(&[token_rewards::exit_vesting(&user, &escrow)], &[user])
+        .unwrap();
+
+    // 40% vested, the 200 withdrawn earlier plus 600 now, and the other 1200 are never minted
+    assert_eq!(
+        fixture.svm.token_balance(&pda::reward_token_account(&user)),
+        2 * SOL + 800
+    );
+    assert_eq!(fixture.svm.mint(&pda::mint().0).supply, 2 * SOL + 800);
+    assert!(fixture.svm.account(&escrow).is_none());
+    let [exited] = &fixture.svm.events::<VestingExited>()[..] else {
+        panic!("expected one VestingExited event");
+    };
+    assert_eq!((exited.amount, exited.forfeited), (600, 1_200));
+}
+
+#[test]
+fn exit_vesting_requires_the_pool_to_allow_it() {
+    let (mut fixture, position) = vesting_fixture(Vesting {
+        period: 1_000,
+        cliff: 0,
+        early_exit: false,
+    });
+    let user = fixture.user;
+    let escrow = claim_vesting(&mut fixture, &position);
+    fixture.svm.warp_by(500);
+
+    let err = fixture
+        .svm
+        .process(&[token_rewards::exit_vesting(&user, &escrow)], &[user])
+        .unwrap_err();
+    assert_staking_error(err, StakingError::EarlyExitDisabled);
+    assert_eq!(fixture.svm.get::<VestingEscrow>(&escrow).withdrawn, 0);
+}
+
+/// `setup` with `user` holding the 2 SOL of reward tokens its stake minted, and an airdrop of
+/// 100, 200 and 300 of them to three fresh wallets
+fn airdrop_fixture() -> (Fixture, Airdrop) {
+    let mut fixture = setup();
+    let user = fixture.user;
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    let airdrop = Airdrop::new(
+        [100, 200, 300]
+            .into_iter()
+            .map(|amount| (fixture.svm.new_wallet(), amount))
+            .collect(),
+    );
+    (fixture, airdrop)
+}
+
+fn create_distributor(fixture: &mut Fixture, airdrop: &Airdrop) -> Pubkey {
+    let user = fixture.user;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::create_distributor(
+                &user,
+                7,
+                &pda::mint().0,
+                airdrop,
+            )],
+            &[user],
+        )
+        .unwrap();
+    pda::distributor(&user, 7).0
+}
+
+fn claim_airdrop(
+    fixture: &mut Fixture,
+    claimant: &Pubkey,
+    claim: token_rewards::AirdropClaim,
+) -> Result<(), program_tests::TransactionError> {
+    let user = fixture.user;
+    fixture.svm.process(
+        &[token_rewards::claim_airdrop(
+            claimant,
+            &user,
+            7,
+            &pda::mint().0,
+            claim,
+        )],
+        &[*claimant],
+    )
+}
+
+#[test]
+fn airdrop_claims_pay_each_recipient_once() {
+    let (mut fixture, airdrop) = airdrop_fixture();
+    let distributor = create_distributor(&mut fixture, &airdrop);
+
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::distributor_vault(&distributor).0),
+        600
+    );
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::reward_token_account(&fixture.user)),
+        2 * SOL - 600
+    );
+    let state: Distributor = fixture.svm.get(&distributor);
+    assert_eq!(state.root, airdrop.root());
+    assert_eq!((state.total_amount, state.num_recipients), (600, 3));
+
+    let (claimant, _) = airdrop.recipients()[1];
+    claim_airdrop(&mut fixture, &claimant, airdrop.claim(1).unwrap()).unwrap();
+
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::reward_token_account(&claimant)),
+        200
+    );
+    let [claimed] = &fixture.svm.events::<AirdropClaimed>()[..] else {
+        panic!("expected one AirdropClaimed event");
+    };
+    assert_eq!((claimed.index, claimed.amount), (1, 200));
+
+    // The bit for index 1 is set, the other claims are untouched
+    let state: Distributor = fixture.svm.get(&distributor);
+    assert!(state.is_claimed(1));
+    assert!(!state.is_claimed(0) && !state.is_claimed(2));
+    assert_eq!(state.claimed_amount, 200);
+
+    fixture.svm.warp_by(1);
+    let err = claim_airdrop(&mut fixture, &claimant, airdrop.claim(1).unwrap()).unwrap_err();
+    assert_staking_error(err, StakingError::AlreadyClaimed);
+
+    let (first, _) = airdrop.recipients()[0];
+    claim_airdrop(&mut fixture, &first, airdrop.claim(0).unwrap()).unwrap();
+    assert_eq!(
+        fixture
+            .svm
+            .token_balance(&pda::distributor_vault(&distributor).0),
+        300
+    );
+}
+
+#[test]
+fn claim_airdrop_rejects_claims_that_do_not_match_the_root() {
+    let (mut fixture, airdrop) = airdrop_fixture();
+    create_distributor(&mut fixture, &airdrop);
+    let (claimant, _) = airdrop.recipients()[0];
+
+    // Someone else's claim
+    let err = claim_airdrop(&mut fixture, &claimant, airdrop.claim(2).unwrap()).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidProof);
+
+    let mut inflated = airdrop.claim(0).unwrap();
+    inflated.amount = 300;
+    let err = claim_airdrop(&mut fixture, &claimant, inflated).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidProof);
+
+    let mut out_of_range = airdrop.claim(0).unwrap();
+    out_of_range.index = 3;
+    let err = claim_airdrop(&mut fixture, &claimant, out_of_range).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidProof);
+}
+
+#[test]
+fn create_distributor_requires_recipients() {
+    let (mut fixture, _) = airdrop_fixture();
+    let user = fixture.user;
+
+    for airdrop in [
+        Airdrop::new(Vec::new()),
+        Airdrop::new(vec![(Pubkey::new_unique(), 0)]),
+    ] {
+        let err = fixture
+            .svm
+            .process(
+                &[token_rewards::create_distributor(
+                    &user,
+                    7,
+                    &pda::mint().0,
+                    &airdrop,
+                )],
+                &[user],
+            )
+            .unwrap_err();
+        assert_staking_error(err, StakingError::InvalidAirdrop);
+    }
+}
+
+/// Writes `owner`'s single position in the layout from before `migrate_account`, last updated
+/// 31 days ago
+fn write_v1_position(svm: &mut Svm, owner: &Pubkey, staked_amount: u64) -> Pubkey {
+    let address = pda::legacy_stake_account(owner);
+    let legacy = StakeAccountV1 {
+        owner: *owner,
+        staked_amount,
+        total_points: 42,
+        last_update_time: svm.clock().unix_timestamp - 31 * DAY,
+        bump: address.1,
+    };
+    let mut data = StakeAccount::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    assert_eq!(data.len(), StakeAccountV1::LEN);
+
+    svm.set_account(
+        address.0,
+        Account {
+            lamports: svm.rent().minimum_balance(data.len()),
+            data,
+            owner: token_rewards::ID,
+            executable: false,
+        },
+    );
+    address.0
+}
+
+#[test]
+fn migrate_account_moves_a_v1_position_to_an_indexed_one() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let legacy = write_v1_position(&mut svm, &user, 0);
+    let refund = svm.lamports(&legacy);
+    let rent = svm.rent().minimum_balance(8 + StakeAccount::INIT_SPACE)
+        + svm.rent().minimum_balance(8 + Staker::INIT_SPACE);
+    let before = svm.lamports(&user);
+    let clock = svm.clock().clone();
+
+    svm.process(
+        &[token_rewards::migrate_account(&user, 1, Some(&authority))],
+        &[user],
+    )
+    .unwrap();
+
+    assert!(svm.account(&legacy).is_none());
+    assert_eq!(svm.lamports(&user), before + refund - rent);
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user, 1).0);
+    assert_eq!(position.version, STAKE_ACCOUNT_VERSION);
+    assert_eq!(position.owner, user);
+    assert_eq!(position.total_points, 42);
+    assert_eq!(position.position_index, 1);
+    assert_eq!(position.lock_tier, LockTier::Days30);
+    // Accrual and the lock restart at the migration
+    assert_eq!(position.last_update_time, clock.unix_timestamp);
+    assert_eq!(position.lock_start, clock.unix_timestamp);
+    assert_eq!(position.last_update_slot, clock.slot);
+    assert_eq!(position.lock_start_epoch, clock.epoch);
+    assert_eq!(position.position_mint, Pubkey::default());
+    assert_eq!(position.vault, Pubkey::default());
+    assert_eq!(position.pending_stake, 0);
+    assert!(position.counted);
+    assert_eq!(position.reserved, [0; 19]);
+}
+
+#[test]
+fn migrate_account_of_an_empty_position_needs_no_pool() {
+    let mut svm = Svm::new();
+    let user = svm.new_wallet();
+    write_v1_position(&mut svm, &user, 0);
+
+    svm.process(&[token_rewards::migrate_account(&user, 1, None)], &[user])
+        .unwrap();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user, 1).0);
+    assert_eq!(position.staked_amount, 0);
+    assert_eq!(position.vault, Pubkey::default());
+
+    // Staked legacy positions still need a pool to count them
+    let other = svm.new_wallet();
+    write_v1_position(&mut svm, &other, SOL);
+    let err = svm
+        .process(
+            &[token_rewards::signed_by_authority(
+                token_rewards::migrate_account(&other, 1, None),
+            )],
+            &[other],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::VaultAuthorityRequired);
+}
+
+#[test]
+fn migrate_account_binds_legacy_stake_with_the_authority_co_signing() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    write_v1_position(&mut svm, &user, 2 * SOL);
+
+    let err = svm
+        .process(
+            &[token_rewards::migrate_account(&user, 1, Some(&authority))],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::VaultAuthorityRequired);
+
+    svm.process(
+        &[token_rewards::signed_by_authority(
+            token_rewards::migrate_account(&user, 1, Some(&authority)),
+        )],
+        &[user, authority],
+    )
+    .unwrap();
+
+    let position: StakeAccount = svm.get(&pda::stake_account(&user, 1).0);
+    assert_eq!(position.staked_amount, 2 * SOL);
+    assert_eq!(position.vault, pda::vault(&authority).0);
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2 * SOL);
+}
+
+#[test]
+fn migrate_account_rejects_another_owners_position_and_a_second_run() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let address = write_v1_position(&mut svm, &user, 0);
+    let mut legacy = svm.account(&address).unwrap();
+    legacy.data[8..40].copy_from_slice(Pubkey::new_unique().as_ref());
+    svm.set_account(address, legacy);
+
+    let err = svm
+        .process(
+            &[token_rewards::migrate_account(&user, 1, Some(&authority))],
+            &[user],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NotPositionHolder);
+
+    write_v1_position(&mut svm, &user, 0);
+    svm.process(
+        &[token_rewards::migrate_account(&user, 1, Some(&authority))],
+        &[user],
+    )
+    .unwrap();
+    let err = svm
+        .process(
+            &[token_rewards::migrate_account(&user, 2, Some(&authority))],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(err.custom_code(), Some(ErrorCode::ConstraintOwner as u32));
+}
+
+#[test]
+fn a_migrated_v1_position_unstakes_from_the_vault() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    // Stake deposited before the pool tracked its vault
+    svm.airdrop(&pda::vault(&authority).0, 2 * SOL);
+    write_v1_position(&mut svm, &user, 2 * SOL);
+    let position = pda::stake_account(&user, 1).0;
+
+    svm.process(
+        &[token_rewards::signed_by_authority(
+            token_rewards::migrate_account(&user, 1, Some(&authority)),
+        )],
+        &[user, authority],
+    )
+    .unwrap();
+
+    // The lock restarted at the migration
+    let unstake = || token_rewards::unstake(&user, &user, &position, None, &authority, None, SOL);
+    let err = svm.process(&[unstake()], &[user]).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidUnstake);
+    svm.warp_by(30 * DAY);
+    svm.process(&[unstake()], &[user]).unwrap();
+
+    let position: StakeAccount = svm.get(&position);
+    assert_eq!(position.staked_amount, SOL);
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, SOL);
+}
+
+fn reconcile(svm: &mut Svm, authority: &Pubkey) -> Reconciliation {
+    svm.process(&[token_rewards::reconcile(authority)], &[])
+        .unwrap();
+    let (program_id, data) = svm.return_data().unwrap();
+    assert_eq!(*program_id, token_rewards::ID);
+    Reconciliation::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn sweep_surplus_moves_only_the_surplus_to_the_treasury() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    let vault = pda::vault(&authority).0;
+    let treasury = pda::treasury(&authority).0;
+    fixture.svm.airdrop(&vault, 5_000);
+    let rent_reserve = fixture.svm.rent().minimum_balance(0);
+
+    assert_eq!(
+        reconcile(&mut fixture.svm, &authority),
+        Reconciliation {
+            vault_balance: rent_reserve + 2 * SOL + 5_000,
+            rent_reserve,
+            total_staked: 2 * SOL,
+            total_pending_withdrawals: 0,
+            surplus: 5_000,
+            shortfall: 0,
+        }
+    );
+
+    let treasury_before = fixture.svm.lamports(&treasury);
+    fixture
+        .svm
+        .process(&[token_rewards::sweep_surplus(&authority)], &[authority])
+        .unwrap();
+
+    assert_eq!(fixture.svm.lamports(&treasury), treasury_before + 5_000);
+    assert_eq!(fixture.svm.lamports(&vault), rent_reserve + 2 * SOL);
+
+    let err = fixture
+        .svm
+        .process(&[token_rewards::sweep_surplus(&authority)], &[authority])
+        .unwrap_err();
+    assert_staking_error(err, StakingError::NoSurplus);
+
+    // Every staked lamport is still there to be paid back
+    fixture.svm.warp_by(30 * DAY);
+    unstake(&mut fixture, &user, 0, 2 * SOL - 1).unwrap();
+}
+
+#[test]
+fn withdraw_treasury_keeps_the_treasury_rent_exempt() {
+    let mut fixture = setup();
+    let authority = fixture.authority;
+    fixture.svm.airdrop(&pda::vault(&authority).0, 5_000);
+    fixture
+        .svm
+        .process(&[token_rewards::sweep_surplus(&authority)], &[authority])
+        .unwrap();
+    let authority_before = fixture.svm.lamports(&authority);
+
+    let err = fixture
+        .svm
+        .process(
+            &[token_rewards::withdraw_treasury(&authority, 5_001)],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_staking_error(err, StakingError::InvalidBalance);
+
+    fixture
+        .svm
+        .process(
+            &[token_rewards::withdraw_treasury(&authority, 5_000)],
+            &[authority],
+        )
+        .unwrap();
+    assert_eq!(fixture.svm.lamports(&authority), authority_before + 5_000);
+}
+
+/// Gives the fixture's pool a 1 SOL minimum, a 3 SOL per-wallet cap and a 4 SOL total cap
+fn limit_pool(fixture: &mut Fixture) {
+    let authority = fixture.authority;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::update_pool_params(
+                &authority,
+                PoolParams {
+                    max_boost_bps: 15_000,
+                    referral_bps: 1_000,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    vesting: Default::default(),
+                    min_stake: SOL,
+                    max_stake_per_wallet: Some(3 * SOL),
+                    max_total_staked: Some(4 * SOL),
+                },
+            )],
+            &[authority],
+        )
+        .unwrap();
+}
+
+#[test]
+fn stake_enforces_the_pool_limits() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    limit_pool(&mut fixture);
+    fixture.create_position(&user, 1, LockTier::Days30);
+
+    let err = fixture.stake(&user, 0, 0, None).unwrap_err();
+    assert_staking_error(err, StakingError::InvalidAmount);
+    let err = fixture.stake(&user, 0, SOL - 1, None).unwrap_err();
+    assert_staking_error(err, StakingError::BelowMinimumStake);
+
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+    let err = fixture.stake(&user, 0, 2 * SOL, None).unwrap_err();
+    assert_staking_error(err, StakingError::WalletCapExceeded);
+    let err = fixture.stake(&user, 1, 3 * SOL, None).unwrap_err();
+    assert_staking_error(err, StakingError::PoolCapExceeded);
+    // The cap covers all of the wallet's positions
+    let err = fixture.stake(&user, 1, 2 * SOL, None).unwrap_err();
+    assert_staking_error(err, StakingError::WalletCapExceeded);
+
+    fixture.stake(&user, 1, SOL, None).unwrap();
+    let other = fixture.svm.new_wallet();
+    fixture.create_position(&other, 0, LockTier::Days30);
+    fixture.stake(&other, 0, SOL, None).unwrap();
+    let pool: Pool = fixture.svm.get(&pda::pool(&fixture.authority).0);
+    assert_eq!(pool.total_staked, 4 * SOL);
+    assert_eq!(wallet_staked(&fixture, &user), 3 * SOL);
+}
+
+fn wallet_staked(fixture: &Fixture, owner: &Pubkey) -> u64 {
+    let staker: Staker = fixture.svm.get(&pda::staker(&fixture.authority, owner).0);
+    staker.total_staked
+}
+
+#[test]
+fn stake_counts_positions_staked_before_the_wallet_totals() {
+    let mut fixture = setup();
+    let user = fixture.user;
+    limit_pool(&mut fixture);
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+
+    // As it was before the program kept wallet totals
+    let position = pda::stake_account(&user, 0).0;
+    let mut staked = fixture.position(&user, 0);
+    staked.counted = false;
+    let mut account = fixture.svm.account(&position).unwrap();
+    account.data.clear();
+    staked.try_serialize(&mut account.data).unwrap();
+    fixture.svm.set_account(position, account);
+    fixture.svm.set_account(
+        pda::staker(&fixture.authority, &user).0,
+        Account {
+            lamports: 0,
+            data: vec![],
+            owner: solana_system_interface::program::ID,
+            executable: false,
+        },
+    );
+
+    let err = fixture.stake(&user, 0, 2 * SOL, None).unwrap_err();
+    assert_staking_error(err, StakingError::WalletCapExceeded);
+    fixture.stake(&user, 0, SOL, None).unwrap();
+    assert!(fixture.position(&user, 0).counted);
+    assert_eq!(wallet_staked(&fixture, &user), 3 * SOL);
+}
+
+#[test]
+fn the_wallet_cap_holds_across_split_and_merge() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    limit_pool(&mut fixture);
+    fixture.stake(&user, 0, 2 * SOL, None).unwrap();
+
+    split(&mut fixture, SOL, 1).unwrap();
+    assert_eq!(wallet_staked(&fixture, &user), 2 * SOL);
+    let err = fixture.stake(&user, 1, 2 * SOL, None).unwrap_err();
+    assert_staking_error(err, StakingError::WalletCapExceeded);
+
+    // Another wallet's position, whose NFT the user then holds
+    let other = fixture.svm.new_wallet();
+    let source = fixture.create_position(&other, 0, LockTier::Days30);
+    fixture.stake(&other, 0, SOL, None).unwrap();
+    let position_mint = pda::position_mint(&source).0;
+    fixture
+        .svm
+        .process(
+            &[
+                create_associated_token_account(&user, &user, &position_mint, &spl_token::ID),
+                spl_token::instruction::transfer(
+                    &spl_token::ID,
+                    &pda::position_token_account(&other, &source),
+                    &pda::position_token_account(&user, &source),
+                    &other,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+            ],
+            &[user, other],
+        )
+        .unwrap();
+
+    let destination = pda::stake_account(&user, 0).0;
+    let merge = |destination_owner| {
+        token_rewards::merge_positions(
+            &user,
+            &other,
+            &source,
+            destination_owner,
+            &destination,
+            Some(pda::position_token_account(&user, &source)),
+            Some(pda::position_token_account(&user, &destination)),
+            &authority,
+            None,
+        )
+    };
+    let err = fixture.svm.process(&[merge(None)], &[user]).unwrap_err();
+    assert_staking_error(err, StakingError::StakerRequired);
+    fixture.svm.process(&[merge(Some(&user))], &[user]).unwrap();
+    assert_eq!(wallet_staked(&fixture, &user), 3 * SOL);
+    assert_eq!(wallet_staked(&fixture, &other), 0);
+    let err = fixture.stake(&user, 1, SOL, None).unwrap_err();
+    assert_staking_error(err, StakingError::WalletCapExceeded);
+
+    // Merging two of the user's own positions leaves their total as it is
+    let source = pda::stake_account(&user, 1).0;
+    fixture
+        .svm
+        .process(
+            &[token_rewards::merge_positions(
+                &user,
+                &user,
+                &source,
+                None,
+                &destination,
+                Some(pda::position_token_account(&user, &source)),
+                Some(pda::position_token_account(&user, &destination)),
+                &authority,
+                None,
+            )],
+            &[user],
+        )
+        .unwrap();
+    assert_eq!(fixture.position(&user, 0).staked_amount, 3 * SOL);
+    assert_eq!(wallet_staked(&fixture, &user), 3 * SOL);
+}
+
+#[test]
+fn transfer_position_moves_the_stake_between_wallet_totals() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    limit_pool(&mut fixture);
+    // A migrated v1 position is the untokenized kind that can be transferred
+    fixture.svm.airdrop(&pda::vault(&authority).0, 2 * SOL);
+    write_v1_position(&mut fixture.svm, &user, 2 * SOL);
+    fixture
+        .svm
+        .process(
+            &[token_rewards::signed_by_authority(
+                token_rewards::migrate_account(&user, 1, Some(&authority)),
+            )],
+            &[user, authority],
+        )
+        .unwrap();
+    assert!(fixture.position(&user, 1).counted);
+    assert_eq!(wallet_staked(&fixture, &user), 2 * SOL);
+
+    let full = fixture.svm.new_wallet();
+    fixture.create_position(&full, 0, LockTier::Days30);
+    fixture.stake(&full, 0, 2 * SOL, None).unwrap();
+    let transfer =
+        |new_owner, authority| token_rewards::transfer_position(&user, 1, new_owner, 1, authority);
+    let err = fixture
+        .svm
+        .process(&[transfer(&full, Some(&authority))], &[user])
+        .unwrap_err();
+    assert_staking_error(err, StakingError::WalletCapExceeded);
+
+    let new_owner = Pubkey::new_unique();
+    let err = fixture
+        .svm
+        .process(&[transfer(&new_owner, None)], &[user])
+        .unwrap_err();
+    assert_staking_error(err, StakingError::StakerRequired);
+    fixture
+        .svm
+        .process(&[transfer(&new_owner, Some(&authority))], &[user])
+        .unwrap();
+
+    assert_eq!(wallet_staked(&fixture, &user), 0);
+    assert_eq!(wallet_staked(&fixture, &new_owner), 2 * SOL);
+    assert!(fixture.position(&new_owner, 1).counted);
+}
+
+fn capacity(svm: &mut Svm, authority: &Pubkey, owner: Option<&Pubkey>) -> Capacity {
+    svm.process(&[token_rewards::capacity(authority, owner)], &[])
+        .unwrap();
+    let (program_id, data) = svm.return_data().unwrap();
+    assert_eq!(*program_id, token_rewards::ID);
+    Capacity::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn capacity_reports_what_the_pool_and_a_wallet_can_still_take() {
+    let mut fixture = setup();
+    let (user, authority) = (fixture.user, fixture.authority);
+    limit_pool(&mut fixture);
+    let other = fixture.svm.new_wallet();
+    fixture.create_position(&other, 0, LockTier::Days30);
+    fixture.stake(&other, 0, 2 * SOL, None).unwrap();
+    fixture.stake(&user, 0, SOL, None).unwrap();
+
+    assert_eq!(
+        capacity(&mut fixture.svm, &authority, Some(&user)),
+        Capacity {
+            min_stake: SOL,
+            total_staked: 3 * SOL,
+            pool_remaining: Some(SOL),
+            wallet_staked: SOL,
+            wallet_remaining: Some(SOL),
+        }
+    );
+    assert_eq!(
+        capacity(&mut fixture.svm, &authority, None).wallet_remaining,
+        Some(SOL)
+    );
+}
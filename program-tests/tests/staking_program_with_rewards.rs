@@ -0,0 +1,2497 @@
+use anchor_lang::error::ErrorCode;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountSerialize, AnchorDeserialize, AnchorSerialize, Discriminator, Space};
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account;
+use anchor_spl::token::spl_token;
+use program_tests::{Account, Svm};
+use solana_system_interface::error::SystemError;
+use solana_system_interface::instruction as system_instruction;
+use staking_client::rewards::{
+    self, pda, Allowlist, Capacity, CatalogItem, ItemKind, ItemParams, Leaderboard,
+    LeaderboardEntry, LeaderboardSnapshot, PointsDecay, PointsOutlook, Pool, PoolParams,
+    Reconciliation, StakeAccount, StakeAccountV1, StakingError, StreakTier, TimeBase, Treasury,
+    WhitelistSpot, LEADERBOARD_SIZE, MAX_STREAK_BONUS_BPS, MAX_STREAK_TIERS, STAKE_ACCOUNT_VERSION,
+};
+use staking_client::Pubkey;
+use staking_program_with_rewards::{
+    AllowlistRootUpdated, FeeCollected, FeesWithdrawn, ItemPublished, ItemRedeemed, PointsClaimed,
+    PointsExpired,
+};
+
+const SOL: u64 = 1_000_000_000;
+
+struct Fixture {
+    svm: Svm,
+    authority: Pubkey,
+    user: Pubkey,
+}
+
+/// A vault and a 10% referral pool for `authority`, and an empty position for `user`
+fn setup() -> Fixture {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+
+    svm.process(
+        &[
+            rewards::create_vault_pda_account(&authority),
+            rewards::initialize_pool(
+                &authority,
+                PoolParams {
+                    referral_bps: 1_000,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            ),
+            rewards::initialize_treasury(&authority),
+            rewards::initialize_leaderboard(&authority),
+        ],
+        &[authority],
+    )
+    .unwrap();
+    svm.process(&[rewards::create_pda_account(&user)], &[user])
+        .unwrap();
+
+    Fixture {
+        svm,
+        authority,
+        user,
+    }
+}
+
+fn position(svm: &Svm, owner: &Pubkey) -> StakeAccount {
+    svm.get(&pda::stake_account(owner).0)
+}
+
+/// Overwrites `owner`'s position with one in the layout from before `migrate_account`
+fn write_v1_position(svm: &mut Svm, owner: &Pubkey, staked_amount: u64) {
+    let legacy = StakeAccountV1 {
+        owner: *owner,
+        staked_amount,
+        total_points: 42,
+        last_update_time: 1_700_000_000,
+        bump: pda::stake_account(owner).1,
+    };
+    let mut data = StakeAccount::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    assert_eq!(data.len(), StakeAccountV1::LEN);
+
+    svm.set_account(
+        pda::stake_account(owner).0,
+        Account {
+            lamports: svm.rent().minimum_balance(data.len()),
+            data,
+            owner: rewards::ID,
+            executable: false,
+        },
+    );
+}
+
+#[test]
+fn initialize_pool_stores_the_params() {
+    let Fixture { svm, authority, .. } = setup();
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+
+    assert_eq!(pool.authority, authority);
+    assert_eq!(pool.referral_bps, 1_000);
+    assert_eq!(pool.total_staked, 0);
+    assert_eq!(pool.allowlist_root, [0; 32]);
+    assert_eq!(pool.bump, pda::pool(&authority).1);
+}
+
+#[test]
+fn initialize_pool_rejects_a_referral_rate_above_100_percent() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+
+    let err = svm
+        .process(
+            &[rewards::initialize_pool(
+                &authority,
+                PoolParams {
+                    referral_bps: 10_001,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidReferralRate))
+    );
+}
+
+#[test]
+fn update_pool_params_changes_the_referral_rate() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    svm.process(
+        &[rewards::update_pool_params(
+            &authority,
+            PoolParams {
+                referral_bps: 2_500,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+                decay: PointsDecay::None,
+                streak_tiers: Default::default(),
+            },
+        )],
+        &[authority],
+    )
+    .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.referral_bps, 2_500);
+}
+
+#[test]
+fn update_pool_params_validates_the_params() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    let err = svm
+        .process(
+            &[rewards::update_pool_params(
+                &authority,
+                PoolParams {
+                    referral_bps: 20_000,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidReferralRate))
+    );
+}
+
+#[test]
+fn update_pool_params_of_a_missing_pool_fails() {
+    let Fixture { mut svm, user, .. } = setup();
+
+    let err = svm
+        .process(
+            &[rewards::update_pool_params(
+                &user,
+                PoolParams {
+                    referral_bps: 0,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotInitialized))
+    );
+}
+
+#[test]
+fn stake_accrues_points_per_sol_per_second() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let vault = pda::vault(&authority).0;
+    let vault_before = svm.lamports(&vault);
+
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    assert_eq!(svm.lamports(&vault), vault_before + 2 * SOL);
+
+    svm.warp_by(100);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    let position = position(&svm, &user);
+    assert_eq!(position.staked_amount, SOL);
+    assert_eq!(position.total_points, 200);
+    assert_eq!(position.last_update_time, svm.clock().unix_timestamp);
+    assert_eq!(position.referral_points, 0);
+}
+
+#[test]
+fn stake_more_than_the_wallet_holds_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let balance = svm.lamports(&user);
+
+    let err = svm
+        .process(
+            &[rewards::stake(&user, &authority, balance + 1, None)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::ResultWithNegativeLamports as u32)
+    );
+}
+
+#[test]
+fn stake_without_a_pool_fails() {
+    let Fixture { mut svm, user, .. } = setup();
+    let other = svm.new_wallet();
+    svm.process(&[rewards::create_vault_pda_account(&other)], &[other])
+        .unwrap();
+
+    let err = svm
+        .process(&[rewards::stake(&user, &other, SOL, None)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotInitialized))
+    );
+}
+
+#[test]
+fn stake_records_the_referrer_once() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let referrer = Pubkey::new_unique();
+
+    svm.process(
+        &[rewards::stake(&user, &authority, SOL, Some(referrer))],
+        &[user],
+    )
+    .unwrap();
+    svm.process(
+        &[rewards::stake(
+            &user,
+            &authority,
+            SOL,
+            Some(Pubkey::new_unique()),
+        )],
+        &[user],
+    )
+    .unwrap();
+
+    assert_eq!(position(&svm, &user).referrer, referrer);
+}
+
+#[test]
+fn stake_referring_yourself_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+
+    let err = svm
+        .process(
+            &[rewards::stake(&user, &authority, SOL, Some(user))],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidReferrer))
+    );
+}
+
+/// A second vault, pool, treasury and leaderboard, holding `SOL` staked by a new wallet
+fn other_funded_vault(svm: &mut Svm) -> Pubkey {
+    let other = svm.new_wallet();
+    let staker = svm.new_wallet();
+    svm.process(
+        &[
+            rewards::create_vault_pda_account(&other),
+            rewards::initialize_pool(
+                &other,
+                PoolParams {
+                    referral_bps: 0,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            ),
+            rewards::initialize_treasury(&other),
+            rewards::initialize_leaderboard(&other),
+        ],
+        &[other],
+    )
+    .unwrap();
+    svm.process(
+        &[
+            rewards::create_pda_account(&staker),
+            rewards::stake(&staker, &other, SOL, None),
+        ],
+        &[staker],
+    )
+    .unwrap();
+    other
+}
+
+#[test]
+fn stake_binds_the_position_to_its_vault() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let other = other_funded_vault(&mut svm);
+
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+    assert_eq!(position(&svm, &user).vault, pda::vault(&authority).0);
+
+    let err = svm
+        .process(&[rewards::stake(&user, &other, SOL, None)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+}
+
+#[test]
+fn unstake_from_another_vault_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let other = other_funded_vault(&mut svm);
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+
+    let err = svm
+        .process(&[rewards::unstake(&user, &other, SOL / 2)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+}
+
+#[test]
+fn unstake_zero_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+
+    let err = svm
+        .process(&[rewards::unstake(&user, &authority, 0)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidAmount))
+    );
+}
+
+#[test]
+fn unstake_requires_more_staked_than_requested() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+
+    let err = svm
+        .process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidBalance))
+    );
+}
+
+#[test]
+fn claim_points_resets_the_balance() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    svm.warp_by(50);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+    assert_eq!(position(&svm, &user).total_points, 100);
+
+    svm.process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap();
+
+    assert_eq!(position(&svm, &user).total_points, 0);
+}
+
+#[test]
+fn claim_points_without_points_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+
+    let err = svm
+        .process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidPoints))
+    );
+}
+
+/// `user` staked 2 SOL referred by a new wallet with its own position, and accrued 100 seconds
+fn referred_fixture() -> (Fixture, Pubkey) {
+    let mut fixture = setup();
+    let Fixture {
+        svm,
+        authority,
+        user,
+    } = &mut fixture;
+    let referrer = svm.new_wallet();
+    svm.process(&[rewards::create_pda_account(&referrer)], &[referrer])
+        .unwrap();
+
+    svm.process(
+        &[rewards::stake(user, authority, 2 * SOL, Some(referrer))],
+        &[*user],
+    )
+    .unwrap();
+    svm.warp_by(100);
+    svm.process(&[rewards::unstake(user, authority, SOL)], &[*user])
+        .unwrap();
+
+    (fixture, referrer)
+}
+
+#[test]
+fn claim_referral_points_credits_the_referrer() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        referrer,
+    ) = referred_fixture();
+    assert_eq!(position(&svm, &user).referral_points, 20);
+
+    svm.process(
+        &[rewards::claim_referral_points(&referrer, &user, &authority)],
+        &[referrer],
+    )
+    .unwrap();
+
+    assert_eq!(position(&svm, &user).referral_points, 0);
+    assert_eq!(position(&svm, &user).total_points, 200);
+    assert_eq!(position(&svm, &referrer).total_points, 20);
+}
+
+#[test]
+fn claim_referral_points_by_another_wallet_fails() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        _,
+    ) = referred_fixture();
+    let impostor = svm.new_wallet();
+    svm.process(&[rewards::create_pda_account(&impostor)], &[impostor])
+        .unwrap();
+
+    let err = svm
+        .process(
+            &[rewards::claim_referral_points(&impostor, &user, &authority)],
+            &[impostor],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidReferrer))
+    );
+}
+
+#[test]
+fn claim_referral_points_twice_fails() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        referrer,
+    ) = referred_fixture();
+    svm.process(
+        &[rewards::claim_referral_points(&referrer, &user, &authority)],
+        &[referrer],
+    )
+    .unwrap();
+    svm.warp_by(1);
+
+    let err = svm
+        .process(
+            &[rewards::claim_referral_points(&referrer, &user, &authority)],
+            &[referrer],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidPoints))
+    );
+}
+
+#[test]
+fn transfer_position_carries_points_and_referrer() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        referrer,
+    ) = referred_fixture();
+    let new_owner = Pubkey::new_unique();
+
+    svm.process(
+        &[rewards::transfer_position(&user, &new_owner, &authority)],
+        &[user],
+    )
+    .unwrap();
+
+    assert!(svm.account(&pda::stake_account(&user).0).is_none());
+    let position = position(&svm, &new_owner);
+    assert_eq!(position.owner, new_owner);
+    assert_eq!(position.staked_amount, SOL);
+    assert_eq!(position.total_points, 200);
+    assert_eq!(position.referrer, referrer);
+    assert_eq!(position.referral_points, 20);
+}
+
+#[test]
+fn transfer_position_to_a_wallet_with_a_position_fails() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        referrer,
+    ) = referred_fixture();
+
+    let err = svm
+        .process(
+            &[rewards::transfer_position(&user, &referrer, &authority)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn stake_and_unstake_track_the_pool_total() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let other = other_funded_vault(&mut svm);
+
+    svm.process(&[rewards::stake(&user, &authority, 3 * SOL, None)], &[user])
+        .unwrap();
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2 * SOL);
+    let other_pool: Pool = svm.get(&pda::pool(&other).0);
+    assert_eq!(other_pool.total_staked, SOL);
+}
+
+/// `user` stakes 2 SOL and someone sends the vault `donation` lamports on top
+fn donated_fixture(donation: u64) -> Fixture {
+    let mut fixture = setup();
+    let Fixture {
+        svm,
+        authority,
+        user,
+    } = &mut fixture;
+    let donor = svm.new_wallet();
+    svm.process(&[rewards::stake(user, authority, 2 * SOL, None)], &[*user])
+        .unwrap();
+    svm.process(
+        &[system_instruction::transfer(
+            &donor,
+            &pda::vault(authority).0,
+            donation,
+        )],
+        &[donor],
+    )
+    .unwrap();
+    fixture
+}
+
+fn reconcile(svm: &mut Svm, authority: &Pubkey) -> Reconciliation {
+    svm.process(&[rewards::reconcile(authority)], &[]).unwrap();
+    let (program_id, data) = svm.return_data().unwrap();
+    assert_eq!(*program_id, rewards::ID);
+    Reconciliation::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn reconcile_reports_lamports_beyond_the_stake() {
+    let Fixture {
+        mut svm, authority, ..
+    } = donated_fixture(5_000);
+    let rent_reserve = svm.rent().minimum_balance(0);
+
+    assert_eq!(
+        reconcile(&mut svm, &authority),
+        Reconciliation {
+            vault_balance: rent_reserve + 2 * SOL + 5_000,
+            rent_reserve,
+            total_staked: 2 * SOL,
+            total_pending_withdrawals: 0,
+            surplus: 5_000,
+            shortfall: 0,
+        }
+    );
+}
+
+#[test]
+fn sweep_surplus_moves_only_the_surplus_to_the_treasury() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = donated_fixture(5_000);
+    let vault = pda::vault(&authority).0;
+    let treasury = pda::treasury(&authority).0;
+    let treasury_before = svm.lamports(&treasury);
+
+    svm.process(&[rewards::sweep_surplus(&authority)], &[authority])
+        .unwrap();
+
+    assert_eq!(svm.lamports(&treasury), treasury_before + 5_000);
+    assert_eq!(svm.get::<Treasury>(&treasury).total_swept, 5_000);
+    assert_eq!(
+        svm.lamports(&vault),
+        svm.rent().minimum_balance(0) + 2 * SOL
+    );
+    assert_eq!(reconcile(&mut svm, &authority).surplus, 0);
+
+    // Every staked lamport is still there to be paid back
+    svm.process(&[rewards::unstake(&user, &authority, 2 * SOL - 1)], &[user])
+        .unwrap();
+}
+
+#[test]
+fn sweep_surplus_without_surplus_fails() {
+    let Fixture {
+        mut svm, authority, ..
+    } = donated_fixture(0);
+
+    let err = svm
+        .process(&[rewards::sweep_surplus(&authority)], &[authority])
+        .unwrap_err();
+    assert_eq!(err.custom_code(), Some(u32::from(StakingError::NoSurplus)));
+}
+
+#[test]
+fn sweep_surplus_requires_the_authority_signature() {
+    let Fixture {
+        mut svm, authority, ..
+    } = donated_fixture(5_000);
+
+    let err = svm
+        .process(&[rewards::sweep_surplus(&authority)], &[])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(ErrorCode::AccountNotSigner))
+    );
+}
+
+#[test]
+fn withdraw_treasury_keeps_the_treasury_rent_exempt() {
+    let Fixture {
+        mut svm, authority, ..
+    } = donated_fixture(5_000);
+    svm.process(&[rewards::sweep_surplus(&authority)], &[authority])
+        .unwrap();
+    let authority_before = svm.lamports(&authority);
+
+    let err = svm
+        .process(
+            &[rewards::withdraw_treasury(&authority, 5_001)],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidBalance))
+    );
+
+    svm.process(
+        &[rewards::withdraw_treasury(&authority, 5_000)],
+        &[authority],
+    )
+    .unwrap();
+    assert_eq!(svm.lamports(&authority), authority_before + 5_000);
+}
+
+#[test]
+fn initialize_pool_rejects_a_fee_above_100_percent() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+
+    let err = svm
+        .process(
+            &[rewards::initialize_pool(
+                &authority,
+                PoolParams {
+                    referral_bps: 0,
+                    fee_bps: 10_001,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidFeeRate))
+    );
+}
+
+/// `setup` with a 10% fee, and `user` holding 100 points from 2 SOL over 50 seconds
+fn fee_fixture() -> Fixture {
+    let mut fixture = setup();
+    let Fixture {
+        svm,
+        authority,
+        user,
+    } = &mut fixture;
+    svm.process(
+        &[rewards::update_pool_params(
+            authority,
+            PoolParams {
+                referral_bps: 1_000,
+                fee_bps: 1_000,
+                time_base: TimeBase::UnixSeconds,
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+                decay: PointsDecay::None,
+                streak_tiers: Default::default(),
+            },
+        )],
+        &[*authority],
+    )
+    .unwrap();
+    svm.process(&[rewards::stake(user, authority, 2 * SOL, None)], &[*user])
+        .unwrap();
+    svm.warp_by(50);
+    svm.process(&[rewards::unstake(user, authority, SOL)], &[*user])
+        .unwrap();
+    fixture
+}
+
+#[test]
+fn claim_points_routes_the_fee_to_the_treasury() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = fee_fixture();
+
+    svm.process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap();
+
+    let treasury: Treasury = svm.get(&pda::treasury(&authority).0);
+    assert_eq!(treasury.fee_points, 10);
+    let [claimed] = &svm.events::<PointsClaimed>()[..] else {
+        panic!("expected one PointsClaimed event");
+    };
+    assert_eq!(claimed.amount, 90);
+    let [fee] = &svm.events::<FeeCollected>()[..] else {
+        panic!("expected one FeeCollected event");
+    };
+    assert_eq!(fee.amount, 10);
+    assert_eq!(fee.treasury, pda::treasury(&authority).0);
+    assert_eq!(fee.position, pda::stake_account(&user).0);
+}
+
+#[test]
+fn claim_points_needs_the_treasury_only_to_pay_a_fee() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+    // A pool whose treasury was never initialized
+    svm.process(
+        &[
+            rewards::create_vault_pda_account(&authority),
+            rewards::initialize_pool(
+                &authority,
+                PoolParams {
+                    referral_bps: 1_000,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            ),
+            rewards::initialize_leaderboard(&authority),
+        ],
+        &[authority],
+    )
+    .unwrap();
+    svm.process(&[rewards::create_pda_account(&user)], &[user])
+        .unwrap();
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    svm.warp_by(50);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    let claim = || rewards::without_treasury(rewards::claim_points(&user, &authority), &authority);
+    svm.process(&[claim()], &[user]).unwrap();
+    assert_eq!(position(&svm, &user).total_points, 0);
+
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = fee_fixture();
+    let err = svm
+        .process(
+            &[rewards::without_treasury(
+                rewards::claim_points(&user, &authority),
+                &authority,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::TreasuryRequired))
+    );
+}
+
+#[test]
+fn claim_points_takes_the_fee_of_a_large_balance() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = fee_fixture();
+    let address = pda::stake_account(&user).0;
+    let mut position = position(&svm, &user);
+    position.total_points = u64::MAX / 2;
+    let mut account = svm.account(&address).unwrap();
+    account.data.clear();
+    position.try_serialize(&mut account.data).unwrap();
+    svm.set_account(address, account);
+
+    svm.process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap();
+
+    let treasury: Treasury = svm.get(&pda::treasury(&authority).0);
+    assert_eq!(treasury.fee_points, u64::MAX / 2 / 10);
+}
+
+#[test]
+fn claim_points_through_another_pool_fails() {
+    let Fixture { mut svm, user, .. } = fee_fixture();
+    let other = other_funded_vault(&mut svm);
+
+    let err = svm
+        .process(&[rewards::claim_points(&user, &other)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+}
+
+#[test]
+fn withdraw_fees_credits_the_authority_position() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = fee_fixture();
+    svm.process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap();
+
+    // The fees are credited to a position, so the authority needs one
+    let err = svm
+        .process(&[rewards::withdraw_fees(&authority)], &[authority])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(ErrorCode::AccountNotInitialized as u32)
+    );
+    svm.process(&[rewards::create_pda_account(&authority)], &[authority])
+        .unwrap();
+
+    svm.process(&[rewards::withdraw_fees(&authority)], &[authority])
+        .unwrap();
+
+    let treasury: Treasury = svm.get(&pda::treasury(&authority).0);
+    assert_eq!(treasury.fee_points, 0);
+    assert_eq!(position(&svm, &authority).total_points, 10);
+    let [withdrawn] = &svm.events::<FeesWithdrawn>()[..] else {
+        panic!("expected one FeesWithdrawn event");
+    };
+    assert_eq!(withdrawn.amount, 10);
+
+    let err = svm
+        .process(&[rewards::withdraw_fees(&authority)], &[authority])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidPoints))
+    );
+}
+
+/// `setup` with an allowlist capping `user` at 2 SOL, next to two uncapped wallets
+fn allowlisted_fixture() -> (Fixture, Allowlist) {
+    let mut fixture = setup();
+    let allowlist = Allowlist::new(vec![
+        (Pubkey::new_unique(), u64::MAX),
+        (fixture.user, 2 * SOL),
+        (Pubkey::new_unique(), u64::MAX),
+    ]);
+    fixture
+        .svm
+        .process(
+            &[rewards::set_allowlist_root(
+                &fixture.authority,
+                allowlist.root(),
+            )],
+            &[fixture.authority],
+        )
+        .unwrap();
+    (fixture, allowlist)
+}
+
+#[test]
+fn set_allowlist_root_rotates_the_root() {
+    let (
+        Fixture {
+            mut svm, authority, ..
+        },
+        allowlist,
+    ) = allowlisted_fixture();
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.allowlist_root, allowlist.root());
+
+    svm.process(
+        &[rewards::set_allowlist_root(&authority, [7; 32])],
+        &[authority],
+    )
+    .unwrap();
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.allowlist_root, [7; 32]);
+    let [updated] = &svm.events::<AllowlistRootUpdated>()[..] else {
+        panic!("expected one AllowlistRootUpdated event");
+    };
+    assert_eq!(updated.previous_root, allowlist.root());
+    assert_eq!(updated.root, [7; 32]);
+}
+
+#[test]
+fn stake_into_an_allowlisted_pool_requires_a_proof() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        _,
+    ) = allowlisted_fixture();
+
+    let err = svm
+        .process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::NotAllowlisted))
+    );
+}
+
+#[test]
+fn stake_is_capped_at_the_proven_allocation() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        allowlist,
+    ) = allowlisted_fixture();
+    let proof = allowlist.proof(&user).unwrap();
+
+    for _ in 0..2 {
+        svm.process(
+            &[rewards::stake_allowlisted(
+                &user,
+                &authority,
+                SOL,
+                None,
+                proof.clone(),
+            )],
+            &[user],
+        )
+        .unwrap();
+    }
+    assert_eq!(position(&svm, &user).staked_amount, 2 * SOL);
+
+    let err = svm
+        .process(
+            &[rewards::stake_allowlisted(
+                &user, &authority, 1, None, proof,
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::AllocationExceeded))
+    );
+}
+
+#[test]
+fn stake_with_a_forged_or_borrowed_proof_fails() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        allowlist,
+    ) = allowlisted_fixture();
+    let outsider = svm.new_wallet();
+    svm.process(&[rewards::create_pda_account(&outsider)], &[outsider])
+        .unwrap();
+
+    let mut forged = allowlist.proof(&user).unwrap();
+    forged.max_allocation = u64::MAX;
+    let borrowed = allowlist.proof(&user).unwrap();
+
+    for (wallet, proof) in [(user, forged), (outsider, borrowed)] {
+        let err = svm
+            .process(
+                &[rewards::stake_allowlisted(
+                    &wallet, &authority, SOL, None, proof,
+                )],
+                &[wallet],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::NotAllowlisted))
+        );
+    }
+}
+
+#[test]
+fn rotating_the_allowlist_invalidates_old_proofs_and_clearing_it_opens_the_pool() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        allowlist,
+    ) = allowlisted_fixture();
+    let rotated = Allowlist::new(vec![(Pubkey::new_unique(), u64::MAX)]);
+    svm.process(
+        &[rewards::set_allowlist_root(&authority, rotated.root())],
+        &[authority],
+    )
+    .unwrap();
+
+    let err = svm
+        .process(
+            &[rewards::stake_allowlisted(
+                &user,
+                &authority,
+                SOL,
+                None,
+                allowlist.proof(&user).unwrap(),
+            )],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::NotAllowlisted))
+    );
+
+    svm.process(
+        &[rewards::set_allowlist_root(&authority, [0; 32])],
+        &[authority],
+    )
+    .unwrap();
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+}
+
+/// Sets `authority`'s pool to a 1 SOL minimum, a 3 SOL per-wallet cap and a 4 SOL pool cap
+fn limit_stakes(svm: &mut Svm, authority: &Pubkey) {
+    svm.process(
+        &[rewards::update_pool_params(
+            authority,
+            PoolParams {
+                referral_bps: 1_000,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                min_stake: SOL,
+                max_stake_per_wallet: Some(3 * SOL),
+                max_total_staked: Some(4 * SOL),
+                decay: PointsDecay::None,
+                streak_tiers: Default::default(),
+            },
+        )],
+        &[*authority],
+    )
+    .unwrap();
+}
+
+#[test]
+fn stake_zero_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+
+    let err = svm
+        .process(&[rewards::stake(&user, &authority, 0, None)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidAmount))
+    );
+}
+
+#[test]
+fn update_pool_params_rejects_a_wallet_cap_below_the_minimum() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    let err = svm
+        .process(
+            &[rewards::update_pool_params(
+                &authority,
+                PoolParams {
+                    referral_bps: 0,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 2 * SOL,
+                    max_stake_per_wallet: Some(SOL),
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            )],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidStakeLimits))
+    );
+}
+
+#[test]
+fn stake_enforces_the_minimum_and_both_caps() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    limit_stakes(&mut svm, &authority);
+    let other = svm.new_wallet();
+    svm.process(&[rewards::create_pda_account(&other)], &[other])
+        .unwrap();
+
+    for (wallet, amount, expected) in [
+        (user, SOL - 1, Some(StakingError::BelowMinimumStake)),
+        (user, 3 * SOL, None),
+        (user, SOL, Some(StakingError::WalletCapExceeded)),
+        (other, 2 * SOL, Some(StakingError::PoolCapExceeded)),
+        (other, SOL, None),
+    ] {
+        let result = svm.process(
+            &[rewards::stake(&wallet, &authority, amount, None)],
+            &[wallet],
+        );
+        match expected {
+            Some(error) => assert_eq!(result.unwrap_err().custom_code(), Some(u32::from(error))),
+            None => result.unwrap(),
+        }
+    }
+
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 4 * SOL);
+}
+
+fn capacity(svm: &mut Svm, authority: &Pubkey, position: Option<&Pubkey>) -> Capacity {
+    svm.process(&[rewards::capacity(authority, position)], &[])
+        .unwrap();
+    let (_, data) = svm.return_data().unwrap();
+    Capacity::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn capacity_reports_the_room_left_under_each_cap() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let position = pda::stake_account(&user).0;
+
+    assert_eq!(
+        capacity(&mut svm, &authority, Some(&position)),
+        Capacity {
+            min_stake: 0,
+            total_staked: 0,
+            pool_remaining: None,
+            wallet_staked: 0,
+            wallet_remaining: None,
+        }
+    );
+
+    limit_stakes(&mut svm, &authority);
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+
+    assert_eq!(
+        capacity(&mut svm, &authority, Some(&position)),
+        Capacity {
+            min_stake: SOL,
+            total_staked: 2 * SOL,
+            pool_remaining: Some(2 * SOL),
+            wallet_staked: 2 * SOL,
+            wallet_remaining: Some(SOL),
+        }
+    );
+    assert_eq!(
+        capacity(&mut svm, &authority, None).wallet_remaining,
+        Some(2 * SOL)
+    );
+}
+
+fn leaderboard(svm: &Svm, authority: &Pubkey) -> Leaderboard {
+    svm.get(&pda::leaderboard(authority).0)
+}
+
+fn standings(entries: &[LeaderboardEntry]) -> Vec<(Pubkey, u64)> {
+    entries
+        .iter()
+        .map(|entry| (entry.position, entry.points))
+        .collect()
+}
+
+#[test]
+fn settling_ranks_positions_by_season_points() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let other = svm.new_wallet();
+    svm.process(&[rewards::create_pda_account(&other)], &[other])
+        .unwrap();
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    svm.process(
+        &[rewards::stake(&other, &authority, 3 * SOL, None)],
+        &[other],
+    )
+    .unwrap();
+    svm.warp_by(10);
+
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+    svm.process(&[rewards::unstake(&other, &authority, SOL)], &[other])
+        .unwrap();
+
+    let board = leaderboard(&svm, &authority);
+    assert_eq!(board.season, 1);
+    assert_eq!(
+        standings(board.ranked()),
+        vec![
+            (pda::stake_account(&other).0, 30),
+            (pda::stake_account(&user).0, 20),
+        ]
+    );
+
+    // Claiming spends points but not what was earned this season
+    svm.process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap();
+    svm.warp_by(15);
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+
+    assert_eq!(
+        standings(leaderboard(&svm, &authority).ranked()),
+        vec![
+            (pda::stake_account(&user).0, 35),
+            (pda::stake_account(&other).0, 30),
+        ]
+    );
+}
+
+#[test]
+fn leaderboard_keeps_only_the_top_positions() {
+    let Fixture { svm, authority, .. } = setup();
+    let mut board = leaderboard(&svm, &authority);
+    let positions: Vec<Pubkey> = (0..LEADERBOARD_SIZE + 8)
+        .map(|_| Pubkey::new_unique())
+        .collect();
+
+    for (points, position) in (1..).zip(&positions) {
+        board.record(*position, points);
+    }
+    // Moving up replaces the position's entry instead of adding a second one
+    board.record(positions[0], 1_000);
+
+    let ranked = board.ranked();
+    assert_eq!(ranked.len(), LEADERBOARD_SIZE);
+    assert_eq!(
+        (ranked[0].position, ranked[0].points),
+        (positions[0], 1_000)
+    );
+    assert!(ranked
+        .windows(2)
+        .all(|pair| pair[0].points >= pair[1].points));
+    assert_eq!(
+        ranked[LEADERBOARD_SIZE - 1].points,
+        positions.len() as u64 - LEADERBOARD_SIZE as u64 + 2
+    );
+}
+
+#[test]
+fn transfer_position_moves_its_leaderboard_entry() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let new_owner = svm.new_wallet();
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    svm.warp_by(10);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    svm.process(
+        &[rewards::transfer_position(&user, &new_owner, &authority)],
+        &[user],
+    )
+    .unwrap();
+
+    assert_eq!(
+        standings(leaderboard(&svm, &authority).ranked()),
+        vec![(pda::stake_account(&new_owner).0, 20)]
+    );
+    assert_eq!(position(&svm, &new_owner).season_points, 20);
+}
+
+#[test]
+fn reset_season_archives_the_standings_and_restarts_the_count() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    svm.warp_by(10);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    svm.process(&[rewards::reset_season(&authority, 1)], &[authority])
+        .unwrap();
+
+    let snapshot: LeaderboardSnapshot = svm.get(&pda::leaderboard_snapshot(&authority, 1).0);
+    assert_eq!(snapshot.season, 1);
+    assert_eq!(
+        standings(snapshot.ranked()),
+        vec![(pda::stake_account(&user).0, 20)]
+    );
+    let board = leaderboard(&svm, &authority);
+    assert_eq!(board.season, 2);
+    assert!(board.ranked().is_empty());
+
+    svm.warp_by(5);
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+
+    let position = position(&svm, &user);
+    assert_eq!((position.season, position.season_points), (2, 5));
+    assert_eq!(position.total_points, 25);
+    assert_eq!(
+        standings(leaderboard(&svm, &authority).ranked()),
+        vec![(pda::stake_account(&user).0, 5)]
+    );
+}
+
+#[test]
+fn reset_season_only_resets_the_current_season() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+    svm.process(&[rewards::reset_season(&authority, 1)], &[authority])
+        .unwrap();
+
+    let err = svm
+        .process(&[rewards::reset_season(&authority, 3)], &[authority])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::SeasonMismatch))
+    );
+
+    // Season 1 is already archived
+    let err = svm
+        .process(&[rewards::reset_season(&authority, 1)], &[authority])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn a_pool_without_a_leaderboard_stakes_without_one() {
+    let mut svm = Svm::new();
+    let authority = svm.new_wallet();
+    let user = svm.new_wallet();
+    svm.process(
+        &[
+            rewards::create_vault_pda_account(&authority),
+            rewards::initialize_pool(
+                &authority,
+                PoolParams {
+                    referral_bps: 0,
+                    fee_bps: 0,
+                    time_base: TimeBase::UnixSeconds,
+                    min_stake: 0,
+                    max_stake_per_wallet: None,
+                    max_total_staked: None,
+                    decay: PointsDecay::None,
+                    streak_tiers: Default::default(),
+                },
+            ),
+        ],
+        &[authority],
+    )
+    .unwrap();
+    svm.process(
+        &[
+            rewards::create_pda_account(&user),
+            rewards::without_leaderboard(rewards::stake(&user, &authority, 2 * SOL, None)),
+        ],
+        &[user],
+    )
+    .unwrap();
+    svm.warp_by(10);
+
+    svm.process(
+        &[rewards::without_leaderboard(rewards::unstake(
+            &user, &authority, SOL,
+        ))],
+        &[user],
+    )
+    .unwrap();
+    let settled = position(&svm, &user);
+    assert_eq!(settled.total_points, 20);
+    assert_eq!((settled.season, settled.season_points), (0, 0));
+
+    let new_owner = Pubkey::new_unique();
+    svm.process(
+        &[rewards::without_leaderboard(rewards::transfer_position(
+            &user, &new_owner, &authority,
+        ))],
+        &[user],
+    )
+    .unwrap();
+    assert_eq!(position(&svm, &new_owner).staked_amount, SOL);
+}
+
+#[test]
+fn a_ranked_position_needs_the_leaderboard() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    assert_eq!(position(&svm, &user).season, 1);
+
+    for ix in [
+        rewards::unstake(&user, &authority, SOL),
+        rewards::transfer_position(&user, &Pubkey::new_unique(), &authority),
+    ] {
+        let err = svm
+            .process(&[rewards::without_leaderboard(ix)], &[user])
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::LeaderboardRequired))
+        );
+    }
+}
+
+#[test]
+fn a_pool_with_a_leaderboard_needs_it_even_for_unranked_positions() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert!(pool.has_leaderboard);
+
+    for ix in [
+        rewards::stake(&user, &authority, 2 * SOL, None),
+        rewards::transfer_position(&user, &Pubkey::new_unique(), &authority),
+    ] {
+        let err = svm
+            .process(&[rewards::without_leaderboard(ix)], &[user])
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::LeaderboardRequired))
+        );
+    }
+}
+
+/// A mint with `supply` tokens in `owner`'s associated account
+fn mint_to_wallet(svm: &mut Svm, owner: &Pubkey, decimals: u8, supply: u64) -> Pubkey {
+    let mint = svm.new_keypair();
+    let space = spl_token::state::Mint::LEN;
+    svm.process(
+        &[
+            system_instruction::create_account(
+                owner,
+                &mint,
+                svm.rent().minimum_balance(space),
+                space as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(&spl_token::ID, &mint, owner, None, decimals)
+                .unwrap(),
+            create_associated_token_account(owner, owner, &mint, &spl_token::ID),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &mint,
+                &get_associated_token_address(owner, &mint),
+                owner,
+                &[],
+                supply,
+            )
+            .unwrap(),
+        ],
+        &[*owner, mint],
+    )
+    .unwrap();
+    mint
+}
+
+/// Settles 100 points onto `user`'s position, leaving 1 SOL staked
+fn earn_points(svm: &mut Svm, authority: &Pubkey, user: &Pubkey) {
+    svm.process(&[rewards::stake(user, authority, 2 * SOL, None)], &[*user])
+        .unwrap();
+    svm.warp_by(50);
+    svm.process(&[rewards::unstake(user, authority, SOL)], &[*user])
+        .unwrap();
+}
+
+/// Item 1 of `authority`'s catalogue, 3 bundles of 50 tokens at 40 points each, and an
+/// associated account for `user` to receive them in
+fn catalog_fixture() -> (Fixture, Pubkey) {
+    let mut fixture = setup();
+    let Fixture {
+        svm,
+        authority,
+        user,
+    } = &mut fixture;
+    let mint = mint_to_wallet(svm, authority, 6, 1_000);
+
+    svm.process(
+        &[
+            rewards::publish_item(
+                authority,
+                1,
+                ItemParams {
+                    kind: ItemKind::Tokens,
+                    price: 40,
+                    stock: 3,
+                    amount: 50,
+                },
+                Some(&mint),
+            ),
+            create_associated_token_account(user, user, &mint, &spl_token::ID),
+        ],
+        &[*authority, *user],
+    )
+    .unwrap();
+
+    (fixture, mint)
+}
+
+#[test]
+fn publish_item_escrows_the_whole_stock() {
+    let (
+        Fixture {
+            mut svm, authority, ..
+        },
+        mint,
+    ) = catalog_fixture();
+    let item_key = pda::catalog_item(&authority, 1).0;
+    let escrow = pda::item_escrow(&item_key, &mint);
+
+    let item: CatalogItem = svm.get(&item_key);
+    assert_eq!((item.mint, item.price, item.stock), (mint, 40, 3));
+    assert_eq!(svm.token_balance(&escrow), 150);
+    assert_eq!(
+        svm.token_balance(&get_associated_token_address(&authority, &mint)),
+        850
+    );
+
+    svm.process(
+        &[rewards::update_item(&authority, 1, 60, 2, Some(&mint))],
+        &[authority],
+    )
+    .unwrap();
+
+    let item: CatalogItem = svm.get(&item_key);
+    assert_eq!((item.price, item.stock), (60, 5));
+    assert_eq!(svm.token_balance(&escrow), 250);
+}
+
+#[test]
+fn redeem_delivers_the_tokens_and_spends_the_points() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        mint,
+    ) = catalog_fixture();
+    earn_points(&mut svm, &authority, &user);
+
+    svm.process(
+        &[rewards::redeem(&user, &authority, 1, Some(&mint))],
+        &[user],
+    )
+    .unwrap();
+
+    let item_key = pda::catalog_item(&authority, 1).0;
+    assert_eq!(
+        svm.token_balance(&get_associated_token_address(&user, &mint)),
+        50
+    );
+    assert_eq!(svm.token_balance(&pda::item_escrow(&item_key, &mint)), 100);
+    assert_eq!(position(&svm, &user).total_points, 60);
+    let item: CatalogItem = svm.get(&item_key);
+    assert_eq!((item.stock, item.redeemed), (2, 1));
+
+    let [event] = &svm.events::<ItemRedeemed>()[..] else {
+        panic!("expected one ItemRedeemed event");
+    };
+    assert_eq!(event.item, item_key);
+    assert_eq!((event.price, event.stock, event.total_points), (40, 2, 60));
+
+    // 20 points left is not enough for another bundle
+    svm.process(
+        &[rewards::redeem(&user, &authority, 1, Some(&mint))],
+        &[user],
+    )
+    .unwrap();
+    let err = svm
+        .process(
+            &[rewards::redeem(&user, &authority, 1, Some(&mint))],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidPoints))
+    );
+}
+
+#[test]
+fn redeem_settles_the_points_earned_so_far() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        mint,
+    ) = catalog_fixture();
+    earn_points(&mut svm, &authority, &user);
+
+    // The SOL still staked earned 30 more points since the unstake settled
+    svm.warp_by(30);
+    svm.process(
+        &[rewards::redeem(&user, &authority, 1, Some(&mint))],
+        &[user],
+    )
+    .unwrap();
+
+    let position = position(&svm, &user);
+    assert_eq!(position.total_points, 100 + 30 - 40);
+    assert_eq!(position.season_points, 130);
+    assert_eq!(position.last_update_time, svm.clock().unix_timestamp);
+}
+
+#[test]
+fn redeem_from_a_position_that_never_staked_fails() {
+    let (
+        Fixture {
+            mut svm,
+            authority,
+            user,
+        },
+        mint,
+    ) = catalog_fixture();
+
+    let err = svm
+        .process(
+            &[rewards::redeem(&user, &authority, 1, Some(&mint))],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultMismatch))
+    );
+}
+
+#[test]
+fn redeem_an_item_out_of_stock_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(
+        &[rewards::publish_item(
+            &authority,
+            7,
+            ItemParams {
+                kind: ItemKind::WhitelistSpot,
+                price: 10,
+                stock: 0,
+                amount: 0,
+            },
+            None,
+        )],
+        &[authority],
+    )
+    .unwrap();
+    earn_points(&mut svm, &authority, &user);
+
+    let err = svm
+        .process(&[rewards::redeem(&user, &authority, 7, None)], &[user])
+        .unwrap_err();
+    assert_eq!(err.custom_code(), Some(u32::from(StakingError::OutOfStock)));
+    assert_eq!(position(&svm, &user).total_points, 100);
+}
+
+#[test]
+fn redeem_a_whitelist_spot_issues_one_receipt_per_wallet() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    svm.process(
+        &[rewards::publish_item(
+            &authority,
+            2,
+            ItemParams {
+                kind: ItemKind::WhitelistSpot,
+                price: 30,
+                stock: 10,
+                amount: 0,
+            },
+            None,
+        )],
+        &[authority],
+    )
+    .unwrap();
+    let [published] = &svm.events::<ItemPublished>()[..] else {
+        panic!("expected one ItemPublished event");
+    };
+    assert_eq!(
+        (published.kind, published.mint),
+        (ItemKind::WhitelistSpot, Pubkey::default())
+    );
+    earn_points(&mut svm, &authority, &user);
+
+    svm.process(&[rewards::redeem(&user, &authority, 2, None)], &[user])
+        .unwrap();
+
+    let item_key = pda::catalog_item(&authority, 2).0;
+    let spot: WhitelistSpot = svm.get(&pda::whitelist_spot(&item_key, &user).0);
+    assert_eq!((spot.owner, spot.item), (user, item_key));
+    assert_eq!(spot.redeemed_at, svm.clock().unix_timestamp);
+    assert_eq!(position(&svm, &user).total_points, 70);
+
+    let err = svm
+        .process(&[rewards::redeem(&user, &authority, 2, None)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(SystemError::AccountAlreadyInUse as u32)
+    );
+}
+
+#[test]
+fn publish_an_nft_requires_a_single_zero_decimals_token() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+    let fungible = mint_to_wallet(&mut svm, &authority, 6, 10);
+    let nft = mint_to_wallet(&mut svm, &authority, 0, 2);
+    let params = |amount, stock| ItemParams {
+        kind: ItemKind::Nft,
+        price: 500,
+        stock,
+        amount,
+    };
+
+    for (mint, amount, stock) in [(fungible, 1, 1), (nft, 2, 1), (nft, 1, 2), (nft, 1, 0)] {
+        let err = svm
+            .process(
+                &[rewards::publish_item(
+                    &authority,
+                    3,
+                    params(amount, stock),
+                    Some(&mint),
+                )],
+                &[authority],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::InvalidItem))
+        );
+    }
+
+    let err = svm
+        .process(
+            &[rewards::publish_item(&authority, 3, params(1, 1), None)],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::MissingItemAccounts))
+    );
+
+    svm.process(
+        &[rewards::publish_item(
+            &authority,
+            3,
+            params(1, 1),
+            Some(&nft),
+        )],
+        &[authority],
+    )
+    .unwrap();
+    let item_key = pda::catalog_item(&authority, 3).0;
+    assert_eq!(svm.token_balance(&pda::item_escrow(&item_key, &nft)), 1);
+
+    // Nor can the item be restocked with the mint's other token
+    let err = svm
+        .process(
+            &[rewards::update_item(&authority, 3, 500, 1, Some(&nft))],
+            &[authority],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidItem))
+    );
+    svm.process(
+        &[rewards::update_item(&authority, 3, 600, 0, Some(&nft))],
+        &[authority],
+    )
+    .unwrap();
+}
+
+const DAY: i64 = 86_400;
+
+/// `setup` with the pool's points decaying by `decay`, and the clock at the start of a day
+fn decay_fixture(decay: PointsDecay) -> Fixture {
+    let mut fixture = setup();
+    let Fixture { svm, authority, .. } = &mut fixture;
+    svm.process(
+        &[rewards::update_pool_params(
+            authority,
+            PoolParams {
+                referral_bps: 1_000,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+                decay,
+                streak_tiers: Default::default(),
+            },
+        )],
+        &[*authority],
+    )
+    .unwrap();
+    svm.warp_to((svm.clock().unix_timestamp / DAY + 1) * DAY);
+    fixture
+}
+
+/// Keeps 1 SOL staked for `seconds` to settle `seconds` points. A position can never be
+/// emptied, so half a SOL stays behind, too little to earn anything.
+fn earn_points_for(svm: &mut Svm, authority: &Pubkey, user: &Pubkey, seconds: i64) {
+    let amount = match position(svm, user).staked_amount {
+        0 => SOL + SOL / 2,
+        _ => SOL,
+    };
+    svm.process(&[rewards::stake(user, authority, amount, None)], &[*user])
+        .unwrap();
+    svm.warp_by(seconds);
+    svm.process(&[rewards::unstake(user, authority, SOL)], &[*user])
+        .unwrap();
+}
+
+fn points_outlook(svm: &mut Svm, authority: &Pubkey, owner: &Pubkey) -> PointsOutlook {
+    svm.process(&[rewards::points_outlook(authority, owner)], &[])
+        .unwrap();
+    let (_, data) = svm.return_data().unwrap();
+    PointsOutlook::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn update_pool_params_validates_the_decay() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    for decay in [
+        PointsDecay::Rate {
+            bps: 0,
+            period: DAY,
+        },
+        PointsDecay::Rate {
+            bps: 10_001,
+            period: DAY,
+        },
+        PointsDecay::Rate {
+            bps: 1_000,
+            period: DAY - 1,
+        },
+        PointsDecay::Expiry { window: 3_600 },
+    ] {
+        let err = svm
+            .process(
+                &[rewards::update_pool_params(
+                    &authority,
+                    PoolParams {
+                        referral_bps: 1_000,
+                        fee_bps: 0,
+                        time_base: TimeBase::UnixSeconds,
+                        min_stake: 0,
+                        max_stake_per_wallet: None,
+                        max_total_staked: None,
+                        decay,
+                        streak_tiers: Default::default(),
+                    },
+                )],
+                &[authority],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::InvalidDecayParams)),
+            "{decay:?}"
+        );
+    }
+}
+
+#[test]
+fn points_outlook_without_decay_expires_nothing() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    earn_points(&mut svm, &authority, &user);
+    svm.warp_by(365 * DAY);
+
+    assert_eq!(
+        points_outlook(&mut svm, &authority, &user),
+        PointsOutlook {
+            total_points: 100,
+            expiring_points: 0,
+            expires_at: None,
+        }
+    );
+}
+
+#[test]
+fn decay_rate_compounds_every_period_until_settlement() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = decay_fixture(PointsDecay::Rate {
+        bps: 5_000,
+        period: DAY,
+    });
+    let start = svm.clock().unix_timestamp;
+    earn_points_for(&mut svm, &authority, &user, 400);
+
+    // Nothing is taken until the position settles
+    svm.warp_to(start + 2 * DAY + 10);
+    assert_eq!(position(&svm, &user).total_points, 400);
+    assert_eq!(
+        points_outlook(&mut svm, &authority, &user),
+        PointsOutlook {
+            total_points: 100,
+            expiring_points: 50,
+            expires_at: Some(start + 3 * DAY),
+        }
+    );
+
+    svm.process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap();
+
+    let [expired] = &svm.events::<PointsExpired>()[..] else {
+        panic!("expected one PointsExpired event");
+    };
+    assert_eq!((expired.amount, expired.total_points), (300, 100));
+    let [claimed] = &svm.events::<PointsClaimed>()[..] else {
+        panic!("expected one PointsClaimed event");
+    };
+    assert_eq!(claimed.amount, 100);
+}
+
+#[test]
+fn claimed_referral_points_expire_from_the_claim() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = decay_fixture(PointsDecay::Expiry { window: DAY });
+    let start = svm.clock().unix_timestamp;
+    let referrer = svm.new_wallet();
+    svm.process(&[rewards::create_pda_account(&referrer)], &[referrer])
+        .unwrap();
+    svm.process(
+        &[rewards::stake(&user, &authority, 2 * SOL, Some(referrer))],
+        &[user],
+    )
+    .unwrap();
+    svm.warp_by(100);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    // The referrer's position last decayed two windows before the claim
+    svm.warp_to(start + 2 * DAY);
+    svm.process(
+        &[rewards::claim_referral_points(&referrer, &user, &authority)],
+        &[referrer],
+    )
+    .unwrap();
+
+    assert_eq!(
+        points_outlook(&mut svm, &authority, &referrer),
+        PointsOutlook {
+            total_points: 20,
+            expiring_points: 0,
+            expires_at: Some(start + 3 * DAY),
+        }
+    );
+    svm.warp_to(start + 3 * DAY);
+    assert_eq!(
+        points_outlook(&mut svm, &authority, &referrer).total_points,
+        20
+    );
+}
+
+#[test]
+fn expiry_drops_points_the_window_after_they_settle() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = decay_fixture(PointsDecay::Expiry { window: DAY });
+    let start = svm.clock().unix_timestamp;
+    svm.process(
+        &[rewards::publish_item(
+            &authority,
+            1,
+            ItemParams {
+                kind: ItemKind::WhitelistSpot,
+                price: 40,
+                stock: 1,
+                amount: 0,
+            },
+            None,
+        )],
+        &[authority],
+    )
+    .unwrap();
+    earn_points_for(&mut svm, &authority, &user, 100);
+
+    svm.warp_to(start + DAY);
+    assert_eq!(
+        points_outlook(&mut svm, &authority, &user),
+        PointsOutlook {
+            total_points: 100,
+            expiring_points: 100,
+            expires_at: Some(start + 2 * DAY),
+        }
+    );
+
+    // Fresh points last a window longer, and spending takes the oldest ones first
+    earn_points_for(&mut svm, &authority, &user, 30);
+    svm.process(&[rewards::redeem(&user, &authority, 1, None)], &[user])
+        .unwrap();
+    let position_now = position(&svm, &user);
+    assert_eq!(
+        (position_now.total_points, position_now.expiring_points),
+        (90, 60)
+    );
+
+    svm.warp_to(start + 2 * DAY);
+    assert_eq!(points_outlook(&mut svm, &authority, &user).total_points, 30);
+
+    svm.warp_to(start + 3 * DAY);
+    assert_eq!(points_outlook(&mut svm, &authority, &user).total_points, 0);
+    let err = svm
+        .process(&[rewards::claim_points(&user, &authority)], &[user])
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::InvalidPoints))
+    );
+}
+
+/// `setup` with a 10% streak bonus from 30 days and 25% from 90
+fn streak_fixture() -> Fixture {
+    let mut fixture = setup();
+    let Fixture { svm, authority, .. } = &mut fixture;
+    svm.process(
+        &[rewards::update_pool_params(
+            authority,
+            PoolParams {
+                referral_bps: 1_000,
+                fee_bps: 0,
+                time_base: TimeBase::UnixSeconds,
+                min_stake: 0,
+                max_stake_per_wallet: None,
+                max_total_staked: None,
+                decay: PointsDecay::None,
+                streak_tiers: streak_tiers(&[(30 * DAY, 1_000), (90 * DAY, 2_500)]),
+            },
+        )],
+        &[*authority],
+    )
+    .unwrap();
+    fixture
+}
+
+fn streak_tiers(tiers: &[(i64, u16)]) -> [StreakTier; MAX_STREAK_TIERS] {
+    let mut all = [StreakTier::default(); MAX_STREAK_TIERS];
+    for (slot, &(after, bonus_bps)) in all.iter_mut().zip(tiers) {
+        *slot = StreakTier { after, bonus_bps };
+    }
+    all
+}
+
+#[test]
+fn update_pool_params_validates_the_streak_tiers() {
+    let Fixture {
+        mut svm, authority, ..
+    } = setup();
+
+    for tiers in [
+        // Shorter than the tier before
+        streak_tiers(&[(90 * DAY, 1_000), (30 * DAY, 2_500)]),
+        // No larger than the tier before
+        streak_tiers(&[(30 * DAY, 1_000), (90 * DAY, 1_000)]),
+        // More than doubling the rate
+        streak_tiers(&[(30 * DAY, MAX_STREAK_BONUS_BPS + 1)]),
+        // After an unused tier
+        streak_tiers(&[(30 * DAY, 0), (90 * DAY, 2_500)]),
+    ] {
+        let err = svm
+            .process(
+                &[rewards::update_pool_params(
+                    &authority,
+                    PoolParams {
+                        referral_bps: 1_000,
+                        fee_bps: 0,
+                        time_base: TimeBase::UnixSeconds,
+                        min_stake: 0,
+                        max_stake_per_wallet: None,
+                        max_total_staked: None,
+                        decay: PointsDecay::None,
+                        streak_tiers: tiers,
+                    },
+                )],
+                &[authority],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.custom_code(),
+            Some(u32::from(StakingError::InvalidStreakTiers)),
+            "{tiers:?}"
+        );
+    }
+}
+
+#[test]
+fn streak_bonus_applies_from_each_tier_boundary() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = streak_fixture();
+    let start = svm.clock().unix_timestamp;
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    assert_eq!(position(&svm, &user).streak_start, start);
+
+    svm.warp_by(100 * DAY);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    // 100 days at the base rate, 60 more at 10% and 10 at 25%, for 2 SOL
+    let bonus = 60 * DAY / 10 + 10 * DAY / 4;
+    assert_eq!(
+        position(&svm, &user).total_points,
+        2 * (100 * DAY + bonus) as u64
+    );
+}
+
+#[test]
+fn unstake_gives_up_a_matching_share_of_the_streak() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = streak_fixture();
+    let start = svm.clock().unix_timestamp;
+    svm.process(&[rewards::stake(&user, &authority, 2 * SOL, None)], &[user])
+        .unwrap();
+    svm.warp_by(40 * DAY);
+    svm.process(&[rewards::unstake(&user, &authority, SOL)], &[user])
+        .unwrap();
+
+    let settled = position(&svm, &user);
+    assert_eq!(settled.streak_start, start + 20 * DAY);
+
+    // The streak reaches 30 days 10 days from now, and topping up does not restart it
+    svm.warp_by(20 * DAY);
+    svm.process(&[rewards::stake(&user, &authority, SOL, None)], &[user])
+        .unwrap();
+
+    let position = position(&svm, &user);
+    assert_eq!(position.streak_start, start + 20 * DAY);
+    assert_eq!(
+        position.total_points - settled.total_points,
+        (20 * DAY + DAY) as u64
+    );
+}
+
+#[test]
+fn migrate_account_upgrades_a_v1_position_in_place() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    write_v1_position(&mut svm, &user, 0);
+    let address = pda::stake_account(&user).0;
+    let space = 8 + StakeAccount::INIT_SPACE;
+    let clock = svm.clock().clone();
+
+    svm.process(
+        &[rewards::migrate_account(&user, &user, &authority)],
+        &[user],
+    )
+    .unwrap();
+
+    let account = svm.account(&address).unwrap();
+    assert_eq!(account.data.len(), space);
+    assert_eq!(account.lamports, svm.rent().minimum_balance(space));
+
+    let position = position(&svm, &user);
+    assert_eq!(position.version, STAKE_ACCOUNT_VERSION);
+    assert_eq!(position.owner, user);
+    assert_eq!(position.total_points, 42);
+    assert_eq!(position.last_update_time, 1_700_000_000);
+    assert_eq!(position.bump, pda::stake_account(&user).1);
+    assert_eq!(position.vault, Pubkey::default());
+    assert_eq!(position.streak_start, 0);
+    // v1 never recorded slots or epochs, so those count from the migration
+    assert_eq!(position.last_update_slot, clock.slot);
+    assert_eq!(position.last_update_epoch, clock.epoch);
+    assert_eq!(position.reserved, [0; 8]);
+}
+
+#[test]
+fn migrate_account_binds_legacy_stake_with_the_authority_co_signing() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    write_v1_position(&mut svm, &user, 2 * SOL);
+
+    let err = svm
+        .process(
+            &[rewards::migrate_account(&user, &user, &authority)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::VaultAuthorityRequired))
+    );
+
+    svm.process(
+        &[rewards::signed_by_authority(rewards::migrate_account(
+            &user, &user, &authority,
+        ))],
+        &[user, authority],
+    )
+    .unwrap();
+
+    let position = position(&svm, &user);
+    assert_eq!(position.staked_amount, 2 * SOL);
+    assert_eq!(position.vault, pda::vault(&authority).0);
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, 2 * SOL);
+}
+
+#[test]
+fn migrate_account_twice_fails() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    write_v1_position(&mut svm, &user, 0);
+    svm.process(
+        &[rewards::migrate_account(&user, &user, &authority)],
+        &[user],
+    )
+    .unwrap();
+
+    let err = svm
+        .process(
+            &[rewards::migrate_account(&user, &user, &authority)],
+            &[user],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.custom_code(),
+        Some(u32::from(StakingError::AlreadyMigrated))
+    );
+}
+
+#[test]
+fn a_migrated_v1_position_unstakes_from_the_vault() {
+    let Fixture {
+        mut svm,
+        authority,
+        user,
+    } = setup();
+    // Stake deposited before the pool tracked its vault
+    svm.airdrop(&pda::vault(&authority).0, 2 * SOL);
+    write_v1_position(&mut svm, &user, 2 * SOL);
+
+    svm.process(
+        &[
+            rewards::signed_by_authority(rewards::migrate_account(&user, &user, &authority)),
+            rewards::unstake(&user, &authority, SOL),
+        ],
+        &[user, authority],
+    )
+    .unwrap();
+
+    assert_eq!(position(&svm, &user).staked_amount, SOL);
+    let pool: Pool = svm.get(&pda::pool(&authority).0);
+    assert_eq!(pool.total_staked, SOL);
+}
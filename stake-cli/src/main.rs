@@ -0,0 +1,1974 @@
+//! Command-line tool for the staking programs, built on `staking-client`.
+//!
+//! Every command that changes state accepts `--simulate`, which runs the transaction through
+//! `simulateTransaction` and prints the logs and the accounts as they would look afterwards.
+
+mod rpc;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anchor_lang::prelude::{AnchorDeserialize, Pubkey};
+use anchor_lang::Discriminator;
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Map, Value};
+use solana_keypair::{read_keypair_file, Keypair};
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+use staking_client::snapshot::{self, AllocationFormula, Holding};
+use staking_client::{basic, decode_account, rewards, token_rewards, Instruction};
+
+use rpc::RpcClient;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// RPC URL of the cluster
+    #[arg(
+        long,
+        short = 'u',
+        global = true,
+        default_value = "http://127.0.0.1:8899"
+    )]
+    url: String,
+
+    /// Keypair that signs and pays for transactions
+    #[arg(
+        long,
+        short = 'k',
+        global = true,
+        default_value = "~/.config/solana/id.json"
+    )]
+    keypair: String,
+
+    /// Program to talk to
+    #[arg(long, global = true, value_enum, default_value_t = ProgramKind::Rewards)]
+    program: ProgramKind,
+
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Human)]
+    output: OutputFormat,
+
+    /// Simulate the transaction and print the resulting state instead of sending it
+    #[arg(long, global = true)]
+    simulate: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ProgramKind {
+    /// 01. Basic Staking Program
+    Basic,
+    /// 02. Staking Program with Rewards
+    Rewards,
+    /// 03. Staking Program with Token Rewards
+    TokenRewards,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Tier {
+    Days30,
+    Days90,
+    Days180,
+    Days365,
+}
+
+impl From<Tier> for token_rewards::LockTier {
+    fn from(tier: Tier) -> Self {
+        match tier {
+            Tier::Days30 => Self::Days30,
+            Tier::Days90 => Self::Days90,
+            Tier::Days180 => Self::Days180,
+            Tier::Days365 => Self::Days365,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ItemKind {
+    Tokens,
+    Nft,
+    WhitelistSpot,
+}
+
+impl From<ItemKind> for rewards::ItemKind {
+    fn from(kind: ItemKind) -> Self {
+        match kind {
+            ItemKind::Tokens => Self::Tokens,
+            ItemKind::Nft => Self::Nft,
+            ItemKind::WhitelistSpot => Self::WhitelistSpot,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimeBase {
+    UnixSeconds,
+    Slots,
+    Epochs,
+}
+
+impl From<TimeBase> for rewards::TimeBase {
+    fn from(time_base: TimeBase) -> Self {
+        match time_base {
+            TimeBase::UnixSeconds => Self::UnixSeconds,
+            TimeBase::Slots => Self::Slots,
+            TimeBase::Epochs => Self::Epochs,
+        }
+    }
+}
+
+impl From<TimeBase> for token_rewards::TimeBase {
+    fn from(time_base: TimeBase) -> Self {
+        match time_base {
+            TimeBase::UnixSeconds => Self::UnixSeconds,
+            TimeBase::Slots => Self::Slots,
+            TimeBase::Epochs => Self::Epochs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Formula {
+    /// Pro rata to lamports staked
+    Stake,
+    /// Pro rata to the square root of lamports staked
+    SqrtStake,
+    /// Pro rata to settled points
+    Points,
+    /// The same for every wallet with stake
+    Flat,
+}
+
+impl Formula {
+    fn allocation_formula(self) -> &'static dyn AllocationFormula {
+        match self {
+            Formula::Stake => &snapshot::ByStake,
+            Formula::SqrtStake => &snapshot::BySqrtStake,
+            Formula::Points => &snapshot::ByPoints,
+            Formula::Flat => &snapshot::Flat,
+        }
+    }
+}
+
+/// The vault authority a command stakes against
+#[derive(clap::Args)]
+struct AuthorityArgs {
+    /// Vault authority
+    #[arg(long)]
+    authority: Option<Pubkey>,
+
+    /// Vault authority keypair, required by the token rewards program where it co-signs
+    #[arg(long)]
+    authority_keypair: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create the vault and the pool for the signing authority
+    InitPool {
+        /// Share of a referee's rewards paid to their referrer
+        #[arg(long, default_value_t = 0)]
+        referral_bps: u16,
+
+        /// Maximum ve boost, token rewards program only
+        #[arg(long, default_value_t = 10_000)]
+        max_boost_bps: u16,
+
+        /// What accrual and lockups are measured in, rewards and token rewards programs only
+        #[arg(long, value_enum, default_value_t = TimeBase::UnixSeconds)]
+        time_base: TimeBase,
+
+        /// Share of every claim paid to the pool's treasury
+        #[arg(long, default_value_t = 0)]
+        fee_bps: u16,
+
+        /// Seconds claimed reward tokens vest over, paid out at once when 0, token rewards
+        /// program only
+        #[arg(long, default_value_t = 0)]
+        vesting_period: i64,
+
+        /// Seconds after a claim before any of it vests
+        #[arg(long, default_value_t = 0, requires = "vesting_period")]
+        vesting_cliff: i64,
+
+        /// Let holders close a vesting escrow early, forfeiting what has not vested
+        #[arg(long, requires = "vesting_period")]
+        early_exit: bool,
+
+        /// Smallest deposit in lamports
+        #[arg(long, default_value_t = 0)]
+        min_stake: u64,
+
+        /// Most a single position may hold in lamports
+        #[arg(long)]
+        max_stake_per_wallet: Option<u64>,
+
+        /// Most the whole pool may hold in lamports
+        #[arg(long)]
+        max_total_staked: Option<u64>,
+
+        /// Share of unspent points lost every decay period, rewards program only
+        #[arg(long, conflicts_with = "expiry_window")]
+        decay_bps: Option<u16>,
+
+        /// Decay period in seconds
+        #[arg(long, default_value_t = 30 * 86_400, requires = "decay_bps")]
+        decay_period: i64,
+
+        /// Expire points at the end of the window after the one they were earned in, in
+        /// seconds, rewards program only
+        #[arg(long)]
+        expiry_window: Option<i64>,
+
+        /// Reward rate bonus once a position has staked without a break for a number of days,
+        /// as `DAYS:BONUS_BPS`. Repeat for up to four tiers, rewards program only.
+        #[arg(long = "streak-tier", value_parser = parse_streak_tier)]
+        streak_tiers: Vec<rewards::StreakTier>,
+    },
+    /// Create a stake position for the signer
+    CreateAccount {
+        /// Position index, token rewards program only
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+
+        /// Lock tier, token rewards program only
+        #[arg(long, value_enum, default_value_t = Tier::Days30)]
+        lock_tier: Tier,
+    },
+    /// Stake lamports into the signer's position
+    Stake {
+        /// Amount in lamports
+        amount: u64,
+
+        #[command(flatten)]
+        authority: AuthorityArgs,
+
+        #[arg(long)]
+        referrer: Option<Pubkey>,
+
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+
+        /// Allowlist file of the pool, to prove the signer is on it, rewards program only
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+    },
+    /// Unstake lamports from the signer's position
+    Unstake {
+        /// Amount in lamports
+        amount: u64,
+
+        #[command(flatten)]
+        authority: AuthorityArgs,
+
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Claim the points or reward tokens accrued on the signer's position
+    Claim {
+        #[command(flatten)]
+        authority: AuthorityArgs,
+
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Mint the reward tokens vested so far in one of the signer's vesting escrows, token
+    /// rewards program only
+    WithdrawVested {
+        /// Vesting escrow, as reported by `claim`
+        escrow: Pubkey,
+    },
+    /// Close one of the signer's vesting escrows early, taking what has vested and forfeiting
+    /// the rest, token rewards program only
+    ExitVesting {
+        /// Vesting escrow, as reported by `claim`
+        escrow: Pubkey,
+    },
+    /// Upgrade a position to the current account layout
+    MigrateAccount {
+        /// Position owner, defaults to the signer. Token rewards positions are moved by
+        /// their owner, who has to sign.
+        #[arg(long)]
+        owner: Option<Pubkey>,
+
+        /// Vault the legacy stake is bound to, rewards and token rewards programs only. Its
+        /// keypair has to co-sign when the position holds stake.
+        #[command(flatten)]
+        authority: AuthorityArgs,
+
+        /// Position index a token rewards position moves to
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Show a stake position
+    ShowPosition {
+        /// Position owner, defaults to the signer
+        #[arg(long)]
+        owner: Option<Pubkey>,
+
+        #[arg(long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Compare what a pool's vault owes its stakers with what it holds
+    Reconcile {
+        /// Vault authority, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+    },
+    /// Show how much more a pool, and a wallet in it, can take
+    Capacity {
+        /// Vault authority, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+
+        /// Wallet whose remaining allowance to report, defaults to the signer
+        #[arg(long)]
+        owner: Option<Pubkey>,
+    },
+    /// Show a wallet's points in a rewards pool and how many of them expire next
+    PointsOutlook {
+        /// Vault authority, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+
+        /// Position owner, defaults to the signer
+        #[arg(long)]
+        owner: Option<Pubkey>,
+    },
+    /// Move the vault's lamports beyond what it owes into the signer's treasury
+    SweepSurplus,
+    /// Restrict staking in the signer's rewards pool to the wallets in an allowlist file
+    SetAllowlist {
+        /// JSON object mapping each wallet to its max allocation in lamports, or to null for
+        /// no cap. Without a file the pool is opened to everyone.
+        file: Option<PathBuf>,
+    },
+    /// Withdraw the claim fees collected in the signer's treasury
+    WithdrawFees {
+        /// Reward tokens to move to the signer's reward token account, token rewards program
+        /// only. The rewards program's fees are points, always credited in full to the
+        /// signer's own position.
+        amount: Option<u64>,
+    },
+    /// Archive the current season of the signer's rewards pool leaderboard and start the next
+    ResetSeason,
+    /// Show a rewards pool's leaderboard, or the final standings of a past season
+    ShowLeaderboard {
+        /// Vault authority, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+
+        /// Past season to show instead of the current one
+        #[arg(long)]
+        season: Option<u64>,
+    },
+    /// List an item in the signer's rewards pool catalogue, paying its token stock from the
+    /// signer's associated token account
+    PublishItem {
+        item_id: u64,
+
+        #[arg(long, value_enum)]
+        kind: ItemKind,
+
+        /// Points one unit costs
+        #[arg(long)]
+        price: u64,
+
+        /// Units for sale, exactly 1 for NFTs
+        #[arg(long)]
+        stock: u64,
+
+        /// Mint of token and NFT items
+        #[arg(long)]
+        mint: Option<Pubkey>,
+
+        /// Tokens per redemption, 1 for NFTs
+        #[arg(long, default_value_t = 1)]
+        amount: u64,
+    },
+    /// Reprice an item in the signer's catalogue and add to its stock, NFT items excepted
+    UpdateItem {
+        item_id: u64,
+
+        #[arg(long)]
+        price: u64,
+
+        #[arg(long, default_value_t = 0)]
+        added_stock: u64,
+    },
+    /// Spend the signer's points on an item, tokens go to the signer's associated token account
+    Redeem {
+        item_id: u64,
+
+        #[command(flatten)]
+        authority: AuthorityArgs,
+    },
+    /// Show an item in a rewards pool's catalogue
+    ShowItem {
+        item_id: u64,
+
+        /// Vault authority, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+    },
+    /// Write every stake position of the program to a snapshot file. RPC nodes only serve
+    /// current state, so `--slot` is the earliest slot they may answer from; for an exact past
+    /// slot, pass a dump of the accounts taken at it.
+    Snapshot {
+        /// Slot the snapshot is taken at, exactly with `--dump`
+        #[arg(long)]
+        slot: Option<u64>,
+
+        /// Saved `getProgramAccounts` result, requested `withContext` and base64 encoded, to
+        /// read instead of asking the RPC node
+        #[arg(long)]
+        dump: Option<PathBuf>,
+
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Split an airdrop over a snapshot's wallets and write the Merkle claim of each
+    BuildAirdrop {
+        /// Snapshot file written by `snapshot`
+        snapshot: PathBuf,
+
+        #[arg(long, value_enum)]
+        formula: Formula,
+
+        /// Tokens to split, in base units
+        #[arg(long)]
+        amount: u64,
+
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Fund an airdrop from the signer's associated token account for the mint, token rewards
+    /// program only
+    CreateDistributor {
+        /// Airdrop file written by `build-airdrop`
+        airdrop: PathBuf,
+
+        #[arg(long)]
+        id: u64,
+
+        #[arg(long)]
+        mint: Pubkey,
+    },
+    /// Claim the signer's share of an airdrop, token rewards program only
+    ClaimAirdrop {
+        /// Airdrop file written by `build-airdrop`
+        airdrop: PathBuf,
+
+        #[arg(long)]
+        id: u64,
+
+        /// Authority that created the distributor
+        #[arg(long)]
+        authority: Pubkey,
+    },
+    /// Show an airdrop distributor and how much of it has been claimed
+    ShowDistributor {
+        #[arg(long)]
+        id: u64,
+
+        /// Authority that created the distributor, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+    },
+    /// Show a pool and its vault
+    ShowPool {
+        /// Vault authority, defaults to the signer
+        #[arg(long)]
+        authority: Option<Pubkey>,
+    },
+}
+
+struct Context {
+    rpc: RpcClient,
+    payer: Keypair,
+    program: ProgramKind,
+    simulate: bool,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let context = Context {
+        rpc: RpcClient::new(&cli.url),
+        payer: read_keypair(&cli.keypair)?,
+        program: cli.program,
+        simulate: cli.simulate,
+    };
+
+    let report = run(&context, cli.command)?;
+
+    match cli.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Human => print_human(&report, 0),
+    }
+    Ok(())
+}
+
+fn run(context: &Context, command: Command) -> Result<Value> {
+    let user = context.payer.pubkey();
+
+    match command {
+        Command::InitPool {
+            referral_bps,
+            max_boost_bps,
+            time_base,
+            fee_bps,
+            vesting_period,
+            vesting_cliff,
+            early_exit,
+            min_stake,
+            max_stake_per_wallet,
+            max_total_staked,
+            decay_bps,
+            decay_period,
+            expiry_window,
+            streak_tiers,
+        } => {
+            if streak_tiers.len() > rewards::MAX_STREAK_TIERS {
+                bail!("at most {} streak tiers", rewards::MAX_STREAK_TIERS);
+            }
+            let mut tiers = [rewards::StreakTier::default(); rewards::MAX_STREAK_TIERS];
+            tiers[..streak_tiers.len()].copy_from_slice(&streak_tiers);
+            let decay = match (decay_bps, expiry_window) {
+                (Some(bps), _) => rewards::PointsDecay::Rate {
+                    bps,
+                    period: decay_period,
+                },
+                (None, Some(window)) => rewards::PointsDecay::Expiry { window },
+                (None, None) => rewards::PointsDecay::None,
+            };
+            let basic_params = basic::PoolParams {
+                min_stake,
+                max_stake_per_wallet,
+                max_total_staked,
+            };
+            let (instructions, watch) = match context.program {
+                // A vault created before pools existed only needs its pool, which counts the
+                // positions already bound to it
+                ProgramKind::Basic if context.rpc.balance(&basic::pda::vault(&user).0)? > 0 => (
+                    vec![
+                        basic::initialize_pool(
+                            &user,
+                            basic_params,
+                            &bound_positions(context, &user)?,
+                        ),
+                        basic::initialize_treasury(&user),
+                    ],
+                    vec![basic::pda::pool(&user).0, basic::pda::treasury(&user).0],
+                ),
+                ProgramKind::Basic => (
+                    vec![
+                        basic::create_vault_pda_account(&user),
+                        basic::initialize_pool(&user, basic_params, &[]),
+                        basic::initialize_treasury(&user),
+                    ],
+                    vec![basic::pda::pool(&user).0, basic::pda::treasury(&user).0],
+                ),
+                ProgramKind::Rewards => (
+                    vec![
+                        rewards::create_vault_pda_account(&user),
+                        rewards::initialize_pool(
+                            &user,
+                            rewards::PoolParams {
+                                referral_bps,
+                                fee_bps,
+                                time_base: time_base.into(),
+                                min_stake,
+                                max_stake_per_wallet,
+                                max_total_staked,
+                                decay,
+                                streak_tiers: tiers,
+                            },
+                        ),
+                        rewards::initialize_treasury(&user),
+                        rewards::initialize_leaderboard(&user),
+                    ],
+                    vec![
+                        rewards::pda::pool(&user).0,
+                        rewards::pda::treasury(&user).0,
+                        rewards::pda::leaderboard(&user).0,
+                    ],
+                ),
+                ProgramKind::TokenRewards => (
+                    vec![
+                        token_rewards::create_vault_pda_account(&user),
+                        token_rewards::initialize_pool(
+                            &user,
+                            token_rewards::PoolParams {
+                                max_boost_bps,
+                                referral_bps,
+                                fee_bps,
+                                time_base: time_base.into(),
+                                vesting: token_rewards::Vesting {
+                                    period: vesting_period,
+                                    cliff: vesting_cliff,
+                                    early_exit,
+                                },
+                                min_stake,
+                                max_stake_per_wallet,
+                                max_total_staked,
+                            },
+                        ),
+                        token_rewards::initialize_treasury(&user),
+                    ],
+                    vec![
+                        token_rewards::pda::pool(&user).0,
+                        token_rewards::pda::treasury(&user).0,
+                    ],
+                ),
+            };
+            execute(context, &instructions, &[], &watch)
+        }
+        Command::CreateAccount { index, lock_tier } => {
+            let (instruction, position) = match context.program {
+                ProgramKind::Basic => (
+                    basic::create_pda_account(&user),
+                    basic::pda::stake_account(&user).0,
+                ),
+                ProgramKind::Rewards => (
+                    rewards::create_pda_account(&user),
+                    rewards::pda::stake_account(&user).0,
+                ),
+                ProgramKind::TokenRewards => (
+                    token_rewards::create_pda_account(&user, index, lock_tier.into()),
+                    token_rewards::pda::stake_account(&user, index).0,
+                ),
+            };
+            execute(context, &[instruction], &[], &[position])
+        }
+        Command::Stake {
+            amount,
+            authority,
+            referrer,
+            index,
+            allowlist,
+        } => {
+            if allowlist.is_some() && context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have allowlists");
+            }
+            let authority_keypair = authority_keypair(context, &authority)?;
+            let authority = resolve_authority(&authority, authority_keypair.as_ref())?;
+
+            match context.program {
+                ProgramKind::Basic => execute(
+                    context,
+                    &[basic::stake(&user, &authority, amount)],
+                    &[],
+                    &[basic::pda::stake_account(&user).0],
+                ),
+                ProgramKind::Rewards => {
+                    let instruction = match allowlist {
+                        Some(path) => {
+                            let proof = read_allowlist(&path)?.proof(&user).ok_or_else(|| {
+                                anyhow!("{user} is not on the allowlist {}", path.display())
+                            })?;
+                            rewards::stake_allowlisted(&user, &authority, amount, referrer, proof)
+                        }
+                        None => rewards::stake(&user, &authority, amount, referrer),
+                    };
+                    execute(
+                        context,
+                        &[leaderboard_if_any(context, &authority, instruction)?],
+                        &[],
+                        &[rewards::pda::stake_account(&user).0],
+                    )
+                }
+                ProgramKind::TokenRewards => {
+                    let authority_keypair = authority_keypair
+                        .ok_or_else(|| anyhow!("--authority-keypair is required to stake"))?;
+                    let position = token_rewards::pda::stake_account(&user, index).0;
+                    let instruction = token_rewards::stake(
+                        &user,
+                        &user,
+                        &position,
+                        &authority,
+                        &user,
+                        &user,
+                        existing_ve_lock(context, &user)?,
+                        amount,
+                        referrer,
+                    );
+                    execute(context, &[instruction], &[&authority_keypair], &[position])
+                }
+            }
+        }
+        Command::Unstake {
+            amount,
+            authority,
+            index,
+        } => {
+            let authority = resolve_authority(&authority, None)?;
+
+            match context.program {
+                ProgramKind::Basic => execute(
+                    context,
+                    &[basic::unstake(&user, &authority, amount)],
+                    &[],
+                    &[basic::pda::stake_account(&user).0],
+                ),
+                ProgramKind::Rewards => execute(
+                    context,
+                    &[leaderboard_if_any(
+                        context,
+                        &authority,
+                        rewards::unstake(&user, &authority, amount),
+                    )?],
+                    &[],
+                    &[rewards::pda::stake_account(&user).0],
+                ),
+                ProgramKind::TokenRewards => {
+                    let position = token_rewards::pda::stake_account(&user, index).0;
+                    let instruction = token_rewards::unstake(
+                        &user,
+                        &user,
+                        &position,
+                        position_token_account(context, &user, &position)?,
+                        &authority,
+                        existing_ve_lock(context, &user)?,
+                        amount,
+                    );
+                    execute(context, &[instruction], &[], &[position])
+                }
+            }
+        }
+        Command::Claim { authority, index } => match context.program {
+            ProgramKind::Basic => bail!("the basic staking program has no rewards to claim"),
+            ProgramKind::Rewards => {
+                let authority = resolve_authority(&authority, None)?;
+                let instruction = treasury_if_any(
+                    context,
+                    &authority,
+                    rewards::claim_points(&user, &authority),
+                )?;
+                execute(
+                    context,
+                    &[instruction],
+                    &[],
+                    &[rewards::pda::stake_account(&user).0],
+                )
+            }
+            ProgramKind::TokenRewards => {
+                let authority = resolve_authority(&authority, None)?;
+                let position = token_rewards::pda::stake_account(&user, index).0;
+                let vesting_escrow = next_vesting_escrow(context, &authority)?;
+                let instruction = token_rewards::claim_rewards(
+                    &user,
+                    &user,
+                    &position,
+                    position_token_account(context, &user, &position)?,
+                    &authority,
+                    vesting_escrow,
+                );
+                let instruction = treasury_if_any(context, &authority, instruction)?;
+                let mut watch = vec![position, token_rewards::pda::reward_token_account(&user)];
+                watch.extend(vesting_escrow);
+                execute(context, &[instruction], &[], &watch)
+            }
+        },
+        Command::WithdrawVested { escrow } => {
+            if context.program != ProgramKind::TokenRewards {
+                bail!("only the token rewards program vests claims");
+            }
+            execute(
+                context,
+                &[token_rewards::withdraw_vested(&user, &escrow)],
+                &[],
+                &[escrow, token_rewards::pda::reward_token_account(&user)],
+            )
+        }
+        Command::ExitVesting { escrow } => {
+            if context.program != ProgramKind::TokenRewards {
+                bail!("only the token rewards program vests claims");
+            }
+            execute(
+                context,
+                &[token_rewards::exit_vesting(&user, &escrow)],
+                &[],
+                &[escrow, token_rewards::pda::reward_token_account(&user)],
+            )
+        }
+        Command::MigrateAccount {
+            owner,
+            authority,
+            index,
+        } => {
+            let owner = owner.unwrap_or(user);
+            if context.program == ProgramKind::Basic {
+                return execute(
+                    context,
+                    &[basic::migrate_account(&user, &owner)],
+                    &[],
+                    &[basic::pda::stake_account(&owner).0],
+                );
+            }
+
+            let authority_keypair = authority
+                .authority_keypair
+                .as_deref()
+                .map(read_keypair)
+                .transpose()?;
+            let signers: Vec<&Keypair> = authority_keypair.iter().collect();
+            let authority = resolve_authority(&authority, authority_keypair.as_ref())?;
+            match context.program {
+                ProgramKind::Rewards => {
+                    let mut ix = rewards::migrate_account(&user, &owner, &authority);
+                    if authority_keypair.is_some() {
+                        ix = rewards::signed_by_authority(ix);
+                    }
+                    execute(
+                        context,
+                        &[ix],
+                        &signers,
+                        &[rewards::pda::stake_account(&owner).0],
+                    )
+                }
+                ProgramKind::Basic => unreachable!("basic positions are migrated above"),
+                ProgramKind::TokenRewards => {
+                    if owner != user {
+                        bail!("token rewards positions are migrated by their owner");
+                    }
+                    // An empty position can migrate without any pool
+                    let pool = token_rewards::pda::pool(&authority).0;
+                    let authority = (context.rpc.balance(&pool)? > 0).then_some(&authority);
+                    let mut ix = token_rewards::migrate_account(&user, index, authority);
+                    if authority_keypair.is_some() {
+                        ix = token_rewards::signed_by_authority(ix);
+                    }
+                    execute(
+                        context,
+                        &[ix],
+                        &signers,
+                        &[token_rewards::pda::stake_account(&user, index).0],
+                    )
+                }
+            }
+        }
+        Command::Reconcile { authority } => {
+            let authority = authority.unwrap_or(user);
+            reconcile(context, &authority)
+        }
+        Command::Capacity { authority, owner } => {
+            let authority = authority.unwrap_or(user);
+            let owner = owner.unwrap_or(user);
+            capacity(context, &authority, &owner)
+        }
+        Command::PointsOutlook { authority, owner } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools expire points");
+            }
+            let authority = authority.unwrap_or(user);
+            let owner = owner.unwrap_or(user);
+            points_outlook(context, &authority, &owner)
+        }
+        Command::SweepSurplus => {
+            let (instruction, watch) = match context.program {
+                ProgramKind::Basic => (basic::sweep_surplus(&user), basic::pda::treasury(&user).0),
+                ProgramKind::Rewards => (
+                    rewards::sweep_surplus(&user),
+                    rewards::pda::treasury(&user).0,
+                ),
+                ProgramKind::TokenRewards => (
+                    token_rewards::sweep_surplus(&user),
+                    token_rewards::pda::treasury(&user).0,
+                ),
+            };
+            execute(context, &[instruction], &[], &[watch])
+        }
+        Command::SetAllowlist { file } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have allowlists");
+            }
+            let root = match file {
+                Some(path) => read_allowlist(&path)?.root(),
+                None => [0; 32],
+            };
+            execute(
+                context,
+                &[rewards::set_allowlist_root(&user, root)],
+                &[],
+                &[rewards::pda::pool(&user).0],
+            )
+        }
+        Command::WithdrawFees { amount } => match context.program {
+            ProgramKind::Basic => bail!("the basic staking program takes no fees"),
+            ProgramKind::Rewards => execute(
+                context,
+                &[rewards::withdraw_fees(&user)],
+                &[],
+                &[
+                    rewards::pda::treasury(&user).0,
+                    rewards::pda::stake_account(&user).0,
+                ],
+            ),
+            ProgramKind::TokenRewards => {
+                let amount = amount.ok_or_else(|| anyhow!("an amount is required"))?;
+                let destination = token_rewards::pda::reward_token_account(&user);
+                execute(
+                    context,
+                    &[token_rewards::withdraw_fees(&user, &destination, amount)],
+                    &[],
+                    &[token_rewards::pda::treasury(&user).0, destination],
+                )
+            }
+        },
+        Command::ShowPosition { owner, index } => {
+            let owner = owner.unwrap_or(user);
+            let position = match context.program {
+                ProgramKind::Basic => basic::pda::stake_account(&owner).0,
+                ProgramKind::Rewards => rewards::pda::stake_account(&owner).0,
+                ProgramKind::TokenRewards => token_rewards::pda::stake_account(&owner, index).0,
+            };
+            show_accounts(context, &[position])
+        }
+        Command::ResetSeason => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have leaderboards");
+            }
+            let leaderboard = rewards::pda::leaderboard(&user).0;
+            let data = context
+                .rpc
+                .account_data(&leaderboard)?
+                .ok_or_else(|| anyhow!("leaderboard {leaderboard} does not exist"))?;
+            let season = decode_account::<rewards::Leaderboard>(&data)?.season;
+            execute(
+                context,
+                &[rewards::reset_season(&user, season)],
+                &[],
+                &[
+                    leaderboard,
+                    rewards::pda::leaderboard_snapshot(&user, season).0,
+                ],
+            )
+        }
+        Command::ShowLeaderboard { authority, season } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have leaderboards");
+            }
+            let authority = authority.unwrap_or(user);
+            let address = match season {
+                Some(season) => rewards::pda::leaderboard_snapshot(&authority, season).0,
+                None => rewards::pda::leaderboard(&authority).0,
+            };
+            show_accounts(context, &[address])
+        }
+        Command::PublishItem {
+            item_id,
+            kind,
+            price,
+            stock,
+            mint,
+            amount,
+        } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have a points catalogue");
+            }
+            let kind = rewards::ItemKind::from(kind);
+            if (kind == rewards::ItemKind::WhitelistSpot) != mint.is_none() {
+                bail!("--mint is required for token and NFT items, and only for them");
+            }
+            let params = rewards::ItemParams {
+                kind,
+                price,
+                stock,
+                amount,
+            };
+            execute(
+                context,
+                &[rewards::publish_item(&user, item_id, params, mint.as_ref())],
+                &[],
+                &[rewards::pda::catalog_item(&user, item_id).0],
+            )
+        }
+        Command::UpdateItem {
+            item_id,
+            price,
+            added_stock,
+        } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have a points catalogue");
+            }
+            let (address, item) = catalog_item(context, &user, item_id)?;
+            execute(
+                context,
+                &[rewards::update_item(
+                    &user,
+                    item_id,
+                    price,
+                    added_stock,
+                    item_mint(&item),
+                )],
+                &[],
+                &[address],
+            )
+        }
+        Command::Redeem { item_id, authority } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have a points catalogue");
+            }
+            let authority = resolve_authority(&authority, None)?;
+            let (address, item) = catalog_item(context, &authority, item_id)?;
+            execute(
+                context,
+                &[rewards::redeem(
+                    &user,
+                    &authority,
+                    item_id,
+                    item_mint(&item),
+                )],
+                &[],
+                &[rewards::pda::stake_account(&user).0, address],
+            )
+        }
+        Command::ShowItem { item_id, authority } => {
+            if context.program != ProgramKind::Rewards {
+                bail!("only rewards pools have a points catalogue");
+            }
+            let authority = authority.unwrap_or(user);
+            show_accounts(
+                context,
+                &[rewards::pda::catalog_item(&authority, item_id).0],
+            )
+        }
+        Command::Snapshot { slot, dump, out } => {
+            take_snapshot(context, slot, dump.as_deref(), &out)
+        }
+        Command::BuildAirdrop {
+            snapshot,
+            formula,
+            amount,
+            out,
+        } => build_airdrop(&snapshot, formula, amount, &out),
+        Command::CreateDistributor { airdrop, id, mint } => {
+            if context.program != ProgramKind::TokenRewards {
+                bail!("only the token rewards program distributes airdrops");
+            }
+            let airdrop = read_airdrop(&airdrop)?;
+            if airdrop.recipients().len() > token_rewards::MAX_AIRDROP_RECIPIENTS as usize {
+                bail!(
+                    "a distributor takes at most {} recipients",
+                    token_rewards::MAX_AIRDROP_RECIPIENTS
+                );
+            }
+            execute(
+                context,
+                &[token_rewards::create_distributor(
+                    &user, id, &mint, &airdrop,
+                )],
+                &[],
+                &[token_rewards::pda::distributor(&user, id).0],
+            )
+        }
+        Command::ClaimAirdrop {
+            airdrop,
+            id,
+            authority,
+        } => {
+            if context.program != ProgramKind::TokenRewards {
+                bail!("only the token rewards program distributes airdrops");
+            }
+            let airdrop = read_airdrop(&airdrop)?;
+            let index = airdrop
+                .recipients()
+                .iter()
+                .position(|(claimant, _)| *claimant == user)
+                .ok_or_else(|| anyhow!("{user} has no share of this airdrop"))?;
+            let claim = airdrop
+                .claim(index as u32)
+                .expect("the index of a listed recipient");
+
+            let distributor = token_rewards::pda::distributor(&authority, id).0;
+            let data = context
+                .rpc
+                .account_data(&distributor)?
+                .ok_or_else(|| anyhow!("distributor {distributor} does not exist"))?;
+            let state: token_rewards::Distributor = decode_account(&data)?;
+            if state.root != airdrop.root() {
+                bail!("distributor {distributor} was created from a different airdrop file");
+            }
+
+            execute(
+                context,
+                &[token_rewards::claim_airdrop(
+                    &user,
+                    &authority,
+                    id,
+                    &state.mint,
+                    claim,
+                )],
+                &[],
+                &[distributor],
+            )
+        }
+        Command::ShowDistributor { id, authority } => {
+            if context.program != ProgramKind::TokenRewards {
+                bail!("only the token rewards program distributes airdrops");
+            }
+            let authority = authority.unwrap_or(user);
+            show_accounts(
+                context,
+                &[token_rewards::pda::distributor(&authority, id).0],
+            )
+        }
+        Command::ShowPool { authority } => {
+            let authority = authority.unwrap_or(user);
+            let (pool, vault) = match context.program {
+                ProgramKind::Basic => (
+                    Some(basic::pda::pool(&authority).0),
+                    basic::pda::vault(&authority).0,
+                ),
+                ProgramKind::Rewards => (
+                    Some(rewards::pda::pool(&authority).0),
+                    rewards::pda::vault(&authority).0,
+                ),
+                ProgramKind::TokenRewards => (
+                    Some(token_rewards::pda::pool(&authority).0),
+                    token_rewards::pda::vault(&authority).0,
+                ),
+            };
+
+            let mut report = match pool {
+                Some(pool) => show_accounts(context, &[pool])?,
+                None => json!({}),
+            };
+            report["vault"] = json!({
+                "address": vault.to_string(),
+                "lamports": context.rpc.balance(&vault)?,
+            });
+            Ok(report)
+        }
+    }
+}
+
+/// `DAYS:BONUS_BPS`, as taken by `--streak-tier`
+fn parse_streak_tier(value: &str) -> Result<rewards::StreakTier> {
+    let (days, bonus_bps) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected DAYS:BONUS_BPS"))?;
+    Ok(rewards::StreakTier {
+        after: days.parse::<i64>()? * 86_400,
+        bonus_bps: bonus_bps.parse()?,
+    })
+}
+
+fn catalog_item(
+    context: &Context,
+    authority: &Pubkey,
+    item_id: u64,
+) -> Result<(Pubkey, rewards::CatalogItem)> {
+    let address = rewards::pda::catalog_item(authority, item_id).0;
+    let data = context
+        .rpc
+        .account_data(&address)?
+        .ok_or_else(|| anyhow!("item {item_id} does not exist"))?;
+    Ok((address, decode_account(&data)?))
+}
+
+/// The mint token and NFT items are paid in, the builders take none for whitelist spots
+fn item_mint(item: &rewards::CatalogItem) -> Option<&Pubkey> {
+    (item.kind != rewards::ItemKind::WhitelistSpot).then_some(&item.mint)
+}
+
+/// Runs a read-only instruction through simulation and decodes what it returns
+fn view<T: AnchorDeserialize>(
+    context: &Context,
+    name: &str,
+    instruction: Instruction,
+) -> Result<T> {
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.rpc.latest_blockhash()?,
+    );
+    let simulation = context.rpc.simulate(&transaction, &[])?;
+    if !simulation.err.is_null() {
+        bail!("{name} failed: {}", simulation.err);
+    }
+    let data = simulation
+        .return_data
+        .ok_or_else(|| anyhow!("{name} returned no data"))?;
+    Ok(T::deserialize(&mut &data[..])?)
+}
+
+fn reconcile(context: &Context, authority: &Pubkey) -> Result<Value> {
+    let (vault, instruction) = match context.program {
+        ProgramKind::Basic => (basic::pda::vault(authority).0, basic::reconcile(authority)),
+        ProgramKind::Rewards => (
+            rewards::pda::vault(authority).0,
+            rewards::reconcile(authority),
+        ),
+        ProgramKind::TokenRewards => (
+            token_rewards::pda::vault(authority).0,
+            token_rewards::reconcile(authority),
+        ),
+    };
+    // All three programs return the same borsh layout
+    let reconciliation: rewards::Reconciliation = view(context, "reconcile", instruction)?;
+
+    Ok(json!({
+        "vault": vault.to_string(),
+        "vault_balance": reconciliation.vault_balance,
+        "rent_reserve": reconciliation.rent_reserve,
+        "total_staked": reconciliation.total_staked,
+        "total_pending_withdrawals": reconciliation.total_pending_withdrawals,
+        "surplus": reconciliation.surplus,
+        "shortfall": reconciliation.shortfall,
+    }))
+}
+
+fn capacity(context: &Context, authority: &Pubkey, owner: &Pubkey) -> Result<Value> {
+    if context.program == ProgramKind::TokenRewards {
+        let staker = token_rewards::pda::staker(authority, owner).0;
+        let owner = context.rpc.account_data(&staker)?.map(|_| owner);
+        let capacity: token_rewards::Capacity = view(
+            context,
+            "capacity",
+            token_rewards::capacity(authority, owner),
+        )?;
+
+        return Ok(json!({
+            "pool": token_rewards::pda::pool(authority).0.to_string(),
+            "min_stake": capacity.min_stake,
+            "total_staked": capacity.total_staked,
+            "pool_remaining": capacity.pool_remaining,
+            "wallet_staked": capacity.wallet_staked,
+            "wallet_remaining": capacity.wallet_remaining,
+        }));
+    }
+
+    let (pool, position) = match context.program {
+        ProgramKind::Basic => (
+            basic::pda::pool(authority).0,
+            basic::pda::stake_account(owner).0,
+        ),
+        _ => (
+            rewards::pda::pool(authority).0,
+            rewards::pda::stake_account(owner).0,
+        ),
+    };
+    let position = context.rpc.account_data(&position)?.map(|_| position);
+    let instruction = match context.program {
+        ProgramKind::Basic => basic::capacity(authority, position.as_ref()),
+        _ => rewards::capacity(authority, position.as_ref()),
+    };
+    // The basic and rewards programs return the same borsh layout
+    let capacity: rewards::Capacity = view(context, "capacity", instruction)?;
+
+    Ok(json!({
+        "pool": pool.to_string(),
+        "min_stake": capacity.min_stake,
+        "total_staked": capacity.total_staked,
+        "pool_remaining": capacity.pool_remaining,
+        "wallet_staked": capacity.wallet_staked,
+        "wallet_remaining": capacity.wallet_remaining,
+    }))
+}
+
+fn points_outlook(context: &Context, authority: &Pubkey, owner: &Pubkey) -> Result<Value> {
+    let outlook: rewards::PointsOutlook = view(
+        context,
+        "points_outlook",
+        rewards::points_outlook(authority, owner),
+    )?;
+
+    Ok(json!({
+        "position": rewards::pda::stake_account(owner).0.to_string(),
+        "total_points": outlook.total_points,
+        "expiring_points": outlook.expiring_points,
+        "expires_at": outlook.expires_at,
+    }))
+}
+
+/// Signs with the payer plus `signers`, then either sends or simulates the transaction.
+/// `watch` lists the accounts whose resulting state is reported.
+fn execute(
+    context: &Context,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+    watch: &[Pubkey],
+) -> Result<Value> {
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        context.rpc.latest_blockhash()?,
+    );
+
+    if context.simulate {
+        let simulation = context.rpc.simulate(&transaction, watch)?;
+        let mut accounts = Map::new();
+        for (address, data) in watch.iter().zip(&simulation.accounts) {
+            accounts.insert(
+                address.to_string(),
+                data.as_deref()
+                    .map_or(Value::Null, |data| render_account(context.program, data)),
+            );
+        }
+
+        return Ok(json!({
+            "simulated": true,
+            "err": simulation.err,
+            "units_consumed": simulation.units_consumed,
+            "accounts": accounts,
+            "logs": simulation.logs,
+        }));
+    }
+
+    let signature = context.rpc.send_and_confirm(&transaction)?;
+    let mut report = show_accounts(context, watch)?;
+    report["signature"] = json!(signature);
+    Ok(report)
+}
+
+fn show_accounts(context: &Context, addresses: &[Pubkey]) -> Result<Value> {
+    let mut accounts = Map::new();
+    for address in addresses {
+        let account = context.rpc.account_data(address)?;
+        accounts.insert(
+            address.to_string(),
+            account
+                .as_deref()
+                .map_or(Value::Null, |data| render_account(context.program, data)),
+        );
+    }
+    Ok(json!({ "accounts": accounts }))
+}
+
+/// Decodes whichever of the program's accounts `data` holds
+fn render_account(program: ProgramKind, data: &[u8]) -> Value {
+    match program {
+        ProgramKind::Basic => {
+            if let Ok(pool) = decode_account::<basic::Pool>(data) {
+                return json!({
+                    "authority": pool.authority.to_string(),
+                    "total_staked": pool.total_staked,
+                    "total_pending_withdrawals": pool.total_pending_withdrawals,
+                    "min_stake": pool.min_stake,
+                    "max_stake_per_wallet": pool.max_stake_per_wallet,
+                    "max_total_staked": pool.max_total_staked,
+                });
+            }
+            if let Ok(position) = decode_account::<basic::StakeAccount>(data) {
+                return json!({
+                    "version": position.version,
+                    "owner": position.owner.to_string(),
+                    "staked_amount": position.staked_amount,
+                    "total_points": position.total_points,
+                    "last_update_time": position.last_update_time,
+                    "vault": position.vault.to_string(),
+                });
+            }
+            if let Ok(treasury) = decode_account::<basic::Treasury>(data) {
+                return json!({
+                    "authority": treasury.authority.to_string(),
+                    "total_swept": treasury.total_swept,
+                });
+            }
+            if let Some(position) = v1_position::<basic::StakeAccount, basic::StakeAccountV1>(
+                data,
+                basic::StakeAccountV1::LEN,
+            ) {
+                return render_v1_position(
+                    position.owner,
+                    position.staked_amount,
+                    position.total_points,
+                    position.last_update_time,
+                );
+            }
+        }
+        ProgramKind::Rewards => {
+            if let Ok(position) = decode_account::<rewards::StakeAccount>(data) {
+                return json!({
+                    "version": position.version,
+                    "owner": position.owner.to_string(),
+                    "staked_amount": position.staked_amount,
+                    "total_points": position.total_points,
+                    "last_update_time": position.last_update_time,
+                    "referrer": position.referrer.to_string(),
+                    "referral_points": position.referral_points,
+                    "vault": position.vault.to_string(),
+                    "season": position.season,
+                    "season_points": position.season_points,
+                    "expiring_points": position.expiring_points,
+                    "decayed_at": position.decayed_at,
+                    "streak_start": position.streak_start,
+                });
+            }
+            if let Some(position) = v1_position::<rewards::StakeAccount, rewards::StakeAccountV1>(
+                data,
+                rewards::StakeAccountV1::LEN,
+            ) {
+                return render_v1_position(
+                    position.owner,
+                    position.staked_amount,
+                    position.total_points,
+                    position.last_update_time,
+                );
+            }
+            if let Ok(pool) = decode_account::<rewards::Pool>(data) {
+                return json!({
+                    "authority": pool.authority.to_string(),
+                    "referral_bps": pool.referral_bps,
+                    "fee_bps": pool.fee_bps,
+                    "time_base": format!("{:?}", pool.time_base),
+                    "min_stake": pool.min_stake,
+                    "max_stake_per_wallet": pool.max_stake_per_wallet,
+                    "max_total_staked": pool.max_total_staked,
+                    "decay": format!("{:?}", pool.decay),
+                    "streak_tiers": pool
+                        .streak_tiers
+                        .iter()
+                        .filter(|tier| tier.bonus_bps > 0)
+                        .map(|tier| json!({ "after": tier.after, "bonus_bps": tier.bonus_bps }))
+                        .collect::<Vec<_>>(),
+                    "total_staked": pool.total_staked,
+                    "total_pending_withdrawals": pool.total_pending_withdrawals,
+                    "allowlist_root": (pool.allowlist_root != [0; 32])
+                        .then(|| hex(&pool.allowlist_root)),
+                });
+            }
+            if let Ok(treasury) = decode_account::<rewards::Treasury>(data) {
+                return json!({
+                    "authority": treasury.authority.to_string(),
+                    "total_swept": treasury.total_swept,
+                    "fee_points": treasury.fee_points,
+                });
+            }
+            if let Ok(leaderboard) = decode_account::<rewards::Leaderboard>(data) {
+                return json!({
+                    "authority": leaderboard.authority.to_string(),
+                    "season": leaderboard.season,
+                    "season_started_at": leaderboard.season_started_at,
+                    "ranking": render_ranking(leaderboard.ranked()),
+                });
+            }
+            if let Ok(item) = decode_account::<rewards::CatalogItem>(data) {
+                return json!({
+                    "authority": item.authority.to_string(),
+                    "item_id": item.item_id,
+                    "kind": format!("{:?}", item.kind),
+                    "mint": (item.kind != rewards::ItemKind::WhitelistSpot)
+                        .then(|| item.mint.to_string()),
+                    "amount": item.amount,
+                    "price": item.price,
+                    "stock": item.stock,
+                    "redeemed": item.redeemed,
+                });
+            }
+            if let Ok(snapshot) = decode_account::<rewards::LeaderboardSnapshot>(data) {
+                return json!({
+                    "authority": snapshot.authority.to_string(),
+                    "season": snapshot.season,
+                    "started_at": snapshot.started_at,
+                    "ended_at": snapshot.ended_at,
+                    "ranking": render_ranking(snapshot.ranked()),
+                });
+            }
+        }
+        ProgramKind::TokenRewards => {
+            if let Ok(position) = decode_account::<token_rewards::StakeAccount>(data) {
+                return json!({
+                    "version": position.version,
+                    "owner": position.owner.to_string(),
+                    "position_index": position.position_index,
+                    "staked_amount": position.staked_amount,
+                    "total_points": position.total_points,
+                    "last_update_time": position.last_update_time,
+                    "lock_start": position.lock_start,
+                    "lock_tier": format!("{:?}", position.lock_tier),
+                    "referrer": position.referrer.to_string(),
+                    "referral_rewards": position.referral_rewards,
+                    "position_mint": position.position_mint.to_string(),
+                    "vault": position.vault.to_string(),
+                });
+            }
+            if let Some(position) = v1_position::<
+                token_rewards::StakeAccount,
+                token_rewards::StakeAccountV1,
+            >(data, token_rewards::StakeAccountV1::LEN)
+            {
+                return render_v1_position(
+                    position.owner,
+                    position.staked_amount,
+                    position.total_points,
+                    position.last_update_time,
+                );
+            }
+            if let Ok(pool) = decode_account::<token_rewards::Pool>(data) {
+                return json!({
+                    "authority": pool.authority.to_string(),
+                    "max_boost_bps": pool.max_boost_bps,
+                    "time_base": format!("{:?}", pool.time_base),
+                    "referral_bps": pool.referral_bps,
+                    "fee_bps": pool.fee_bps,
+                    "vesting": {
+                        "period": pool.vesting.period,
+                        "cliff": pool.vesting.cliff,
+                        "early_exit": pool.vesting.early_exit,
+                    },
+                    "vesting_claims": pool.vesting_claims,
+                    "total_staked": pool.total_staked,
+                    "total_pending_withdrawals": pool.total_pending_withdrawals,
+                    "min_stake": pool.min_stake,
+                    "max_stake_per_wallet": pool.max_stake_per_wallet,
+                    "max_total_staked": pool.max_total_staked,
+                });
+            }
+            if let Ok(distributor) = decode_account::<token_rewards::Distributor>(data) {
+                let claims: u32 = distributor
+                    .claimed
+                    .iter()
+                    .map(|byte| byte.count_ones())
+                    .sum();
+                return json!({
+                    "authority": distributor.authority.to_string(),
+                    "distributor_id": distributor.distributor_id,
+                    "mint": distributor.mint.to_string(),
+                    "root": hex(&distributor.root),
+                    "total_amount": distributor.total_amount,
+                    "claimed_amount": distributor.claimed_amount,
+                    "num_recipients": distributor.num_recipients,
+                    "claims": claims,
+                });
+            }
+            if let Ok(escrow) = decode_account::<token_rewards::VestingEscrow>(data) {
+                return json!({
+                    "beneficiary": escrow.beneficiary.to_string(),
+                    "position": escrow.position.to_string(),
+                    "pool": escrow.pool.to_string(),
+                    "claim_index": escrow.claim_index,
+                    "total": escrow.total,
+                    "withdrawn": escrow.withdrawn,
+                    "start": escrow.start,
+                    "cliff_end": escrow.start + escrow.cliff,
+                    "end": escrow.start + escrow.period,
+                    "early_exit": escrow.early_exit,
+                });
+            }
+            if let Ok(treasury) = decode_account::<token_rewards::Treasury>(data) {
+                return json!({
+                    "authority": treasury.authority.to_string(),
+                    "total_fees": treasury.total_fees,
+                });
+            }
+        }
+    }
+    json!({ "data_len": data.len() })
+}
+
+/// Reads an allowlist file, a JSON object mapping each wallet to its max allocation or null
+fn read_allowlist(path: &Path) -> Result<rewards::Allowlist> {
+    let file = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("reading allowlist {}: {err}", path.display()))?;
+    let Value::Object(wallets) = serde_json::from_str(&file)? else {
+        bail!("allowlist {} is not a JSON object", path.display());
+    };
+    if wallets.is_empty() {
+        bail!("allowlist {} lists no wallets", path.display());
+    }
+
+    let entries = wallets
+        .iter()
+        .map(|(wallet, allocation)| {
+            let max_allocation = match allocation {
+                Value::Null => u64::MAX,
+                allocation => allocation
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("allocation of {wallet} is not a lamport amount"))?,
+            };
+            Ok((Pubkey::from_str(wallet)?, max_allocation))
+        })
+        .collect::<Result<_>>()?;
+    Ok(rewards::Allowlist::new(entries))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(value: &str) -> Result<[u8; 32]> {
+    if value.len() != 64 || !value.is_ascii() {
+        bail!("{value} is not a 32 byte hex string");
+    }
+    let mut bytes = [0; 32];
+    for (byte, pair) in bytes.iter_mut().zip(value.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair)?, 16)?;
+    }
+    Ok(bytes)
+}
+
+fn program_id(program: ProgramKind) -> Pubkey {
+    match program {
+        ProgramKind::Basic => basic::ID,
+        ProgramKind::Rewards => rewards::ID,
+        ProgramKind::TokenRewards => token_rewards::ID,
+    }
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let file = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("reading {}: {err}", path.display()))?;
+    serde_json::from_str(&file).map_err(|err| anyhow!("parsing {}: {err}", path.display()))
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(value)?)
+        .map_err(|err| anyhow!("writing {}: {err}", path.display()))
+}
+
+/// Writes every stake position of the program, as of the slot the RPC node or the dump is at
+fn take_snapshot(
+    context: &Context,
+    slot: Option<u64>,
+    dump: Option<&Path>,
+    out: &Path,
+) -> Result<Value> {
+    let program_id = program_id(context.program);
+    let discriminator = snapshot::stake_account_discriminator(&program_id)
+        .expect("every program has stake positions");
+
+    let result = match dump {
+        Some(path) => read_json(path)?,
+        None => context
+            .rpc
+            .program_accounts(&program_id, discriminator, slot)?,
+    };
+    let rpc::ProgramAccounts {
+        slot: taken_at,
+        accounts,
+    } = rpc::parse_program_accounts(&result)?;
+    match slot {
+        Some(slot) if dump.is_some() && taken_at != slot => {
+            bail!("the dump is of slot {taken_at}, not {slot}")
+        }
+        Some(slot) if taken_at < slot => bail!("the RPC node answered from slot {taken_at}"),
+        _ => {}
+    }
+
+    let holdings: Vec<Holding> = accounts
+        .iter()
+        .filter_map(|(address, data)| snapshot::decode_holding(&program_id, *address, data))
+        .collect();
+
+    write_json(
+        out,
+        &json!({
+            "program": program_id.to_string(),
+            "slot": taken_at,
+            "positions": holdings
+                .iter()
+                .map(|holding| json!({
+                    "position": holding.position.to_string(),
+                    "owner": holding.owner.to_string(),
+                    "staked_amount": holding.staked_amount,
+                    "points": holding.points,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    )?;
+
+    Ok(json!({
+        "slot": taken_at,
+        "positions": holdings.len(),
+        "skipped": accounts.len() - holdings.len(),
+        "wallets": snapshot::holders(&holdings).len(),
+        "staked_amount": holdings.iter().map(|holding| holding.staked_amount).sum::<u64>(),
+        "out": out.display().to_string(),
+    }))
+}
+
+/// Allocates `amount` over a snapshot with `formula` and writes the airdrop's Merkle claims
+fn build_airdrop(snapshot: &Path, formula: Formula, amount: u64, out: &Path) -> Result<Value> {
+    let file = read_json(snapshot)?;
+    let holdings = file["positions"]
+        .as_array()
+        .ok_or_else(|| anyhow!("{} lists no positions", snapshot.display()))?
+        .iter()
+        .map(|position| {
+            let field = |name: &str| {
+                position[name]
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("position without {name}"))
+            };
+            let address = |name: &str| -> Result<Pubkey> {
+                Ok(Pubkey::from_str(
+                    position[name].as_str().unwrap_or_default(),
+                )?)
+            };
+            Ok(Holding {
+                position: address("position")?,
+                owner: address("owner")?,
+                staked_amount: field("staked_amount")?,
+                points: field("points")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let airdrop = token_rewards::Airdrop::new(snapshot::allocate(
+        &holdings,
+        formula.allocation_formula(),
+        amount,
+    ));
+    if airdrop.recipients().is_empty() {
+        bail!("no wallet in {} has any weight", snapshot.display());
+    }
+
+    let claims = (0..airdrop.recipients().len() as u32)
+        .filter_map(|index| airdrop.claim(index))
+        .zip(airdrop.recipients())
+        .map(|(claim, (claimant, _))| {
+            json!({
+                "index": claim.index,
+                "claimant": claimant.to_string(),
+                "amount": claim.amount,
+                "proof": claim.proof.iter().map(|node| hex(node)).collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    write_json(
+        out,
+        &json!({
+            "slot": file["slot"],
+            "root": hex(&airdrop.root()),
+            "total_amount": airdrop.total_amount(),
+            "claims": claims,
+        }),
+    )?;
+
+    Ok(json!({
+        "root": hex(&airdrop.root()),
+        "recipients": airdrop.recipients().len(),
+        "total_amount": airdrop.total_amount(),
+        "out": out.display().to_string(),
+    }))
+}
+
+/// Rebuilds the airdrop in a file written by `build-airdrop`, refusing one whose claims no
+/// longer add up to its root
+fn read_airdrop(path: &Path) -> Result<token_rewards::Airdrop> {
+    let file = read_json(path)?;
+    let recipients = file["claims"]
+        .as_array()
+        .ok_or_else(|| anyhow!("{} lists no claims", path.display()))?
+        .iter()
+        .map(|claim| {
+            let claimant = Pubkey::from_str(claim["claimant"].as_str().unwrap_or_default())?;
+            let amount = claim["amount"]
+                .as_u64()
+                .ok_or_else(|| anyhow!("claim of {claimant} without an amount"))?;
+            Ok((claimant, amount))
+        })
+        .collect::<Result<_>>()?;
+
+    let airdrop = token_rewards::Airdrop::new(recipients);
+    let root = unhex(file["root"].as_str().unwrap_or_default())?;
+    if airdrop.root() != root {
+        bail!("the claims in {} do not match its root", path.display());
+    }
+    Ok(airdrop)
+}
+
+fn render_ranking(entries: &[rewards::LeaderboardEntry]) -> Value {
+    entries
+        .iter()
+        .zip(1..)
+        .map(|(entry, rank)| {
+            json!({
+                "rank": rank,
+                "position": entry.position.to_string(),
+                "points": entry.points,
+            })
+        })
+        .collect()
+}
+
+/// A position of type `T` still in the layout `V` from before `migrate_account`, which is
+/// `len` bytes long and shares the discriminator of `T`
+fn v1_position<T: Discriminator, V: AnchorDeserialize>(data: &[u8], len: usize) -> Option<V> {
+    if data.len() != len || !data.starts_with(T::DISCRIMINATOR) {
+        return None;
+    }
+    V::deserialize(&mut &data[8..]).ok()
+}
+
+fn render_v1_position(
+    owner: Pubkey,
+    staked_amount: u64,
+    total_points: u64,
+    last_update_time: i64,
+) -> Value {
+    json!({
+        "version": 1,
+        "owner": owner.to_string(),
+        "staked_amount": staked_amount,
+        "total_points": total_points,
+        "last_update_time": last_update_time,
+    })
+}
+
+/// The NFT token account of a tokenized position held by `holder`
+fn position_token_account(
+    context: &Context,
+    holder: &Pubkey,
+    position: &Pubkey,
+) -> Result<Option<Pubkey>> {
+    let data = context
+        .rpc
+        .account_data(position)?
+        .ok_or_else(|| anyhow!("position {position} does not exist"))?;
+    let account: token_rewards::StakeAccount = decode_account(&data)?;
+
+    Ok((account.position_mint != Pubkey::default())
+        .then(|| token_rewards::pda::position_token_account(holder, position)))
+}
+
+/// The escrow the next claim vests into, when `authority`'s pool vests claims
+fn next_vesting_escrow(context: &Context, authority: &Pubkey) -> Result<Option<Pubkey>> {
+    let address = token_rewards::pda::pool(authority).0;
+    let data = context
+        .rpc
+        .account_data(&address)?
+        .ok_or_else(|| anyhow!("pool {address} does not exist"))?;
+    let pool: token_rewards::Pool = decode_account(&data)?;
+    if !pool.vesting.is_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        token_rewards::pda::vesting_escrow(&address, pool.vesting_claims).0,
+    ))
+}
+
+/// Basic positions staked into `authority`'s vault
+fn bound_positions(context: &Context, authority: &Pubkey) -> Result<Vec<Pubkey>> {
+    let vault = basic::pda::vault(authority).0;
+    let result =
+        context
+            .rpc
+            .program_accounts(&basic::ID, basic::StakeAccount::DISCRIMINATOR, None)?;
+    Ok(rpc::parse_program_accounts(&result)?
+        .accounts
+        .into_iter()
+        .filter(|(_, data)| {
+            decode_account::<basic::StakeAccount>(data)
+                .is_ok_and(|position| position.vault == vault)
+        })
+        .map(|(address, _)| address)
+        .collect())
+}
+
+/// The owner's ve lock, only when it exists so it can boost their rewards
+fn existing_ve_lock(context: &Context, owner: &Pubkey) -> Result<Option<Pubkey>> {
+    let ve_lock = token_rewards::pda::ve_lock(owner).0;
+    Ok(context.rpc.account_data(&ve_lock)?.map(|_| ve_lock))
+}
+
+/// Leaves the leaderboard out of a rewards `ix` when the pool `authority` runs has none
+fn leaderboard_if_any(
+    context: &Context,
+    authority: &Pubkey,
+    ix: Instruction,
+) -> Result<Instruction> {
+    let leaderboard = rewards::pda::leaderboard(authority).0;
+    Ok(match context.rpc.account_data(&leaderboard)? {
+        Some(_) => ix,
+        None => rewards::without_leaderboard(ix),
+    })
+}
+
+/// Leaves the treasury out of a claim `ix` when the pool `authority` runs has none yet, which
+/// only works while the pool takes no fee
+fn treasury_if_any(context: &Context, authority: &Pubkey, ix: Instruction) -> Result<Instruction> {
+    let treasury = match context.program {
+        ProgramKind::TokenRewards => token_rewards::pda::treasury(authority).0,
+        _ => rewards::pda::treasury(authority).0,
+    };
+    if context.rpc.account_data(&treasury)?.is_some() {
+        return Ok(ix);
+    }
+    Ok(match context.program {
+        ProgramKind::TokenRewards => token_rewards::without_treasury(ix, authority),
+        _ => rewards::without_treasury(ix, authority),
+    })
+}
+
+fn authority_keypair(context: &Context, args: &AuthorityArgs) -> Result<Option<Keypair>> {
+    if context.program != ProgramKind::TokenRewards {
+        return Ok(None);
+    }
+    args.authority_keypair
+        .as_deref()
+        .map(read_keypair)
+        .transpose()
+}
+
+fn resolve_authority(args: &AuthorityArgs, keypair: Option<&Keypair>) -> Result<Pubkey> {
+    if let Some(keypair) = keypair {
+        return Ok(keypair.pubkey());
+    }
+    if let Some(authority) = args.authority {
+        return Ok(authority);
+    }
+    if let Some(path) = &args.authority_keypair {
+        return Ok(read_keypair(path)?.pubkey());
+    }
+    bail!("--authority or --authority-keypair is required")
+}
+
+fn read_keypair(path: &str) -> Result<Keypair> {
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var("HOME")?).join(rest),
+        None => PathBuf::from(path),
+    };
+    read_keypair_file(&path).map_err(|err| anyhow!("reading keypair {}: {err}", path.display()))
+}
+
+fn print_human(value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{pad}{key}:");
+                        print_human(value, indent + 1);
+                    }
+                    _ => println!("{pad}{key}: {}", scalar(value)),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::Object(_) | Value::Array(_) => print_human(item, indent + 1),
+                    _ => println!("{pad}{}", scalar(item)),
+                }
+            }
+        }
+        _ => println!("{pad}{}", scalar(value)),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,229 @@
+use std::thread::sleep;
+use std::time::Duration;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use solana_hash::Hash;
+use solana_transaction::Transaction;
+
+const CONFIRMATION_ATTEMPTS: usize = 60;
+
+/// Minimal JSON-RPC client, just the calls the CLI needs
+pub struct RpcClient {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .with_context(|| format!("{method} request to {} failed", self.url))?
+            .into_json()?;
+
+        if let Some(error) = response.get("error") {
+            bail!("{method} returned an error: {error}");
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("{method} returned no result"))
+    }
+
+    pub fn latest_blockhash(&self) -> Result<Hash> {
+        let result = self.call("getLatestBlockhash", json!([{ "commitment": "confirmed" }]))?;
+        result["value"]["blockhash"]
+            .as_str()
+            .ok_or_else(|| anyhow!("getLatestBlockhash returned no blockhash"))?
+            .parse()
+            .map_err(|_| anyhow!("getLatestBlockhash returned an invalid blockhash"))
+    }
+
+    pub fn balance(&self, address: &Pubkey) -> Result<u64> {
+        let result = self.call(
+            "getBalance",
+            json!([address.to_string(), { "commitment": "confirmed" }]),
+        )?;
+        result["value"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("getBalance returned no balance"))
+    }
+
+    /// Account data, `None` when the account does not exist
+    pub fn account_data(&self, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self.call(
+            "getAccountInfo",
+            json!([address.to_string(), { "encoding": "base64", "commitment": "confirmed" }]),
+        )?;
+        decode_account_data(&result["value"])
+    }
+
+    /// `getProgramAccounts` of `program_id` starting with `discriminator`, with the slot it
+    /// was answered at. `min_context_slot` makes the node refuse to answer from earlier state.
+    pub fn program_accounts(
+        &self,
+        program_id: &Pubkey,
+        discriminator: &[u8],
+        min_context_slot: Option<u64>,
+    ) -> Result<Value> {
+        let mut config = json!({
+            "encoding": "base64",
+            "commitment": "confirmed",
+            "withContext": true,
+            "filters": [{
+                "memcmp": { "offset": 0, "bytes": STANDARD.encode(discriminator), "encoding": "base64" }
+            }],
+        });
+        if let Some(slot) = min_context_slot {
+            config["minContextSlot"] = json!(slot);
+        }
+        self.call(
+            "getProgramAccounts",
+            json!([program_id.to_string(), config]),
+        )
+    }
+
+    /// Sends the transaction and waits until it is confirmed, returns its signature
+    pub fn send_and_confirm(&self, transaction: &Transaction) -> Result<String> {
+        let signature = self
+            .call(
+                "sendTransaction",
+                json!([
+                    encode_transaction(transaction)?,
+                    { "encoding": "base64", "preflightCommitment": "confirmed" }
+                ]),
+            )?
+            .as_str()
+            .ok_or_else(|| anyhow!("sendTransaction returned no signature"))?
+            .to_string();
+
+        for _ in 0..CONFIRMATION_ATTEMPTS {
+            let statuses = self.call("getSignatureStatuses", json!([[signature]]))?;
+            let status = &statuses["value"][0];
+            if !status.is_null() {
+                if !status["err"].is_null() {
+                    bail!("transaction {signature} failed: {}", status["err"]);
+                }
+                if matches!(
+                    status["confirmationStatus"].as_str(),
+                    Some("confirmed" | "finalized")
+                ) {
+                    return Ok(signature);
+                }
+            }
+            sleep(Duration::from_millis(500));
+        }
+
+        bail!("transaction {signature} was not confirmed in time")
+    }
+
+    /// Simulates the transaction, returning the error, logs and post-state of `addresses`
+    pub fn simulate(&self, transaction: &Transaction, addresses: &[Pubkey]) -> Result<Simulation> {
+        let result = self.call(
+            "simulateTransaction",
+            json!([
+                encode_transaction(transaction)?,
+                {
+                    "encoding": "base64",
+                    "sigVerify": true,
+                    "commitment": "confirmed",
+                    "accounts": {
+                        "encoding": "base64",
+                        "addresses": addresses.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+                    },
+                }
+            ]),
+        )?;
+        let value = &result["value"];
+
+        let accounts = match value["accounts"].as_array() {
+            Some(accounts) => accounts
+                .iter()
+                .map(decode_account_data)
+                .collect::<Result<_>>()?,
+            None => vec![None; addresses.len()],
+        };
+
+        let return_data = match value["returnData"]["data"][0].as_str() {
+            Some(data) => Some(STANDARD.decode(data)?),
+            None => None,
+        };
+
+        Ok(Simulation {
+            err: value["err"].clone(),
+            logs: value["logs"].clone(),
+            units_consumed: value["unitsConsumed"].as_u64(),
+            accounts,
+            return_data,
+        })
+    }
+}
+
+pub struct Simulation {
+    pub err: Value,
+    pub logs: Value,
+    pub units_consumed: Option<u64>,
+    /// Post-simulation data of each requested address, in request order
+    pub accounts: Vec<Option<Vec<u8>>>,
+    /// Data the last instruction returned, if any
+    pub return_data: Option<Vec<u8>>,
+}
+
+pub struct ProgramAccounts {
+    /// Slot the node answered at
+    pub slot: u64,
+    pub accounts: Vec<(Pubkey, Vec<u8>)>,
+}
+
+/// Reads a `getProgramAccounts` result requested `withContext`
+pub fn parse_program_accounts(result: &Value) -> Result<ProgramAccounts> {
+    let slot = result["context"]["slot"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("getProgramAccounts result without a context slot"))?;
+    let accounts = result["value"]
+        .as_array()
+        .ok_or_else(|| anyhow!("getProgramAccounts result without accounts"))?
+        .iter()
+        .map(|entry| {
+            let address = entry["pubkey"]
+                .as_str()
+                .ok_or_else(|| anyhow!("program account without a pubkey"))?
+                .parse()
+                .map_err(|_| anyhow!("program account with an invalid pubkey"))?;
+            let data = decode_account_data(&entry["account"])?
+                .ok_or_else(|| anyhow!("program account {address} without data"))?;
+            Ok((address, data))
+        })
+        .collect::<Result<_>>()?;
+    Ok(ProgramAccounts { slot, accounts })
+}
+
+fn encode_transaction(transaction: &Transaction) -> Result<String> {
+    Ok(STANDARD.encode(bincode::serialize(transaction)?))
+}
+
+fn decode_account_data(account: &Value) -> Result<Option<Vec<u8>>> {
+    if account.is_null() {
+        return Ok(None);
+    }
+    let data = account["data"][0]
+        .as_str()
+        .ok_or_else(|| anyhow!("account without base64 data"))?;
+    Ok(Some(STANDARD.decode(data)?))
+}
@@ -0,0 +1,226 @@
+//! `basic_staking_program`: one SOL position per wallet held in an authority's vault.
+
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::system_program;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use basic_staking_program::{accounts, instruction};
+
+use crate::{Instruction, Pubkey};
+
+pub use basic_staking_program::{
+    Capacity, Pool, PoolParams, Reconciliation, StakeAccount, StakeAccountV1, StakingError,
+    Treasury, ID, STAKE_ACCOUNT_VERSION,
+};
+
+pub mod pda {
+    use super::*;
+
+    /// `[b"client1", owner]`, the owner's stake position
+    pub fn stake_account(owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"client1", owner.as_ref()], &ID)
+    }
+
+    /// `[b"pdaVault", authority]`, the system account holding staked lamports
+    pub fn vault(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"pdaVault", authority.as_ref()], &ID)
+    }
+
+    /// `[b"pool", authority]`, what the authority's vault owes its stakers
+    pub fn pool(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"pool", authority.as_ref()], &ID)
+    }
+
+    /// `[b"treasury", authority]`, where the vault's surplus is swept
+    pub fn treasury(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"treasury", authority.as_ref()], &ID)
+    }
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn create_pda_account(payer: &Pubkey) -> Instruction {
+    build(
+        accounts::CreatePdaAccount {
+            payer: *payer,
+            pda_account: pda::stake_account(payer).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePdaAccount {},
+    )
+}
+
+pub fn create_vault_pda_account(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::VaultPdaAccount {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreateVaultPdaAccount {},
+    )
+}
+
+/// Starts tracking the vault's principal, counting the stake of `positions`, which must be
+/// every position already bound to the vault
+pub fn initialize_pool(
+    authority: &Pubkey,
+    params: PoolParams,
+    positions: &[Pubkey],
+) -> Instruction {
+    let mut ix = build(
+        accounts::InitializePool {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializePool { params },
+    );
+    ix.accounts.extend(
+        positions
+            .iter()
+            .map(|position| AccountMeta::new_readonly(*position, false)),
+    );
+    ix
+}
+
+pub fn stake(user: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::Stake {
+            user: *user,
+            pda_account: pda::stake_account(user).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::Stake { amount },
+    )
+}
+
+pub fn unstake(user: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::Unstake {
+            user: *user,
+            pda_account: pda::stake_account(user).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::Unstake { amount },
+    )
+}
+
+/// Has the vault authority co-sign a `stake` or `unstake`, which binds a position staked
+/// before positions recorded their vault
+pub fn signed_by_authority(mut ix: Instruction) -> Instruction {
+    ix.accounts[2].is_signer = true;
+    ix
+}
+
+pub fn transfer_position(owner: &Pubkey, new_owner: &Pubkey) -> Instruction {
+    build(
+        accounts::TransferPosition {
+            owner: *owner,
+            pda_account: pda::stake_account(owner).0,
+            new_owner: *new_owner,
+            new_pda_account: pda::stake_account(new_owner).0,
+            system_program: system_program::ID,
+        },
+        instruction::TransferPosition {},
+    )
+}
+
+/// Upgrades `owner`'s position from the v1 layout, `payer` covers the extra rent
+pub fn migrate_account(payer: &Pubkey, owner: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateAccount {
+            payer: *payer,
+            pda_account: pda::stake_account(owner).0,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateAccount {},
+    )
+}
+
+/// Read-only, the program returns a borsh `Reconciliation`
+pub fn reconcile(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::Reconcile {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+        },
+        instruction::Reconcile {},
+    )
+}
+
+/// Read-only, returns the pool's `Capacity` and that of `position` when given
+pub fn capacity(authority: &Pubkey, position: Option<&Pubkey>) -> Instruction {
+    build(
+        accounts::CapacityView {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            pda_account: position.copied(),
+        },
+        instruction::Capacity {},
+    )
+}
+
+pub fn initialize_treasury(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeTreasury {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeTreasury {},
+    )
+}
+
+/// Moves the vault's surplus into `authority`'s treasury
+pub fn sweep_surplus(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::SweepSurplus {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            treasury: pda::treasury(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::SweepSurplus {},
+    )
+}
+
+/// Pays `amount` lamports out of `authority`'s treasury to the authority
+pub fn withdraw_treasury(authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::WithdrawTreasury {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+        },
+        instruction::WithdrawTreasury { amount },
+    )
+}
+
+crate::staking_error_decoder!(
+    InvalidAmount,
+    InvalidBalance,
+    InvalidNewOwner,
+    AlreadyMigrated,
+    VaultMismatch,
+    NoSurplus,
+    DuplicatePosition,
+    MathOverflow,
+    BelowMinimumStake,
+    WalletCapExceeded,
+    PoolCapExceeded,
+    InvalidStakeLimits,
+);
@@ -0,0 +1,175 @@
+//! Client-side helpers for the three staking programs: typed PDA derivation, instruction
+//! builders for every instruction, account decoding and `StakingError` decoding.
+//!
+//! Each program has its own module, `basic` for `basic_staking_program`, `rewards` for
+//! `staking_program_with_rewards` and `token_rewards` for `stake_with_token_reward`.
+//! `snapshot` reads the positions of all three for airdrops.
+
+use anchor_lang::solana_program::instruction::InstructionError;
+use anchor_lang::AccountDeserialize;
+
+pub mod basic;
+mod merkle;
+pub mod rewards;
+pub mod snapshot;
+pub mod token_rewards;
+
+pub use anchor_lang::solana_program::instruction::Instruction;
+pub use anchor_lang::solana_program::pubkey::Pubkey;
+
+/// Decodes any of the programs' accounts, checking its discriminator
+pub fn decode_account<T: AccountDeserialize>(data: &[u8]) -> anchor_lang::Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+/// Custom error code carried by a failed instruction, if it failed with one
+pub fn custom_error_code(error: &InstructionError) -> Option<u32> {
+    match error {
+        InstructionError::Custom(code) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Generates `staking_error`, mapping an error code back onto the program's `StakingError`
+macro_rules! staking_error_decoder {
+    ($($variant:ident),* $(,)?) => {
+        /// The `StakingError` behind a custom error code, `None` for Anchor or system errors
+        pub fn staking_error(code: u32) -> Option<StakingError> {
+            [$(StakingError::$variant),*]
+                .into_iter()
+                .find(|error| u32::from(*error) == code)
+        }
+    };
+}
+pub(crate) use staking_error_decoder;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    #[test]
+    fn decodes_staking_errors_from_custom_codes() {
+        let code = u32::from(token_rewards::StakingError::PositionTokenized);
+
+        assert!(matches!(
+            custom_error_code(&InstructionError::Custom(code))
+                .and_then(token_rewards::staking_error),
+            Some(token_rewards::StakingError::PositionTokenized)
+        ));
+        assert!(matches!(
+            rewards::staking_error(6000),
+            Some(rewards::StakingError::InvalidAmount)
+        ));
+        assert!(basic::staking_error(100).is_none());
+    }
+
+    #[test]
+    fn builds_instructions_against_the_right_accounts() {
+        let user = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let ix = rewards::stake(&user, &authority, 42, None);
+
+        assert_eq!(ix.program_id, rewards::ID);
+        assert!(ix
+            .data
+            .starts_with(staking_program_with_rewards::instruction::Stake::DISCRIMINATOR));
+        assert_eq!(ix.accounts[1].pubkey, rewards::pda::stake_account(&user).0);
+        assert_eq!(ix.accounts[3].pubkey, rewards::pda::vault(&authority).0);
+        assert!(ix.accounts[0].is_signer);
+    }
+
+    #[test]
+    fn every_allowlist_proof_verifies_against_the_root() {
+        for size in 1..=7 {
+            let wallets: Vec<Pubkey> = (0..size).map(|_| Pubkey::new_unique()).collect();
+            let allowlist = rewards::Allowlist::new(
+                wallets
+                    .iter()
+                    .zip(1..)
+                    .map(|(wallet, allocation)| (*wallet, allocation))
+                    .collect(),
+            );
+
+            for wallet in &wallets {
+                let proof = allowlist.proof(wallet).unwrap();
+                assert!(proof.verify(&allowlist.root(), wallet));
+            }
+            assert!(allowlist.proof(&Pubkey::new_unique()).is_none());
+        }
+    }
+
+    #[test]
+    fn every_airdrop_claim_verifies_against_the_root() {
+        for size in 1..=7 {
+            let wallets: Vec<Pubkey> = (0..size).map(|_| Pubkey::new_unique()).collect();
+            let airdrop = token_rewards::Airdrop::new(
+                wallets
+                    .iter()
+                    .zip(1..)
+                    .map(|(wallet, amount)| (*wallet, amount))
+                    .collect(),
+            );
+            assert_eq!(airdrop.total_amount(), size * (size + 1) / 2);
+
+            for (index, wallet) in (0..).zip(&wallets) {
+                let mut claim = airdrop.claim(index).unwrap();
+                assert!(claim.verify(&airdrop.root(), wallet));
+                assert!(!claim.verify(&airdrop.root(), &Pubkey::new_unique()));
+                claim.amount += 1;
+                assert!(!claim.verify(&airdrop.root(), wallet));
+            }
+            assert!(airdrop.claim(size as u32).is_none());
+        }
+    }
+
+    #[test]
+    fn allocations_split_the_airdrop_by_wallet() {
+        use snapshot::{allocate, ByPoints, ByStake, Flat, Holding};
+
+        let (whale, minnow, idle) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let holding = |owner, staked_amount, points| Holding {
+            position: Pubkey::new_unique(),
+            owner,
+            staked_amount,
+            points,
+        };
+        let holdings = [
+            holding(whale, 5, 10),
+            holding(minnow, 1, 0),
+            holding(whale, 4, 20),
+            holding(idle, 0, 30),
+        ];
+        let share = |allocations: &[(Pubkey, u64)], wallet| {
+            allocations
+                .iter()
+                .find(|(owner, _)| *owner == wallet)
+                .map(|(_, amount)| *amount)
+        };
+
+        // A wallet's positions count together, shares round down
+        let by_stake = allocate(&holdings, &ByStake, 1_000);
+        assert_eq!(share(&by_stake, whale), Some(900));
+        assert_eq!(share(&by_stake, minnow), Some(100));
+        assert_eq!(
+            share(&by_stake, idle),
+            None,
+            "nothing staked, nothing allocated"
+        );
+
+        let by_points = allocate(&holdings, &ByPoints, 1_000);
+        assert_eq!(share(&by_points, whale), Some(500));
+        assert_eq!(share(&by_points, minnow), None);
+        assert_eq!(share(&by_points, idle), Some(500));
+
+        let flat = allocate(&holdings, &Flat, 1_001);
+        assert_eq!(share(&flat, whale), Some(500));
+        assert_eq!(share(&flat, minnow), Some(500));
+
+        assert!(allocate(&holdings[3..], &ByStake, 1_000).is_empty());
+    }
+}
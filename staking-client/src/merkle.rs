@@ -0,0 +1,47 @@
+//! The Merkle tree layout shared by pool allowlists and airdrops. Each program hashes its own
+//! leaves and pairs, the tree only arranges them.
+
+/// Leaves first, an odd node out is carried up to the next layer unchanged
+pub(crate) struct MerkleTree {
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    pub(crate) fn new(leaves: Vec<[u8; 32]>, parent: fn(&[u8; 32], &[u8; 32]) -> [u8; 32]) -> Self {
+        let mut layers = vec![leaves];
+        while let Some(layer) = layers.last().filter(|layer| layer.len() > 1) {
+            let parents = layer
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => parent(left, right),
+                    [odd] => *odd,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(parents);
+        }
+
+        Self { layers }
+    }
+
+    /// All zero for an empty tree
+    pub(crate) fn root(&self) -> [u8; 32] {
+        self.layers
+            .last()
+            .and_then(|layer| layer.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sibling hashes from leaf `index` up to the root
+    pub(crate) fn proof(&self, mut index: usize) -> Vec<[u8; 32]> {
+        let mut proof = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
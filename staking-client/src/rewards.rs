@@ -0,0 +1,550 @@
+//! `staking_program_with_rewards`: one SOL position per wallet earning points, with a
+//! per-authority pool and referral points.
+
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::system_program;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token;
+use staking_program_with_rewards::{accounts, instruction};
+
+use crate::merkle::MerkleTree;
+use crate::{Instruction, Pubkey};
+
+pub use staking_program_with_rewards::{
+    allowlist_leaf, merkle_parent, AllowlistProof, Capacity, CatalogItem, ItemKind, ItemParams,
+    Leaderboard, LeaderboardEntry, LeaderboardSnapshot, PointsDecay, PointsOutlook, Pool,
+    PoolParams, Reconciliation, StakeAccount, StakeAccountV1, StakingError, StreakTier, TimeBase,
+    TimeSource, Treasury, WhitelistSpot, ID, LEADERBOARD_SIZE, MAX_STREAK_BONUS_BPS,
+    MAX_STREAK_TIERS, MIN_DECAY_PERIOD, STAKE_ACCOUNT_VERSION,
+};
+
+pub mod pda {
+    use super::*;
+
+    /// `[b"client1", owner]`, the owner's stake position
+    pub fn stake_account(owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"client1", owner.as_ref()], &ID)
+    }
+
+    /// `[b"pdaVault", authority]`, the system account holding staked lamports
+    pub fn vault(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"pdaVault", authority.as_ref()], &ID)
+    }
+
+    /// `[b"pool", authority]`, the authority's pool parameters
+    pub fn pool(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"pool", authority.as_ref()], &ID)
+    }
+
+    /// `[b"treasury", authority]`, where surplus swept out of the vault goes
+    pub fn treasury(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"treasury", authority.as_ref()], &ID)
+    }
+
+    /// `[b"leaderboard", authority]`, the pool's top positions this season
+    pub fn leaderboard(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"leaderboard", authority.as_ref()], &ID)
+    }
+
+    /// `[b"leaderboardSnapshot", authority, season]`, the final standings of a past season
+    pub fn leaderboard_snapshot(authority: &Pubkey, season: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"leaderboardSnapshot",
+                authority.as_ref(),
+                &season.to_le_bytes(),
+            ],
+            &ID,
+        )
+    }
+
+    /// `[b"catalogItem", authority, item_id]`, an item in the pool's points catalogue
+    pub fn catalog_item(authority: &Pubkey, item_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"catalogItem", authority.as_ref(), &item_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    /// `[b"whitelistSpot", item, owner]`, the receipt for a redeemed whitelist spot
+    pub fn whitelist_spot(item: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"whitelistSpot", item.as_ref(), owner.as_ref()], &ID)
+    }
+
+    /// The item's associated token account, holding the tokens its stock is worth
+    pub fn item_escrow(item: &Pubkey, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address(item, mint)
+    }
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn create_pda_account(payer: &Pubkey) -> Instruction {
+    build(
+        accounts::CreatePdaAccount {
+            payer: *payer,
+            pda_account: pda::stake_account(payer).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePdaAccount {},
+    )
+}
+
+pub fn create_vault_pda_account(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::VaultPdaAccount {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreateVaultPdaAccount {},
+    )
+}
+
+pub fn initialize_pool(authority: &Pubkey, params: PoolParams) -> Instruction {
+    build(
+        accounts::InitializePool {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializePool { params },
+    )
+}
+
+pub fn update_pool_params(authority: &Pubkey, params: PoolParams) -> Instruction {
+    build(
+        accounts::UpdatePoolParams {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+        },
+        instruction::UpdatePoolParams { params },
+    )
+}
+
+pub fn stake(
+    user: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    referrer: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::Stake {
+            user: *user,
+            pda_account: pda::stake_account(user).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            leaderboard: Some(pda::leaderboard(authority).0),
+            system_program: system_program::ID,
+        },
+        instruction::Stake {
+            amount,
+            referrer,
+            allowlist: None,
+        },
+    )
+}
+
+/// `stake` into a pool with an allowlist, proving `user`'s allocation
+pub fn stake_allowlisted(
+    user: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    referrer: Option<Pubkey>,
+    allowlist: AllowlistProof,
+) -> Instruction {
+    build(
+        accounts::Stake {
+            user: *user,
+            pda_account: pda::stake_account(user).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            leaderboard: Some(pda::leaderboard(authority).0),
+            system_program: system_program::ID,
+        },
+        instruction::Stake {
+            amount,
+            referrer,
+            allowlist: Some(allowlist),
+        },
+    )
+}
+
+pub fn unstake(user: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::Unstake {
+            user: *user,
+            pda_account: pda::stake_account(user).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            leaderboard: Some(pda::leaderboard(authority).0),
+            system_program: system_program::ID,
+        },
+        instruction::Unstake { amount },
+    )
+}
+
+/// Claims `payer`'s points, less the fee of the pool `authority` runs, which must be the one
+/// the position is staked in
+pub fn claim_points(payer: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::ClaimPoints {
+            payer: *payer,
+            pda_account: pda::stake_account(payer).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            treasury: Some(pda::treasury(authority).0),
+            system_program: system_program::ID,
+        },
+        instruction::ClaimPoints {},
+    )
+}
+
+/// Credits the referral points `referee` accrued to `referrer`'s own position, after the
+/// decay of the pool `authority` runs, the one that position is staked in
+pub fn claim_referral_points(
+    referrer: &Pubkey,
+    referee: &Pubkey,
+    authority: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ClaimReferralPoints {
+            referrer: *referrer,
+            referrer_account: pda::stake_account(referrer).0,
+            referee_account: pda::stake_account(referee).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+        },
+        instruction::ClaimReferralPoints {},
+    )
+}
+
+/// Moves `owner`'s position to `new_owner`, along with its entry on the leaderboard of the pool
+/// `authority` runs, which must be the one the position is staked in
+pub fn transfer_position(owner: &Pubkey, new_owner: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::TransferPosition {
+            owner: *owner,
+            pda_account: pda::stake_account(owner).0,
+            new_owner: *new_owner,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            leaderboard: Some(pda::leaderboard(authority).0),
+            pool: pda::pool(authority).0,
+            new_pda_account: pda::stake_account(new_owner).0,
+            system_program: system_program::ID,
+        },
+        instruction::TransferPosition {},
+    )
+}
+
+pub fn initialize_treasury(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeTreasury {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeTreasury {},
+    )
+}
+
+/// Read-only, the program returns a borsh `Reconciliation`
+pub fn reconcile(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::Reconcile {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+        },
+        instruction::Reconcile {},
+    )
+}
+
+pub fn initialize_leaderboard(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeLeaderboard {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            leaderboard: pda::leaderboard(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeLeaderboard {},
+    )
+}
+
+/// Archives `season`, which must be the current one, and starts the next
+pub fn reset_season(authority: &Pubkey, season: u64) -> Instruction {
+    build(
+        accounts::ResetSeason {
+            authority: *authority,
+            leaderboard: pda::leaderboard(authority).0,
+            snapshot: pda::leaderboard_snapshot(authority, season).0,
+            system_program: system_program::ID,
+        },
+        instruction::ResetSeason { season },
+    )
+}
+
+/// Read-only, returns the pool's `Capacity` and that of `position` when given
+pub fn capacity(authority: &Pubkey, position: Option<&Pubkey>) -> Instruction {
+    build(
+        accounts::CapacityView {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            pda_account: position.copied(),
+        },
+        instruction::Capacity {},
+    )
+}
+
+/// Read-only, returns the `PointsOutlook` of `owner`'s position in the pool `authority` runs
+pub fn points_outlook(authority: &Pubkey, owner: &Pubkey) -> Instruction {
+    build(
+        accounts::PointsOutlookView {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            pda_account: pda::stake_account(owner).0,
+        },
+        instruction::PointsOutlook {},
+    )
+}
+
+pub fn sweep_surplus(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::SweepSurplus {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            treasury: pda::treasury(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::SweepSurplus {},
+    )
+}
+
+pub fn withdraw_treasury(authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::WithdrawTreasury {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+        },
+        instruction::WithdrawTreasury { amount },
+    )
+}
+
+/// Credits the fees collected by `authority`'s treasury to the authority's own position
+pub fn withdraw_fees(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::WithdrawFees {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+            pda_account: pda::stake_account(authority).0,
+        },
+        instruction::WithdrawFees {},
+    )
+}
+
+/// Sets the root `stake` checks allowlist proofs against, all zero opens the pool to everyone
+pub fn set_allowlist_root(authority: &Pubkey, root: [u8; 32]) -> Instruction {
+    build(
+        accounts::SetAllowlistRoot {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+        },
+        instruction::SetAllowlistRoot { root },
+    )
+}
+
+/// Lists item `item_id` in the catalogue of the pool `authority` runs. Token and NFT items
+/// take their `mint`, and the whole stock is paid from the authority's associated account.
+pub fn publish_item(
+    authority: &Pubkey,
+    item_id: u64,
+    params: ItemParams,
+    mint: Option<&Pubkey>,
+) -> Instruction {
+    let item = pda::catalog_item(authority, item_id).0;
+
+    build(
+        accounts::PublishItem {
+            authority: *authority,
+            item,
+            mint: mint.copied(),
+            escrow: mint.map(|mint| pda::item_escrow(&item, mint)),
+            source: mint.map(|mint| get_associated_token_address(authority, mint)),
+            token_program: mint.map(|_| token::ID),
+            associated_token_program: mint.map(|_| associated_token::ID),
+            system_program: system_program::ID,
+        },
+        instruction::PublishItem { item_id, params },
+    )
+}
+
+/// Reprices an item and restocks it, `mint` as for `publish_item`
+pub fn update_item(
+    authority: &Pubkey,
+    item_id: u64,
+    price: u64,
+    added_stock: u64,
+    mint: Option<&Pubkey>,
+) -> Instruction {
+    let item = pda::catalog_item(authority, item_id).0;
+
+    build(
+        accounts::UpdateItem {
+            authority: *authority,
+            item,
+            escrow: mint.map(|mint| pda::item_escrow(&item, mint)),
+            source: mint.map(|mint| get_associated_token_address(authority, mint)),
+            token_program: mint.map(|_| token::ID),
+        },
+        instruction::UpdateItem { price, added_stock },
+    )
+}
+
+/// Buys one unit of an item with `user`'s points. Token and NFT items go to the user's
+/// associated account for `mint`, without one the item is taken to be a whitelist spot.
+pub fn redeem(
+    user: &Pubkey,
+    authority: &Pubkey,
+    item_id: u64,
+    mint: Option<&Pubkey>,
+) -> Instruction {
+    let item = pda::catalog_item(authority, item_id).0;
+
+    build(
+        accounts::Redeem {
+            user: *user,
+            pda_account: pda::stake_account(user).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            leaderboard: Some(pda::leaderboard(authority).0),
+            item,
+            escrow: mint.map(|mint| pda::item_escrow(&item, mint)),
+            destination: mint.map(|mint| get_associated_token_address(user, mint)),
+            whitelist_spot: mint.is_none().then(|| pda::whitelist_spot(&item, user).0),
+            token_program: mint.map(|_| token::ID),
+            system_program: system_program::ID,
+        },
+        instruction::Redeem {},
+    )
+}
+
+/// A pool allowlist as a Merkle tree, giving the root for `set_allowlist_root` and each
+/// wallet's proof for `stake_allowlisted`
+pub struct Allowlist {
+    entries: Vec<(Pubkey, u64)>,
+    tree: MerkleTree,
+}
+
+impl Allowlist {
+    /// `entries` pairs each wallet with its max allocation, `u64::MAX` for no cap
+    pub fn new(entries: Vec<(Pubkey, u64)>) -> Self {
+        let leaves = entries
+            .iter()
+            .map(|(wallet, max_allocation)| allowlist_leaf(wallet, *max_allocation))
+            .collect();
+        let tree = MerkleTree::new(leaves, merkle_parent);
+
+        Self { entries, tree }
+    }
+
+    /// All zero for an empty allowlist, the same as an open pool
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    /// `wallet`'s allocation and proof, `None` if it is not listed
+    pub fn proof(&self, wallet: &Pubkey) -> Option<AllowlistProof> {
+        let index = self.entries.iter().position(|(entry, _)| entry == wallet)?;
+
+        Some(AllowlistProof {
+            max_allocation: self.entries[index].1,
+            proof: self.tree.proof(index),
+        })
+    }
+}
+
+/// Upgrades `owner`'s position from the v1 layout, `payer` covers the extra rent. A position
+/// holding stake is bound to the vault of `authority`, which then has to co-sign.
+pub fn migrate_account(payer: &Pubkey, owner: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateAccount {
+            payer: *payer,
+            pda_account: pda::stake_account(owner).0,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateAccount {},
+    )
+}
+
+/// Has the vault authority co-sign a `migrate_account`, which a position holding stake needs
+pub fn signed_by_authority(mut ix: Instruction) -> Instruction {
+    ix.accounts[2].is_signer = true;
+    ix
+}
+
+/// Leaves the leaderboard out of a `stake`, `unstake` or `transfer_position`, for a pool that
+/// has none
+pub fn without_leaderboard(mut ix: Instruction) -> Instruction {
+    ix.accounts[5] = AccountMeta::new_readonly(ID, false);
+    ix
+}
+
+/// Drops the treasury accounts of `authority`'s pool from a `claim_points`, so a claim that pays
+/// no fee goes through before the treasury is initialized
+pub fn without_treasury(mut ix: Instruction, authority: &Pubkey) -> Instruction {
+    let treasury = [pda::treasury(authority).0];
+    for meta in &mut ix.accounts {
+        if treasury.contains(&meta.pubkey) {
+            *meta = AccountMeta::new_readonly(ID, false);
+        }
+    }
+    ix
+}
+
+crate::staking_error_decoder!(
+    InvalidAmount,
+    InvalidBalance,
+    InvalidPoints,
+    InvalidReferrer,
+    InvalidReferralRate,
+    InvalidNewOwner,
+    VaultMismatch,
+    NoSurplus,
+    InvalidFeeRate,
+    NotAllowlisted,
+    AllocationExceeded,
+    BelowMinimumStake,
+    WalletCapExceeded,
+    PoolCapExceeded,
+    InvalidStakeLimits,
+    SeasonMismatch,
+    OutOfStock,
+    InvalidItem,
+    MissingItemAccounts,
+    InvalidDecayParams,
+    InvalidStreakTiers,
+    AlreadyMigrated,
+    VaultAuthorityRequired,
+    LeaderboardRequired,
+    TreasuryRequired,
+    MathOverflow,
+);
@@ -0,0 +1,155 @@
+//! Snapshots of the stake positions of any of the three programs, and airdrop allocations
+//! over them for `token_rewards::Airdrop`.
+//!
+//! How a snapshot is split is pluggable: `allocate` takes any `AllocationFormula`, the ones
+//! here cover stake, points, square-root stake and a flat share per wallet.
+
+use std::collections::BTreeMap;
+
+use anchor_lang::{AnchorDeserialize, Discriminator};
+
+use crate::{basic, decode_account, rewards, token_rewards, Pubkey};
+
+/// A stake position as of the snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holding {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    /// Points as last settled on the position, not accrued up to the snapshot
+    pub points: u64,
+}
+
+/// Discriminator of `program_id`'s stake positions, `None` for any other program
+pub fn stake_account_discriminator(program_id: &Pubkey) -> Option<&'static [u8]> {
+    if *program_id == basic::ID {
+        Some(basic::StakeAccount::DISCRIMINATOR)
+    } else if *program_id == rewards::ID {
+        Some(rewards::StakeAccount::DISCRIMINATOR)
+    } else if *program_id == token_rewards::ID {
+        Some(token_rewards::StakeAccount::DISCRIMINATOR)
+    } else {
+        None
+    }
+}
+
+/// Reads `position` as a stake position of `program_id`, `None` if it is not one. Basic
+/// positions still in the pre-migration layout are read too.
+pub fn decode_holding(program_id: &Pubkey, position: Pubkey, data: &[u8]) -> Option<Holding> {
+    let (owner, staked_amount, points) = if *program_id == basic::ID {
+        match decode_account::<basic::StakeAccount>(data) {
+            Ok(account) => (account.owner, account.staked_amount, account.total_points),
+            Err(_) if data.len() == basic::StakeAccountV1::LEN => {
+                let account = basic::StakeAccountV1::deserialize(&mut data.get(8..)?).ok()?;
+                (account.owner, account.staked_amount, account.total_points)
+            }
+            Err(_) => return None,
+        }
+    } else if *program_id == rewards::ID {
+        let account = decode_account::<rewards::StakeAccount>(data).ok()?;
+        (account.owner, account.staked_amount, account.total_points)
+    } else if *program_id == token_rewards::ID {
+        let account = decode_account::<token_rewards::StakeAccount>(data).ok()?;
+        (account.owner, account.staked_amount, account.total_points)
+    } else {
+        return None;
+    };
+
+    Some(Holding {
+        position,
+        owner,
+        staked_amount,
+        points,
+    })
+}
+
+/// Every position of one wallet, summed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holder {
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub points: u64,
+    pub positions: u32,
+}
+
+/// Groups `holdings` by owner, in owner order
+pub fn holders(holdings: &[Holding]) -> Vec<Holder> {
+    let mut holders = BTreeMap::new();
+    for holding in holdings {
+        let holder = holders.entry(holding.owner).or_insert(Holder {
+            owner: holding.owner,
+            staked_amount: 0,
+            points: 0,
+            positions: 0,
+        });
+        holder.staked_amount += holding.staked_amount;
+        holder.points += holding.points;
+        holder.positions += 1;
+    }
+    holders.into_values().collect()
+}
+
+/// How much of an airdrop a wallet earns, relative to every other wallet
+pub trait AllocationFormula {
+    fn weight(&self, holder: &Holder) -> u128;
+}
+
+/// Pro rata to lamports staked
+pub struct ByStake;
+
+impl AllocationFormula for ByStake {
+    fn weight(&self, holder: &Holder) -> u128 {
+        holder.staked_amount as u128
+    }
+}
+
+/// Pro rata to the square root of lamports staked, flattening the share of large wallets
+pub struct BySqrtStake;
+
+impl AllocationFormula for BySqrtStake {
+    fn weight(&self, holder: &Holder) -> u128 {
+        holder.staked_amount.isqrt() as u128
+    }
+}
+
+/// Pro rata to settled points
+pub struct ByPoints;
+
+impl AllocationFormula for ByPoints {
+    fn weight(&self, holder: &Holder) -> u128 {
+        holder.points as u128
+    }
+}
+
+/// The same share for every wallet with stake
+pub struct Flat;
+
+impl AllocationFormula for Flat {
+    fn weight(&self, holder: &Holder) -> u128 {
+        (holder.staked_amount > 0) as u128
+    }
+}
+
+/// Splits `amount` across the wallets in `holdings` by their weight under `formula`, each
+/// share rounded down. Wallets left with nothing are dropped, so the shares can sum to a
+/// little less than `amount`.
+pub fn allocate(
+    holdings: &[Holding],
+    formula: &dyn AllocationFormula,
+    amount: u64,
+) -> Vec<(Pubkey, u64)> {
+    let weighted: Vec<(Pubkey, u128)> = holders(holdings)
+        .iter()
+        .map(|holder| (holder.owner, formula.weight(holder)))
+        .collect();
+    let total_weight: u128 = weighted.iter().map(|(_, weight)| weight).sum();
+    if total_weight == 0 {
+        return Vec::new();
+    }
+
+    weighted
+        .into_iter()
+        .map(|(owner, weight)| (owner, (amount as u128 * weight / total_weight) as u64))
+        .filter(|(_, share)| *share > 0)
+        .collect()
+}
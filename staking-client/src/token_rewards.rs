@@ -0,0 +1,922 @@
+//! `stake_with_token_reward`: indexed, lock-tiered SOL positions paying reward tokens, with
+//! position NFTs, ve locks and an SPL Governance voter weight add-in.
+//!
+//! Optional accounts are passed as `Option<Pubkey>`, `None` leaves them out the same way the
+//! TypeScript client does with `null`.
+
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::system_program;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::{metadata, token};
+use stake_with_token_reward::{accounts, instruction};
+
+use crate::merkle::MerkleTree;
+use crate::{Instruction, Pubkey};
+
+pub use stake_with_token_reward::{
+    airdrop_leaf, merkle_parent, AirdropClaim, Capacity, Distributor, LockTier,
+    MaxVoterWeightRecord, Pool, PoolParams, ProposalV2Header, RealmV2Header, Reconciliation,
+    Registrar, StakeAccount, StakeAccountV1, Staker, StakingError, TimeBase, TimeSource,
+    TokenMintMetadata, Treasury, VeLock, Vesting, VestingEscrow, VoteRecord, VoterWeightAction,
+    VoterWeightRecord, ID, MAX_AIRDROP_RECIPIENTS, STAKE_ACCOUNT_VERSION,
+};
+
+pub mod pda {
+    use super::*;
+
+    /// `[b"client1", owner]`, where the owner's only position lived before positions were
+    /// indexed
+    pub fn legacy_stake_account(owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"client1", owner.as_ref()], &ID)
+    }
+
+    /// `[b"client1", owner, position_index]`, one of the owner's stake positions
+    pub fn stake_account(owner: &Pubkey, position_index: u32) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"client1", owner.as_ref(), &position_index.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    /// `[b"pdaVault", authority]`, the system account holding staked lamports
+    pub fn vault(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"pdaVault", authority.as_ref()], &ID)
+    }
+
+    /// `[b"pool", authority]`, the authority's pool parameters
+    pub fn pool(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"pool", authority.as_ref()], &ID)
+    }
+
+    /// `[b"mint"]`, the reward token mint
+    pub fn mint() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"mint"], &ID)
+    }
+
+    /// `[b"authority"]`, mint authority of the reward and position mints, owner of the ve vault
+    pub fn authority() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"authority"], &ID)
+    }
+
+    /// `[b"metadata", token_metadata_program, mint]`, Metaplex metadata of the reward mint
+    pub fn metadata(mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"metadata", metadata::ID.as_ref(), mint.as_ref()],
+            &metadata::ID,
+        )
+    }
+
+    /// `[b"positionMint", position]`, the NFT mint representing a position
+    pub fn position_mint(position: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"positionMint", position.as_ref()], &ID)
+    }
+
+    /// `[b"veLock", owner]`, the owner's vote-escrowed reward tokens
+    pub fn ve_lock(owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"veLock", owner.as_ref()], &ID)
+    }
+
+    /// `[b"staker", authority, owner]`, what `owner` has staked into the pool of `authority`
+    pub fn staker(authority: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"staker", authority.as_ref(), owner.as_ref()], &ID)
+    }
+
+    /// `[b"treasury", authority]`, the pool's fee treasury
+    pub fn treasury(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"treasury", authority.as_ref()], &ID)
+    }
+
+    /// `[b"treasuryTokens", authority]`, the reward token account the treasury's fees are
+    /// minted to
+    pub fn treasury_token_account(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"treasuryTokens", authority.as_ref()], &ID)
+    }
+
+    /// `[b"vesting", pool, claim_index]`, the escrow a vesting claim against the pool is
+    /// released from
+    pub fn vesting_escrow(pool: &Pubkey, claim_index: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"vesting", pool.as_ref(), &claim_index.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    /// `[b"distributor", authority, distributor_id]`, one of the authority's airdrops
+    pub fn distributor(authority: &Pubkey, distributor_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"distributor",
+                authority.as_ref(),
+                &distributor_id.to_le_bytes(),
+            ],
+            &ID,
+        )
+    }
+
+    /// `[b"distributorVault", distributor]`, the token account an airdrop is paid from
+    pub fn distributor_vault(distributor: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"distributorVault", distributor.as_ref()], &ID)
+    }
+
+    /// `[b"veVault"]`, the token account holding every ve lock
+    pub fn ve_vault() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"veVault"], &ID)
+    }
+
+    /// `[b"registrar", realm, governing_token_mint]`, the voter weight add-in registrar
+    pub fn registrar(realm: &Pubkey, governing_token_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"registrar", realm.as_ref(), governing_token_mint.as_ref()],
+            &ID,
+        )
+    }
+
+    /// `[registrar, b"voter-weight-record", voter]`
+    pub fn voter_weight_record(registrar: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[registrar.as_ref(), b"voter-weight-record", voter.as_ref()],
+            &ID,
+        )
+    }
+
+    /// `[b"voteRecord", proposal, position]`, marking `position` as counted towards a vote on
+    /// `proposal`
+    pub fn vote_record(proposal: &Pubkey, position: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"voteRecord", proposal.as_ref(), position.as_ref()], &ID)
+    }
+
+    /// `[registrar, b"max-voter-weight-record"]`
+    pub fn max_voter_weight_record(registrar: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[registrar.as_ref(), b"max-voter-weight-record"], &ID)
+    }
+
+    /// Associated token account `holder` keeps a position's NFT in
+    pub fn position_token_account(holder: &Pubkey, position: &Pubkey) -> Pubkey {
+        get_associated_token_address(holder, &position_mint(position).0)
+    }
+
+    /// Associated reward token account of `owner`
+    pub fn reward_token_account(owner: &Pubkey) -> Pubkey {
+        get_associated_token_address(owner, &mint().0)
+    }
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn create_pda_account(payer: &Pubkey, position_index: u32, lock_tier: LockTier) -> Instruction {
+    build(
+        accounts::CreatePdaAccount {
+            payer: *payer,
+            pda_account: pda::stake_account(payer, position_index).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreatePdaAccount {
+            position_index,
+            lock_tier,
+        },
+    )
+}
+
+pub fn create_vault_pda_account(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::VaultPdaAccount {
+            authorityVault: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreateVaultPdaAccount {},
+    )
+}
+
+pub fn create_token_mint(payer: &Pubkey, metadata: TokenMintMetadata) -> Instruction {
+    let mint = pda::mint().0;
+    build(
+        accounts::CreateTokenMint {
+            metadata: pda::metadata(&mint).0,
+            mint,
+            authority: pda::authority().0,
+            payer: *payer,
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            token_program: token::ID,
+            token_metadata_program: metadata::ID,
+        },
+        instruction::CreateTokenMint { metadata },
+    )
+}
+
+pub fn initialize_pool(authority: &Pubkey, params: PoolParams) -> Instruction {
+    build(
+        accounts::InitializePool {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializePool { params },
+    )
+}
+
+pub fn update_pool_params(authority: &Pubkey, params: PoolParams) -> Instruction {
+    build(
+        accounts::UpdatePoolParams {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+        },
+        instruction::UpdatePoolParams { params },
+    )
+}
+
+/// Stakes into `position` of `owner`, minting its NFT to `user` on the first stake and the
+/// reward receipt to `destination_owner`. `authority` is the vault authority and co-signs.
+#[allow(clippy::too_many_arguments)]
+pub fn stake(
+    user: &Pubkey,
+    owner: &Pubkey,
+    position: &Pubkey,
+    authority: &Pubkey,
+    destination_owner: &Pubkey,
+    payer: &Pubkey,
+    ve_lock: Option<Pubkey>,
+    amount: u64,
+    referrer: Option<Pubkey>,
+) -> Instruction {
+    let position_mint = pda::position_mint(position).0;
+    build(
+        accounts::Stake {
+            user: *user,
+            pda_account: *position,
+            authorityVault: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            staker: pda::staker(authority, owner).0,
+            ve_lock,
+            mint: pda::mint().0,
+            authority: pda::authority().0,
+            destination: pda::reward_token_account(destination_owner),
+            destination_owner: *destination_owner,
+            position_mint,
+            position_token_account: get_associated_token_address(user, &position_mint),
+            payer: *payer,
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::Stake { amount, referrer },
+    )
+}
+
+pub fn unstake(
+    user: &Pubkey,
+    owner: &Pubkey,
+    position: &Pubkey,
+    position_token_account: Option<Pubkey>,
+    authority: &Pubkey,
+    ve_lock: Option<Pubkey>,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::Unstake {
+            user: *user,
+            pda_account: *position,
+            position_token_account,
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            staker: pda::staker(authority, owner).0,
+            ve_lock,
+            system_program: system_program::ID,
+        },
+        instruction::Unstake { amount },
+    )
+}
+
+/// Pools that vest claims need `vesting_escrow`, the pool's next `pda::vesting_escrow` at
+/// its `vesting_claims`. `owner` owns the position, its ve boost is weighed against their total.
+pub fn claim_rewards(
+    user: &Pubkey,
+    owner: &Pubkey,
+    position: &Pubkey,
+    position_token_account: Option<Pubkey>,
+    authority: &Pubkey,
+    vesting_escrow: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::ClaimRewards {
+            user: *user,
+            pda_account: *position,
+            position_token_account,
+            authority_vault: *authority,
+            pool: pda::pool(authority).0,
+            staker: pda::staker(authority, owner).0,
+            treasury: Some(pda::treasury(authority).0),
+            treasury_token_account: Some(pda::treasury_token_account(authority).0),
+            ve_lock: pda::ve_lock(user).0,
+            mint: pda::mint().0,
+            authority: pda::authority().0,
+            destination: pda::reward_token_account(user),
+            vesting_escrow,
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::ClaimRewards {},
+    )
+}
+
+/// Mints the referral rewards accrued on `referee_position` to `referrer`, less the fee of
+/// `authority`'s pool
+pub fn claim_referral_rewards(
+    referrer: &Pubkey,
+    referee_position: &Pubkey,
+    authority: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ClaimReferralRewards {
+            referrer: *referrer,
+            referee_account: *referee_position,
+            authority_vault: *authority,
+            pool: pda::pool(authority).0,
+            treasury: Some(pda::treasury(authority).0),
+            treasury_token_account: Some(pda::treasury_token_account(authority).0),
+            ve_lock: pda::ve_lock(referrer).0,
+            mint: pda::mint().0,
+            authority: pda::authority().0,
+            destination: pda::reward_token_account(referrer),
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::ClaimReferralRewards {},
+    )
+}
+
+pub fn lock_reward_tokens(
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    amount: u64,
+    lock_duration: i64,
+) -> Instruction {
+    build(
+        accounts::LockRewardTokens {
+            user: *user,
+            ve_lock: pda::ve_lock(user).0,
+            ve_vault: pda::ve_vault().0,
+            mint: pda::mint().0,
+            authority: pda::authority().0,
+            user_token_account: *user_token_account,
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::LockRewardTokens {
+            amount,
+            lock_duration,
+        },
+    )
+}
+
+pub fn unlock_reward_tokens(user: &Pubkey, user_token_account: &Pubkey) -> Instruction {
+    build(
+        accounts::UnlockRewardTokens {
+            user: *user,
+            ve_lock: pda::ve_lock(user).0,
+            ve_vault: pda::ve_vault().0,
+            authority: pda::authority().0,
+            user_token_account: *user_token_account,
+            token_program: token::ID,
+        },
+        instruction::UnlockRewardTokens {},
+    )
+}
+
+/// Registers `authority`'s pool as the voter weight add-in of `realm`, which
+/// `realm_authority` co-signs for
+pub fn create_registrar(
+    authority: &Pubkey,
+    realm_authority: &Pubkey,
+    governance_program_id: &Pubkey,
+    realm: &Pubkey,
+    governing_token_mint: &Pubkey,
+    lock_weighted: bool,
+) -> Instruction {
+    build(
+        accounts::CreateRegistrar {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            registrar: pda::registrar(realm, governing_token_mint).0,
+            governance_program_id: *governance_program_id,
+            realm: *realm,
+            realm_authority: *realm_authority,
+            governing_token_mint: *governing_token_mint,
+            system_program: system_program::ID,
+        },
+        instruction::CreateRegistrar { lock_weighted },
+    )
+}
+
+pub fn create_voter_weight_record(voter: &Pubkey, registrar: &Pubkey) -> Instruction {
+    build(
+        accounts::CreateVoterWeightRecord {
+            voter: *voter,
+            registrar: *registrar,
+            voter_weight_record: pda::voter_weight_record(registrar, voter).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreateVoterWeightRecord {},
+    )
+}
+
+/// Refreshes the voter's weight for `action` on `target` from every position in `positions`,
+/// each with its position token account when it is tokenized. No positions sets the weight to
+/// zero. Casting a vote on the proposal `target` opens a vote record for every position.
+#[allow(clippy::too_many_arguments)]
+pub fn update_voter_weight_record(
+    voter: &Pubkey,
+    registrar: &Pubkey,
+    pool: &Pubkey,
+    ve_lock: Option<Pubkey>,
+    action: VoterWeightAction,
+    target: &Pubkey,
+    positions: &[(Pubkey, Option<Pubkey>)],
+) -> Instruction {
+    let casting_vote = action == VoterWeightAction::CastVote;
+    let vote_record =
+        |position: &Pubkey| casting_vote.then(|| pda::vote_record(target, position).0);
+    let (first, others) = match positions.split_first() {
+        Some((first, others)) => (Some(*first), others),
+        None => (None, positions),
+    };
+    let mut ix = build(
+        accounts::UpdateVoterWeightRecord {
+            voter: *voter,
+            registrar: *registrar,
+            pool: *pool,
+            pda_account: first.map(|(position, _)| position),
+            position_token_account: first.and_then(|(_, token_account)| token_account),
+            proposal: casting_vote.then_some(*target),
+            vote_record: first.and_then(|(position, _)| vote_record(&position)),
+            ve_lock,
+            voter_weight_record: pda::voter_weight_record(registrar, voter).0,
+            system_program: system_program::ID,
+        },
+        instruction::UpdateVoterWeightRecord {
+            action,
+            target: *target,
+        },
+    );
+    for (position, position_token_account) in others {
+        // Votes count the position's open vote records on it
+        ix.accounts.push(AccountMeta {
+            pubkey: *position,
+            is_signer: false,
+            is_writable: casting_vote,
+        });
+        ix.accounts.extend(
+            position_token_account.map(|account| AccountMeta::new_readonly(account, false)),
+        );
+        ix.accounts
+            .extend(vote_record(position).map(|record| AccountMeta::new(record, false)));
+    }
+    ix
+}
+
+/// Closes the vote record of `position` on `proposal` once the proposal is no longer being
+/// voted on, refunding `voter`
+pub fn relinquish_vote(
+    voter: &Pubkey,
+    registrar: &Pubkey,
+    position: &Pubkey,
+    proposal: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::RelinquishVote {
+            voter: *voter,
+            registrar: *registrar,
+            pda_account: *position,
+            proposal: *proposal,
+            vote_record: pda::vote_record(proposal, position).0,
+        },
+        instruction::RelinquishVote {},
+    )
+}
+
+pub fn create_max_voter_weight_record(payer: &Pubkey, registrar: &Pubkey) -> Instruction {
+    build(
+        accounts::CreateMaxVoterWeightRecord {
+            payer: *payer,
+            registrar: *registrar,
+            max_voter_weight_record: pda::max_voter_weight_record(registrar).0,
+            system_program: system_program::ID,
+        },
+        instruction::CreateMaxVoterWeightRecord {},
+    )
+}
+
+pub fn update_max_voter_weight_record(registrar: &Pubkey, pool_authority: &Pubkey) -> Instruction {
+    build(
+        accounts::UpdateMaxVoterWeightRecord {
+            registrar: *registrar,
+            pool: pda::pool(pool_authority).0,
+            max_voter_weight_record: pda::max_voter_weight_record(registrar).0,
+        },
+        instruction::UpdateMaxVoterWeightRecord {},
+    )
+}
+
+/// Moves an untokenized position of `owner` into a new position of `new_owner`. A position
+/// holding stake moves between the owners' totals in the pool of `authority`, an empty one
+/// can leave it `None`.
+pub fn transfer_position(
+    owner: &Pubkey,
+    position_index: u32,
+    new_owner: &Pubkey,
+    new_position_index: u32,
+    authority: Option<&Pubkey>,
+) -> Instruction {
+    build(
+        accounts::TransferPosition {
+            owner: *owner,
+            pda_account: pda::stake_account(owner, position_index).0,
+            new_owner: *new_owner,
+            new_pda_account: pda::stake_account(new_owner, new_position_index).0,
+            authority_vault: *authority.unwrap_or(owner),
+            pool: authority.map(|authority| pda::pool(authority).0),
+            staker: authority.map(|authority| pda::staker(authority, owner).0),
+            new_staker: authority.map(|authority| pda::staker(authority, new_owner).0),
+            system_program: system_program::ID,
+        },
+        instruction::TransferPosition { new_position_index },
+    )
+}
+
+/// Splits `amount` off `position` (owned by `owner`) into a new position at `new_position_index`
+#[allow(clippy::too_many_arguments)]
+pub fn split_position(
+    user: &Pubkey,
+    owner: &Pubkey,
+    position: &Pubkey,
+    position_token_account: Option<Pubkey>,
+    authority: &Pubkey,
+    ve_lock: Option<Pubkey>,
+    amount: u64,
+    new_position_index: u32,
+) -> Instruction {
+    let new_position = pda::stake_account(owner, new_position_index).0;
+    let new_position_mint = pda::position_mint(&new_position).0;
+    build(
+        accounts::SplitPosition {
+            user: *user,
+            pda_account: *position,
+            position_token_account,
+            new_pda_account: new_position,
+            new_position_mint,
+            new_position_token_account: get_associated_token_address(user, &new_position_mint),
+            authority: pda::authority().0,
+            authority_vault: *authority,
+            pool: pda::pool(authority).0,
+            staker: pda::staker(authority, owner).0,
+            ve_lock,
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::SplitPosition {
+            amount,
+            new_position_index,
+        },
+    )
+}
+
+/// Merges `source` of `source_owner` into `destination`, burning the source NFT when it is
+/// tokenized. `destination_owner` is only given when it differs from `source_owner`.
+#[allow(clippy::too_many_arguments)]
+pub fn merge_positions(
+    user: &Pubkey,
+    source_owner: &Pubkey,
+    source: &Pubkey,
+    destination_owner: Option<&Pubkey>,
+    destination: &Pubkey,
+    source_token_account: Option<Pubkey>,
+    destination_token_account: Option<Pubkey>,
+    authority: &Pubkey,
+    ve_lock: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::MergePositions {
+            user: *user,
+            source: *source,
+            destination: *destination,
+            source_token_account,
+            destination_token_account,
+            source_position_mint: source_token_account.map(|_| pda::position_mint(source).0),
+            authority_vault: *authority,
+            pool: pda::pool(authority).0,
+            source_staker: pda::staker(authority, source_owner).0,
+            destination_staker: destination_owner.map(|owner| pda::staker(authority, owner).0),
+            ve_lock,
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::MergePositions {},
+    )
+}
+
+pub fn initialize_treasury(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeTreasury {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+            treasury_token_account: pda::treasury_token_account(authority).0,
+            mint: pda::mint().0,
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::InitializeTreasury {},
+    )
+}
+
+/// Moves `amount` collected fees to the reward token account `destination`
+pub fn withdraw_fees(authority: &Pubkey, destination: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::WithdrawFees {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+            treasury_token_account: pda::treasury_token_account(authority).0,
+            destination: *destination,
+            token_program: token::ID,
+        },
+        instruction::WithdrawFees { amount },
+    )
+}
+
+/// Read-only, the program returns a borsh `Reconciliation`
+pub fn reconcile(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::Reconcile {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+        },
+        instruction::Reconcile {},
+    )
+}
+
+/// Read-only, returns the pool's `Capacity` and that of `owner` when given. An owner only
+/// has a staker account once they have staked into the pool.
+pub fn capacity(authority: &Pubkey, owner: Option<&Pubkey>) -> Instruction {
+    build(
+        accounts::CapacityView {
+            authority: *authority,
+            pool: pda::pool(authority).0,
+            staker: owner.map(|owner| pda::staker(authority, owner).0),
+        },
+        instruction::Capacity {},
+    )
+}
+
+pub fn sweep_surplus(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::SweepSurplus {
+            authority: *authority,
+            pda_vault_account: pda::vault(authority).0,
+            pool: pda::pool(authority).0,
+            treasury: pda::treasury(authority).0,
+            system_program: system_program::ID,
+        },
+        instruction::SweepSurplus {},
+    )
+}
+
+pub fn withdraw_treasury(authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::WithdrawTreasury {
+            authority: *authority,
+            treasury: pda::treasury(authority).0,
+        },
+        instruction::WithdrawTreasury { amount },
+    )
+}
+
+/// Mints whatever has vested in `escrow` since the last withdrawal to `beneficiary`
+pub fn withdraw_vested(beneficiary: &Pubkey, escrow: &Pubkey) -> Instruction {
+    build(
+        vesting_accounts(beneficiary, escrow),
+        instruction::WithdrawVested {},
+    )
+}
+
+/// Mints what has vested in `escrow` and closes it, forfeiting the rest
+pub fn exit_vesting(beneficiary: &Pubkey, escrow: &Pubkey) -> Instruction {
+    build(
+        vesting_accounts(beneficiary, escrow),
+        instruction::ExitVesting {},
+    )
+}
+
+fn vesting_accounts(beneficiary: &Pubkey, escrow: &Pubkey) -> accounts::WithdrawVested {
+    accounts::WithdrawVested {
+        beneficiary: *beneficiary,
+        vesting_escrow: *escrow,
+        ve_lock: pda::ve_lock(beneficiary).0,
+        mint: pda::mint().0,
+        authority: pda::authority().0,
+        destination: pda::reward_token_account(beneficiary),
+        system_program: system_program::ID,
+        token_program: token::ID,
+        associated_token_program: associated_token::ID,
+    }
+}
+
+/// Funds airdrop `distributor_id` with `airdrop`'s total from `authority`'s associated token
+/// account for `mint`
+pub fn create_distributor(
+    authority: &Pubkey,
+    distributor_id: u64,
+    mint: &Pubkey,
+    airdrop: &Airdrop,
+) -> Instruction {
+    let distributor = pda::distributor(authority, distributor_id).0;
+    build(
+        accounts::CreateDistributor {
+            authority: *authority,
+            distributor,
+            mint: *mint,
+            vault: pda::distributor_vault(&distributor).0,
+            source: get_associated_token_address(authority, mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+        },
+        instruction::CreateDistributor {
+            distributor_id,
+            root: airdrop.root(),
+            total_amount: airdrop.total_amount(),
+            num_recipients: airdrop.recipients().len() as u32,
+        },
+    )
+}
+
+/// Pays `claimant`'s share of `authority`'s airdrop `distributor_id` to their associated token
+/// account for `mint`
+pub fn claim_airdrop(
+    claimant: &Pubkey,
+    authority: &Pubkey,
+    distributor_id: u64,
+    mint: &Pubkey,
+    claim: AirdropClaim,
+) -> Instruction {
+    let distributor = pda::distributor(authority, distributor_id).0;
+    build(
+        accounts::ClaimAirdrop {
+            claimant: *claimant,
+            distributor,
+            vault: pda::distributor_vault(&distributor).0,
+            mint: *mint,
+            destination: get_associated_token_address(claimant, mint),
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+        },
+        instruction::ClaimAirdrop { claim },
+    )
+}
+
+/// An airdrop as a Merkle tree, giving the root for `create_distributor` and each recipient's
+/// claim for `claim_airdrop`
+pub struct Airdrop {
+    recipients: Vec<(Pubkey, u64)>,
+    tree: MerkleTree,
+}
+
+impl Airdrop {
+    /// `recipients` pairs each wallet with its amount, its place in the list is its claim index
+    pub fn new(recipients: Vec<(Pubkey, u64)>) -> Self {
+        let leaves = recipients
+            .iter()
+            .zip(0..)
+            .map(|((claimant, amount), index)| airdrop_leaf(index, claimant, *amount))
+            .collect();
+        let tree = MerkleTree::new(leaves, merkle_parent);
+
+        Self { recipients, tree }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn recipients(&self) -> &[(Pubkey, u64)] {
+        &self.recipients
+    }
+
+    pub fn total_amount(&self) -> u64 {
+        self.recipients.iter().map(|(_, amount)| amount).sum()
+    }
+
+    /// The claim at `index`, `None` past the last recipient
+    pub fn claim(&self, index: u32) -> Option<AirdropClaim> {
+        let (_, amount) = self.recipients.get(index as usize)?;
+
+        Some(AirdropClaim {
+            index,
+            amount: *amount,
+            proof: self.tree.proof(index as usize),
+        })
+    }
+}
+
+/// Moves `owner`'s v1 position into a new position at `position_index`. A position holding
+/// stake is bound to the vault of `authority`, which then has to co-sign. An empty position
+/// needs no pool, so `authority` can be `None`.
+pub fn migrate_account(
+    owner: &Pubkey,
+    position_index: u32,
+    authority: Option<&Pubkey>,
+) -> Instruction {
+    let vault_authority = authority.unwrap_or(owner);
+    build(
+        accounts::MigrateAccount {
+            owner: *owner,
+            legacy_account: pda::legacy_stake_account(owner).0,
+            pda_account: pda::stake_account(owner, position_index).0,
+            authority: *vault_authority,
+            pda_vault_account: pda::vault(vault_authority).0,
+            pool: authority.map(|authority| pda::pool(authority).0),
+            staker: authority.map(|authority| pda::staker(authority, owner).0),
+            system_program: system_program::ID,
+        },
+        instruction::MigrateAccount { position_index },
+    )
+}
+
+/// Has the vault authority co-sign a `migrate_account`, which a position holding stake needs
+pub fn signed_by_authority(mut ix: Instruction) -> Instruction {
+    ix.accounts[3].is_signer = true;
+    ix
+}
+
+/// Drops the treasury accounts of `authority`'s pool from a `claim_rewards` or
+/// `claim_referral_rewards`, so a claim that pays no fee goes through before the treasury is
+/// initialized
+pub fn without_treasury(mut ix: Instruction, authority: &Pubkey) -> Instruction {
+    let treasury = [
+        pda::treasury(authority).0,
+        pda::treasury_token_account(authority).0,
+    ];
+    for meta in &mut ix.accounts {
+        if treasury.contains(&meta.pubkey) {
+            *meta = AccountMeta::new_readonly(ID, false);
+        }
+    }
+    ix
+}
+
+crate::staking_error_decoder!(
+    InvalidAmount,
+    InvalidBalance,
+    InvalidUnstake,
+    InvalidPoints,
+    InvalidBoost,
+    InvalidLockDuration,
+    LockNotExpired,
+    InvalidReferrer,
+    InvalidReferralRate,
+    InvalidNewOwner,
+    PositionMismatch,
+    NotPositionHolder,
+    PositionTokenized,
+    InvalidFeeRate,
+    InvalidVesting,
+    VestingEscrowMismatch,
+    NothingVested,
+    EarlyExitDisabled,
+    NotBeneficiary,
+    InvalidAirdrop,
+    InvalidProof,
+    AlreadyClaimed,
+    VaultMismatch,
+    InvalidRealm,
+    NotRealmAuthority,
+    AlreadyMigrated,
+    VaultAuthorityRequired,
+    NoSurplus,
+    BelowMinimumStake,
+    WalletCapExceeded,
+    PoolCapExceeded,
+    InvalidStakeLimits,
+    StakerRequired,
+    TreasuryRequired,
+    NotClaimed,
+    InvalidProposal,
+    InvalidVoteRecord,
+    AlreadyVoted,
+    PositionVoting,
+    MathOverflow,
+);